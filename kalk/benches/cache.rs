@@ -0,0 +1,41 @@
+use kalk::parser;
+use std::time::{Duration, Instant};
+
+const INPUT: &str = "gamma(1.5)";
+const PRECISION: u32 = 3072;
+const ITERATIONS: u32 = 10000;
+
+fn main() {
+    // A fresh context every iteration means the cache is always cold. They are created and dropped outside
+    // of the measurement, so that only the evaluation is measured, like for the cached one.
+    let mut fresh_contexts = (0..ITERATIONS)
+        .map(|_| parser::Context::new())
+        .collect::<Vec<_>>();
+    let mut used_contexts = Vec::with_capacity(fresh_contexts.len());
+    let uncached = measure(|| {
+        let mut context = fresh_contexts.pop().unwrap();
+        parser::eval(&mut context, INPUT, PRECISION).unwrap();
+        used_contexts.push(context);
+    });
+
+    let mut context = parser::Context::new();
+    let cached = measure(|| {
+        parser::eval(&mut context, INPUT, PRECISION).unwrap();
+    });
+
+    println!("uncached: {:?}", uncached);
+    println!("cached:   {:?}", cached);
+    println!(
+        "speedup:  {:.1}x",
+        uncached.as_secs_f64() / cached.as_secs_f64()
+    );
+}
+
+fn measure<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+
+    start.elapsed()
+}
@@ -0,0 +1,42 @@
+use kalk::parser;
+use rug::Float;
+use std::time::{Duration, Instant};
+
+const INPUT: &str = "sin(x)";
+const PRECISION: u32 = 53;
+const ITERATIONS: i32 = 10000;
+
+fn main() {
+    let values: Vec<Float> = (0..ITERATIONS)
+        .map(|i| Float::with_val(PRECISION, i) / 100)
+        .collect();
+    let context = parser::Context::new();
+
+    // Every value gets a context of its own, and the input is parsed every time.
+    let sequential = measure(|| {
+        for value in &values {
+            let mut context = parser::Context::new();
+            let declaration = format!("x = {}", value.to_f64());
+            parser::eval(&mut context, &declaration, PRECISION).unwrap();
+            parser::eval(&mut context, INPUT, PRECISION).unwrap();
+        }
+    });
+    let parallel = measure(|| {
+        context.parallel_sweep(INPUT, "x", &values, PRECISION);
+    });
+
+    println!("threads:    {:?}", std::thread::available_parallelism());
+    println!("sequential: {:?}", sequential);
+    println!("parallel:   {:?}", parallel);
+    println!(
+        "speedup:    {:.1}x",
+        sequential.as_secs_f64() / parallel.as_secs_f64()
+    );
+}
+
+fn measure<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    f();
+
+    start.elapsed()
+}
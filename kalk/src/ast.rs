@@ -1,41 +1,115 @@
 use crate::lexer::TokenKind;
-use crate::parser::CalcError;
-use crate::parser::Unit;
+use std::fmt;
 
 /// A tree structure of a statement.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Stmt {
     VarDecl(String, Box<Expr>),
     FnDecl(String, Vec<String>, Box<Expr>),
+    /// A unit, eg. `unit m`, or a unit defined as an amount of another unit, eg. `unit km = 1000 m`.
+    /// The definition is always an `Expr::Unit`.
+    UnitDecl(String, Option<Box<Expr>>),
     /// For simplicity, expressions can be put into statements. This is the form in which expressions are passed to the interpreter.
     Expr(Box<Expr>),
 }
 
 /// A tree structure of an expression.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Expr {
     Binary(Box<Expr>, TokenKind, Box<Expr>),
     Unary(TokenKind, Box<Expr>),
-    Unit(Box<Expr>, TokenKind),
+    /// A value in a unit, eg. `30deg` or `5 km`. It evaluates to the value in the base unit,
+    /// or in the angle unit of the context for angles.
+    Unit(Box<Expr>, String),
     Var(String),
     Group(Box<Expr>),
     FnCall(String, Vec<Expr>),
     Literal(String),
+    /// An equation to solve for its unknown, eg. `2x + 3 = 11`. It evaluates to the value of the unknown.
+    Equation(Box<Expr>, Box<Expr>),
+    /// An expression with a description, eg. `note(m*v^2/2, "kinetic energy")`.
+    /// It evaluates to the value of the expression.
+    Annotated(Box<Expr>, String),
+    /// A list of numbers, eg. `[1, 2, 3]` or `(1, 2, 3)`. Arithmetic on vectors is done element-wise.
+    Vector(Vec<Expr>),
+    /// The second expression if the first one (the condition) isn't zero, otherwise the third one,
+    /// eg. `if x < 0 then -x else x`. Only the chosen expression is evaluated.
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// A value converted to another unit of the same kind, eg. `5 km to mi`.
+    Conversion(Box<Expr>, String),
 }
 
-impl TokenKind {
-    pub fn is_unit(&self) -> bool {
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            TokenKind::Deg | TokenKind::Rad => true,
-            _ => false,
+            Stmt::VarDecl(identifier, expr) => write!(f, "{} = {}", identifier, expr),
+            Stmt::FnDecl(identifier, parameters, expr) => {
+                write!(f, "{}({}) = {}", identifier, parameters.join(", "), expr)
+            }
+            Stmt::UnitDecl(identifier, Some(definition)) => {
+                write!(f, "unit {} = {}", identifier, definition)
+            }
+            Stmt::UnitDecl(identifier, None) => write!(f, "unit {}", identifier),
+            Stmt::Expr(expr) => write!(f, "{}", expr),
         }
     }
+}
 
-    pub fn to_unit(&self) -> Result<Unit, CalcError> {
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            TokenKind::Deg => Ok(Unit::Degrees),
-            TokenKind::Rad => Ok(Unit::Radians),
-            _ => Err(CalcError::InvalidUnit),
+            Expr::Binary(left, op, right) => {
+                let op = match op {
+                    TokenKind::Plus => "+",
+                    TokenKind::Minus => "-",
+                    TokenKind::Star => "*",
+                    TokenKind::Slash => "/",
+                    TokenKind::DoubleSlash => "//",
+                    TokenKind::Percent => "%",
+                    TokenKind::Power => "^",
+                    TokenKind::Distance => "<->",
+                    TokenKind::EqualsEquals => "==",
+                    TokenKind::NotEquals => "!=",
+                    TokenKind::GreaterThan => ">",
+                    TokenKind::LessThan => "<",
+                    TokenKind::GreaterOrEquals => ">=",
+                    TokenKind::LessOrEquals => "<=",
+                    TokenKind::And => "and",
+                    TokenKind::Or => "or",
+                    TokenKind::Ampersand => "&",
+                    TokenKind::Pipe => "|",
+                    TokenKind::Xor => "xor",
+                    TokenKind::ShiftLeft => "<<",
+                    TokenKind::ShiftRight => ">>",
+                    _ => "?",
+                };
+
+                write!(f, "{} {} {}", left, op, right)
+            }
+            Expr::Unary(TokenKind::Exclamation, expr) => write!(f, "{}!", expr),
+            Expr::Unary(TokenKind::Not, expr) => write!(f, "not {}", expr),
+            Expr::Unary(TokenKind::Tilde, expr) => write!(f, "~{}", expr),
+            Expr::Unary(_, expr) => write!(f, "-{}", expr),
+            Expr::Equation(left, right) => write!(f, "{} = {}", left, right),
+            Expr::Annotated(expr, annotation) => write!(f, "note({}, \"{}\")", expr, annotation),
+            Expr::Unit(expr, unit) => write!(f, "{} {}", expr, unit),
+            Expr::Var(identifier) | Expr::Literal(identifier) => write!(f, "{}", identifier),
+            Expr::Group(expr) => write!(f, "({})", expr),
+            Expr::FnCall(identifier, expressions) => {
+                let arguments: Vec<String> =
+                    expressions.iter().map(|expr| expr.to_string()).collect();
+
+                write!(f, "{}({})", identifier, arguments.join(", "))
+            }
+            Expr::Vector(elements) => {
+                let elements: Vec<String> = elements.iter().map(|expr| expr.to_string()).collect();
+
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Expr::Conditional(condition, then, otherwise) => {
+                write!(f, "if {} then {} else {}", condition, then, otherwise)
+            }
+            Expr::Conversion(expr, unit) => write!(f, "{} to {}", expr, unit),
         }
     }
 }
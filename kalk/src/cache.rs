@@ -0,0 +1,230 @@
+use crate::ast::Expr;
+use crate::hooks::EvalEvent;
+use crate::parser::Unit;
+use rug::float::Round;
+use rug::Float;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+
+const DEFAULT_CAPACITY: usize = 128;
+
+/// The settings of the interpreter that the value of an expression depends on.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Settings {
+    pub precision: u32,
+    pub angle_unit: Unit,
+    pub rounding: Round,
+    pub decimal_mode: bool,
+    pub integer_mode: bool,
+    pub integration_steps: u32,
+}
+
+/// What evaluating an expression resulted in, so that re-using it has the same effects as evaluating it again.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Evaluation {
+    pub value: Float,
+    /// The number of operations that were evaluated.
+    pub operations: usize,
+    /// The events that were emitted, or `None` if there were no callbacks to emit them to.
+    pub events: Option<Vec<EvalEvent>>,
+}
+
+/// The expression and settings are kept, rather than just their hash, so that collisions can't return
+/// the value of another expression.
+struct Entry {
+    expr: Expr,
+    settings: Settings,
+    evaluation: Evaluation,
+    /// When the entry was last used, see `Cache::clock`.
+    last_used: u64,
+}
+
+/// Least-recently-used cache for evaluated expressions, keyed on the expression and the settings it was evaluated with.
+pub struct Cache {
+    capacity: usize,
+    /// The entries by the hash of their expression and settings.
+    entries: HashMap<u64, Entry>,
+    /// The hashes of the entries by when they were last used, the least recently used one first.
+    recency: BTreeMap<u64, u64>,
+    /// Increased every time an entry is used.
+    clock: u64,
+    /// The symbol table generation the entries were computed in.
+    generation: usize,
+}
+
+impl Cache {
+    pub fn new() -> Self {
+        Cache::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Cache {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            generation: 0,
+        }
+    }
+
+    pub(crate) fn get(
+        &mut self,
+        expr: &Expr,
+        settings: &Settings,
+        generation: usize,
+    ) -> Option<Evaluation> {
+        self.sync(generation);
+
+        let hash = hash(expr, settings);
+        let entry = self.entries.get_mut(&hash)?;
+        if entry.expr != *expr || entry.settings != *settings {
+            return None;
+        }
+
+        // The entry is now the most recently used one.
+        self.recency.remove(&entry.last_used);
+        self.clock += 1;
+        entry.last_used = self.clock;
+        self.recency.insert(self.clock, hash);
+
+        Some(entry.evaluation.clone())
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        expr: &Expr,
+        settings: &Settings,
+        generation: usize,
+        evaluation: Evaluation,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.sync(generation);
+
+        let hash = hash(expr, settings);
+        self.clock += 1;
+        let entry = Entry {
+            expr: expr.clone(),
+            settings: settings.clone(),
+            evaluation,
+            last_used: self.clock,
+        };
+        if let Some(replaced) = self.entries.insert(hash, entry) {
+            self.recency.remove(&replaced.last_used);
+        }
+        self.recency.insert(self.clock, hash);
+
+        while self.entries.len() > self.capacity {
+            let oldest = *self.recency.keys().next().unwrap();
+            if let Some(hash) = self.recency.remove(&oldest) {
+                self.entries.remove(&hash);
+            }
+        }
+    }
+
+    #[cfg(feature = "profiling")]
+    pub fn values(&self) -> impl Iterator<Item = &Float> {
+        self.entries.values().map(|entry| &entry.evaluation.value)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    /// Throw away every entry if the symbol table has been mutated since they were computed.
+    fn sync(&mut self, generation: usize) {
+        if self.generation != generation {
+            self.clear();
+            self.generation = generation;
+        }
+    }
+}
+
+fn hash(expr: &Expr, settings: &Settings) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    expr.hash(&mut hasher);
+    settings.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::TokenKind::*;
+    use crate::parser::Unit;
+    use crate::test_helpers::*;
+
+    fn evaluation(value: i32) -> Evaluation {
+        Evaluation {
+            value: Float::with_val(53, value),
+            operations: 1,
+            events: None,
+        }
+    }
+
+    fn settings(precision: u32) -> Settings {
+        Settings {
+            precision,
+            angle_unit: Unit::Radians,
+            rounding: Round::Nearest,
+            decimal_mode: false,
+            integer_mode: false,
+            integration_steps: 1000,
+        }
+    }
+
+    #[test]
+    fn test_hit() {
+        let mut cache = Cache::new();
+        let expr = binary(literal("1"), Plus, literal("2"));
+        cache.insert(&expr, &settings(53), 0, evaluation(3));
+
+        assert_eq!(cache.get(&expr, &settings(53), 0), Some(evaluation(3)));
+        assert_eq!(cache.get(&expr, &settings(64), 0), None);
+
+        let degrees = Settings {
+            angle_unit: Unit::Degrees,
+            ..settings(53)
+        };
+        assert_eq!(cache.get(&expr, &degrees, 0), None);
+    }
+
+    #[test]
+    fn test_generation_change_clears() {
+        let mut cache = Cache::new();
+        let expr = var("x");
+        cache.insert(&expr, &settings(53), 0, evaluation(1));
+
+        assert_eq!(cache.get(&expr, &settings(53), 1), None);
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = Cache::with_capacity(2);
+        let a = literal("1");
+        let b = literal("2");
+        let c = literal("3");
+        cache.insert(&a, &settings(53), 0, evaluation(1));
+        cache.insert(&b, &settings(53), 0, evaluation(2));
+        cache.get(&a, &settings(53), 0); // `b` is now the least recently used entry.
+        cache.insert(&c, &settings(53), 0, evaluation(3));
+
+        assert_eq!(cache.entries.len(), 2);
+        assert_eq!(cache.recency.len(), 2);
+        assert!(cache.get(&a, &settings(53), 0).is_some());
+        assert!(cache.get(&b, &settings(53), 0).is_none());
+        assert!(cache.get(&c, &settings(53), 0).is_some());
+    }
+}
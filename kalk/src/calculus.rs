@@ -0,0 +1,424 @@
+use crate::ast::{Expr, Stmt};
+use crate::exact;
+use crate::interpreter;
+use crate::lexer::TokenKind;
+use crate::parser::{CalcError, Unit};
+use crate::simplify;
+use crate::symbol_table::SymbolTable;
+
+/// Differentiate an expression with respect to a variable, eg. `x^3 + y` becomes `3 * (x ^ 2)`.
+///
+/// Other variables are treated as constants. Calls to user-defined functions are inlined,
+/// but the variable is only looked for in their arguments, not in the variables they use.
+/// `CalcError::Message` is returned if the expression contains something that can't be differentiated, eg. `x!`.
+pub fn derivative(
+    symbol_table: &SymbolTable,
+    angle_unit: &Unit,
+    expr: &Expr,
+    variable: &str,
+) -> Result<Expr, CalcError> {
+    let differentiator = Differentiator {
+        symbol_table,
+        angle_unit,
+        variable,
+        functions: Vec::new(),
+    };
+
+    // The parentheses around the whole derivative aren't needed.
+    Ok(
+        match simplify::simplify_expr(
+            differentiator.derive(expr)?,
+            &simplify::Scope::symbolic(symbol_table),
+        ) {
+            Expr::Group(expr) => *expr,
+            expr => expr,
+        },
+    )
+}
+
+struct Differentiator<'a> {
+    symbol_table: &'a SymbolTable,
+    angle_unit: &'a Unit,
+    variable: &'a str,
+    /// The user-defined functions that are being inlined, since a function that calls itself can't be.
+    functions: Vec<&'a str>,
+}
+
+impl<'a> Differentiator<'a> {
+    fn derive(&self, expr: &Expr) -> Result<Expr, CalcError> {
+        if !self.depends_on_variable(expr) {
+            return Ok(literal("0"));
+        }
+
+        match expr {
+            // Other variables don't depend on the variable.
+            Expr::Var(_) => Ok(literal("1")),
+            Expr::Group(expr) | Expr::Annotated(expr, _) => self.derive(expr),
+            // The derivative is in the same unit, eg. the derivative of `x km` is `1 km`.
+            Expr::Unit(expr, unit) => Ok(Expr::Unit(Box::new(self.derive(expr)?), unit.clone())),
+            Expr::Conversion(expr, unit) => {
+                Ok(Expr::Conversion(Box::new(self.derive(expr)?), unit.clone()))
+            }
+            Expr::Unary(TokenKind::Minus, expr) => Ok(negate(self.derive(expr)?)),
+            Expr::Binary(left, op, right) => self.derive_binary_expr(left, op, right),
+            Expr::FnCall(identifier, expressions) => {
+                self.derive_fn_call_expr(identifier, expressions)
+            }
+            Expr::Vector(elements) => Ok(Expr::Vector(
+                elements
+                    .iter()
+                    .map(|expr| self.derive(expr))
+                    .collect::<Result<Vec<Expr>, CalcError>>()?,
+            )),
+            // Each piece is differentiated on its own, so the derivative is wrong where the condition changes.
+            Expr::Conditional(condition, then, otherwise) => Ok(Expr::Conditional(
+                condition.clone(),
+                Box::new(self.derive(then)?),
+                Box::new(self.derive(otherwise)?),
+            )),
+            _ => Err(cannot_differentiate(expr)),
+        }
+    }
+
+    fn derive_binary_expr(
+        &self,
+        left: &Expr,
+        op: &TokenKind,
+        right: &Expr,
+    ) -> Result<Expr, CalcError> {
+        let (d_left, d_right) = (self.derive(left)?, self.derive(right)?);
+        let (left, right) = (left.clone(), right.clone());
+
+        Ok(match op {
+            TokenKind::Plus | TokenKind::Minus => binary(d_left, op.clone(), d_right),
+            // (uv)' = u'v + uv'
+            TokenKind::Star => binary(
+                binary(d_left, TokenKind::Star, right),
+                TokenKind::Plus,
+                binary(left, TokenKind::Star, d_right),
+            ),
+            // (u/v)' = (u'v - uv') / v^2
+            TokenKind::Slash => binary(
+                binary(
+                    binary(d_left, TokenKind::Star, right.clone()),
+                    TokenKind::Minus,
+                    binary(left, TokenKind::Star, d_right),
+                ),
+                TokenKind::Slash,
+                binary(right, TokenKind::Power, literal("2")),
+            ),
+            // (u^n)' = n * u^(n - 1) * u'
+            TokenKind::Power if !self.depends_on_variable(&right) => binary(
+                binary(
+                    right.clone(),
+                    TokenKind::Star,
+                    binary(left, TokenKind::Power, minus_one(right)),
+                ),
+                TokenKind::Star,
+                d_left,
+            ),
+            // (a^v)' = a^v * ln(a) * v'
+            TokenKind::Power if !self.depends_on_variable(&left) => binary(
+                binary(
+                    binary(left.clone(), TokenKind::Power, right),
+                    TokenKind::Star,
+                    call("ln", left),
+                ),
+                TokenKind::Star,
+                d_right,
+            ),
+            // (u^v)' = u^v * (v' * ln(u) + v * u' / u)
+            TokenKind::Power => binary(
+                binary(left.clone(), TokenKind::Power, right.clone()),
+                TokenKind::Star,
+                binary(
+                    binary(d_right, TokenKind::Star, call("ln", left.clone())),
+                    TokenKind::Plus,
+                    binary(
+                        binary(right, TokenKind::Star, d_left),
+                        TokenKind::Slash,
+                        left,
+                    ),
+                ),
+            ),
+            _ => {
+                return Err(cannot_differentiate(&Expr::Binary(
+                    Box::new(left),
+                    op.clone(),
+                    Box::new(right),
+                )))
+            }
+        })
+    }
+
+    fn derive_fn_call_expr(
+        &self,
+        identifier: &str,
+        expressions: &[Expr],
+    ) -> Result<Expr, CalcError> {
+        // Like when evaluating, built-in functions take precedence over user-defined ones.
+        if let [argument] = expressions {
+            if let Some(outer) = self.derive_prelude_fn(identifier, argument) {
+                // Chain rule: f(u)' = f'(u) * u'
+                return Ok(binary(outer, TokenKind::Star, self.derive(argument)?));
+            }
+        }
+
+        match self.symbol_table.get_fn(identifier) {
+            Some(Stmt::FnDecl(name, parameters, body))
+                if !self.functions.contains(&name.as_str()) =>
+            {
+                if parameters.len() != expressions.len() {
+                    return Err(CalcError::IncorrectAmountOfArguments(
+                        parameters.len(),
+                        identifier.into(),
+                        expressions.len(),
+                    ));
+                }
+
+                let arguments = parameters
+                    .iter()
+                    .cloned()
+                    .zip(expressions.iter().cloned().map(group))
+                    .collect();
+
+                let mut functions = self.functions.clone();
+                functions.push(name);
+
+                Differentiator { functions, ..*self }
+                    .derive(&interpreter::substitute(body, &arguments))
+            }
+            _ => Err(cannot_differentiate(&Expr::FnCall(
+                identifier.into(),
+                expressions.to_vec(),
+            ))),
+        }
+    }
+
+    /// The derivative of a built-in function of one argument, before the chain rule is applied.
+    fn derive_prelude_fn(&self, identifier: &str, x: &Expr) -> Option<Expr> {
+        let x = x.clone();
+        let square = || binary(x.clone(), TokenKind::Power, literal("2"));
+        let one_over = |expr: Expr| binary(literal("1"), TokenKind::Slash, expr);
+
+        // The derivatives of trigonometric functions are scaled when the angles aren't in radians,
+        // eg. sin'(x) is cos(x) * π/180 in degrees.
+        let (derivative, is_trig) = match identifier {
+            "sin" => (call("cos", x), true),
+            "cos" => (negate(call("sin", x)), true),
+            "tan" => (one_over(power_of_call("cos", x, "2")), true),
+            "sinh" => (call("cosh", x), true),
+            "cosh" => (call("sinh", x), true),
+            "tanh" => (one_over(power_of_call("cosh", x, "2")), true),
+            "asin" => (
+                one_over(call(
+                    "sqrt",
+                    binary(literal("1"), TokenKind::Minus, square()),
+                )),
+                false,
+            ),
+            "acos" => (
+                negate(one_over(call(
+                    "sqrt",
+                    binary(literal("1"), TokenKind::Minus, square()),
+                ))),
+                false,
+            ),
+            "atan" => (
+                one_over(binary(literal("1"), TokenKind::Plus, square())),
+                false,
+            ),
+            "asinh" => (
+                one_over(call(
+                    "sqrt",
+                    binary(square(), TokenKind::Plus, literal("1")),
+                )),
+                false,
+            ),
+            "acosh" => (
+                one_over(call(
+                    "sqrt",
+                    binary(square(), TokenKind::Minus, literal("1")),
+                )),
+                false,
+            ),
+            "atanh" => (
+                one_over(binary(literal("1"), TokenKind::Minus, square())),
+                false,
+            ),
+            "exp" => return Some(call("exp", x)),
+            "ln" => return Some(one_over(x)),
+            "log" => {
+                return Some(one_over(binary(
+                    x,
+                    TokenKind::Star,
+                    call("ln", literal("10")),
+                )))
+            }
+            "sqrt" | "√" => {
+                return Some(one_over(binary(
+                    literal("2"),
+                    TokenKind::Star,
+                    call("sqrt", x),
+                )))
+            }
+            "cbrt" => {
+                return Some(one_over(binary(
+                    literal("3"),
+                    TokenKind::Star,
+                    power_of_call("cbrt", x, "2"),
+                )))
+            }
+            "abs" => return Some(binary(x.clone(), TokenKind::Slash, call("abs", x))),
+            _ => return None,
+        };
+
+        let degrees_per_radian = binary(literal("180"), TokenKind::Slash, Expr::Var("π".into()));
+        Some(match self.angle_unit {
+            Unit::Radians => derivative,
+            // The argument is converted to radians before the function is applied.
+            Unit::Degrees if is_trig => binary(derivative, TokenKind::Slash, degrees_per_radian),
+            // The result is converted to degrees after the function is applied.
+            Unit::Degrees => binary(derivative, TokenKind::Star, degrees_per_radian),
+        })
+    }
+
+    fn depends_on_variable(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Binary(left, _, right) | Expr::Equation(left, right) => {
+                self.depends_on_variable(left) || self.depends_on_variable(right)
+            }
+            Expr::Unary(_, expr)
+            | Expr::Unit(expr, _)
+            | Expr::Conversion(expr, _)
+            | Expr::Group(expr)
+            | Expr::Annotated(expr, _) => self.depends_on_variable(expr),
+            Expr::Var(identifier) => identifier == self.variable,
+            Expr::Literal(_) => false,
+            Expr::FnCall(_, expressions) | Expr::Vector(expressions) => expressions
+                .iter()
+                .any(|expr| self.depends_on_variable(expr)),
+            Expr::Conditional(condition, then, otherwise) => {
+                self.depends_on_variable(condition)
+                    || self.depends_on_variable(then)
+                    || self.depends_on_variable(otherwise)
+            }
+        }
+    }
+}
+
+/// Subtract one from the expression, eg. for the new exponent in the power rule.
+fn minus_one(expr: Expr) -> Expr {
+    match &expr {
+        Expr::Literal(value) => match exact::parse_decimal(value) {
+            Some(value) if value.denom() == &1 => literal(&(value - 1u32).numer().to_string()),
+            _ => binary(expr, TokenKind::Minus, literal("1")),
+        },
+        _ => binary(expr, TokenKind::Minus, literal("1")),
+    }
+}
+
+/// Put the expression in parentheses, unless it is a single value.
+fn group(expr: Expr) -> Expr {
+    match expr {
+        Expr::Binary(_, _, _) | Expr::Unary(_, _) => Expr::Group(Box::new(expr)),
+        _ => expr,
+    }
+}
+
+fn binary(left: Expr, op: TokenKind, right: Expr) -> Expr {
+    Expr::Binary(Box::new(group(left)), op, Box::new(group(right)))
+}
+
+fn negate(expr: Expr) -> Expr {
+    Expr::Unary(TokenKind::Minus, Box::new(group(expr)))
+}
+
+fn call(identifier: &str, argument: Expr) -> Expr {
+    Expr::FnCall(identifier.into(), vec![argument])
+}
+
+/// Eg. cos(x)^2
+fn power_of_call(identifier: &str, argument: Expr, exponent: &str) -> Expr {
+    binary(
+        call(identifier, argument),
+        TokenKind::Power,
+        literal(exponent),
+    )
+}
+
+fn literal(value: &str) -> Expr {
+    Expr::Literal(value.into())
+}
+
+fn cannot_differentiate(expr: &Expr) -> CalcError {
+    CalcError::Message(format!("Can't differentiate {}.", expr))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{self, CalcError, Context, Unit};
+    use crate::test_helpers::*;
+    use rug::Float;
+    use test_case::test_case;
+
+    fn diff(input: &str) -> String {
+        let mut context = Context::new();
+
+        context.derivative(input, "x").unwrap().to_string()
+    }
+
+    #[test_case("x^2", "2 * x")]
+    #[test_case("3x + y", "3")]
+    #[test_case("sin(x)", "cos(x)")]
+    #[test_case("-x", "-1")]
+    #[test_case("ln(x)", "1 / x")]
+    #[test_case("2^x", "(2 ^ x) * ln(2)")]
+    #[test_case("x^3", "3 * x ^ 2")]
+    fn test_derivative(input: &str, expected: &str) {
+        assert_eq!(diff(input), expected);
+    }
+
+    #[test_case("diff(x^2, x, 3)", 6f64)]
+    #[test_case("diff(x^3 - 2x, x, 2)", 10f64)]
+    #[test_case("diff(x/(x + 1), x, 1)", 0.25)]
+    #[test_case("diff(sqrt(x), x, 4)", 0.25)]
+    #[test_case("diff(exp(2x), x, 0)", 2f64)]
+    #[test_case("diff(x^x, x, 1)", 1f64)]
+    #[test_case("diff(atan(x), x, 1)", 0.5)]
+    #[test_case("diff(f(x), x, 2)", 12f64 ; "user-defined function")]
+    #[test_case("diff(a * x, x, 5)", 3f64 ; "declared variable")]
+    #[test_case("diff(2a, a)", 2f64 ; "declared variable as the variable")]
+    fn test_diff_fn(input: &str, expected: f64) {
+        let mut context = Context::new();
+        parser::eval(&mut context, "f(t) = t^3", 53).unwrap();
+        parser::eval(&mut context, "a = 3", 53).unwrap();
+        let result = parser::eval(&mut context, input, 53).unwrap().unwrap();
+
+        assert_approx_eq!(&result, &Float::with_val(53, expected), 4);
+    }
+
+    #[test]
+    fn test_degrees() {
+        let mut context = Context::new().set_angle_unit(Unit::Degrees);
+        let result = parser::eval(&mut context, "diff(sin(x), x, 0)", 53)
+            .unwrap()
+            .unwrap();
+
+        // π is only given with 8 decimals.
+        let pi = Float::with_val(53, Float::parse("3.14159265").unwrap());
+        assert_approx_eq!(&result, &(pi / 180), 4);
+    }
+
+    #[test_case("diff(x!, x, 2)")]
+    #[test_case("diff(floor(x), x, 2)")]
+    #[test_case("diff(x^2, 2)")]
+    fn test_diff_fn_error(input: &str) {
+        let mut context = Context::new();
+
+        assert!(matches!(
+            parser::eval(&mut context, input, 53),
+            Err(CalcError::Message(_))
+        ));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::lexer::TokenKind;
+use crate::parser::{CalcError, Unit};
+use rug::float::Constant;
+use rug::ops::Pow;
+use rug::{Complex, Float};
+
+/// The name of the imaginary unit. A variable with the same name takes precedence.
+pub const IMAGINARY_UNIT: &str = "i";
+
+/// Calculate `left op right` for complex numbers, eg. `(1 + 2i) * (3 - i)`.
+///
+/// Only arithmetic, `==`, `!=`, `and` and `or` are supported, since eg. `<` and `%`
+/// only make sense for real numbers.
+pub(crate) fn calculate_binary(
+    precision: u32,
+    left: &Complex,
+    op: &TokenKind,
+    right: &Complex,
+) -> Result<Complex, CalcError> {
+    let p = precision;
+
+    Ok(match op {
+        TokenKind::Plus => Complex::with_val(p, left + right),
+        TokenKind::Minus => Complex::with_val(p, left - right),
+        TokenKind::Star => Complex::with_val(p, left * right),
+        TokenKind::Slash => Complex::with_val(p, left / right),
+        // Integer exponents are calculated by repeated multiplication, so that eg. i^2 is exactly -1.
+        TokenKind::Power => match right.real().to_i32_saturating() {
+            Some(exponent) if right.imag().is_zero() && *right.real() == exponent => {
+                Complex::with_val(p, left.pow(exponent))
+            }
+            _ => Complex::with_val(p, left.pow(right)),
+        },
+        TokenKind::Distance => Complex::with_val(p, left - right).abs(),
+        TokenKind::EqualsEquals => boolean(p, left == right),
+        TokenKind::NotEquals => boolean(p, left != right),
+        TokenKind::And => boolean(p, *left != 0 && *right != 0),
+        TokenKind::Or => boolean(p, *left != 0 || *right != 0),
+        TokenKind::DoubleSlash => return Err(unsupported("//")),
+        TokenKind::Percent => return Err(unsupported("%")),
+        TokenKind::Ampersand
+        | TokenKind::Pipe
+        | TokenKind::Xor
+        | TokenKind::ShiftLeft
+        | TokenKind::ShiftRight => return Err(unsupported("Bitwise arithmetic")),
+        TokenKind::GreaterThan
+        | TokenKind::LessThan
+        | TokenKind::GreaterOrEquals
+        | TokenKind::LessOrEquals => return Err(unsupported("Comparison")),
+        _ => return Err(CalcError::InvalidOperator),
+    })
+}
+
+pub(crate) fn calculate_unary(
+    precision: u32,
+    op: &TokenKind,
+    x: Complex,
+) -> Result<Complex, CalcError> {
+    match op {
+        // Subtracted from zero rather than negated, so that eg. `-1` doesn't get a negative zero
+        // as its imaginary part, which would put it on the other side of the branch cut of `sqrt` and `ln`.
+        TokenKind::Minus => Ok(Complex::with_val(precision, 0) - x),
+        TokenKind::Not => Ok(boolean(precision, x == 0)),
+        TokenKind::Tilde => Err(unsupported("~")),
+        TokenKind::Exclamation => Err(unsupported("!")),
+        _ => Err(CalcError::InvalidOperator),
+    }
+}
+
+/// Call one of the built-in functions that support complex numbers: `sqrt`, `exp`, `ln`, `sin`, `cos`, `tan`,
+/// `abs`, `re`, `im`, `arg`, `conj`, and `floor`, `ceil` and `round`, which round the real and imaginary parts
+/// separately. `None` is returned for the other functions.
+pub(crate) fn call_func(identifier: &str, x: Complex, angle_unit: &Unit) -> Option<Complex> {
+    let precision = x.prec().0;
+    let to_radians = |x: Complex| match angle_unit {
+        Unit::Radians => x,
+        Unit::Degrees => x * Float::with_val(precision, Constant::Pi) / 180,
+    };
+
+    Some(match identifier {
+        "sqrt" | "√" => x.sqrt(),
+        "exp" => x.exp(),
+        "ln" => x.ln(),
+        "sin" => to_radians(x).sin(),
+        "cos" => to_radians(x).cos(),
+        "tan" => to_radians(x).tan(),
+        "abs" => x.abs(),
+        "re" => Complex::with_val(precision, x.real()),
+        "im" => Complex::with_val(precision, x.imag()),
+        "arg" => x.arg(),
+        "conj" => x.conj(),
+        "floor" => {
+            let (real, imaginary) = x.into_real_imag();
+            Complex::with_val(precision, (real.floor(), imaginary.floor()))
+        }
+        "ceil" => {
+            let (real, imaginary) = x.into_real_imag();
+            Complex::with_val(precision, (real.ceil(), imaginary.ceil()))
+        }
+        "round" => {
+            let (real, imaginary) = x.into_real_imag();
+            Complex::with_val(precision, (real.round(), imaginary.round()))
+        }
+        _ => return None,
+    })
+}
+
+/// Whether the built-in function can result in a complex number when given a real number, eg. `sqrt(-1)`.
+pub(crate) fn is_complex_valued_fn(identifier: &str) -> bool {
+    matches!(identifier, "sqrt" | "√" | "ln")
+}
+
+pub(crate) fn unsupported(name: &str) -> CalcError {
+    CalcError::Message(format!("{} is not supported for complex numbers.", name))
+}
+
+fn boolean(precision: u32, value: bool) -> Complex {
+    Complex::with_val(precision, if value { 1 } else { 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Value;
+    use crate::parser::{self, CalcError, Context, KalkValue};
+    use crate::test_helpers::*;
+    use rug::ops::Pow;
+    use rug::{Complex, Float};
+    use test_case::test_case;
+
+    fn eval_complex(input: &str) -> KalkValue {
+        let mut context = Context::new();
+
+        context.eval_complex(input, 53).unwrap().unwrap()
+    }
+
+    #[test_case("sqrt(-1)", 0f64, 1f64)]
+    #[test_case("i^2", -1f64, 0f64)]
+    #[test_case("(1 + 2i) * (3 - i)", 5f64, 5f64)]
+    #[test_case("(3 + 4i) / (1 + 2i)", 2.2, -0.4)]
+    #[test_case("2 + sqrt(-4)", 2f64, 2f64)]
+    #[test_case("abs(3 + 4i)", 5f64, 0f64)]
+    #[test_case("conj(1 + i) + re(2i) + im(3i)", 4f64, -1f64)]
+    #[test_case("max(i^2, -3)", -1f64, 0f64)]
+    #[test_case("floor(1.5 + 2.7i)", 1f64, 2f64)]
+    #[test_case("ceil(1.5 - 2.7i)", 2f64, -2f64)]
+    #[test_case("round(1.5 + 2.4i)", 2f64, 2f64)]
+    #[test_case("floor(0.5i)", 0f64, 0f64 ; "real result")]
+    fn test_eval(input: &str, real: f64, imaginary: f64) {
+        let result = eval_complex(input);
+
+        assert_eq!(result.real().to_f64(), real);
+        assert_eq!(result.imaginary().to_f64(), imaginary);
+    }
+
+    #[test]
+    fn test_eval_value() {
+        let mut context = Context::new();
+
+        assert_eq!(
+            context.eval_value("sqrt(-4)", 53).unwrap().unwrap(),
+            Value::Complex(Complex::with_val(53, (0, 2)))
+        );
+        // The imaginary part is zero, so it is a real number.
+        assert_eq!(
+            context.eval_value("i^2", 53).unwrap().unwrap(),
+            Value::from(-1f64)
+        );
+        assert_eq!(
+            context.eval_complex("[1, 2]", 53),
+            Err(CalcError::UnexpectedVector)
+        );
+    }
+
+    #[test]
+    fn test_euler() {
+        let result = eval_complex("e^(iπ)");
+
+        // e and π are only given with 8 decimals, so the imaginary part isn't quite 0.
+        let e = Complex::with_val(53, Float::parse("2.71828182").unwrap());
+        let pi = Float::with_val(53, Float::parse("3.14159265").unwrap());
+        let expected = Complex::with_val(53, e.pow(&Complex::with_val(53, (0, pi))));
+        assert_approx_eq!(result.real(), expected.real(), 4);
+        assert_approx_eq!(result.imaginary(), expected.imag(), 4);
+    }
+
+    #[test]
+    fn test_user_defined() {
+        let mut context = Context::new();
+        parser::eval(&mut context, "f(x) = x^2 + 1", 53).unwrap();
+        parser::eval(&mut context, "g(x) = sqrt(x)", 53).unwrap();
+        let result = context.eval_complex("f(i)", 53).unwrap().unwrap();
+        let product = context.eval_complex("g(-4) * g(-9)", 53).unwrap().unwrap();
+        parser::eval(&mut context, "i = 2", 53).unwrap();
+        let shadowed = context.eval_complex("i^2", 53).unwrap().unwrap();
+
+        assert!(result.real().is_zero() && result.imaginary().is_zero());
+        assert_eq!(product, KalkValue::from(Float::with_val(53, -6)));
+        assert!(shadowed.is_real());
+        assert_eq!(shadowed.real().to_f64(), 4f64);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut context = Context::new();
+
+        assert!(matches!(
+            context.eval_complex("i < 1", 53),
+            Err(CalcError::Message(_))
+        ));
+        assert!(matches!(
+            context.eval_complex("frac(i)", 53),
+            Err(CalcError::Message(_))
+        ));
+    }
+}
@@ -0,0 +1,239 @@
+use crate::lexer::{Lexer, Span, Token, TokenKind};
+use crate::parser::{self, CalcError};
+
+/// Format an error in the style of rustc, with the input and a caret pointing
+/// to where the error happened.
+///
+/// Errors with a span, see `CalcError::span`, are underlined exactly. For some other errors,
+/// the location is found by lexing the input again and looking for the name in the error.
+/// If it can't be found, only the description is returned.
+pub fn format_error(err: &CalcError, input: &str) -> String {
+    let tokens = Lexer::lex(input);
+    let end = input.chars().count();
+    let description = describe(err);
+    let mut markers = Vec::new();
+    let mut note = None;
+
+    // The input is expected to be the line, for errors on a line of a file.
+    let located = match err {
+        CalcError::Line(_, err) => err,
+        _ => err,
+    };
+    match located {
+        CalcError::UnexpectedToken(kind, span) => {
+            if *kind == TokenKind::ClosedParenthesis {
+                if let Some(open) = find_unclosed_parenthesis(&tokens) {
+                    markers.push((to_column(input, open.span.0), '-'));
+                    note = Some(String::from("the '(' marked with '-' is never closed"));
+                }
+            }
+
+            mark_span(input, *span, &mut markers);
+        }
+        CalcError::UnterminatedGroup(_, span) => {
+            if let Some(opening) = input.get(span.0..span.1) {
+                markers.push((to_column(input, span.0), '-'));
+                note = Some(format!("the '{}' marked with '-' is never closed", opening));
+            }
+
+            markers.push((end, '^'));
+        }
+        CalcError::EmptyInput(span) | CalcError::InvalidCharacter(_, span) => {
+            mark_span(input, *span, &mut markers);
+        }
+        CalcError::UndefinedFn(name)
+        | CalcError::UndefinedVar(name)
+        | CalcError::InvalidNumberLiteral(name) => {
+            if let Some(token) = tokens.iter().find(|token| &token.value == name) {
+                markers.push((to_column(input, token.span.0), '^'));
+            }
+        }
+        _ => (),
+    }
+
+    if markers.is_empty() {
+        return format!("error: {}", description);
+    }
+
+    let mut underline = vec![' '; end + 1];
+    for (column, marker) in markers {
+        underline[column] = marker;
+    }
+    let underline: String = underline.into_iter().collect();
+
+    let mut output = format!(
+        "error: {}\n  |\n  | {}\n  | {} {}",
+        description,
+        input,
+        underline.trim_end(),
+        description
+    );
+    if let Some(note) = note {
+        output.push_str(&format!("\n  |\n  = note: {}", note));
+    }
+
+    output
+}
+
+/// Underline the characters of a span with carets, or the end of the input if the span is empty.
+fn mark_span(input: &str, span: Span, markers: &mut Vec<(usize, char)>) {
+    // The span may not fit the input if macros were expanded before parsing.
+    let end = input.chars().count();
+    let (start, span_end) = match input.get(span.0..span.1) {
+        Some(_) => (to_column(input, span.0), to_column(input, span.1)),
+        None => (end, end),
+    };
+    for column in start..span_end.max(start + 1) {
+        markers.push((column, '^'));
+    }
+}
+
+/// The column of a byte offset, counted in characters.
+fn to_column(input: &str, offset: usize) -> usize {
+    input
+        .get(..offset)
+        .map_or(input.chars().count(), |before| before.chars().count())
+}
+
+/// A short description of the error, ie. its message without the capital letter and the period,
+/// eg. "undefined variable: 'y'". Errors with a span are described by what was expected there instead.
+pub(crate) fn describe(err: &CalcError) -> String {
+    match err {
+        CalcError::Line(line, err) => format!("line {}: {}", line, describe(err)),
+        CalcError::UnexpectedToken(TokenKind::ClosedParenthesis, _) => String::from("expected ')'"),
+        CalcError::UnexpectedToken(TokenKind::Equals, _) => {
+            String::from("expected a name to declare before '='")
+        }
+        CalcError::UnexpectedToken(TokenKind::Unknown, _) => String::from("unrecognized input"),
+        // Otherwise, the kind is the token that was expected at the span.
+        CalcError::UnexpectedToken(kind, _) => format!("expected '{:?}'", kind),
+        CalcError::UnterminatedGroup(kind, _) => {
+            format!("expected '{}'", parser::closing_symbol(kind))
+        }
+        _ => {
+            let message = err.to_string();
+            let message = message.strip_suffix('.').unwrap_or(&message);
+            let mut chars = message.chars();
+
+            match chars.next() {
+                Some(first) => first.to_lowercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        }
+    }
+}
+
+fn find_unclosed_parenthesis(tokens: &[Token]) -> Option<&Token> {
+    let mut open = Vec::new();
+    for token in tokens {
+        match token.kind {
+            TokenKind::OpenParenthesis => open.push(token),
+            TokenKind::ClosedParenthesis => {
+                open.pop();
+            }
+            _ => (),
+        }
+    }
+
+    open.pop()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::describe;
+    use crate::parser::{self, CalcError, Context};
+    use test_case::test_case;
+
+    fn eval_and_format(input: &str) -> String {
+        let mut context = Context::new();
+        let err = parser::eval(&mut context, input, 53).unwrap_err();
+
+        context.format_error(&err, input)
+    }
+
+    #[test]
+    fn test_missing_parenthesis() {
+        let expected = "error: expected ')'
+  |
+  | 2 * (1 + 3
+  |     -     ^ expected ')'
+  |
+  = note: the '(' marked with '-' is never closed";
+
+        assert_eq!(eval_and_format("2 * (1 + 3"), expected);
+    }
+
+    #[test]
+    fn test_leading_equals() {
+        let expected = "error: expected a name to declare before '='
+  |
+  | = 5
+  | ^ expected a name to declare before '='";
+
+        assert_eq!(eval_and_format("= 5"), expected);
+    }
+
+    #[test]
+    fn test_undefined_var() {
+        let expected = "error: undefined variable: 'y'
+  |
+  | 1 + y
+  |     ^ undefined variable: 'y'";
+
+        assert_eq!(eval_and_format("1 + y"), expected);
+    }
+
+    #[test]
+    fn test_unexpected_token_span() {
+        let expected = "error: expected 'Then'
+  |
+  | if π else 2
+  |      ^^^^ expected 'Then'";
+
+        assert_eq!(eval_and_format("if π else 2"), expected);
+    }
+
+    #[test]
+    fn test_unterminated_ceil() {
+        let expected = "error: expected '⌉'
+  |
+  | 1 + ⌈2.5
+  |     -   ^ expected '⌉'
+  |
+  = note: the '⌈' marked with '-' is never closed";
+
+        assert_eq!(eval_and_format("1 + ⌈2.5"), expected);
+    }
+
+    #[test]
+    fn test_invalid_character() {
+        let expected = "error: invalid character: '$'
+  |
+  | 2 $ 3
+  |   ^ invalid character: '$'";
+
+        assert_eq!(eval_and_format("2 $ 3"), expected);
+    }
+
+    #[test]
+    fn test_no_location() {
+        assert_eq!(
+            eval_and_format("sum(1, 3, 0, n)"),
+            "error: invalid step, it needs to lead from the start to the end"
+        );
+    }
+
+    #[test_case(CalcError::UndefinedFn(String::from("f")), "undefined function: 'f'")]
+    #[test_case(CalcError::Overflow, "too big to process")]
+    #[test_case(
+        CalcError::Line(2, Box::new(CalcError::UndefinedVar(String::from("y")))),
+        "line 2: undefined variable: 'y'"
+    )]
+    #[test_case(
+        CalcError::Message(String::from("Something went wrong.")),
+        "something went wrong"
+    )]
+    fn test_describe(err: CalcError, expected: &str) {
+        assert_eq!(describe(&err), expected);
+    }
+}
@@ -0,0 +1,265 @@
+use crate::ast::{Expr, Stmt};
+use crate::lexer::TokenKind;
+use crate::parser::CalcError;
+use crate::symbol_table::SymbolTable;
+use rug::ops::Pow;
+use rug::{Integer, Rational};
+
+/// Exponents larger than this are not calculated exactly, since the result would grow too large.
+const MAX_EXPONENT: i32 = 4096;
+
+/// Results whose numerator or denominator need more bits than this are not calculated exactly,
+/// since eg. nested powers like `(10^1000)^1000` would otherwise take too long and too much memory.
+const MAX_BITS: u64 = 1 << 16;
+
+/// Evaluate an expression exactly, as a rational number.
+///
+/// Decimal literals are represented exactly, which means eg. `0.1 + 0.2` is exactly `0.3`.
+/// `None` is returned if the expression contains anything that can't be calculated exactly,
+/// such as function calls or division by zero.
+pub fn eval(symbol_table: &SymbolTable, expr: &Expr) -> Option<Rational> {
+    match expr {
+        Expr::Binary(left, op, right) => {
+            eval_binary_expr(symbol_table, eval(symbol_table, left)?, op, right)
+        }
+        Expr::Unary(TokenKind::Minus, expr) => Some(-eval(symbol_table, expr)?),
+        Expr::Unary(TokenKind::Not, expr) => Some(from_bool(eval(symbol_table, expr)? == 0)),
+        Expr::Group(expr) | Expr::Annotated(expr, _) => eval(symbol_table, expr),
+        Expr::Var(identifier) => match symbol_table.get_var(identifier) {
+            Some(Stmt::VarDecl(_, expr)) => eval(symbol_table, expr),
+            _ => None,
+        },
+        Expr::Literal(value) => parse_decimal(value),
+        _ => None,
+    }
+}
+
+fn eval_binary_expr(
+    symbol_table: &SymbolTable,
+    left: Rational,
+    op: &TokenKind,
+    right: &Expr,
+) -> Option<Rational> {
+    eval_rational_binary(left, op, eval(symbol_table, right)?)
+}
+
+/// Calculate a binary operation on rational numbers, or `None` if the result isn't rational, eg. `2^0.5`,
+/// or would be larger than `MAX_BITS`.
+pub fn eval_rational_binary(left: Rational, op: &TokenKind, right: Rational) -> Option<Rational> {
+    let result = match op {
+        TokenKind::Plus => Some(left + right),
+        TokenKind::Minus => Some(left - right),
+        TokenKind::Star => Some(left * right),
+        TokenKind::Slash if right != 0 => Some(left / right),
+        TokenKind::DoubleSlash if right != 0 => Some((left / right).floor()),
+        TokenKind::Percent if right != 0 => {
+            let quotient = Rational::from(&left / &right).floor();
+
+            Some(left - right * quotient)
+        }
+        TokenKind::Distance => Some((left - right).abs()),
+        TokenKind::EqualsEquals => Some(from_bool(left == right)),
+        TokenKind::NotEquals => Some(from_bool(left != right)),
+        TokenKind::GreaterThan => Some(from_bool(left > right)),
+        TokenKind::LessThan => Some(from_bool(left < right)),
+        TokenKind::GreaterOrEquals => Some(from_bool(left >= right)),
+        TokenKind::LessOrEquals => Some(from_bool(left <= right)),
+        TokenKind::And => Some(from_bool(left != 0 && right != 0)),
+        TokenKind::Or => Some(from_bool(left != 0 || right != 0)),
+        TokenKind::Power => {
+            // Only integer exponents give rational results.
+            if *right.denom() != 1 {
+                return None;
+            }
+
+            let exponent = right.numer().to_i32()?;
+            if exponent.abs() > MAX_EXPONENT
+                || (exponent < 0 && left == 0)
+                || bits(&left) * u64::from(exponent.unsigned_abs()) > MAX_BITS
+            {
+                return None;
+            }
+
+            Some(left.pow(exponent))
+        }
+        _ => None,
+    };
+
+    result.filter(|result| bits(result) <= MAX_BITS)
+}
+
+/// The number of bits needed for the larger of the numerator and the denominator.
+fn bits(value: &Rational) -> u64 {
+    u64::from(
+        value
+            .numer()
+            .significant_bits()
+            .max(value.denom().significant_bits()),
+    )
+}
+
+fn from_bool(value: bool) -> Rational {
+    Rational::from(if value { 1 } else { 0 })
+}
+
+/// Calculate a binary operation on integers, for integer mode.
+///
+/// `CalcError::NotDivisible` is returned if the result is not an integer, eg. `7/2` or `2^-1`,
+/// and `CalcError::Overflow` if it needs more than `max_bits` bits.
+/// `None` is returned for operators that aren't arithmetic.
+pub fn eval_integer_binary(
+    left: &Integer,
+    op: &TokenKind,
+    right: &Integer,
+    max_bits: u32,
+) -> Result<Option<Integer>, CalcError> {
+    let result = match op {
+        TokenKind::Plus => Integer::from(left + right),
+        TokenKind::Minus => Integer::from(left - right),
+        TokenKind::Star => Integer::from(left * right),
+        TokenKind::Distance => Integer::from(left - right).abs(),
+        TokenKind::Slash => {
+            if *right == 0 || !left.is_divisible(right) {
+                return Err(CalcError::NotDivisible);
+            }
+
+            left.clone().div_exact(right)
+        }
+        TokenKind::DoubleSlash | TokenKind::Percent => {
+            if *right == 0 {
+                return Err(CalcError::NotDivisible);
+            }
+
+            let (quotient, remainder) = left.clone().div_rem_floor(right.clone());
+            if let TokenKind::DoubleSlash = op {
+                quotient
+            } else {
+                remainder
+            }
+        }
+        TokenKind::Power => {
+            if *right < 0 {
+                return match left.to_i8() {
+                    Some(1) => Ok(Some(Integer::from(1))),
+                    Some(-1) => Ok(Some(Integer::from(if right.is_even() { 1 } else { -1 }))),
+                    _ => Err(CalcError::NotDivisible),
+                };
+            }
+
+            // The result has at least this many bits, so don't calculate it if it's too large.
+            let exponent = right.to_u32().ok_or(CalcError::Overflow)?;
+            let min_bits =
+                u64::from(left.significant_bits().saturating_sub(1)) * u64::from(exponent);
+            if min_bits >= u64::from(max_bits) {
+                return Err(CalcError::Overflow);
+            }
+
+            Integer::from(left.pow(exponent))
+        }
+        _ => return Ok(None),
+    };
+
+    if result.significant_bits() > max_bits {
+        return Err(CalcError::Overflow);
+    }
+
+    Ok(Some(result))
+}
+
+/// Parse a decimal number literal, eg. `12.34` or `1.5e-10`, into a rational number.
+pub fn parse_decimal(value: &str) -> Option<Rational> {
+    let (value, exponent) = match value.find(['e', 'E']) {
+        Some(pos) => (&value[..pos], value[pos + 1..].parse::<i32>().ok()?),
+        None => (value, 0),
+    };
+    if exponent.abs() > MAX_EXPONENT {
+        return None;
+    }
+
+    let (integer_part, fraction_part) = match value.find('.') {
+        Some(pos) => (&value[..pos], &value[pos + 1..]),
+        None => (value, ""),
+    };
+
+    let digits = format!("{}{}", integer_part, fraction_part);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let numerator = Integer::from_str_radix(&digits, 10).ok()?;
+    let denominator = Integer::from(10).pow(fraction_part.len() as u32);
+
+    Some(Rational::from((numerator, denominator)) * Rational::from(10).pow(exponent))
+        .filter(|value| bits(value) <= MAX_BITS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::TokenKind::*;
+    use crate::test_helpers::*;
+    use test_case::test_case;
+
+    #[test_case("12", 12, 1)]
+    #[test_case("0.1", 1, 10)]
+    #[test_case("1.25", 5, 4)]
+    #[test_case("3.", 3, 1)]
+    #[test_case("1.5e2", 150, 1)]
+    #[test_case("25E-3", 1, 40 ; "negative exponent")]
+    fn test_parse_decimal(input: &str, numerator: i32, denominator: i32) {
+        assert_eq!(
+            parse_decimal(input).unwrap(),
+            Rational::from((numerator, denominator))
+        );
+    }
+
+    #[test_case(2, Plus, 3, 5)]
+    #[test_case(2, Minus, 3, -1)]
+    #[test_case(-4, Star, 3, -12)]
+    #[test_case(12, Slash, -4, -3)]
+    #[test_case(-7, DoubleSlash, 2, -4)]
+    #[test_case(-7, Percent, 2, 1)]
+    #[test_case(7, Percent, -2, -1 ; "modulo negative divisor")]
+    #[test_case(3, Power, 4, 81)]
+    #[test_case(-1, Power, -3, -1)]
+    fn test_eval_integer_binary(left: i32, op: TokenKind, right: i32, expected: i32) {
+        let result = eval_integer_binary(&Integer::from(left), &op, &Integer::from(right), 53);
+
+        assert_eq!(result, Ok(Some(Integer::from(expected))));
+    }
+
+    #[test_case(7, Slash, 2, CalcError::NotDivisible ; "not divisible")]
+    #[test_case(7, Slash, 0, CalcError::NotDivisible ; "divided by zero")]
+    #[test_case(7, Percent, 0, CalcError::NotDivisible ; "modulo zero")]
+    #[test_case(2, Power, -1, CalcError::NotDivisible ; "negative exponent")]
+    #[test_case(2, Power, 53, CalcError::Overflow ; "too many bits")]
+    fn test_eval_integer_binary_err(left: i32, op: TokenKind, right: i32, expected: CalcError) {
+        let result = eval_integer_binary(&Integer::from(left), &op, &Integer::from(right), 53);
+
+        assert_eq!(result, Err(expected));
+    }
+
+    #[test]
+    fn test_eval() {
+        let symbol_table = SymbolTable::new();
+        let sum = binary(literal("0.1"), Plus, literal("0.2"));
+        let pow = binary(literal("0.5"), Power, unary(Minus, literal("2")));
+        let div_by_zero = binary(literal("1"), Slash, literal("0"));
+        let fn_call = fn_call("sqrt", vec![*literal("2")]);
+
+        assert_eq!(eval(&symbol_table, &sum).unwrap(), Rational::from((3, 10)));
+        assert_eq!(eval(&symbol_table, &pow).unwrap(), 4);
+        assert_eq!(eval(&symbol_table, &div_by_zero), None);
+        assert_eq!(eval(&symbol_table, &fn_call), None);
+    }
+
+    #[test]
+    fn test_eval_too_large() {
+        let symbol_table = SymbolTable::new();
+        let power = binary(literal("10"), Power, literal("1000"));
+        let nested_power = binary(group(power.clone()), Power, literal("1000"));
+
+        assert!(eval(&symbol_table, &power).is_some());
+        assert_eq!(eval(&symbol_table, &nested_power), None);
+    }
+}
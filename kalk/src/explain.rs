@@ -0,0 +1,244 @@
+use crate::ast::{Expr, Stmt};
+use crate::lexer::TokenKind;
+
+const FUNC_NAMES: phf::Map<&'static str, &'static str> = phf::phf_map! {
+    "abs" => "absolute value",
+    "acos" => "arccosine",
+    "asin" => "arcsine",
+    "atan" => "arctangent",
+    "avg" => "average",
+    "binom" => "binomial coefficient",
+    "cbrt" => "cube root",
+    "ceil" => "ceiling",
+    "contfrac" => "continued fraction",
+    "cos" => "cosine",
+    "cosh" => "hyperbolic cosine",
+    "det" => "determinant",
+    "diff" => "derivative",
+    "exp" => "exponential",
+    "factor" => "prime factors",
+    "gamma" => "gamma",
+    "Γ" => "gamma",
+    "gcd" => "greatest common divisor",
+    "hyp" => "hypotenuse",
+    "identity" => "identity matrix",
+    "integrate" => "integral",
+    "∫" => "integral",
+    "inv" => "inverse",
+    "isprime" => "primality",
+    "lcm" => "least common multiple",
+    "len" => "length",
+    "ln" => "natural logarithm",
+    "log" => "logarithm",
+    "max" => "maximum",
+    "mean" => "mean",
+    "median" => "median",
+    "min" => "minimum",
+    "mode" => "mode",
+    "nCr" => "binomial coefficient",
+    "nPr" => "number of permutations",
+    "nextprime" => "next prime",
+    "ones" => "matrix of ones",
+    "percentile" => "percentile",
+    "prod" => "product",
+    "∏" => "product",
+    "sin" => "sine",
+    "sinh" => "hyperbolic sine",
+    "sqrt" => "square root",
+    "√" => "square root",
+    "stdev" => "standard deviation",
+    "sum" => "sum",
+    "Σ" => "sum",
+    "tan" => "tangent",
+    "tanh" => "hyperbolic tangent",
+    "totient" => "totient",
+    "variance" => "variance",
+    "weighted_mean" => "weighted mean",
+    "zeros" => "matrix of zeros",
+};
+
+const SYMBOL_NAMES: phf::Map<&'static str, &'static str> = phf::phf_map! {
+    "π" => "pi",
+    "τ" => "tau",
+    "ϕ" => "phi",
+};
+
+/// Describe the statements in plain English, eg. `x^2 + 1` becomes "x squared plus 1".
+pub fn explain(statements: &[Stmt]) -> String {
+    statements
+        .iter()
+        .map(explain_stmt)
+        .collect::<Vec<String>>()
+        .join(", then ")
+}
+
+fn explain_stmt(stmt: &Stmt) -> String {
+    match stmt {
+        Stmt::VarDecl(identifier, expr) => format!("{} is {}", identifier, explain_expr(expr)),
+        Stmt::FnDecl(identifier, parameters, expr) => format!(
+            "{} of {} is {}",
+            identifier,
+            parameters.join(" and "),
+            explain_expr(expr)
+        ),
+        Stmt::UnitDecl(identifier, Some(definition)) => {
+            format!("a {} is {}", identifier, explain_expr(definition))
+        }
+        Stmt::UnitDecl(identifier, None) => format!("{} is a unit", identifier),
+        Stmt::Expr(expr) => explain_expr(expr),
+    }
+}
+
+fn explain_expr(expr: &Expr) -> String {
+    match expr {
+        Expr::Binary(left, op, right) => explain_binary_expr(left, op, right),
+        Expr::Unary(op, expr) => explain_unary_expr(op, expr),
+        Expr::Unit(expr, unit) => format!("{} {}", explain_expr(expr), explain_unit(unit)),
+        Expr::Var(identifier) => explain_symbol(identifier),
+        Expr::Group(expr) => format!("the quantity {}", explain_expr(expr)),
+        Expr::FnCall(identifier, expressions) => explain_fn_call_expr(identifier, expressions),
+        Expr::Literal(value) => value.clone(),
+        Expr::Equation(left, right) => format!(
+            "the solution of {} equals {}",
+            explain_expr(left),
+            explain_expr(right)
+        ),
+        Expr::Annotated(expr, annotation) => format!("{} ({})", explain_expr(expr), annotation),
+        Expr::Vector(elements) => format!(
+            "the vector of {}",
+            elements
+                .iter()
+                .map(explain_expr)
+                .collect::<Vec<String>>()
+                .join(" and ")
+        ),
+        Expr::Conditional(condition, then, otherwise) => format!(
+            "{} if {}, otherwise {}",
+            explain_expr(then),
+            explain_expr(condition),
+            explain_expr(otherwise)
+        ),
+        Expr::Conversion(expr, unit) => {
+            format!("{} in {}", explain_expr(expr), explain_unit(unit))
+        }
+    }
+}
+
+fn explain_binary_expr(left: &Expr, op: &TokenKind, right: &Expr) -> String {
+    let left = explain_expr(left);
+
+    if let TokenKind::Distance = op {
+        return format!("the distance between {} and {}", left, explain_expr(right));
+    }
+
+    if let TokenKind::Power = op {
+        if let Expr::Literal(exponent) = right {
+            match exponent.as_ref() {
+                "2" => return format!("{} squared", left),
+                "3" => return format!("{} cubed", left),
+                _ => (),
+            }
+        }
+    }
+
+    let op = match op {
+        TokenKind::Plus => "plus",
+        TokenKind::Minus => "minus",
+        TokenKind::Star => "times",
+        TokenKind::Slash => "divided by",
+        TokenKind::DoubleSlash => "integer divided by",
+        TokenKind::Percent => "modulo",
+        TokenKind::Power => "to the power of",
+        TokenKind::EqualsEquals => "is equal to",
+        TokenKind::And => "and",
+        TokenKind::Or => "or",
+        TokenKind::Ampersand => "bitwise and",
+        TokenKind::Pipe => "bitwise or",
+        TokenKind::Xor => "xor",
+        TokenKind::ShiftLeft => "shifted left by",
+        TokenKind::ShiftRight => "shifted right by",
+        TokenKind::NotEquals => "is not equal to",
+        TokenKind::GreaterThan => "is greater than",
+        TokenKind::LessThan => "is less than",
+        TokenKind::GreaterOrEquals => "is greater than or equal to",
+        TokenKind::LessOrEquals => "is less than or equal to",
+        _ => "?",
+    };
+
+    format!("{} {} {}", left, op, explain_expr(right))
+}
+
+fn explain_unary_expr(op: &TokenKind, expr: &Expr) -> String {
+    match op {
+        TokenKind::Minus => format!("negative {}", explain_expr(expr)),
+        TokenKind::Exclamation => format!("{} factorial", explain_expr(expr)),
+        TokenKind::Not => format!("not {}", explain_expr(expr)),
+        TokenKind::Tilde => format!("bitwise not {}", explain_expr(expr)),
+        _ => explain_expr(expr),
+    }
+}
+
+fn explain_unit(unit: &str) -> &str {
+    match unit {
+        "deg" | "°" => "degrees",
+        "rad" => "radians",
+        _ => unit,
+    }
+}
+
+fn explain_fn_call_expr(identifier: &str, expressions: &[Expr]) -> String {
+    let name = FUNC_NAMES.get(identifier).copied().unwrap_or(identifier);
+    let arguments = expressions
+        .iter()
+        .map(explain_expr)
+        .collect::<Vec<String>>()
+        .join(" and ");
+
+    format!("{} of {}", name, arguments)
+}
+
+fn explain_symbol(identifier: &str) -> String {
+    SYMBOL_NAMES
+        .get(identifier)
+        .copied()
+        .unwrap_or(identifier)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Context;
+    use test_case::test_case;
+
+    #[test_case("sin(pi/2)", "sine of pi divided by 2")]
+    #[test_case("3!", "3 factorial")]
+    #[test_case("x^2 + 2*x + 1", "x squared plus 2 times x plus 1")]
+    #[test_case("-(1 + 2)", "negative the quantity 1 plus 2")]
+    #[test_case("30deg", "30 degrees")]
+    #[test_case("3 <-> 7", "the distance between 3 and 7")]
+    #[test_case("7 % 2", "7 modulo 2")]
+    #[test_case("x >= 2", "x is greater than or equal to 2")]
+    #[test_case("note(x^2, \"area\") * 2", "x squared (area) times 2")]
+    #[test_case("2x + 3 = 11", "the solution of 2 times x plus 3 equals 11")]
+    #[test_case("f(x, y) = xy", "f of x and y is x times y")]
+    #[test_case("a = 2π", "a is 2 times pi")]
+    #[test_case("[1, 2] * 2", "the vector of 1 and 2 times 2")]
+    #[test_case(
+        "if x < 0 then -x else x",
+        "negative x if x is less than 0, otherwise x"
+    )]
+    #[test_case("x > 1 and not x == 3", "x is greater than 1 and not x is equal to 3")]
+    fn test_explain(input: &str, expected: &str) {
+        let context = Context::new();
+
+        assert_eq!(context.explain(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_explain_without_declaring() {
+        let context = Context::new();
+        context.explain("f(x) = 2x").unwrap();
+
+        assert!(context.symbol_table().get_fn("f").is_none());
+    }
+}
@@ -0,0 +1,83 @@
+use crate::parser::CalcError;
+use rug::Float;
+use std::sync::Arc;
+
+/// A function that is called when an event happens during evaluation, see `Context::register_callback`.
+/// Returning an error stops the evaluation with that error, eg. to block calls to a function.
+pub type Callback = Box<dyn Fn(&EvalContext) -> Result<(), CalcError> + Send + Sync>;
+
+/// Callbacks are shared, so that copies of a context can call them as well.
+pub(crate) type SharedCallback = Arc<dyn Fn(&EvalContext) -> Result<(), CalcError> + Send + Sync>;
+
+pub(crate) type Callbacks = Vec<(EvalEvent, SharedCallback)>;
+
+/// A function implemented in Rust that can be called from expressions, see `Context::register_native_fn`.
+/// Shared for the same reason as callbacks.
+pub(crate) type NativeFn = Arc<dyn Fn(&[Float]) -> Result<Float, CalcError> + Send + Sync>;
+
+/// Something that happens during evaluation.
+///
+/// When registering a callback, the event decides which events it is called for.
+/// An empty name means any name, and the value of `ValueComputed` is ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalEvent {
+    BeforeFnCall(String),
+    AfterFnCall(String),
+    BeforeVarAccess(String),
+    ValueComputed(f64),
+}
+
+impl EvalEvent {
+    /// Whether a callback registered for this event should be called for `event`.
+    pub(crate) fn matches(&self, event: &EvalEvent) -> bool {
+        let matches_name = |name: &str, other: &str| name.is_empty() || name == other;
+
+        match (self, event) {
+            (EvalEvent::BeforeFnCall(name), EvalEvent::BeforeFnCall(other))
+            | (EvalEvent::AfterFnCall(name), EvalEvent::AfterFnCall(other))
+            | (EvalEvent::BeforeVarAccess(name), EvalEvent::BeforeVarAccess(other)) => {
+                matches_name(name, other)
+            }
+            (EvalEvent::ValueComputed(_), EvalEvent::ValueComputed(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Read-only view of the evaluation, given to callbacks.
+pub struct EvalContext<'a> {
+    pub(crate) event: &'a EvalEvent,
+    pub(crate) precision: u32,
+    pub(crate) operation_count: usize,
+}
+
+impl<'a> EvalContext<'a> {
+    /// The event that caused the callback to be called.
+    pub fn event(&self) -> &EvalEvent {
+        self.event
+    }
+
+    pub fn precision(&self) -> u32 {
+        self.precision
+    }
+
+    /// The number of operations that have been evaluated so far.
+    pub fn operation_count(&self) -> usize {
+        self.operation_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(EvalEvent::BeforeFnCall(String::new()), EvalEvent::BeforeFnCall("sin".into()), true ; "any name")]
+    #[test_case(EvalEvent::BeforeFnCall("sin".into()), EvalEvent::BeforeFnCall("sin".into()), true ; "same name")]
+    #[test_case(EvalEvent::BeforeFnCall("cos".into()), EvalEvent::BeforeFnCall("sin".into()), false ; "other name")]
+    #[test_case(EvalEvent::AfterFnCall(String::new()), EvalEvent::BeforeFnCall("sin".into()), false ; "other event")]
+    #[test_case(EvalEvent::ValueComputed(0f64), EvalEvent::ValueComputed(2f64), true ; "any value")]
+    fn test_matches(registered: EvalEvent, event: EvalEvent, expected: bool) {
+        assert_eq!(registered.matches(&event), expected);
+    }
+}
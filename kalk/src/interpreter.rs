@@ -1,16 +1,119 @@
 use crate::ast::{Expr, Stmt};
+#[cfg(feature = "cache")]
+use crate::cache::{Cache, Evaluation, Settings};
+use crate::calculus;
+use crate::complex;
+use crate::exact;
+use crate::hooks::{Callbacks, EvalContext, EvalEvent, SharedCallback};
 use crate::lexer::TokenKind;
-use crate::parser::CalcError;
-use crate::parser::Unit;
+use crate::linalg;
+use crate::output::FormatOptions;
+use crate::parser::{CalcError, KalkValue, Unit};
+use crate::parser::{DEFAULT_INTEGRATION_STEPS, DEFAULT_MAX_PRECISION, DEFAULT_RECURSION_LIMIT};
 use crate::prelude;
+use crate::solver;
 use crate::symbol_table::SymbolTable;
-use rug::ops::Pow;
-use rug::Float;
+use rug::float::Round;
+use rug::ops::{AssignRound, Pow};
+use rug::{Complex, Float, Integer};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Results of `<<` with more bits than this are an error, since eg. `1 << 4000000000` would need gigabytes of memory.
+const MAX_SHIFT_BITS: u64 = DEFAULT_MAX_PRECISION as u64;
+
+/// Sums and products with more terms than this are an error, since eg. `sum(1, 10^30, n)` would never finish.
+const MAX_SERIES_TERMS: i128 = 10_000_000;
+
+/// Matrices built by eg. `zeros(m, n)` with more elements than this are an error, since they would use too much memory.
+const MAX_MATRIX_ELEMENTS: usize = 1 << 20;
+
+/// The values that free variables are given when comparing expressions with `equiv`.
+const SAMPLE_POINTS: [f64; 7] = [-2.7, -1.3, -0.4, 0.3, 0.9, 1.6, 3.1];
+
+/// The value of an expression that may be a complex number, vector or matrix, see `Context::interpret_value`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Scalar(Float),
+    /// A number with an imaginary part that isn't zero, eg. `sqrt(-1)`.
+    Complex(Complex),
+    Vector(Vec<Float>),
+    /// A list of rows that all have the same length, eg. `[[1, 2], [3, 4]]`.
+    Matrix(Vec<Vec<Float>>),
+}
+
+impl Value {
+    /// Format the value as text, eg. `1.5`, `1 - 2.5i`, `[1, 2]` or `[[1, 2], [3, 4]]`.
+    pub fn format(&self, options: &FormatOptions) -> String {
+        let format_vector = |values: &[Float]| {
+            let elements: Vec<String> = values.iter().map(|x| options.format_number(x)).collect();
+
+            format!("[{}]", elements.join(", "))
+        };
+
+        match self {
+            Value::Scalar(value) => options.format_number(value),
+            Value::Complex(value) => KalkValue::from(value.clone()).format(options),
+            Value::Vector(values) => format_vector(values),
+            Value::Matrix(rows) => {
+                let rows: Vec<String> = rows.iter().map(|row| format_vector(row)).collect();
+
+                format!("[{}]", rows.join(", "))
+            }
+        }
+    }
+
+    /// Whether every element of the value is a finite number.
+    pub(crate) fn is_finite(&self) -> bool {
+        match self {
+            Value::Scalar(value) => value.is_finite(),
+            Value::Complex(value) => value.real().is_finite() && value.imag().is_finite(),
+            Value::Vector(values) => values.iter().all(|x| x.is_finite()),
+            Value::Matrix(rows) => rows.iter().flatten().all(|x| x.is_finite()),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        // An f64 has 53 bits of precision, so it is represented exactly.
+        Value::Scalar(Float::with_val(53, value))
+    }
+}
+
+impl From<Float> for Value {
+    fn from(value: Float) -> Self {
+        Value::Scalar(value)
+    }
+}
 
 pub struct Context<'a> {
     symbol_table: &'a mut SymbolTable,
     angle_unit: Unit,
     precision: u32,
+    decimal_mode: bool,
+    integer_mode: bool,
+    rounding: Round,
+    integration_steps: u32,
+    recursion_limit: u32,
+    /// The number of calls to user-defined functions that are currently being evaluated.
+    recursion_depth: u32,
+    operation_count: usize,
+    /// The largest number of bits lost to cancellation in a single addition or subtraction.
+    lost_bits: usize,
+    /// Whether the expression being evaluated is part of one that was already tried to be calculated exactly
+    /// in decimal mode, since trying again for every part of it would take quadratic time.
+    exact_attempted: bool,
+    callbacks: &'a [(EvalEvent, SharedCallback)],
+    #[cfg(feature = "cache")]
+    cache: Option<&'a mut Cache>,
+    /// Whether the outermost expression that is being evaluated is volatile, see `is_volatile`.
+    #[cfg(feature = "cache")]
+    volatile: Option<bool>,
+    /// The events emitted while evaluating the outermost expression, so that they can be emitted again
+    /// when a cached result is re-used.
+    #[cfg(feature = "cache")]
+    events: Vec<EvalEvent>,
 }
 
 impl<'a> Context<'a> {
@@ -19,225 +122,2238 @@ impl<'a> Context<'a> {
             angle_unit: angle_unit.clone(),
             symbol_table,
             precision,
+            decimal_mode: false,
+            integer_mode: false,
+            rounding: Round::Nearest,
+            integration_steps: DEFAULT_INTEGRATION_STEPS,
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
+            recursion_depth: 0,
+            operation_count: 0,
+            lost_bits: 0,
+            exact_attempted: false,
+            callbacks: &[],
+            #[cfg(feature = "cache")]
+            cache: None,
+            #[cfg(feature = "cache")]
+            volatile: None,
+            #[cfg(feature = "cache")]
+            events: Vec::new(),
         }
     }
 
+    /// Calculate arithmetic on decimal numbers exactly, and only round the final result.
+    pub fn set_decimal_mode(&mut self, enabled: bool) {
+        self.decimal_mode = enabled;
+    }
+
+    /// Only allow integers, and calculate arithmetic exactly with at most `precision` bits.
+    pub fn set_integer_mode(&mut self, enabled: bool) {
+        self.integer_mode = enabled;
+    }
+
+    /// The direction to round the results of arithmetic and literals in.
+    pub fn set_rounding(&mut self, rounding: Round) {
+        self.rounding = rounding;
+    }
+
+    /// The number of intervals to split integrals into. It has to be even.
+    pub fn set_integration_steps(&mut self, steps: u32) {
+        self.integration_steps = steps;
+    }
+
+    /// The number of calls to user-defined functions that may be nested, eg. when a function calls itself.
+    pub fn set_recursion_limit(&mut self, limit: u32) {
+        self.recursion_limit = limit;
+    }
+
+    /// Functions to call when the events they were registered for happen.
+    pub fn set_callbacks(&mut self, callbacks: &'a Callbacks) {
+        self.callbacks = callbacks;
+    }
+
+    /// The number of operations (binary, unary and function calls) that have been evaluated so far.
+    pub fn operation_count(&self) -> usize {
+        self.operation_count
+    }
+
+    /// An estimate of how many significant bits were lost to cancellation so far, eg. in `1e100 - 1e100 + 1`.
+    /// The estimate is taken from the operation that lost the most, and is at most the precision.
+    pub fn lost_bits(&self) -> usize {
+        self.lost_bits
+    }
+
+    /// Re-use results of previously evaluated expressions, as long as the symbol table has not changed since.
+    #[cfg(feature = "cache")]
+    pub fn set_cache(&mut self, cache: &'a mut Cache) {
+        self.cache = Some(cache);
+    }
+
+    /// Evaluate the statements in order. The answer is the value of the last one, if it is an expression.
+    /// Evaluation stops at the first statement that fails, eg. `foo(1); 2` results in `CalcError::UndefinedFn`.
     pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<Option<Float>, CalcError> {
         for (i, stmt) in statements.iter().enumerate() {
-            let value = eval_stmt(self, stmt);
+            let value = eval_stmt(self, stmt)?;
 
             if i == statements.len() - 1 {
                 if let Stmt::Expr(_) = stmt {
-                    return Ok(Some(value?));
+                    let value = without_negative_zero(value);
+                    self.symbol_table.set_last_answer(value.clone());
+
+                    return Ok(Some(value));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Like `interpret`, but the answer may also be a complex number, vector or matrix, eg. `sqrt(-1)` or `[1, 2, 3] * 2`.
+    /// Only real numbers are remembered as the last answer.
+    pub fn interpret_value(&mut self, statements: Vec<Stmt>) -> Result<Option<Value>, CalcError> {
+        let expr = match statements.last() {
+            Some(Stmt::Expr(expr)) => expr.clone(),
+            _ => return Ok(self.interpret(statements)?.map(Value::Scalar)),
+        };
+
+        // Like in `interpret`, evaluation stops at the first statement that fails.
+        for stmt in &statements[..statements.len() - 1] {
+            match stmt {
+                Stmt::Expr(expr) => eval_value(self, expr).map(|_| ())?,
+                _ => eval_stmt(self, stmt).map(|_| ())?,
+            }
+        }
+
+        Ok(Some(match eval_value(self, &expr)? {
+            Value::Scalar(value) => {
+                let value = without_negative_zero(value);
+                self.symbol_table.set_last_answer(value.clone());
+
+                Value::Scalar(value)
+            }
+            Value::Complex(value) => Value::Complex(value),
+            Value::Vector(values) => {
+                Value::Vector(values.into_iter().map(without_negative_zero).collect())
+            }
+            Value::Matrix(rows) => Value::Matrix(
+                rows.into_iter()
+                    .map(|row| row.into_iter().map(without_negative_zero).collect())
+                    .collect(),
+            ),
+        }))
+    }
+
+    /// Interpret the statements and find out which unit the answer is in, if any.
+    pub fn interpret_with_unit(
+        &mut self,
+        statements: Vec<Stmt>,
+    ) -> Result<Option<(Float, Option<String>)>, CalcError> {
+        let last_stmt = statements.last().cloned();
+        let value = self.interpret(statements)?;
+
+        Ok(match (value, last_stmt) {
+            (Some(value), Some(Stmt::Expr(expr))) => {
+                Some((value, eval_unit(self, &expr, &HashMap::new(), &[])))
+            }
+            _ => None,
+        })
+    }
+}
+
+/// Negative zero is equal to zero, but confusing to get as an answer.
+fn without_negative_zero(value: Float) -> Float {
+    if value.is_zero() {
+        value.abs()
+    } else {
+        value
+    }
+}
+
+fn eval_stmt(context: &mut Context, stmt: &Stmt) -> Result<Float, CalcError> {
+    match stmt {
+        Stmt::VarDecl(_, _) => eval_var_decl_stmt(context, stmt),
+        Stmt::FnDecl(_, _, _) | Stmt::UnitDecl(_, _) => eval_fn_decl_stmt(context),
+        Stmt::Expr(expr) => eval_expr_stmt(context, expr),
+    }
+}
+
+fn eval_var_decl_stmt(context: &mut Context, stmt: &Stmt) -> Result<Float, CalcError> {
+    context.symbol_table.insert(stmt.clone());
+    Ok(Float::with_val(context.precision, 1))
+}
+
+fn eval_fn_decl_stmt(context: &mut Context) -> Result<Float, CalcError> {
+    Ok(Float::with_val(context.precision, 1)) // Nothing needs to happen here, since the parser will already have added the FnDecl's and UnitDecl's to the symbol table.
+}
+
+fn eval_expr_stmt(context: &mut Context, expr: &Expr) -> Result<Float, CalcError> {
+    eval_expr(context, expr)
+}
+
+#[cfg(feature = "cache")]
+fn eval_expr(context: &mut Context, expr: &Expr) -> Result<Float, CalcError> {
+    // Literals and variables are cheap to evaluate, so they are not worth the hashing.
+    if context.cache.is_none() || matches!(expr, Expr::Literal(_) | Expr::Var(_)) {
+        return eval_expr_uncached(context, expr);
+    }
+
+    // Volatile expressions, eg. ones using the last answer, aren't cached. Whether the outermost expression is volatile
+    // is used for everything inside it as well, rather than looking through every subexpression again.
+    match context.volatile {
+        Some(true) => return eval_expr_uncached(context, expr),
+        Some(false) => (),
+        None => {
+            context.volatile = Some(is_volatile(context.symbol_table, expr));
+            let value = eval_expr(context, expr);
+            context.volatile = None;
+            context.events.clear();
+
+            return value;
+        }
+    }
+
+    let generation = context.symbol_table.generation();
+    let settings = Settings {
+        precision: context.precision,
+        angle_unit: context.angle_unit.clone(),
+        rounding: context.rounding,
+        decimal_mode: context.decimal_mode,
+        integer_mode: context.integer_mode,
+        integration_steps: context.integration_steps,
+    };
+    let cached = context
+        .cache
+        .as_mut()
+        .and_then(|cache| cache.get(expr, &settings, generation))
+        // The events are needed if callbacks have been registered since.
+        .filter(|evaluation| context.callbacks.is_empty() || evaluation.events.is_some());
+    if let Some(evaluation) = cached {
+        // Re-using the result should look the same as evaluating it again, eg. to callbacks.
+        context.operation_count += evaluation.operations;
+        for event in evaluation.events.unwrap_or_default() {
+            emit(context, || event)?;
+        }
+
+        return Ok(evaluation.value);
+    }
+
+    let operation_count = context.operation_count;
+    let event_count = context.events.len();
+    let value = eval_expr_uncached(context, expr)?;
+
+    // Only cache the result if evaluating it didn't mutate the symbol table (eg. function calls),
+    // since the result may then depend on the state the symbol table was in beforehand.
+    if context.symbol_table.generation() == generation {
+        let evaluation = Evaluation {
+            value: value.clone(),
+            operations: context.operation_count - operation_count,
+            events: if context.callbacks.is_empty() {
+                None
+            } else {
+                Some(context.events[event_count..].to_vec())
+            },
+        };
+        if let Some(cache) = &mut context.cache {
+            cache.insert(expr, &settings, generation, evaluation);
+        }
+    }
+
+    Ok(value)
+}
+
+#[cfg(not(feature = "cache"))]
+fn eval_expr(context: &mut Context, expr: &Expr) -> Result<Float, CalcError> {
+    eval_expr_uncached(context, expr)
+}
+
+/// Whether the value of the expression may change without the symbol table generation changing,
+/// ie. it uses the last answer or calls a native function, which may eg. read from a database.
+/// The definitions of the variables, functions and units it uses are looked at as well,
+/// since eg. `y` in `y = _ + 1` changes with the last answer.
+#[cfg(feature = "cache")]
+fn is_volatile(symbol_table: &SymbolTable, expr: &Expr) -> bool {
+    is_volatile_with(symbol_table, expr, &mut Vec::new())
+}
+
+/// `visited` contains the definitions that have already been looked at, since they may refer to each other,
+/// eg. in recursive functions.
+#[cfg(feature = "cache")]
+fn is_volatile_with<'a>(
+    symbol_table: &'a SymbolTable,
+    expr: &'a Expr,
+    visited: &mut Vec<&'a Stmt>,
+) -> bool {
+    match expr {
+        Expr::Binary(left, _, right) | Expr::Equation(left, right) => {
+            is_volatile_with(symbol_table, left, visited)
+                || is_volatile_with(symbol_table, right, visited)
+        }
+        Expr::Unit(expr, identifier) | Expr::Conversion(expr, identifier) => {
+            definition_is_volatile(symbol_table, symbol_table.get_unit(identifier), visited)
+                || is_volatile_with(symbol_table, expr, visited)
+        }
+        Expr::Unary(_, expr) | Expr::Group(expr) | Expr::Annotated(expr, _) => {
+            is_volatile_with(symbol_table, expr, visited)
+        }
+        Expr::FnCall(identifier, expressions) => {
+            symbol_table.get_native_fn(identifier).is_some()
+                || definition_is_volatile(symbol_table, symbol_table.get_fn(identifier), visited)
+                || expressions
+                    .iter()
+                    .any(|expr| is_volatile_with(symbol_table, expr, visited))
+        }
+        Expr::Vector(expressions) => expressions
+            .iter()
+            .any(|expr| is_volatile_with(symbol_table, expr, visited)),
+        Expr::Conditional(condition, then, otherwise) => {
+            is_volatile_with(symbol_table, condition, visited)
+                || is_volatile_with(symbol_table, then, visited)
+                || is_volatile_with(symbol_table, otherwise, visited)
+        }
+        Expr::Var(identifier) => {
+            symbol_table.is_last_answer(identifier)
+                || definition_is_volatile(symbol_table, symbol_table.get_var(identifier), visited)
+        }
+        Expr::Literal(_) => false,
+    }
+}
+
+#[cfg(feature = "cache")]
+fn definition_is_volatile<'a>(
+    symbol_table: &'a SymbolTable,
+    definition: Option<&'a Stmt>,
+    visited: &mut Vec<&'a Stmt>,
+) -> bool {
+    let stmt = match definition {
+        Some(stmt) if !visited.iter().any(|seen| std::ptr::eq(*seen, stmt)) => stmt,
+        _ => return false,
+    };
+    visited.push(stmt);
+
+    match stmt {
+        Stmt::VarDecl(_, expr) | Stmt::FnDecl(_, _, expr) | Stmt::UnitDecl(_, Some(expr)) => {
+            is_volatile_with(symbol_table, expr, visited)
+        }
+        _ => false,
+    }
+}
+
+fn eval_expr_uncached(context: &mut Context, expr: &Expr) -> Result<Float, CalcError> {
+    if let Expr::Binary(_, _, _) | Expr::Unary(_, _) | Expr::FnCall(_, _) = expr {
+        context.operation_count += 1;
+    }
+
+    // Literals are already rounded correctly, so only operations need to be calculated exactly.
+    let exact_attempted = context.exact_attempted;
+    let attempt_exact = context.decimal_mode
+        && !context.integer_mode
+        && !exact_attempted
+        && matches!(expr, Expr::Binary(_, _, _) | Expr::Unary(_, _));
+    if attempt_exact {
+        if let Some(value) = exact::eval(context.symbol_table, expr) {
+            let value = round(context, value);
+            emit_exact_var_accesses(context, expr)?;
+            emit(context, || EvalEvent::ValueComputed(value.to_f64()))?;
+
+            return Ok(value);
+        }
+    }
+
+    // The parts that `exact::eval` looks at were tried along with this expression,
+    // but eg. the arguments of a function call were not.
+    context.exact_attempted = (exact_attempted || attempt_exact)
+        && matches!(
+            expr,
+            Expr::Binary(_, _, _)
+                | Expr::Unary(TokenKind::Minus, _)
+                | Expr::Unary(TokenKind::Not, _)
+                | Expr::Group(_)
+                | Expr::Annotated(_, _)
+                | Expr::Var(_)
+        );
+    let value = match expr {
+        Expr::Binary(left, op, right) => eval_binary_expr(context, left, op, right),
+        Expr::Unary(op, expr) => eval_unary_expr(context, op, expr),
+        Expr::Unit(expr, unit) => eval_unit_expr(context, expr, unit),
+        Expr::Conversion(expr, unit) => eval_conversion_expr(context, expr, unit),
+        Expr::Var(identifier) => eval_var_expr(context, identifier),
+        Expr::Literal(value) => eval_literal_expr(context, value),
+        Expr::Group(expr) => eval_group_expr(context, expr),
+        Expr::Equation(left, right) => eval_equation(context, left, right),
+        Expr::Annotated(expr, _) => eval_expr(context, expr),
+        Expr::Vector(_) => Err(CalcError::UnexpectedVector),
+        Expr::Conditional(condition, then, otherwise) => {
+            let branch = choose_branch(context, condition, then, otherwise)?;
+            eval_expr(context, branch)
+        }
+        Expr::FnCall(identifier, expressions) => {
+            emit(context, || EvalEvent::BeforeFnCall(identifier.clone()))?;
+            let value = eval_fn_call_expr(context, identifier, expressions);
+            emit(context, || EvalEvent::AfterFnCall(identifier.clone()))?;
+
+            value
+        }
+    };
+    context.exact_attempted = exact_attempted;
+    let value = value?;
+    emit(context, || EvalEvent::ValueComputed(value.to_f64()))?;
+
+    // Everything is checked, since eg. literals and function calls can also result in non-integers.
+    if context.integer_mode {
+        to_integer(&value)?;
+    }
+
+    Ok(value)
+}
+
+/// Emit the variable accesses of an expression that was calculated exactly, like they would have been
+/// if it had been evaluated normally. Only the parts that `exact::eval` looks at are visited.
+fn emit_exact_var_accesses(context: &mut Context, expr: &Expr) -> Result<(), CalcError> {
+    if context.callbacks.is_empty() {
+        return Ok(());
+    }
+
+    match expr {
+        Expr::Binary(left, _, right) => {
+            emit_exact_var_accesses(context, left)?;
+            emit_exact_var_accesses(context, right)
+        }
+        Expr::Unary(_, expr) | Expr::Group(expr) | Expr::Annotated(expr, _) => {
+            emit_exact_var_accesses(context, expr)
+        }
+        Expr::Var(identifier) => {
+            emit(context, || EvalEvent::BeforeVarAccess(identifier.clone()))?;
+            if let Some(Stmt::VarDecl(_, definition)) = context.symbol_table.get_var(identifier) {
+                let definition = definition.clone();
+                emit_exact_var_accesses(context, &definition)?;
+            }
+
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Evaluate the condition, and return the branch that should be evaluated.
+/// Any number other than zero counts as true, like the result of a comparison.
+fn choose_branch<'e>(
+    context: &mut Context,
+    condition: &Expr,
+    then: &'e Expr,
+    otherwise: &'e Expr,
+) -> Result<&'e Expr, CalcError> {
+    let condition = eval_expr(context, condition)?;
+
+    Ok(if condition.is_zero() { otherwise } else { then })
+}
+
+fn eval_binary_expr(
+    context: &mut Context,
+    left: &Expr,
+    op: &TokenKind,
+    right: &Expr,
+) -> Result<Float, CalcError> {
+    check_units(context, left, op, right)?;
+    let left = eval_expr(context, left)?;
+    let right = eval_expr(context, right)?;
+
+    calculate_binary(context, left, op, right)
+}
+
+/// Values in different units, eg. `3 m + 4 s`, can't be added, subtracted or compared.
+/// A value without a unit can be used together with any unit, eg. `3 m + 1`.
+fn check_units(
+    context: &Context,
+    left: &Expr,
+    op: &TokenKind,
+    right: &Expr,
+) -> Result<(), CalcError> {
+    // The built-in units are angles, which are all converted to the same unit,
+    // so the units can only differ if some have been declared.
+    if context.symbol_table.units().next().is_none() {
+        return Ok(());
+    }
+
+    if !matches!(
+        op,
+        TokenKind::Plus
+            | TokenKind::Minus
+            | TokenKind::Distance
+            | TokenKind::EqualsEquals
+            | TokenKind::NotEquals
+            | TokenKind::GreaterThan
+            | TokenKind::LessThan
+            | TokenKind::GreaterOrEquals
+            | TokenKind::LessOrEquals
+    ) {
+        return Ok(());
+    }
+
+    let left = eval_unit(context, left, &HashMap::new(), &[]);
+    let right = eval_unit(context, right, &HashMap::new(), &[]);
+    match (left, right) {
+        (Some(left), Some(right)) if left != right => {
+            Err(CalcError::IncompatibleUnits(left, right))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn calculate_binary(
+    context: &mut Context,
+    left: Float,
+    op: &TokenKind,
+    right: Float,
+) -> Result<Float, CalcError> {
+    if context.integer_mode {
+        let result = exact::eval_integer_binary(
+            &to_integer(&left)?,
+            op,
+            &to_integer(&right)?,
+            context.precision,
+        )?;
+        if let Some(result) = result {
+            return Ok(Float::with_val(context.precision, result));
+        }
+    }
+
+    if let TokenKind::Ampersand
+    | TokenKind::Pipe
+    | TokenKind::Xor
+    | TokenKind::ShiftLeft
+    | TokenKind::ShiftRight = op
+    {
+        return calculate_bitwise(context.precision, &left, op, &right);
+    }
+
+    if let TokenKind::Plus | TokenKind::Minus | TokenKind::Distance = op {
+        let result = match op {
+            TokenKind::Plus => round(context, &left + &right),
+            TokenKind::Minus => round(context, &left - &right),
+            _ => round(context, &left - &right).abs(),
+        };
+        context.lost_bits = context
+            .lost_bits
+            .max(cancelled_bits(&left, &right, &result));
+
+        return Ok(result);
+    }
+
+    Ok(match op {
+        TokenKind::Star => round(context, &left * &right),
+        TokenKind::Slash => round(context, &left / &right),
+        TokenKind::DoubleSlash => prelude::special_funcs::floor_division(left, right),
+        TokenKind::Percent => prelude::special_funcs::modulo(left, right),
+        TokenKind::Power => round(context, (&left).pow(&right)),
+        TokenKind::EqualsEquals => from_bool(context, left == right),
+        TokenKind::NotEquals => from_bool(context, left != right),
+        TokenKind::GreaterThan => from_bool(context, left > right),
+        TokenKind::LessThan => from_bool(context, left < right),
+        TokenKind::GreaterOrEquals => from_bool(context, left >= right),
+        TokenKind::LessOrEquals => from_bool(context, left <= right),
+        TokenKind::And => from_bool(context, !left.is_zero() && !right.is_zero()),
+        TokenKind::Or => from_bool(context, !left.is_zero() || !right.is_zero()),
+        _ => Float::with_val(1, 1),
+    })
+}
+
+/// Calculate a bitwise operator, which is only defined for integers.
+/// Negative numbers act as if they were in two's complement, so eg. `-16 >> 2` is -4.
+fn calculate_bitwise(
+    precision: u32,
+    left: &Float,
+    op: &TokenKind,
+    right: &Float,
+) -> Result<Float, CalcError> {
+    let (left, right) = (to_bitwise_integer(left)?, to_bitwise_integer(right)?);
+    let result = match op {
+        TokenKind::Ampersand => left & right,
+        TokenKind::Pipe => left | right,
+        TokenKind::Xor => left ^ right,
+        _ => {
+            let amount = right.to_u32().ok_or_else(|| {
+                CalcError::Message(String::from(
+                    "Can only shift by a non-negative amount that fits in 32 bits.",
+                ))
+            })?;
+
+            if let TokenKind::ShiftLeft = op {
+                if u64::from(left.significant_bits()) + u64::from(amount) > MAX_SHIFT_BITS {
+                    return Err(CalcError::Message(format!(
+                        "The result of shifting left by {} would have more than {} bits.",
+                        amount, MAX_SHIFT_BITS
+                    )));
                 }
+
+                left << amount
+            } else {
+                left >> amount
             }
         }
+    };
+
+    Ok(Float::with_val(precision, result))
+}
+
+/// The bitwise not of an integer, `~x`, which is `-x - 1`.
+fn bitwise_not(precision: u32, x: &Float) -> Result<Float, CalcError> {
+    Ok(Float::with_val(precision, !to_bitwise_integer(x)?))
+}
+
+fn to_bitwise_integer(value: &Float) -> Result<Integer, CalcError> {
+    if !value.is_integer() {
+        return Err(CalcError::Message(String::from(
+            "Bitwise operations can only be done on integers.",
+        )));
+    }
+
+    Ok(value.to_integer().unwrap())
+}
+
+/// Estimate how many bits were lost to cancellation in an addition or subtraction,
+/// from how much smaller the result is than the largest operand.
+fn cancelled_bits(left: &Float, right: &Float, result: &Float) -> usize {
+    let largest = match left.get_exp().into_iter().chain(right.get_exp()).max() {
+        Some(exp) => exp,
+        // Both operands are zero or not finite.
+        None => return 0,
+    };
+
+    let lost = match result.get_exp() {
+        Some(exp) => (largest - exp).max(0) as usize,
+        // Every bit cancelled out, eg. `1e100 - 1e100`.
+        None if result.is_zero() => result.prec() as usize,
+        None => 0,
+    };
+
+    lost.min(result.prec() as usize)
+}
+
+/// Comparisons and boolean operators result in 1 if they are true and 0 otherwise.
+fn from_bool(context: &Context, value: bool) -> Float {
+    Float::with_val(context.precision, if value { 1 } else { 0 })
+}
+
+/// Calculate the value at the precision of the context, rounded in the direction of the context.
+fn round<T>(context: &Context, value: T) -> Float
+where
+    Float: AssignRound<T, Round = Round, Ordering = Ordering>,
+{
+    Float::with_val_round(context.precision, value, context.rounding).0
+}
+
+/// Call the callbacks that were registered for the event.
+/// The first error returned by one of them stops the evaluation.
+fn emit<F: FnOnce() -> EvalEvent>(context: &mut Context, event: F) -> Result<(), CalcError> {
+    // Don't create the event if nothing is listening, since it may allocate.
+    if context.callbacks.is_empty() {
+        return Ok(());
+    }
+
+    let event = event();
+    #[cfg(feature = "cache")]
+    {
+        if context.cache.is_some() {
+            context.events.push(event.clone());
+        }
+    }
+
+    let view = EvalContext {
+        event: &event,
+        precision: context.precision,
+        operation_count: context.operation_count,
+    };
+    for (registered, callback) in context.callbacks {
+        if registered.matches(&event) {
+            callback(&view)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn to_integer(value: &Float) -> Result<Integer, CalcError> {
+    if !value.is_integer() {
+        return Err(CalcError::NotAnInteger(value.to_string()));
+    }
+
+    Ok(value.to_integer().unwrap())
+}
+
+fn eval_unary_expr(context: &mut Context, op: &TokenKind, expr: &Expr) -> Result<Float, CalcError> {
+    let expr_value = eval_expr(context, expr)?;
+
+    calculate_unary(context, op, expr_value)
+}
+
+fn calculate_unary(
+    context: &Context,
+    op: &TokenKind,
+    expr_value: Float,
+) -> Result<Float, CalcError> {
+    match op {
+        TokenKind::Minus => Ok(-expr_value),
+        TokenKind::Not => Ok(from_bool(context, expr_value.is_zero())),
+        TokenKind::Tilde => bitwise_not(context.precision, &expr_value),
+        TokenKind::Exclamation => Ok(Float::with_val(
+            context.precision,
+            prelude::special_funcs::factorial(expr_value),
+        )),
+        _ => Err(CalcError::InvalidOperator),
+    }
+}
+
+fn eval_unit_expr(context: &mut Context, expr: &Expr, unit: &str) -> Result<Float, CalcError> {
+    let x = eval_expr(context, expr)?;
+
+    Ok(x * working_unit_factor(context, unit)?)
+}
+
+fn eval_conversion_expr(
+    context: &mut Context,
+    expr: &Expr,
+    unit: &str,
+) -> Result<Float, CalcError> {
+    let x = eval_expr(context, expr)?;
+
+    Ok(x * conversion_factor(context, expr, unit)?)
+}
+
+/// The number to multiply a value in the unit by to get it in the unit that is calculated with, see `working_unit`.
+fn working_unit_factor(context: &mut Context, unit: &str) -> Result<Float, CalcError> {
+    check_allowed(context, unit, CalcError::UndefinedUnit)?;
+    let (factor, base) = unit_factor(context, unit, &mut Vec::new())?;
+    let (working_factor, _) = unit_factor(context, &working_unit(context, &base), &mut Vec::new())?;
+
+    Ok(factor / working_factor)
+}
+
+/// The number to multiply the value of the expression by to get it in the unit.
+/// A value without a unit is assumed to be in the unit that is calculated with, eg. `3000 to km` is 3.
+fn conversion_factor(context: &mut Context, expr: &Expr, unit: &str) -> Result<Float, CalcError> {
+    check_allowed(context, unit, CalcError::UndefinedUnit)?;
+    let (factor, base) = unit_factor(context, unit, &mut Vec::new())?;
+    let from = eval_unit(context, expr, &HashMap::new(), &[])
+        .unwrap_or_else(|| working_unit(context, &base));
+    let (from_factor, from_base) = unit_factor(context, &from, &mut Vec::new())?;
+    if from_base != base {
+        return Err(CalcError::IncompatibleUnits(from, unit.into()));
+    }
+
+    Ok(from_factor / factor)
+}
+
+/// Names that aren't allowed, see `SymbolTable::set_allowed_names`, are treated as if they weren't defined.
+/// User-defined ones are hidden by the symbol table, but the built-in ones have to be checked here.
+fn check_allowed<F>(context: &Context, name: &str, undefined: F) -> Result<(), CalcError>
+where
+    F: FnOnce(String) -> CalcError,
+{
+    if context.symbol_table.is_allowed(name) {
+        Ok(())
+    } else {
+        Err(undefined(name.into()))
+    }
+}
+
+/// The size of the unit in its base unit, and the name of the base unit.
+/// `visited` contains the units whose definitions are being looked at, since they may refer to each other.
+fn unit_factor(
+    context: &mut Context,
+    unit: &str,
+    visited: &mut Vec<String>,
+) -> Result<(Float, String), CalcError> {
+    if let Some(info) = prelude::UNITS.get(unit) {
+        return Ok((info.factor(context.precision), info.base().into()));
+    }
+
+    match context.symbol_table.get_unit(unit).cloned() {
+        Some(Stmt::UnitDecl(_, None)) => Ok((Float::with_val(context.precision, 1), unit.into())),
+        Some(Stmt::UnitDecl(_, Some(definition))) => {
+            if visited.iter().any(|x| x == unit) {
+                return Err(CalcError::CircularDependency(unit.into()));
+            }
+
+            visited.push(unit.into());
+            match *definition {
+                Expr::Unit(amount, other) => {
+                    check_allowed(context, &other, CalcError::UndefinedUnit)?;
+                    let amount = eval_expr(context, &amount)?;
+                    let (factor, base) = unit_factor(context, &other, visited)?;
+
+                    Ok((amount * factor, base))
+                }
+                _ => Err(CalcError::InvalidUnit),
+            }
+        }
+        _ => Err(CalcError::UndefinedUnit(unit.into())),
+    }
+}
+
+/// The name of the base unit of the unit, or `None` if the unit isn't defined.
+fn base_unit(symbol_table: &SymbolTable, unit: &str, visited: &mut Vec<String>) -> Option<String> {
+    if let Some(info) = prelude::UNITS.get(unit) {
+        return Some(info.base().into());
+    }
+
+    match symbol_table.get_unit(unit) {
+        Some(Stmt::UnitDecl(_, None)) => Some(unit.into()),
+        Some(Stmt::UnitDecl(_, Some(definition))) if !visited.iter().any(|x| x == unit) => {
+            visited.push(unit.into());
+            match &**definition {
+                Expr::Unit(_, other) => base_unit(symbol_table, other, visited),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The unit that values are converted to before they are used, ie. the base unit,
+/// or the angle unit of the context for angles.
+fn working_unit(context: &Context, base: &str) -> String {
+    if base == Unit::Radians.to_string() {
+        context.angle_unit.to_string()
+    } else {
+        base.into()
+    }
+}
+
+fn eval_var_expr(context: &mut Context, identifier: &str) -> Result<Float, CalcError> {
+    emit(context, || EvalEvent::BeforeVarAccess(identifier.into()))?;
+    check_allowed(context, identifier, CalcError::UndefinedVar)?;
+
+    // If there is a constant with this name, return a literal expression with its value
+    if let Some(value) = prelude::CONSTANTS.get(identifier) {
+        return eval_expr(context, &Expr::Literal((*value).to_string()));
+    }
+
+    if context.symbol_table.is_last_answer(identifier) {
+        return match context.symbol_table.last_answer() {
+            Some(value) => Ok(Float::with_val(context.precision, value)),
+            None => Err(CalcError::UndefinedVar(identifier.into())),
+        };
+    }
+
+    // Look for the variable in the symbol table
+    let var_decl = context.symbol_table.get_var(identifier).cloned();
+    match var_decl {
+        Some(Stmt::VarDecl(_, expr)) => eval_expr(context, &expr),
+        _ => Err(CalcError::UndefinedVar(identifier.into())),
+    }
+}
+
+fn eval_literal_expr(context: &mut Context, value: &str) -> Result<Float, CalcError> {
+    match Float::parse(value) {
+        Ok(parsed_value) => Ok(round(context, parsed_value)),
+        Err(_) => Err(CalcError::InvalidNumberLiteral(value.into())),
+    }
+}
+
+fn eval_group_expr(context: &mut Context, expr: &Expr) -> Result<Float, CalcError> {
+    eval_expr(context, expr)
+}
+
+fn eval_fn_call_expr(
+    context: &mut Context,
+    identifier: &str,
+    expressions: &[Expr],
+) -> Result<Float, CalcError> {
+    check_allowed(context, identifier, CalcError::UndefinedFn)?;
+
+    // Functions of a whole vector, eg. len([1, 2, 3]), or matrix, eg. det([[1, 2], [3, 4]]).
+    if let [argument] = expressions {
+        if is_vector_fn(identifier) {
+            return eval_vector_fn(context, identifier, argument);
+        }
+
+        // These may result in a vector or matrix, eg. factor(12), which is [2, 2, 3], and then aren't numbers.
+        if is_matrix_fn(identifier) || prelude::VECTOR_VALUED_FUNCS.contains_key(identifier) {
+            return eval_scalar_fn_call(context, identifier, expressions);
+        }
+    }
+
+    if expressions.len() == 2 && prelude::BINARY_VECTOR_VALUED_FUNCS.contains_key(identifier) {
+        return eval_scalar_fn_call(context, identifier, expressions);
+    }
+
+    // Eg. identity(3), which is never a number.
+    if is_matrix_constructor(identifier) {
+        return eval_scalar_fn_call(context, identifier, expressions);
+    }
+
+    // Statistics, eg. median(1, 5, 2) or median([1, 5, 2]).
+    if prelude::STAT_FUNCS.contains_key(identifier) {
+        return eval_stat_fn(context, identifier, expressions);
+    }
+
+    // Eg. weighted_mean([1, 2], [3, 1]).
+    if prelude::PAIRED_STAT_FUNCS.contains_key(identifier) {
+        return eval_paired_stat_fn(context, identifier, expressions);
+    }
+
+    // The arguments may contain undefined variables, so they can't be evaluated like the others.
+    if identifier == "equiv" {
+        return eval_equiv_fn(context, expressions);
+    }
+
+    // The variable is a name rather than a value.
+    if identifier == "diff" {
+        return eval_diff_fn(context, expressions);
+    }
+
+    // Only a way to show the simplified form of the expression, see `simplify::simplify_stmt`.
+    if identifier == "simplify" {
+        return eval_simplify_fn(context, expressions);
+    }
+
+    // The variable is bound to different values while integrating.
+    if identifier == "integrate" || identifier == "∫" {
+        return eval_integrate_fn(context, identifier, expressions);
+    }
+
+    // Prelude. The name is looked up first, since the arguments of other functions are evaluated when they are called.
+    let prelude_func = match expressions {
+        [x] if prelude::UNARY_FUNCS.contains_key(identifier) => {
+            let x = eval_expr(context, x)?;
+            prelude::call_unary_func(identifier, x, &context.angle_unit)
+        }
+        [x, y] if prelude::BINARY_FUNCS.contains_key(identifier) => {
+            let x = eval_expr(context, x)?;
+            let y = eval_expr(context, y)?;
+            prelude::call_binary_func(identifier, x, y, &context.angle_unit)
+        }
+        _ => None,
+    };
+
+    if let Some(result) = prelude_func {
+        return Ok(result);
+    }
+
+    // Special functions
+    if matches!(identifier, "sum" | "Σ" | "prod" | "∏") {
+        return eval_series_fn(context, identifier, expressions);
+    }
+
+    // Functions registered by the host application.
+    if context.symbol_table.get_native_fn(identifier).is_some() {
+        return eval_native_fn_call(context, identifier, expressions);
+    }
+
+    // Symbol Table
+    eval_user_fn_call(context, identifier, expressions, eval_expr)
+}
+
+/// Call a function that may result in a vector or matrix where a number is expected.
+/// This is kept out of `eval_fn_call_expr`, since a `Value` is large and it is called recursively.
+fn eval_scalar_fn_call(
+    context: &mut Context,
+    identifier: &str,
+    expressions: &[Expr],
+) -> Result<Float, CalcError> {
+    match eval_fn_call_value(context, identifier, expressions)? {
+        Value::Scalar(value) => Ok(value),
+        _ => Err(CalcError::UnexpectedVector),
+    }
+}
+
+/// Call a function that was registered by `parser::Context::register_native_fn`, with the arguments evaluated.
+fn eval_native_fn_call(
+    context: &mut Context,
+    identifier: &str,
+    expressions: &[Expr],
+) -> Result<Float, CalcError> {
+    let native_fn = match context.symbol_table.get_native_fn(identifier) {
+        Some(native_fn) => native_fn.clone(),
+        None => return Err(CalcError::UndefinedFn(identifier.into())),
+    };
+    let arguments = expressions
+        .iter()
+        .map(|expr| eval_expr(context, expr))
+        .collect::<Result<Vec<Float>, CalcError>>()?;
+
+    native_fn(&arguments)
+}
+
+/// Call a user-defined function and evaluate its body with `eval_body`.
+///
+/// The arguments are evaluated first, and then given to the parameters in a new scope,
+/// so that eg. `n` in `f(n - 1)` is the `n` of the caller when `f` calls itself.
+fn eval_user_fn_call<T, F>(
+    context: &mut Context,
+    identifier: &str,
+    expressions: &[Expr],
+    eval_body: F,
+) -> Result<T, CalcError>
+where
+    F: FnOnce(&mut Context, &Expr) -> Result<T, CalcError>,
+{
+    let (parameters, fn_body) = match context.symbol_table.get_fn(identifier).cloned() {
+        Some(Stmt::FnDecl(_, parameters, fn_body)) => (parameters, fn_body),
+        _ => return Err(CalcError::UndefinedFn(identifier.into())),
+    };
+
+    if parameters.len() != expressions.len() {
+        return Err(CalcError::IncorrectAmountOfArguments(
+            parameters.len(),
+            identifier.into(),
+            expressions.len(),
+        ));
+    }
+
+    if context.recursion_depth >= context.recursion_limit {
+        return Err(CalcError::RecursionLimitReached(identifier.into()));
+    }
+
+    let mut arguments = Vec::with_capacity(expressions.len());
+    for (parameter, expr) in parameters.into_iter().zip(expressions) {
+        // Literals are kept as they are, so that they are still exact in decimal mode.
+        let argument = match expr {
+            Expr::Literal(_) => expr.clone(),
+            _ => value_to_expr(eval_value(context, expr)?),
+        };
+        arguments.push(Stmt::VarDecl(parameter, Box::new(argument)));
+    }
+
+    context.recursion_depth += 1;
+    context.symbol_table.push_scope();
+    for argument in arguments {
+        context.symbol_table.insert_scoped(argument);
+    }
+
+    let result = eval_body(context, &fn_body);
+    context.symbol_table.pop_scope();
+    context.recursion_depth -= 1;
+
+    result
+}
+
+/// Evaluate an expression that may be a complex number, vector or matrix.
+///
+/// Operations on real numbers that don't have a real answer, eg. `sqrt(-1)` or `(-1)^0.5`, result in a complex number,
+/// see `eval_complex_binary` and `call_unary_func_value`. Operators and built-in functions are applied element-wise, eg. `[1, 2] * [3, 4]` is `[3, 8]`,
+/// and an operation on a vector and a number is applied to every element, eg. `[1, 2] * 3` is `[3, 6]`.
+/// Multiplying or raising a matrix to a power is done like in linear algebra instead, see `eval_matrix_binary`.
+fn eval_value(context: &mut Context, expr: &Expr) -> Result<Value, CalcError> {
+    // Expressions that can only be real numbers are evaluated like usual, so that eg. the cache and decimal mode are used for them.
+    // This doesn't apply to a power of something that may be negative, eg. `x^2`, or to `sqrt` and `ln`, since they may be complex.
+    if !may_be_vector(context.symbol_table, expr, &mut Vec::new())
+        && !may_be_complex(context.symbol_table, expr, &mut Vec::new())
+    {
+        return Ok(Value::Scalar(eval_expr(context, expr)?));
+    }
+
+    if let Expr::Binary(_, _, _) | Expr::Unary(_, _) | Expr::FnCall(_, _) = expr {
+        context.operation_count += 1;
+    }
+
+    match expr {
+        Expr::Vector(elements) => {
+            let mut values = Vec::with_capacity(elements.len());
+            for expr in elements {
+                values.push(eval_value(context, expr)?);
+            }
+
+            // A vector of vectors is a matrix, with the vectors as rows.
+            match values.as_slice() {
+                [Value::Vector(first), ..] => {
+                    let columns = first.len();
+                    let rows: Option<linalg::Matrix> = values
+                        .into_iter()
+                        .map(|value| match value {
+                            Value::Vector(row) if row.len() == columns => Some(row),
+                            _ => None,
+                        })
+                        .collect();
+
+                    rows.map(Value::Matrix).ok_or_else(|| {
+                        CalcError::Message(String::from(
+                            "The rows of a matrix must be vectors of the same length.",
+                        ))
+                    })
+                }
+                _ => {
+                    let values: Option<Vec<Float>> = values
+                        .into_iter()
+                        .map(|value| match value {
+                            Value::Scalar(x) => Some(x),
+                            _ => None,
+                        })
+                        .collect();
+
+                    values.map(Value::Vector).ok_or(CalcError::UnexpectedVector)
+                }
+            }
+        }
+        Expr::Group(expr) | Expr::Annotated(expr, _) => eval_value(context, expr),
+        Expr::Conditional(condition, then, otherwise) => {
+            let branch = choose_branch(context, condition, then, otherwise)?;
+            eval_value(context, branch)
+        }
+        Expr::Unit(expr, unit) => {
+            let value = eval_value(context, expr)?;
+            let factor = working_unit_factor(context, unit)?;
+
+            scale_value(value, &factor)
+        }
+        Expr::Conversion(expr, unit) => {
+            let value = eval_value(context, expr)?;
+            let factor = conversion_factor(context, expr, unit)?;
+
+            scale_value(value, &factor)
+        }
+        Expr::Var(identifier) => {
+            emit(context, || EvalEvent::BeforeVarAccess(identifier.clone()))?;
+            check_allowed(context, identifier, CalcError::UndefinedVar)?;
+            match context.symbol_table.get_var(identifier).cloned() {
+                Some(Stmt::VarDecl(_, expr)) => eval_value(context, &expr),
+                _ if identifier == complex::IMAGINARY_UNIT => {
+                    Ok(Value::Complex(Complex::with_val(context.precision, (0, 1))))
+                }
+                _ => Err(CalcError::UndefinedVar(identifier.clone())),
+            }
+        }
+        Expr::Unary(op, expr) => match eval_value(context, expr)? {
+            Value::Complex(x) => {
+                complex::calculate_unary(context.precision, op, x).map(complex_value)
+            }
+            value => map_value(value, |x| calculate_unary(context, op, x)),
+        },
+        Expr::Binary(left, op, right) => {
+            check_units(context, left, op, right)?;
+            let left = eval_value(context, left)?;
+            let right = eval_value(context, right)?;
+
+            if let Some(value) = eval_matrix_binary(context, &left, op, &right) {
+                return value;
+            }
+            if let Some(value) = eval_complex_binary(context, &left, op, &right) {
+                return value;
+            }
+
+            zip_values(left, right, |x, y| calculate_binary(context, x, op, y))
+        }
+        Expr::FnCall(identifier, expressions) => {
+            emit(context, || EvalEvent::BeforeFnCall(identifier.clone()))?;
+            let value = eval_fn_call_value(context, identifier, expressions);
+            emit(context, || EvalEvent::AfterFnCall(identifier.clone()))?;
+
+            value
+        }
+        Expr::Equation(left, right) => eval_equation(context, left, right).map(Value::Scalar),
+        _ => Err(CalcError::UnexpectedVector),
+    }
+}
+
+fn eval_fn_call_value(
+    context: &mut Context,
+    identifier: &str,
+    expressions: &[Expr],
+) -> Result<Value, CalcError> {
+    check_allowed(context, identifier, CalcError::UndefinedFn)?;
+    let angle_unit = context.angle_unit.clone();
+    let undefined = || CalcError::UndefinedFn(identifier.into());
+
+    if let [argument] = expressions {
+        if is_matrix_fn(identifier) {
+            return eval_matrix_fn(context, identifier, argument);
+        }
+
+        if prelude::VECTOR_VALUED_FUNCS.contains_key(identifier) {
+            let x = eval_expr(context, argument)?;
+
+            return prelude::call_vector_valued_func(identifier, x)
+                .map(Value::Vector)
+                .ok_or_else(undefined);
+        }
+    }
+
+    if is_matrix_constructor(identifier) {
+        return eval_matrix_constructor(context, identifier, expressions);
+    }
+
+    if let [x, y] = expressions {
+        if prelude::BINARY_VECTOR_VALUED_FUNCS.contains_key(identifier) {
+            let x = eval_expr(context, x)?;
+            let y = eval_expr(context, y)?;
+
+            return prelude::call_binary_vector_valued_func(identifier, x, y)
+                .map(Value::Vector)
+                .ok_or_else(undefined);
+        }
+    }
+
+    // Prelude
+    match expressions {
+        [x] if prelude::UNARY_FUNCS.contains_key(identifier) => {
+            let x = eval_value(context, x)?;
+
+            return call_unary_func_value(identifier, x, &angle_unit);
+        }
+        [x, y] if prelude::BINARY_FUNCS.contains_key(identifier) => {
+            let x = eval_value(context, x)?;
+            let y = eval_value(context, y)?;
+            if let (Value::Complex(_), _) | (_, Value::Complex(_)) = (&x, &y) {
+                return Err(complex::unsupported(identifier));
+            }
+
+            return zip_values(x, y, |x, y| {
+                prelude::call_binary_func(identifier, x, y, &angle_unit).ok_or_else(undefined)
+            });
+        }
+        _ => (),
+    }
+
+    // Native functions only take numbers.
+    if context.symbol_table.get_native_fn(identifier).is_some() {
+        return eval_fn_call_expr(context, identifier, expressions).map(Value::Scalar);
+    }
+
+    // Symbol Table
+    if context.symbol_table.get_fn(identifier).is_some() {
+        return eval_user_fn_call(context, identifier, expressions, eval_value);
+    }
+
+    // The special functions, eg. `sum(1, 3, n)`, only work with real numbers.
+    if expressions
+        .iter()
+        .any(|expr| may_be_vector(context.symbol_table, expr, &mut Vec::new()))
+    {
+        return Err(CalcError::UnexpectedVector);
+    }
+
+    eval_fn_call_expr(context, identifier, expressions).map(Value::Scalar)
+}
+
+/// Call a built-in function that takes one argument, element-wise if it is given a vector or matrix.
+/// Functions that don't have a real answer for a real number, eg. `sqrt(-1)`, result in a complex number.
+fn call_unary_func_value(
+    identifier: &str,
+    x: Value,
+    angle_unit: &Unit,
+) -> Result<Value, CalcError> {
+    let undefined = || CalcError::UndefinedFn(identifier.into());
+
+    match x {
+        Value::Scalar(x) => {
+            let result = prelude::call_unary_func(identifier, x.clone(), angle_unit)
+                .ok_or_else(undefined)?;
+            if result.is_nan() && !x.is_nan() && complex::is_complex_valued_fn(identifier) {
+                let x = Complex::with_val(x.prec(), x);
+                if let Some(result) = complex::call_func(identifier, x, angle_unit) {
+                    return Ok(complex_value(result));
+                }
+            }
+
+            Ok(Value::Scalar(result))
+        }
+        Value::Complex(x) => complex::call_func(identifier, x, angle_unit)
+            .map(complex_value)
+            .ok_or_else(|| complex::unsupported(identifier)),
+        x => map_value(x, |x| {
+            prelude::call_unary_func(identifier, x, angle_unit).ok_or_else(undefined)
+        }),
+    }
+}
+
+/// Calculate an operation on complex numbers, or on real numbers that doesn't have a real answer, eg. `(-1)^0.5`.
+/// `None` is returned for other operations, which are calculated with real arithmetic instead.
+fn eval_complex_binary(
+    context: &mut Context,
+    left: &Value,
+    op: &TokenKind,
+    right: &Value,
+) -> Option<Result<Value, CalcError>> {
+    let precision = context.precision;
+    let (left, right) = match (left, right) {
+        (Value::Complex(x), Value::Complex(y)) => (x.clone(), y.clone()),
+        (Value::Complex(x), Value::Scalar(y)) => (x.clone(), Complex::with_val(precision, y)),
+        (Value::Scalar(x), Value::Complex(y)) => (Complex::with_val(precision, x), y.clone()),
+        (Value::Scalar(x), Value::Scalar(y))
+            if matches!(op, TokenKind::Power) && !x.is_nan() && !y.is_nan() =>
+        {
+            let result = match calculate_binary(context, x.clone(), op, y.clone()) {
+                Ok(result) if result.is_nan() => result,
+                result => return Some(result.map(Value::Scalar)),
+            };
+            let x = Complex::with_val(precision, x);
+            let y = Complex::with_val(precision, y);
+            let complex_result = complex::calculate_binary(precision, &x, op, &y).ok()?;
+            if complex_result.real().is_nan() || complex_result.imag().is_nan() {
+                return Some(Ok(Value::Scalar(result)));
+            }
+
+            return Some(Ok(complex_value(complex_result)));
+        }
+        _ => return None,
+    };
+
+    Some(complex::calculate_binary(precision, &left, op, &right).map(complex_value))
+}
+
+/// A complex number with a zero imaginary part is a real number, eg. `i^2` is -1.
+fn complex_value(value: Complex) -> Value {
+    if value.imag().is_zero() {
+        Value::Scalar(value.into_real_imag().0)
+    } else {
+        Value::Complex(value)
+    }
+}
+
+/// Multiply every element of the value by `factor`, eg. to convert it to another unit.
+fn scale_value(value: Value, factor: &Float) -> Result<Value, CalcError> {
+    match value {
+        Value::Complex(value) => Ok(Value::Complex(value * factor)),
+        value => map_value(value, |x| Ok(x * factor)),
+    }
+}
+
+/// Whether the expression is a real number that can't be negative, ie. it only consists of literals,
+/// additions, multiplications, divisions and powers, eg. `(2 + 3) / 4`.
+fn is_non_negative(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) => true,
+        Expr::Group(expr) => is_non_negative(expr),
+        Expr::Binary(left, op, right) => {
+            matches!(
+                op,
+                TokenKind::Plus | TokenKind::Star | TokenKind::Slash | TokenKind::Power
+            ) && is_non_negative(left)
+                && is_non_negative(right)
+        }
+        _ => false,
+    }
+}
+
+/// Whether the expression may evaluate to a complex number, ie. it contains the imaginary unit,
+/// a power or a function that can result in one, eg. `(-1)^0.5` or `sqrt(-1)`, or a variable or function that does.
+fn may_be_complex(symbol_table: &SymbolTable, expr: &Expr, visited: &mut Vec<String>) -> bool {
+    match expr {
+        // A non-negative base raised to a real power is real, eg. `2^0.5`.
+        Expr::Binary(left, TokenKind::Power, right) => {
+            !is_non_negative(left) || may_be_complex(symbol_table, right, visited)
+        }
+        // Equations are solved for a real number.
+        Expr::Equation(_, _) => false,
+        Expr::Binary(left, _, right) => {
+            may_be_complex(symbol_table, left, visited)
+                || may_be_complex(symbol_table, right, visited)
+        }
+        Expr::Unary(_, expr)
+        | Expr::Unit(expr, _)
+        | Expr::Conversion(expr, _)
+        | Expr::Group(expr)
+        | Expr::Annotated(expr, _) => may_be_complex(symbol_table, expr, visited),
+        // The condition is always a real number.
+        Expr::Conditional(_, then, otherwise) => {
+            may_be_complex(symbol_table, then, visited)
+                || may_be_complex(symbol_table, otherwise, visited)
+        }
+        // Vectors only contain real numbers.
+        Expr::Vector(_) | Expr::Literal(_) => false,
+        // Declarations are only looked at once, since they may refer to themselves.
+        Expr::Var(identifier) => match symbol_table.get_var(identifier) {
+            Some(Stmt::VarDecl(_, expr)) if !visited.contains(identifier) => {
+                visited.push(identifier.clone());
+                may_be_complex(symbol_table, expr, visited)
+            }
+            Some(_) => false,
+            None => identifier == complex::IMAGINARY_UNIT,
+        },
+        Expr::FnCall(identifier, expressions) => {
+            if complex::is_complex_valued_fn(identifier)
+                || expressions
+                    .iter()
+                    .any(|expr| may_be_complex(symbol_table, expr, visited))
+            {
+                return true;
+            }
+
+            match symbol_table.get_fn(identifier) {
+                Some(Stmt::FnDecl(_, _, body)) if !visited.contains(identifier) => {
+                    visited.push(identifier.clone());
+                    may_be_complex(symbol_table, body, visited)
+                }
+                _ => false,
+            }
+        }
+    }
+}
+
+/// Whether the expression may evaluate to a vector or matrix,
+/// ie. it contains a vector, or a variable or function that does.
+/// Functions that result in a number when given a vector, eg. `len`, don't count.
+fn may_be_vector(symbol_table: &SymbolTable, expr: &Expr, visited: &mut Vec<String>) -> bool {
+    match expr {
+        Expr::Vector(_) => true,
+        Expr::Binary(left, _, right) | Expr::Equation(left, right) => {
+            may_be_vector(symbol_table, left, visited)
+                || may_be_vector(symbol_table, right, visited)
+        }
+        Expr::Unary(_, expr)
+        | Expr::Unit(expr, _)
+        | Expr::Conversion(expr, _)
+        | Expr::Group(expr)
+        | Expr::Annotated(expr, _) => may_be_vector(symbol_table, expr, visited),
+        // The condition is always a number.
+        Expr::Conditional(_, then, otherwise) => {
+            may_be_vector(symbol_table, then, visited)
+                || may_be_vector(symbol_table, otherwise, visited)
+        }
+        // Declarations are only looked at once, since they may refer to themselves.
+        Expr::Var(identifier) => match symbol_table.get_var(identifier) {
+            Some(Stmt::VarDecl(_, expr)) if !visited.contains(identifier) => {
+                visited.push(identifier.clone());
+                may_be_vector(symbol_table, expr, visited)
+            }
+            _ => false,
+        },
+        Expr::FnCall(identifier, expressions) => {
+            if expressions.len() == 1 && (is_vector_fn(identifier) || identifier == "det") {
+                return false;
+            }
+            if prelude::STAT_FUNCS.contains_key(identifier.as_str())
+                || prelude::PAIRED_STAT_FUNCS.contains_key(identifier.as_str())
+            {
+                return false;
+            }
+            if expressions.len() == 1
+                && prelude::VECTOR_VALUED_FUNCS.contains_key(identifier.as_str())
+            {
+                return true;
+            }
+            if expressions.len() == 2
+                && prelude::BINARY_VECTOR_VALUED_FUNCS.contains_key(identifier.as_str())
+            {
+                return true;
+            }
+            if is_matrix_constructor(identifier) {
+                return true;
+            }
+
+            if expressions
+                .iter()
+                .any(|expr| may_be_vector(symbol_table, expr, visited))
+            {
+                return true;
+            }
+
+            match symbol_table.get_fn(identifier) {
+                Some(Stmt::FnDecl(_, _, body)) if !visited.contains(identifier) => {
+                    visited.push(identifier.clone());
+                    may_be_vector(symbol_table, body, visited)
+                }
+                _ => false,
+            }
+        }
+        Expr::Literal(_) => false,
+    }
+}
+
+/// Represent a value as an expression, so that it can be stored in the symbol table.
+pub(crate) fn float_to_expr(value: &Float) -> Expr {
+    let literal = Expr::Literal(Float::with_val(value.prec(), value.abs_ref()).to_string());
+    if value.is_sign_negative() {
+        Expr::Unary(TokenKind::Minus, Box::new(literal))
+    } else {
+        literal
+    }
+}
+
+pub(crate) fn value_to_expr(value: Value) -> Expr {
+    let vector_to_expr =
+        |values: Vec<Float>| Expr::Vector(values.iter().map(float_to_expr).collect());
+
+    match value {
+        Value::Scalar(value) => float_to_expr(&value),
+        Value::Complex(value) => complex_to_expr(value),
+        Value::Vector(values) => vector_to_expr(values),
+        Value::Matrix(rows) => Expr::Vector(rows.into_iter().map(vector_to_expr).collect()),
+    }
+}
+
+/// Represent a complex number as `a + b*i`.
+fn complex_to_expr(value: Complex) -> Expr {
+    let (real, imaginary) = value.into_real_imag();
+    let imaginary = Expr::Binary(
+        Box::new(float_to_expr(&imaginary)),
+        TokenKind::Star,
+        Box::new(Expr::Var(complex::IMAGINARY_UNIT.into())),
+    );
+
+    Expr::Binary(
+        Box::new(float_to_expr(&real)),
+        TokenKind::Plus,
+        Box::new(imaginary),
+    )
+}
+
+/// Apply `f` to every element of the value.
+fn map_value<F>(value: Value, mut f: F) -> Result<Value, CalcError>
+where
+    F: FnMut(Float) -> Result<Float, CalcError>,
+{
+    Ok(match value {
+        Value::Scalar(x) => Value::Scalar(f(x)?),
+        Value::Complex(_) => return Err(complex_element_error()),
+        Value::Vector(values) => Value::Vector(
+            values
+                .into_iter()
+                .map(f)
+                .collect::<Result<Vec<Float>, CalcError>>()?,
+        ),
+        Value::Matrix(rows) => Value::Matrix(
+            rows.into_iter()
+                .map(|row| row.into_iter().map(&mut f).collect())
+                .collect::<Result<linalg::Matrix, CalcError>>()?,
+        ),
+    })
+}
+
+/// Apply `f` to the elements at the same positions in two vectors or matrices of the same size,
+/// or to every element of a vector or matrix together with a number.
+fn zip_values<F>(left: Value, right: Value, mut f: F) -> Result<Value, CalcError>
+where
+    F: FnMut(Float, Float) -> Result<Float, CalcError>,
+{
+    Ok(match (left, right) {
+        (Value::Scalar(x), Value::Scalar(y)) => Value::Scalar(f(x, y)?),
+        (Value::Vector(xs), Value::Vector(ys)) => Value::Vector(zip_elements(xs, ys, &mut f)?),
+        (Value::Matrix(xs), Value::Matrix(ys)) => {
+            if linalg::size(&xs) != linalg::size(&ys) {
+                let (xs_size, ys_size) = (linalg::size(&xs), linalg::size(&ys));
+                return Err(CalcError::Message(format!(
+                    "The matrices have different sizes: {}×{} and {}×{}.",
+                    xs_size.0, xs_size.1, ys_size.0, ys_size.1
+                )));
+            }
+
+            let rows: Result<linalg::Matrix, CalcError> = xs
+                .into_iter()
+                .zip(ys)
+                .map(|(xs, ys)| zip_elements(xs, ys, &mut f))
+                .collect();
+
+            Value::Matrix(rows?)
+        }
+        (Value::Vector(_), Value::Matrix(_)) | (Value::Matrix(_), Value::Vector(_)) => {
+            return Err(CalcError::Message(String::from(
+                "A vector and a matrix can only be multiplied.",
+            )))
+        }
+        (Value::Complex(_), _) | (_, Value::Complex(_)) => return Err(complex_element_error()),
+        (value, Value::Scalar(y)) => map_value(value, |x| f(x, y.clone()))?,
+        (Value::Scalar(x), value) => map_value(value, |y| f(x.clone(), y))?,
+    })
+}
+
+pub(crate) fn complex_element_error() -> CalcError {
+    CalcError::Message(String::from(
+        "Vectors and matrices can only contain real numbers.",
+    ))
+}
+
+fn zip_elements<F>(xs: Vec<Float>, ys: Vec<Float>, f: &mut F) -> Result<Vec<Float>, CalcError>
+where
+    F: FnMut(Float, Float) -> Result<Float, CalcError>,
+{
+    if xs.len() != ys.len() {
+        return Err(CalcError::Message(format!(
+            "The vectors have different lengths: {} and {}.",
+            xs.len(),
+            ys.len()
+        )));
+    }
+
+    xs.into_iter().zip(ys).map(|(x, y)| f(x, y)).collect()
+}
+
+/// Multiply a matrix by a matrix or vector, or raise a matrix to a power, like in linear algebra.
+/// A vector is a column when it's on the right side of the multiplication, and a row when it's on the left side.
+/// `None` is returned if the operation is done element-wise instead.
+fn eval_matrix_binary(
+    context: &Context,
+    left: &Value,
+    op: &TokenKind,
+    right: &Value,
+) -> Option<Result<Value, CalcError>> {
+    let precision = context.precision;
+    Some(match (left, op, right) {
+        (Value::Matrix(left), TokenKind::Star, Value::Matrix(right)) => {
+            linalg::multiply(precision, left, right).map(Value::Matrix)
+        }
+        (Value::Matrix(left), TokenKind::Star, Value::Vector(right)) => {
+            let column: linalg::Matrix = right.iter().map(|x| vec![x.clone()]).collect();
+
+            linalg::multiply(precision, left, &column)
+                .map(|product| Value::Vector(product.into_iter().flatten().collect()))
+        }
+        (Value::Vector(left), TokenKind::Star, Value::Matrix(right)) => {
+            linalg::multiply(precision, std::slice::from_ref(left), right)
+                .map(|product| Value::Vector(product.into_iter().flatten().collect()))
+        }
+        (Value::Matrix(left), TokenKind::Power, Value::Scalar(exponent)) => {
+            linalg::power(precision, left, exponent).map(Value::Matrix)
+        }
+        _ => return None,
+    })
+}
+
+/// Functions that take a whole vector, eg. `len([1, 2, 3])`.
+fn is_vector_fn(identifier: &str) -> bool {
+    matches!(
+        identifier,
+        "len" | "avg" | "sum" | "Σ" | "prod" | "∏" | "min" | "max"
+    )
+}
+
+/// Calculate the length, sum, product, average, minimum or maximum of the elements of a vector.
+/// A number is treated as a vector with one element, and a matrix as a vector of all its elements.
+fn eval_vector_fn(
+    context: &mut Context,
+    identifier: &str,
+    argument: &Expr,
+) -> Result<Float, CalcError> {
+    let values = match eval_value(context, argument)? {
+        Value::Vector(values) => values,
+        Value::Scalar(x) => vec![x],
+        Value::Matrix(rows) => rows.into_iter().flatten().collect(),
+        Value::Complex(_) => return Err(complex::unsupported(identifier)),
+    };
+    let precision = context.precision;
+    let mut iter = values.iter();
+    // Vectors always have at least one element.
+    let first = Float::with_val(precision, iter.next().unwrap());
+
+    Ok(match identifier {
+        "len" => Float::with_val(precision, values.len()),
+        "sum" | "Σ" => iter.fold(first, |sum, x| sum + x),
+        "prod" | "∏" => iter.fold(first, |product, x| product * x),
+        "avg" => iter.fold(first, |sum, x| sum + x) / values.len() as u64,
+        "min" => iter.fold(first, |min, x| min.min(x)),
+        _ => iter.fold(first, |max, x| max.max(x)),
+    })
+}
+
+/// Calculate a statistic of all the arguments, eg. the median.
+/// Vectors and matrices are treated as their elements, so eg. `mean([1, 2], 3)` is the mean of 1, 2 and 3.
+fn eval_stat_fn(
+    context: &mut Context,
+    identifier: &str,
+    expressions: &[Expr],
+) -> Result<Float, CalcError> {
+    let mut values = Vec::new();
+    for expr in expressions {
+        values.extend(eval_elements(context, identifier, expr)?);
+    }
+
+    prelude::call_stat_func(identifier, values)
+        .ok_or_else(|| CalcError::UndefinedFn(identifier.into()))
+}
+
+/// Calculate a statistic of two lists of numbers, eg. the weighted mean of `[1, 2]` with the weights `[3, 1]`.
+fn eval_paired_stat_fn(
+    context: &mut Context,
+    identifier: &str,
+    expressions: &[Expr],
+) -> Result<Float, CalcError> {
+    let (values, weights) = match expressions {
+        [values, weights] => (
+            eval_elements(context, identifier, values)?,
+            eval_elements(context, identifier, weights)?,
+        ),
+        _ => {
+            return Err(CalcError::IncorrectAmountOfArguments(
+                2,
+                identifier.into(),
+                expressions.len(),
+            ))
+        }
+    };
+
+    prelude::call_paired_stat_func(identifier, values, weights)
+        .unwrap_or_else(|| Err(CalcError::UndefinedFn(identifier.into())))
+}
+
+/// The elements of a vector or matrix, or a number as the only element.
+fn eval_elements(
+    context: &mut Context,
+    identifier: &str,
+    expr: &Expr,
+) -> Result<Vec<Float>, CalcError> {
+    Ok(match eval_value(context, expr)? {
+        Value::Scalar(x) => vec![x],
+        Value::Vector(elements) => elements,
+        Value::Matrix(rows) => rows.into_iter().flatten().collect(),
+        Value::Complex(_) => return Err(complex::unsupported(identifier)),
+    })
+}
+
+/// Functions that take a whole matrix, eg. `det([[1, 2], [3, 4]])`.
+fn is_matrix_fn(identifier: &str) -> bool {
+    matches!(identifier, "det" | "inv" | "transpose")
+}
+
+/// Calculate the determinant, inverse or transpose of a matrix.
+/// A number is treated as a 1×1 matrix, and a vector as a matrix with one row.
+fn eval_matrix_fn(
+    context: &mut Context,
+    identifier: &str,
+    argument: &Expr,
+) -> Result<Value, CalcError> {
+    let matrix = match eval_value(context, argument)? {
+        Value::Matrix(rows) => rows,
+        Value::Vector(values) => vec![values],
+        Value::Scalar(x) => vec![vec![x]],
+        Value::Complex(_) => return Err(complex::unsupported(identifier)),
+    };
+    let precision = context.precision;
+
+    let result = match identifier {
+        "det" => return linalg::determinant(precision, &matrix).map(Value::Scalar),
+        "inv" => linalg::inverse(precision, &matrix)?,
+        _ => linalg::transpose(&matrix),
+    };
+
+    Ok(match linalg::size(&result) {
+        (1, 1) => Value::Scalar(result[0][0].clone()),
+        _ => Value::Matrix(result),
+    })
+}
+
+/// Functions that build a matrix of a given size, eg. `zeros(2, 3)`.
+pub(crate) fn is_matrix_constructor(identifier: &str) -> bool {
+    matches!(identifier, "identity" | "zeros" | "ones")
+}
+
+/// Build the identity matrix `identity(n)`, or a matrix where every element is the same, `zeros(m, n)` or `ones(m, n)`.
+fn eval_matrix_constructor(
+    context: &mut Context,
+    identifier: &str,
+    expressions: &[Expr],
+) -> Result<Value, CalcError> {
+    let (rows, columns) = match expressions {
+        [size] if identifier == "identity" => {
+            let size = eval_matrix_size(context, size)?;
+            (size, size)
+        }
+        [rows, columns] if identifier != "identity" => (
+            eval_matrix_size(context, rows)?,
+            eval_matrix_size(context, columns)?,
+        ),
+        _ => {
+            return Err(CalcError::IncorrectAmountOfArguments(
+                if identifier == "identity" { 1 } else { 2 },
+                identifier.into(),
+                expressions.len(),
+            ))
+        }
+    };
 
-        Ok(None)
+    if rows.saturating_mul(columns) > MAX_MATRIX_ELEMENTS {
+        return Err(CalcError::Message(format!(
+            "A matrix can have at most {} elements.",
+            MAX_MATRIX_ELEMENTS
+        )));
     }
+
+    let precision = context.precision;
+    let element = |i: usize, j: usize| match identifier {
+        "identity" => Float::with_val(precision, (i == j) as u32),
+        "zeros" => Float::with_val(precision, 0),
+        _ => Float::with_val(precision, 1),
+    };
+
+    Ok(Value::Matrix(
+        (0..rows)
+            .map(|i| (0..columns).map(|j| element(i, j)).collect())
+            .collect(),
+    ))
 }
 
-fn eval_stmt(context: &mut Context, stmt: &Stmt) -> Result<Float, CalcError> {
-    match stmt {
-        Stmt::VarDecl(identifier, _) => eval_var_decl_stmt(context, stmt, identifier),
-        Stmt::FnDecl(_, _, _) => eval_fn_decl_stmt(context),
-        Stmt::Expr(expr) => eval_expr_stmt(context, &expr),
-    }
+/// The amount of rows or columns of a matrix, which has to be a positive integer.
+fn eval_matrix_size(context: &mut Context, expr: &Expr) -> Result<usize, CalcError> {
+    let size = eval_expr(context, expr)?;
+
+    size.to_integer()
+        .filter(|n| size.is_integer() && *n >= 1)
+        .and_then(|n| n.to_usize())
+        .ok_or_else(|| {
+            CalcError::Message(format!(
+                "The size of a matrix must be a positive integer, but got {}.",
+                size
+            ))
+        })
 }
 
-fn eval_var_decl_stmt(
+/// Add (`sum`) or multiply (`prod`) the last argument for every integer from the start to the end.
+/// The index variable is `n`, unless it is given as the first of four arguments, eg. `sum(k, 1, 10, k^2)`.
+/// Otherwise, a fourth argument is the step, eg. `sum(1, 10, 2, n)`.
+fn eval_series_fn(
     context: &mut Context,
-    stmt: &Stmt,
     identifier: &str,
+    expressions: &[Expr],
 ) -> Result<Float, CalcError> {
-    context.symbol_table.insert(&identifier, stmt.clone());
-    Ok(Float::with_val(context.precision, 1))
-}
-
-fn eval_fn_decl_stmt(context: &mut Context) -> Result<Float, CalcError> {
-    Ok(Float::with_val(context.precision, 1)) // Nothing needs to happen here, since the parser will already have added the FnDecl's to the symbol table.
-}
+    let is_sum = identifier == "sum" || identifier == "Σ";
+    let (variable, start, end, step, expr) = match expressions {
+        // The first argument is only the name of the index variable if it isn't already defined,
+        // since eg. `sum(a, 5, 2, n)` is a sum with a step when `a` is a variable.
+        [Expr::Var(variable), start, end, expr] if !is_defined_var(context, variable) => {
+            (variable.as_str(), start, end, None, expr)
+        }
+        [start, end, step, expr] => ("n", start, end, Some(step), expr),
+        [start, end, expr] => ("n", start, end, None, expr),
+        // Make sure 3 arguments, or 4 if a step or variable is given, were supplied.
+        _ => {
+            return Err(CalcError::IncorrectAmountOfArguments(
+                if expressions.len() < 3 { 3 } else { 4 },
+                if is_sum { "sum" } else { "prod" }.into(),
+                expressions.len(),
+            ))
+        }
+    };
 
-fn eval_expr_stmt(context: &mut Context, expr: &Expr) -> Result<Float, CalcError> {
-    eval_expr(context, &expr)
-}
+    let start = series_bound(eval_expr(context, start)?)?;
+    let end = series_bound(eval_expr(context, end)?)?;
+    let step = if let Some(step) = step {
+        let step = eval_expr(context, step)?;
+        if !step.is_finite() {
+            return Err(CalcError::InvalidStep);
+        }
 
-fn eval_expr(context: &mut Context, expr: &Expr) -> Result<Float, CalcError> {
-    match expr {
-        Expr::Binary(left, op, right) => eval_binary_expr(context, &left, op, &right),
-        Expr::Unary(op, expr) => eval_unary_expr(context, op, expr),
-        Expr::Unit(expr, kind) => eval_unit_expr(context, expr, kind),
-        Expr::Var(identifier) => eval_var_expr(context, identifier),
-        Expr::Literal(value) => eval_literal_expr(context, value),
-        Expr::Group(expr) => eval_group_expr(context, &expr),
-        Expr::FnCall(identifier, expressions) => {
-            eval_fn_call_expr(context, identifier, expressions)
+        // The step has to lead from the start to the end.
+        let step = series_bound(step)?;
+        if step == 0 || (end - start).signum() * step.signum() < 0 {
+            return Err(CalcError::InvalidStep);
         }
+
+        step
+    } else {
+        1
+    };
+
+    if (end - start) / step >= MAX_SERIES_TERMS {
+        return Err(CalcError::Message(format!(
+            "A sum or product can have at most {} terms.",
+            MAX_SERIES_TERMS
+        )));
     }
-}
 
-fn eval_binary_expr(
-    context: &mut Context,
-    left: &Expr,
-    op: &TokenKind,
-    right: &Expr,
-) -> Result<Float, CalcError> {
-    let left = eval_expr(context, &left)?;
-    let right = eval_expr(context, &right)?;
+    // The index variable only exists while evaluating the terms, and hides any other variable with the same name.
+    context.symbol_table.push_scope();
+    let result = eval_series_terms(context, is_sum, variable, (start, end, step), expr);
+    context.symbol_table.pop_scope();
 
-    Ok(match op {
-        TokenKind::Plus => left + right,
-        TokenKind::Minus => left - right,
-        TokenKind::Star => left * right,
-        TokenKind::Slash => left / right,
-        TokenKind::Power => left.pow(right),
-        _ => Float::with_val(1, 1),
-    })
+    result
 }
 
-fn eval_unary_expr(context: &mut Context, op: &TokenKind, expr: &Expr) -> Result<Float, CalcError> {
-    let expr_value = eval_expr(context, &expr)?;
+/// A bound or the step of `sum` or `prod`, which are truncated to integers, eg. 2.5 becomes 2.
+/// They have to fit in 64 bits, so that the number of terms can be calculated without overflowing.
+fn series_bound(value: Float) -> Result<i128, CalcError> {
+    value
+        .to_integer_round(Round::Zero)
+        .and_then(|(value, _)| value.to_i64())
+        .map(i128::from)
+        .ok_or_else(|| {
+            CalcError::Message(format!(
+                "The bounds of a sum or product must be finite and fit in 64 bits, but got {}.",
+                value
+            ))
+        })
+}
 
-    match op {
-        TokenKind::Minus => Ok(-expr_value),
-        TokenKind::Exclamation => Ok(Float::with_val(
-            context.precision,
-            prelude::special_funcs::factorial(expr_value),
-        )),
-        _ => Err(CalcError::InvalidOperator),
-    }
+fn is_defined_var(context: &Context, identifier: &str) -> bool {
+    prelude::CONSTANTS.contains_key(identifier)
+        || context.symbol_table.contains_var(identifier)
+        || (context.symbol_table.is_last_answer(identifier)
+            && context.symbol_table.last_answer().is_some())
 }
 
-fn eval_unit_expr(
+fn eval_series_terms(
     context: &mut Context,
+    is_sum: bool,
+    variable: &str,
+    (start, end, step): (i128, i128, i128),
     expr: &Expr,
-    kind: &TokenKind,
 ) -> Result<Float, CalcError> {
-    let x = eval_expr(context, &expr);
-    let unit = kind.to_unit()?;
+    let mut result = Float::with_val(context.precision, if is_sum { 0 } else { 1 });
+    let mut n = start;
 
-    // Don't do any angle conversions if the defauly angle unit is the same as the unit kind
-    match unit {
-        Unit::Degrees | Unit::Radians => {
-            if context.angle_unit == unit {
-                return x;
-            }
+    while (step > 0 && n <= end) || (step < 0 && n >= end) {
+        let n_expr = Expr::Literal(n.to_string());
+
+        // Update the index variable on every iteration, then calculate the expression and add/multiply it to the result.
+        context
+            .symbol_table
+            .insert_scoped(Stmt::VarDecl(variable.into(), Box::new(n_expr)));
+        let value = eval_expr(context, expr)?;
+        if is_sum {
+            result += value;
+        } else {
+            result *= value;
         }
+        n += step;
     }
 
-    match unit {
-        Unit::Degrees => Ok(prelude::special_funcs::to_radians(x?)),
-        Unit::Radians => Ok(prelude::special_funcs::to_degrees(x?)),
+    Ok(result)
+}
+
+fn eval_simplify_fn(context: &mut Context, expressions: &[Expr]) -> Result<Float, CalcError> {
+    if expressions.len() != 1 {
+        return Err(CalcError::IncorrectAmountOfArguments(
+            1,
+            "simplify".into(),
+            expressions.len(),
+        ));
     }
+
+    eval_expr(context, &expressions[0])
 }
 
-fn eval_var_expr(context: &mut Context, identifier: &str) -> Result<Float, CalcError> {
-    // If there is a constant with this name, return a literal expression with its value
-    if let Some(value) = prelude::CONSTANTS.get(identifier) {
-        return eval_expr(context, &Expr::Literal((*value).to_string()));
+/// Check if two expressions are equivalent by evaluating them with the free variables set to a number of
+/// sample points. This is a probabilistic check: expressions that happen to be equal at every sample point
+/// are considered equivalent. Returns 1 if they are equivalent, otherwise 0.
+fn eval_equiv_fn(context: &mut Context, expressions: &[Expr]) -> Result<Float, CalcError> {
+    if expressions.len() != 2 {
+        return Err(CalcError::IncorrectAmountOfArguments(
+            2,
+            "equiv".into(),
+            expressions.len(),
+        ));
     }
 
-    // Look for the variable in the symbol table
-    let var_decl = context.symbol_table.get(identifier).cloned();
-    match var_decl {
-        Some(Stmt::VarDecl(_, expr)) => eval_expr(context, &expr),
-        _ => Err(CalcError::UndefinedVar(identifier.into())),
+    let mut free_variables = Vec::new();
+    for expr in expressions {
+        collect_free_variables(context, expr, &mut free_variables);
     }
-}
 
-fn eval_literal_expr(context: &mut Context, value: &str) -> Result<Float, CalcError> {
-    match Float::parse(value) {
-        Ok(parsed_value) => Ok(Float::with_val(context.precision, parsed_value)),
-        Err(_) => Err(CalcError::InvalidNumberLiteral(value.into())),
+    let mut compared = false;
+    let mut last_error = None;
+    for point in SAMPLE_POINTS.iter() {
+        // Give each variable a different value, so that eg. `x` and `y` aren't considered equivalent.
+        let values: HashMap<String, Expr> = free_variables
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let value = point + 0.37 * i as f64;
+                (name.clone(), Expr::Literal(value.to_string()))
+            })
+            .collect();
+
+        let left = eval_expr(context, &substitute(&expressions[0], &values));
+        let right = eval_expr(context, &substitute(&expressions[1], &values));
+        match (left, right) {
+            (Ok(left), Ok(right)) if is_close(&left, &right) => compared = true,
+            // Neither is defined at this point, eg. `sqrt(x)` and `sqrt(x)^2` for a negative x,
+            // so it doesn't tell them apart.
+            (Err(err), Err(_)) => last_error = Some(err),
+            // Either they are different or only one of them is defined.
+            _ => return Ok(Float::with_val(context.precision, 0)),
+        }
+    }
+
+    // Nothing was compared if neither expression could be evaluated at any of the points.
+    match last_error {
+        Some(err) if !compared => Err(err),
+        _ => Ok(Float::with_val(context.precision, 1)),
     }
 }
 
-fn eval_group_expr(context: &mut Context, expr: &Expr) -> Result<Float, CalcError> {
-    eval_expr(context, expr)
+/// Differentiate the first argument with respect to the variable given as the second argument,
+/// and evaluate the derivative, at the value of the third argument if there is one.
+fn eval_diff_fn(context: &mut Context, expressions: &[Expr]) -> Result<Float, CalcError> {
+    if expressions.len() != 2 && expressions.len() != 3 {
+        return Err(CalcError::IncorrectAmountOfArguments(
+            2,
+            "diff".into(),
+            expressions.len(),
+        ));
+    }
+
+    let variable = match &expressions[1] {
+        Expr::Var(variable) => variable,
+        _ => {
+            return Err(CalcError::Message(String::from(
+                "The second argument of diff has to be a variable, eg. diff(x^2, x).",
+            )))
+        }
+    };
+    let derivative = calculus::derivative(
+        context.symbol_table,
+        &context.angle_unit,
+        &expressions[0],
+        variable,
+    )?;
+
+    if let Some(at) = expressions.get(2) {
+        let at = eval_expr(context, at)?;
+        let values = [(variable.clone(), Expr::Literal(at.to_string()))]
+            .iter()
+            .cloned()
+            .collect();
+
+        eval_expr(context, &substitute(&derivative, &values))
+    } else {
+        eval_expr(context, &derivative)
+    }
 }
 
-fn eval_fn_call_expr(
+/// Integrate the third argument from the first argument to the second one with Simpson's rule,
+/// eg. `∫(0, 1, x^2)`. The variable is `x`, unless it is given as a fourth argument.
+fn eval_integrate_fn(
     context: &mut Context,
     identifier: &str,
     expressions: &[Expr],
 ) -> Result<Float, CalcError> {
-    // Prelude
-    let prelude_func = match expressions.len() {
-        1 => {
-            let x = eval_expr(context, &expressions[0])?;
-            prelude::call_unary_func(identifier, x, &context.angle_unit)
-        }
-        2 => {
-            let x = eval_expr(context, &expressions[0])?;
-            let y = eval_expr(context, &expressions[1])?;
-            prelude::call_binary_func(identifier, x, y, &context.angle_unit)
+    if expressions.len() != 3 && expressions.len() != 4 {
+        return Err(CalcError::IncorrectAmountOfArguments(
+            3,
+            identifier.into(),
+            expressions.len(),
+        ));
+    }
+
+    let variable = match expressions.get(3) {
+        Some(Expr::Var(variable)) => variable.as_str(),
+        Some(_) => {
+            return Err(CalcError::Message(format!(
+                "The fourth argument of {} has to be a variable, eg. {}(0, 1, t^2, t).",
+                identifier, identifier
+            )))
         }
-        _ => None,
+        None => "x",
     };
+    let start = eval_expr(context, &expressions[0])?;
+    let end = eval_expr(context, &expressions[1])?;
+    let expr = &expressions[2];
+    let steps = context.integration_steps;
+    let step = Float::with_val(context.precision, &end - &start) / steps;
 
-    if let Some(result) = prelude_func {
-        return Ok(result);
+    let mut sum = Float::with_val(context.precision, 0);
+    for i in 0..=steps {
+        let x = Float::with_val(context.precision, &step * i) + &start;
+        let values = [(variable.to_string(), Expr::Literal(x.to_string()))]
+            .iter()
+            .cloned()
+            .collect();
+        let y = eval_expr(context, &substitute(expr, &values))?;
+
+        // The end points are weighted 1, and the points in between alternately 4 and 2.
+        sum += if i == 0 || i == steps {
+            y
+        } else if i % 2 == 1 {
+            y * 4
+        } else {
+            y * 2
+        };
     }
 
-    // Special functions
-    match identifier {
-        "sum" | "Σ" => {
-            // Make sure exactly 3 arguments were supplied.
-            if expressions.len() != 3 {
-                return Err(CalcError::IncorrectAmountOfArguments(
-                    3,
-                    "sum".into(),
-                    expressions.len(),
-                ));
-            }
+    Ok(sum * step / 3)
+}
+
+/// Solve the equation for its unknown, ie. the only variable in it that hasn't been declared.
+fn eval_equation(context: &mut Context, left: &Expr, right: &Expr) -> Result<Float, CalcError> {
+    let mut unknowns = Vec::new();
+    collect_free_variables(context, left, &mut unknowns);
+    collect_free_variables(context, right, &mut unknowns);
+    let unknown = match unknowns.as_slice() {
+        [unknown] => unknown.clone(),
+        [] => {
+            return Err(CalcError::Message(String::from(
+                "There is no unknown to solve for.",
+            )))
+        }
+        _ => {
+            return Err(CalcError::Message(format!(
+                "Can only solve for one unknown, but found {}.",
+                unknowns.join(", ")
+            )))
+        }
+    };
+
+    // Every guess is a different value, so the results aren't worth caching.
+    #[cfg(feature = "cache")]
+    let cache = context.cache.take();
+
+    // The unknown only exists while solving, like the index variable of `sum`.
+    context.symbol_table.push_scope();
+    let root = solver::find_root(context.precision, |x| {
+        let value = Box::new(float_to_expr(x));
+        context
+            .symbol_table
+            .insert_scoped(Stmt::VarDecl(unknown.clone(), value));
 
-            let start = eval_expr(context, &expressions[0])?.to_f64() as i128;
-            let end = eval_expr(context, &expressions[1])?.to_f64() as i128;
-            let mut sum = Float::with_val(context.precision, 0);
+        Ok(eval_expr(context, left)? - eval_expr(context, right)?)
+    });
+    context.symbol_table.pop_scope();
 
-            for n in start..=end {
-                let n_expr = Expr::Literal(n.to_string());
+    #[cfg(feature = "cache")]
+    {
+        context.cache = cache;
+    }
+
+    root?.ok_or_else(|| CalcError::Message(format!("No solution for {} was found.", unknown)))
+}
 
-                // Update the variable "n" in the symbol table on every iteration,
-                // then calculate the expression and add it to the total sum.
-                context
-                    .symbol_table
-                    .set("n", Stmt::VarDecl(String::from("n"), Box::new(n_expr)));
-                sum += eval_expr(context, &expressions[2])?;
+fn collect_free_variables(context: &Context, expr: &Expr, names: &mut Vec<String>) {
+    match expr {
+        Expr::Binary(left, _, right) | Expr::Equation(left, right) => {
+            collect_free_variables(context, left, names);
+            collect_free_variables(context, right, names);
+        }
+        Expr::Unary(_, expr)
+        | Expr::Unit(expr, _)
+        | Expr::Conversion(expr, _)
+        | Expr::Group(expr)
+        | Expr::Annotated(expr, _) => collect_free_variables(context, expr, names),
+        Expr::Var(identifier) => {
+            if !context.symbol_table.contains_var(identifier) && !names.contains(identifier) {
+                names.push(identifier.clone());
+            }
+        }
+        Expr::FnCall(_, expressions) | Expr::Vector(expressions) => {
+            for expr in expressions {
+                collect_free_variables(context, expr, names);
             }
+        }
+        Expr::Conditional(condition, then, otherwise) => {
+            collect_free_variables(context, condition, names);
+            collect_free_variables(context, then, names);
+            collect_free_variables(context, otherwise, names);
+        }
+        Expr::Literal(_) => (),
+    }
+}
+
+/// Replace the variables in the expression with the given expressions.
+pub(crate) fn substitute(expr: &Expr, values: &HashMap<String, Expr>) -> Expr {
+    let substitute_box = |expr: &Expr| Box::new(substitute(expr, values));
 
-            return Ok(sum);
+    match expr {
+        Expr::Binary(left, op, right) => {
+            Expr::Binary(substitute_box(left), op.clone(), substitute_box(right))
         }
-        _ => (),
+        Expr::Unary(op, expr) => Expr::Unary(op.clone(), substitute_box(expr)),
+        Expr::Unit(expr, unit) => Expr::Unit(substitute_box(expr), unit.clone()),
+        Expr::Conversion(expr, unit) => Expr::Conversion(substitute_box(expr), unit.clone()),
+        Expr::Var(identifier) => match values.get(identifier) {
+            Some(value) => Expr::Group(Box::new(value.clone())),
+            None => expr.clone(),
+        },
+        Expr::Group(expr) => Expr::Group(substitute_box(expr)),
+        Expr::Equation(left, right) => Expr::Equation(substitute_box(left), substitute_box(right)),
+        Expr::Annotated(expr, annotation) => {
+            Expr::Annotated(substitute_box(expr), annotation.clone())
+        }
+        Expr::FnCall(identifier, expressions) => Expr::FnCall(
+            identifier.clone(),
+            expressions
+                .iter()
+                .map(|expr| substitute(expr, values))
+                .collect(),
+        ),
+        Expr::Vector(elements) => Expr::Vector(
+            elements
+                .iter()
+                .map(|expr| substitute(expr, values))
+                .collect(),
+        ),
+        Expr::Conditional(condition, then, otherwise) => Expr::Conditional(
+            substitute_box(condition),
+            substitute_box(then),
+            substitute_box(otherwise),
+        ),
+        Expr::Literal(_) => expr.clone(),
+    }
+}
+
+fn is_close(x: &Float, y: &Float) -> bool {
+    if x == y || (x.is_nan() && y.is_nan()) {
+        return true;
     }
 
-    // Symbol Table
-    let stmt_definition = context
-        .symbol_table
-        .get(&format!("{}()", identifier))
-        .cloned();
+    let scale = Float::with_val(x.prec(), x.abs_ref())
+        .max(&Float::with_val(y.prec(), y.abs_ref()))
+        .max(&Float::with_val(x.prec(), 1));
+    let difference = Float::with_val(x.prec(), x - y).abs();
+
+    difference <= scale * 1e-9
+}
+
+/// Find out which unit the result of an expression is in, if any.
+/// `parameters` contains the units of the arguments when inspecting the body of a function,
+/// and `functions` the functions whose bodies are being inspected.
+fn eval_unit(
+    context: &Context,
+    expr: &Expr,
+    parameters: &HashMap<String, Option<String>>,
+    functions: &[&str],
+) -> Option<String> {
+    match expr {
+        Expr::Binary(left, op, right) => {
+            let left = eval_unit(context, left, parameters, functions);
+            let right = eval_unit(context, right, parameters, functions);
 
-    match stmt_definition {
-        Some(Stmt::FnDecl(_, arguments, fn_body)) => {
-            if arguments.len() != expressions.len() {
-                return Err(CalcError::IncorrectAmountOfArguments(
-                    arguments.len(),
-                    identifier.into(),
-                    expressions.len(),
-                ));
+            match op {
+                TokenKind::Plus | TokenKind::Minus => left.or(right),
+                // Eg. 2 * 30deg. The product of two angles is not an angle, however.
+                TokenKind::Star if left.is_some() && right.is_some() => None,
+                TokenKind::Star => left.or(right),
+                // Eg. 30deg / 2
+                TokenKind::Slash if right.is_none() => left,
+                // Eg. 100cm % 30cm
+                TokenKind::Percent if right.is_none() || left == right => left,
+                _ => None,
             }
+        }
+        Expr::Unary(TokenKind::Minus, expr) | Expr::Group(expr) | Expr::Annotated(expr, _) => {
+            eval_unit(context, expr, parameters, functions)
+        }
+        Expr::Unary(_, _) | Expr::Literal(_) | Expr::Equation(_, _) | Expr::Vector(_) => None,
+        Expr::Conditional(_, then, otherwise) => eval_unit(context, then, parameters, functions)
+            .or_else(|| eval_unit(context, otherwise, parameters, functions)),
+        // Values with a unit are always converted to the unit that is calculated with.
+        Expr::Unit(_, unit) => base_unit(context.symbol_table, unit, &mut Vec::new())
+            .map(|base| working_unit(context, &base)),
+        Expr::Conversion(_, unit) => Some(unit.clone()),
+        Expr::Var(identifier) => {
+            if let Some(unit) = parameters.get(identifier) {
+                return unit.clone();
+            }
+
+            match context.symbol_table.get_var(identifier) {
+                Some(Stmt::VarDecl(_, expr)) => eval_unit(context, expr, &HashMap::new(), &[]),
+                _ => None,
+            }
+        }
+        Expr::FnCall(identifier, expressions) => {
+            let argument_units: Vec<Option<String>> = expressions
+                .iter()
+                .map(|expr| eval_unit(context, expr, parameters, functions))
+                .collect();
 
-            // Initialise the arguments as their own variables.
-            for (i, argument) in arguments.iter().enumerate() {
-                eval_stmt(
-                    context,
-                    &Stmt::VarDecl(argument.clone(), Box::new(expressions[i].clone())),
-                )?;
+            match identifier.as_ref() {
+                "sum" | "Σ" => return argument_units.last().cloned().flatten(),
+                _ => (),
             }
 
-            eval_expr(context, &*fn_body)
+            if let Some(Stmt::FnDecl(_, arguments, fn_body)) =
+                context.symbol_table.get_fn(identifier)
+            {
+                // A function that calls itself isn't inspected again.
+                if functions.contains(&identifier.as_str()) {
+                    return None;
+                }
+
+                let parameters = arguments.iter().cloned().zip(argument_units).collect();
+                let functions = [functions, &[identifier.as_str()]].concat();
+
+                eval_unit(context, fn_body, &parameters, &functions)
+            } else {
+                let argument_unit = argument_units.into_iter().flatten().next();
+
+                prelude::get_result_unit(identifier, argument_unit, &context.angle_unit)
+            }
         }
-        _ => Err(CalcError::UndefinedFn(identifier.into())),
     }
 }
 
@@ -245,14 +2361,19 @@ fn eval_fn_call_expr(
 mod tests {
     use super::*;
     use crate::lexer::TokenKind::*;
+    use crate::parser::Unit;
     use crate::test_helpers::*;
     use test_case::test_case;
 
     const PRECISION: u32 = 53;
 
     fn interpret(stmt: Stmt) -> Result<Option<Float>, CalcError> {
+        interpret_with_unit(stmt, Unit::Radians)
+    }
+
+    fn interpret_with_unit(stmt: Stmt, angle_unit: Unit) -> Result<Option<Float>, CalcError> {
         let mut symbol_table = SymbolTable::new();
-        let mut context = Context::new(&mut symbol_table, &Unit::Radians, PRECISION);
+        let mut context = Context::new(&mut symbol_table, &angle_unit, PRECISION);
         context.interpret(vec![stmt])
     }
 
@@ -281,6 +2402,24 @@ mod tests {
         assert_eq!(interpret(pow).unwrap().unwrap(), 8);
     }
 
+    #[test]
+    fn test_distance() {
+        let distance = Stmt::Expr(binary(literal("3"), Distance, literal("7")));
+        let reversed = Stmt::Expr(binary(literal("7"), Distance, literal("3")));
+
+        assert_eq!(interpret(distance).unwrap().unwrap(), 4);
+        assert_eq!(interpret(reversed).unwrap().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_negative_zero() {
+        let neg = Stmt::Expr(unary(Minus, literal("0")));
+        let mul = Stmt::Expr(binary(literal("0"), Star, unary(Minus, literal("5"))));
+
+        assert!(interpret(neg).unwrap().unwrap().is_sign_positive());
+        assert!(interpret(mul).unwrap().unwrap().is_sign_positive());
+    }
+
     #[test]
     fn test_unary() {
         let neg = Stmt::Expr(unary(Minus, literal("1")));
@@ -290,19 +2429,55 @@ mod tests {
         assert_eq!(interpret(neg).unwrap().unwrap(), -1);
         assert_eq!(interpret(fact).unwrap().unwrap(), 120);
 
-        let fact_dec_result = interpret(fact_dec).unwrap().unwrap();
-        assert!(fact_dec_result > 169.406 && fact_dec_result < 169.407);
+        assert_approx_eq!(
+            &interpret(fact_dec).unwrap().unwrap(),
+            &Float::with_val(PRECISION, 169.40609946172307),
+            8
+        );
     }
 
     #[test]
     fn test_unit() {
-        let rad = Stmt::Expr(Box::new(Expr::Unit(literal("1"), Rad)));
-        let deg = Stmt::Expr(Box::new(Expr::Unit(literal("1"), Deg)));
+        let rad = Stmt::Expr(Box::new(Expr::Unit(literal("1"), String::from("rad"))));
+        let deg = Stmt::Expr(Box::new(Expr::Unit(literal("1"), String::from("deg"))));
 
         assert_eq!(interpret(rad).unwrap().unwrap(), 1);
-        assert!(
-            (interpret(deg).unwrap().unwrap() - Float::with_val(PRECISION, 0.017456)).abs()
-                < Float::with_val(PRECISION, 0.0001)
+        assert_approx_eq!(
+            &interpret(deg).unwrap().unwrap(),
+            &Float::with_val(PRECISION, 0.017453292519943295),
+            2
+        );
+    }
+
+    #[test_case(Unit::Degrees, 60f64)]
+    #[test_case(Unit::Radians, std::f64::consts::FRAC_PI_3)]
+    fn test_unit_sum(angle_unit: Unit, expected: f64) {
+        let stmt = Stmt::Expr(binary(
+            Box::new(Expr::Unit(literal("30"), String::from("deg"))),
+            Plus,
+            Box::new(Expr::Unit(literal("30"), String::from("deg"))),
+        ));
+
+        assert_approx_eq!(
+            &interpret_with_unit(stmt, angle_unit).unwrap().unwrap(),
+            &Float::with_val(PRECISION, expected),
+            2
+        );
+    }
+
+    #[test_case(Unit::Degrees, 58.64788975654116)]
+    #[test_case(Unit::Radians, 1.0235987755982987)]
+    fn test_mixed_unit_sum(angle_unit: Unit, expected: f64) {
+        let stmt = Stmt::Expr(binary(
+            Box::new(Expr::Unit(literal("30"), String::from("deg"))),
+            Plus,
+            Box::new(Expr::Unit(literal("0.5"), String::from("rad"))),
+        ));
+
+        assert_approx_eq!(
+            &interpret_with_unit(stmt, angle_unit).unwrap().unwrap(),
+            &Float::with_val(PRECISION, expected),
+            2
         );
     }
 
@@ -312,7 +2487,7 @@ mod tests {
 
         // Prepare by inserting a variable declaration in the symbol table.
         let mut symbol_table = SymbolTable::new();
-        symbol_table.insert("x", var_decl("x", literal("1")));
+        symbol_table.insert(var_decl("x", literal("1")));
 
         let mut context = Context::new(&mut symbol_table, &Unit::Radians, PRECISION);
         assert_eq!(context.interpret(vec![stmt]).unwrap().unwrap(), 1);
@@ -345,14 +2520,11 @@ mod tests {
 
         // Prepare by inserting a variable declaration in the symbol table.
         let mut symbol_table = SymbolTable::new();
-        symbol_table.insert(
-            "f()",
-            fn_decl(
-                "f",
-                vec![String::from("x")],
-                binary(var("x"), TokenKind::Plus, literal("2")),
-            ),
-        );
+        symbol_table.insert(fn_decl(
+            "f",
+            vec![String::from("x")],
+            binary(var("x"), TokenKind::Plus, literal("2")),
+        ));
 
         let mut context = Context::new(&mut symbol_table, &Unit::Radians, PRECISION);
         assert_eq!(context.interpret(vec![stmt]).unwrap().unwrap(), 3);
@@ -368,6 +2540,107 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "cache")]
+    #[test]
+    fn test_cache() {
+        let expr = binary(var("x"), Plus, fn_call("sqrt", vec![*literal("4")]));
+        let stmt = Stmt::Expr(expr.clone());
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.insert(var_decl("x", literal("1")));
+        let mut cache = Cache::new();
+        let settings = Settings {
+            precision: PRECISION,
+            angle_unit: Unit::Radians,
+            rounding: Round::Nearest,
+            decimal_mode: false,
+            integer_mode: false,
+            integration_steps: DEFAULT_INTEGRATION_STEPS,
+        };
+
+        let mut context = Context::new(&mut symbol_table, &Unit::Radians, PRECISION);
+        context.set_cache(&mut cache);
+        assert_eq!(context.interpret(vec![stmt.clone()]).unwrap().unwrap(), 3);
+
+        // Redefining `x` mutates the symbol table, so the cached result must not be used.
+        assert_eq!(
+            context
+                .interpret(vec![var_decl("x", literal("5")), stmt])
+                .unwrap()
+                .unwrap(),
+            7
+        );
+        assert_eq!(
+            cache
+                .get(&expr, &settings, symbol_table.generation())
+                .unwrap()
+                .value,
+            7
+        );
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn test_cache_hit_has_same_effects() {
+        use std::sync::atomic::{self, AtomicUsize};
+        use std::sync::Arc;
+
+        let expr = binary(fn_call("sqrt", vec![*literal("4")]), Plus, literal("1"));
+        let stmt = Stmt::Expr(expr.clone());
+        let mut symbol_table = SymbolTable::new();
+        let mut cache = Cache::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let callback: SharedCallback = Arc::new(move |_: &EvalContext| {
+            counter.fetch_add(1, atomic::Ordering::SeqCst);
+            Ok(())
+        });
+        let callbacks: Callbacks = vec![(EvalEvent::BeforeFnCall(String::from("sqrt")), callback)];
+
+        let mut context = Context::new(&mut symbol_table, &Unit::Radians, PRECISION);
+        context.set_cache(&mut cache);
+        context.set_callbacks(&callbacks);
+        assert_eq!(context.interpret(vec![stmt.clone()]).unwrap().unwrap(), 3);
+        let operation_count = context.operation_count();
+        assert_eq!(context.interpret(vec![stmt]).unwrap().unwrap(), 3);
+
+        assert_eq!(calls.load(atomic::Ordering::SeqCst), 2);
+        assert_eq!(context.operation_count(), 2 * operation_count);
+
+        let settings = Settings {
+            precision: PRECISION,
+            angle_unit: Unit::Radians,
+            rounding: Round::Nearest,
+            decimal_mode: false,
+            integer_mode: false,
+            integration_steps: DEFAULT_INTEGRATION_STEPS,
+        };
+        let evaluation = cache
+            .get(&expr, &settings, symbol_table.generation())
+            .unwrap();
+        assert_eq!(evaluation.operations, operation_count);
+        assert_eq!(
+            evaluation.events.unwrap()[0],
+            EvalEvent::BeforeFnCall(String::from("sqrt"))
+        );
+    }
+
+    #[cfg(all(feature = "cache", feature = "profiling"))]
+    #[test]
+    fn test_equation_not_cached() {
+        let stmt = Stmt::Expr(Box::new(Expr::Equation(
+            binary(var("x"), Star, var("x")),
+            literal("4"),
+        )));
+        let mut symbol_table = SymbolTable::new();
+        let mut cache = Cache::new();
+
+        let mut context = Context::new(&mut symbol_table, &Unit::Radians, PRECISION);
+        context.set_cache(&mut cache);
+        assert_eq!(context.interpret(vec![stmt]).unwrap().unwrap().abs(), 2);
+        assert!(!symbol_table.contains_var("x"));
+        assert_eq!(cache.values().count(), 0);
+    }
+
     #[test_case("1", "2", 9f64)]
     #[test_case("1.2", "2.3", 9f64)]
     fn test_sum_fn(start: &str, to: &str, result: f64) {
@@ -382,4 +2655,122 @@ mod tests {
 
         assert_eq!(interpret(stmt).unwrap().unwrap(), result);
     }
+
+    #[test_case("0", "10", "2", 30f64)]
+    #[test_case("10", "0", "-2", 30f64)]
+    #[test_case("1", "10", "4", 15f64)]
+    fn test_sum_fn_with_step(start: &str, to: &str, step: &str, result: f64) {
+        let stmt = Stmt::Expr(fn_call(
+            "sum",
+            vec![*literal(start), *literal(to), *literal(step), *var("n")],
+        ));
+
+        assert_eq!(interpret(stmt).unwrap().unwrap(), result);
+    }
+
+    #[test_case("0", "10", "0" ; "zero step")]
+    #[test_case("0", "10", "-1" ; "wrong direction")]
+    #[test_case("0", "10", "0.5" ; "truncated to zero")]
+    #[test_case("0", "10", "nan" ; "nan step")]
+    #[test_case("0", "10", "inf" ; "infinite step")]
+    fn test_sum_fn_invalid_step(start: &str, to: &str, step: &str) {
+        let stmt = Stmt::Expr(fn_call(
+            "sum",
+            vec![*literal(start), *literal(to), *literal(step), *var("n")],
+        ));
+
+        assert_eq!(interpret(stmt), Err(CalcError::InvalidStep));
+    }
+
+    #[test_case("1", "inf" ; "infinite")]
+    #[test_case("nan", "10" ; "nan")]
+    #[test_case("1", "1e30" ; "too large")]
+    #[test_case("1", "1e10" ; "too many terms")]
+    fn test_sum_fn_invalid_bounds(start: &str, to: &str) {
+        let stmt = Stmt::Expr(fn_call(
+            "sum",
+            vec![*literal(start), *literal(to), *var("n")],
+        ));
+
+        assert!(matches!(interpret(stmt), Err(CalcError::Message(_))));
+    }
+
+    #[test_case("1", "5", 120f64)]
+    #[test_case("3", "1", 1f64 ; "empty")]
+    fn test_prod_fn(start: &str, to: &str, result: f64) {
+        let stmt = Stmt::Expr(fn_call(
+            "prod",
+            vec![*literal(start), *literal(to), *var("n")],
+        ));
+
+        assert_eq!(interpret(stmt).unwrap().unwrap(), result);
+    }
+
+    #[test_case("sum", 30f64)]
+    #[test_case("prod", 576f64)]
+    fn test_series_fn_with_variable(name: &str, result: f64) {
+        let stmt = Stmt::Expr(fn_call(
+            name,
+            vec![
+                *var("k"),
+                *literal("1"),
+                *literal("4"),
+                *binary(var("k"), TokenKind::Power, literal("2")),
+            ],
+        ));
+
+        assert_eq!(interpret(stmt).unwrap().unwrap(), result);
+    }
+
+    #[test]
+    fn test_series_fn_with_defined_start() {
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.insert(var_decl("a", literal("1")));
+        let mut context = Context::new(&mut symbol_table, &Unit::Radians, PRECISION);
+        let stmt = Stmt::Expr(fn_call(
+            "sum",
+            vec![*var("a"), *literal("5"), *literal("2"), *var("n")],
+        ));
+
+        // 1 + 3 + 5, since `a` is the start rather than the index variable.
+        assert_eq!(context.interpret(vec![stmt]).unwrap().unwrap(), 9);
+    }
+
+    #[test_case(2, 3)]
+    #[test_case(5, 4)]
+    fn test_series_fn_amount_of_arguments(amount: usize, expected: usize) {
+        let stmt = Stmt::Expr(fn_call("sum", vec![*literal("1"); amount]));
+
+        assert_eq!(
+            interpret(stmt),
+            Err(CalcError::IncorrectAmountOfArguments(
+                expected,
+                String::from("sum"),
+                amount
+            ))
+        );
+    }
+
+    #[test]
+    fn test_series_fn_scope() {
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.insert(var_decl("n", literal("10")));
+        let mut context = Context::new(&mut symbol_table, &Unit::Radians, PRECISION);
+        let stmt = Stmt::Expr(fn_call(
+            "sum",
+            vec![
+                *literal("1"),
+                *literal("3"),
+                *fn_call("sum", vec![*var("k"), *literal("1"), *var("n"), *var("k")]),
+            ],
+        ));
+
+        // 1 + (1 + 2) + (1 + 2 + 3)
+        assert_eq!(context.interpret(vec![stmt]).unwrap().unwrap(), 10);
+        assert_eq!(
+            symbol_table.get_var("n"),
+            Some(&var_decl("n", literal("10")))
+        );
+        assert!(symbol_table.get_var("k").is_none());
+    }
 }
@@ -0,0 +1,384 @@
+use crate::ast::{Expr, Stmt};
+use crate::lexer::TokenKind;
+use crate::parser::{CalcError, DEFAULT_RECURSION_LIMIT};
+use crate::prelude;
+use crate::symbol_table::SymbolTable;
+use rug::float::{Round, Special};
+use rug::ops::AssignRound;
+use rug::ops::Pow;
+use rug::Float;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// The lower and upper bound of a value.
+type Interval = (Float, Float);
+
+/// Evaluate an expression with interval arithmetic, and return bounds that the exact answer is within.
+///
+/// Literals are given an uncertainty of half a unit in the last place at the precision,
+/// and constants a unit in their last decimal digit. Every operation rounds its bounds outwards.
+/// Integer literals used as exponents are treated as exact, since eg. `x^2` should stay a square.
+pub fn eval(
+    symbol_table: &SymbolTable,
+    expr: &Expr,
+    precision: u32,
+) -> Result<Interval, CalcError> {
+    Evaluator {
+        symbol_table,
+        precision,
+        parameters: HashMap::new(),
+        depth: 0,
+    }
+    .eval(expr)
+}
+
+struct Evaluator<'a> {
+    symbol_table: &'a SymbolTable,
+    precision: u32,
+    /// The values of the parameters when inside the body of a function.
+    parameters: HashMap<String, Interval>,
+    /// The number of calls to user-defined functions that this is inside of.
+    /// Conditionals can't be evaluated here, so a function that calls itself never finishes,
+    /// and this is limited to `DEFAULT_RECURSION_LIMIT`.
+    depth: u32,
+}
+
+impl<'a> Evaluator<'a> {
+    fn eval(&self, expr: &Expr) -> Result<Interval, CalcError> {
+        match expr {
+            Expr::Binary(left, op, right) => self.eval_binary_expr(left, op, right),
+            Expr::Unary(TokenKind::Minus, expr) => {
+                let (lo, hi) = self.eval(expr)?;
+                Ok((-hi, -lo))
+            }
+            Expr::Group(expr) | Expr::Annotated(expr, _) => self.eval(expr),
+            Expr::Var(identifier) => self.eval_var_expr(identifier),
+            Expr::Literal(value) => self.eval_literal(value),
+            Expr::FnCall(identifier, expressions) => {
+                self.eval_fn_call_expr(identifier, expressions)
+            }
+            _ => Err(unsupported(&expr.to_string())),
+        }
+    }
+
+    fn eval_binary_expr(
+        &self,
+        left: &Expr,
+        op: &TokenKind,
+        right: &Expr,
+    ) -> Result<Interval, CalcError> {
+        let left = self.eval(left)?;
+
+        if let (TokenKind::Power, Expr::Literal(exponent)) = (op, right) {
+            if let Ok(exponent) = exponent.parse::<i32>() {
+                return Ok(self.pow_int(left, exponent));
+            }
+        }
+
+        let right = self.eval(right)?;
+        let p = self.precision;
+
+        Ok(match op {
+            TokenKind::Plus => (
+                round(p, &left.0 + &right.0, Round::Down),
+                round(p, &left.1 + &right.1, Round::Up),
+            ),
+            TokenKind::Minus => (
+                round(p, &left.0 - &right.1, Round::Down),
+                round(p, &left.1 - &right.0, Round::Up),
+            ),
+            TokenKind::Star => {
+                self.corners(&left, &right, |a, b, round_dir| round(p, a * b, round_dir))
+            }
+            TokenKind::Slash => {
+                if right.0 <= 0 && right.1 >= 0 {
+                    return Ok(unbounded(p));
+                }
+
+                self.corners(&left, &right, |a, b, round_dir| round(p, a / b, round_dir))
+            }
+            TokenKind::Power => {
+                // x^y is monotonic in both x and y when x is positive, so the extremes are in the corners.
+                if left.0 <= 0 {
+                    return Err(unsupported("^ with a base that may be negative"));
+                }
+
+                self.corners(&left, &right, |a, b, round_dir| {
+                    round(p, a.pow(b), round_dir)
+                })
+            }
+            TokenKind::Distance => {
+                let (lo, hi) = (
+                    round(p, &left.0 - &right.1, Round::Down),
+                    round(p, &left.1 - &right.0, Round::Up),
+                );
+                abs((lo, hi))
+            }
+            _ => return Err(CalcError::InvalidOperator),
+        })
+    }
+
+    fn eval_var_expr(&self, identifier: &str) -> Result<Interval, CalcError> {
+        if let Some(value) = self.parameters.get(identifier) {
+            return Ok(value.clone());
+        }
+
+        if let Some(value) = prelude::CONSTANTS.get(identifier) {
+            return Ok(self.eval_constant(value));
+        }
+
+        match self.symbol_table.get_var(identifier) {
+            Some(Stmt::VarDecl(_, expr)) => self.eval(expr),
+            _ => Err(CalcError::UndefinedVar(identifier.into())),
+        }
+    }
+
+    fn eval_fn_call_expr(
+        &self,
+        identifier: &str,
+        expressions: &[Expr],
+    ) -> Result<Interval, CalcError> {
+        let mut arguments = Vec::with_capacity(expressions.len());
+        for expr in expressions {
+            arguments.push(self.eval(expr)?);
+        }
+
+        if let Some(Stmt::FnDecl(_, parameters, body)) = self.symbol_table.get_fn(identifier) {
+            if parameters.len() != arguments.len() {
+                return Err(CalcError::IncorrectAmountOfArguments(
+                    parameters.len(),
+                    identifier.into(),
+                    arguments.len(),
+                ));
+            }
+
+            if self.depth >= DEFAULT_RECURSION_LIMIT {
+                return Err(CalcError::RecursionLimitReached(identifier.into()));
+            }
+
+            return Evaluator {
+                symbol_table: self.symbol_table,
+                precision: self.precision,
+                parameters: parameters.iter().cloned().zip(arguments).collect(),
+                depth: self.depth + 1,
+            }
+            .eval(body);
+        }
+
+        let p = self.precision;
+        match (identifier, arguments.as_slice()) {
+            // Increasing functions, so the bounds can be calculated directly.
+            ("sqrt", [(lo, hi)]) if *lo >= 0 => Ok((
+                round(p, lo.sqrt_ref(), Round::Down),
+                round(p, hi.sqrt_ref(), Round::Up),
+            )),
+            ("exp", [(lo, hi)]) => Ok((
+                round(p, lo.exp_ref(), Round::Down),
+                round(p, hi.exp_ref(), Round::Up),
+            )),
+            ("ln", [(lo, hi)]) if *lo > 0 => Ok((
+                round(p, lo.ln_ref(), Round::Down),
+                round(p, hi.ln_ref(), Round::Up),
+            )),
+            ("abs", [value]) => Ok(abs(value.clone())),
+            _ => Err(unsupported(identifier)),
+        }
+    }
+
+    fn eval_literal(&self, value: &str) -> Result<Interval, CalcError> {
+        let parsed = match Float::parse(value) {
+            Ok(parsed) => Float::with_val(self.precision, parsed),
+            Err(_) => return Err(CalcError::InvalidNumberLiteral(value.into())),
+        };
+
+        if parsed.is_zero() {
+            return Ok((parsed.clone(), parsed));
+        }
+
+        // Half a unit in the last place.
+        let exp = parsed.get_exp().unwrap_or(0) - self.precision as i32 - 1;
+        let half_ulp = Float::with_val(self.precision, Float::i_exp(1, exp));
+
+        Ok((
+            round(self.precision, &parsed - &half_ulp, Round::Down),
+            round(self.precision, &parsed + &half_ulp, Round::Up),
+        ))
+    }
+
+    /// The constants are truncated rather than rounded, eg. e is `2.71828182` rather than `2.71828183`,
+    /// so the exact value may be almost a unit in the last decimal digit away, rather than half of one.
+    fn eval_constant(&self, value: &str) -> Interval {
+        let p = self.precision;
+        let decimals = value.find('.').map_or(0, |pos| value.len() - pos - 1) as i32;
+        let power_of_ten = round(p, Float::u_pow_u(10, decimals as u32), Round::Down);
+        let unit = round(p, power_of_ten.recip_ref(), Round::Up);
+        let lo = round(p, Float::parse(value).unwrap(), Round::Down);
+        let hi = round(p, Float::parse(value).unwrap(), Round::Up);
+
+        (
+            round(p, &lo - &unit, Round::Down),
+            round(p, &hi + &unit, Round::Up),
+        )
+    }
+
+    fn pow_int(&self, (lo, hi): Interval, exponent: i32) -> Interval {
+        let p = self.precision;
+        if exponent < 0 {
+            let (lo, hi) = self.pow_int((lo, hi), -exponent);
+            if lo <= 0 && hi >= 0 {
+                return unbounded(p);
+            }
+
+            let one = Float::with_val(p, 1);
+            return self.corners(&(one.clone(), one), &(lo, hi), |a, b, round_dir| {
+                round(p, a / b, round_dir)
+            });
+        }
+
+        if exponent == 0 {
+            return (Float::with_val(p, 1), Float::with_val(p, 1));
+        }
+
+        let pow = |x: &Float, round_dir| round(p, x.pow(exponent), round_dir);
+        if exponent % 2 == 1 || lo >= 0 {
+            (pow(&lo, Round::Down), pow(&hi, Round::Up))
+        } else if hi <= 0 {
+            (pow(&hi, Round::Down), pow(&lo, Round::Up))
+        } else {
+            // Even power of an interval around zero.
+            let max = pow(&lo, Round::Up).max(&pow(&hi, Round::Up));
+            (Float::with_val(p, 0), max)
+        }
+    }
+
+    /// For operations that are monotonic in both arguments, the extremes are found among the combinations of the bounds.
+    fn corners<F>(&self, left: &Interval, right: &Interval, op: F) -> Interval
+    where
+        F: Fn(&Float, &Float, Round) -> Float,
+    {
+        let pairs = [
+            (&left.0, &right.0),
+            (&left.0, &right.1),
+            (&left.1, &right.0),
+            (&left.1, &right.1),
+        ];
+        let min = pairs
+            .iter()
+            .map(|(a, b)| op(a, b, Round::Down))
+            .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .unwrap();
+        let max = pairs
+            .iter()
+            .map(|(a, b)| op(a, b, Round::Up))
+            .max_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .unwrap();
+
+        (min, max)
+    }
+}
+
+fn round<T>(precision: u32, value: T, round_dir: Round) -> Float
+where
+    Float: AssignRound<T, Round = Round, Ordering = Ordering>,
+{
+    Float::with_val_round(precision, value, round_dir).0
+}
+
+fn abs((lo, hi): Interval) -> Interval {
+    if lo >= 0 {
+        (lo, hi)
+    } else if hi <= 0 {
+        (-hi, -lo)
+    } else {
+        let max = if -lo.clone() > hi { -lo } else { hi };
+        (Float::with_val(max.prec(), 0), max)
+    }
+}
+
+fn unbounded(precision: u32) -> Interval {
+    (
+        Float::with_val(precision, Special::NegInfinity),
+        Float::with_val(precision, Special::Infinity),
+    )
+}
+
+fn unsupported(name: &str) -> CalcError {
+    CalcError::Message(format!("{} is not supported in interval arithmetic.", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{self, CalcError, Context};
+    use rug::float::Constant;
+    use rug::{Float, Rational};
+
+    fn eval_interval(input: &str) -> (Float, Float) {
+        let mut context = Context::new();
+
+        context.eval_interval(input, 53).unwrap().unwrap()
+    }
+
+    fn width((lo, hi): &(Float, Float)) -> Float {
+        Float::with_val(53, hi - lo)
+    }
+
+    #[test]
+    fn test_sum_contains_exact() {
+        let (lo, hi) = eval_interval("0.1 + 0.2");
+        let exact = Rational::from((3, 10));
+
+        assert!(lo < exact && exact < hi);
+    }
+
+    #[test]
+    fn test_division_widens() {
+        let sum = eval_interval("0.1 + 0.2");
+        let divided = eval_interval("(0.1 + 0.2) / 0.001");
+
+        assert!(width(&divided) > width(&sum) * 100);
+        assert!(divided.0 < 300 && divided.1 > 300);
+    }
+
+    #[test]
+    fn test_division_by_interval_with_zero() {
+        let (lo, hi) = eval_interval("1 / (1 - 1)");
+
+        assert!(lo.is_infinite() && lo < 0);
+        assert!(hi.is_infinite() && hi > 0);
+    }
+
+    #[test]
+    fn test_constants_and_functions() {
+        let mut context = Context::new();
+        parser::eval(&mut context, "f(x) = x^2 + sqrt(x^2)", 53).unwrap();
+        let pi = Float::with_val(53, Constant::Pi);
+        let (pi_lo, pi_hi) = context.eval_interval("pi", 53).unwrap().unwrap();
+        let (f_lo, f_hi) = context.eval_interval("f(-3)", 53).unwrap().unwrap();
+
+        assert!(pi_lo < pi && pi < pi_hi);
+        assert!(f_lo < 12 && f_hi > 12);
+    }
+
+    #[test]
+    fn test_truncated_constants_contain_exact() {
+        let one = Float::with_val(53, 1);
+        let e = one.clone().exp();
+        let tau = Float::with_val(53, Constant::Pi) * 2;
+        let phi = (Float::with_val(53, 5).sqrt() + one) / 2;
+
+        for (input, exact) in [("e", e), ("tau", tau), ("phi", phi)].iter() {
+            let (lo, hi) = eval_interval(input);
+            assert!(lo < *exact && *exact < hi, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut context = Context::new();
+
+        assert!(matches!(
+            context.eval_interval("sin(1)", 53),
+            Err(CalcError::Message(_))
+        ));
+    }
+}
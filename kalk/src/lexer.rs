@@ -1,23 +1,48 @@
+use rug::Integer;
+use std::collections::{HashMap, VecDeque};
 use std::iter::Peekable;
 use std::str;
 use std::str::Chars;
 
-#[derive(Clone, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum TokenKind {
     Unknown,
     Literal,
     Identifier,
+    Text,
 
     Plus,
     Minus,
     Star,
     Slash,
+    DoubleSlash,
+    Percent,
     Power,
     Equals,
+    EqualsEquals,
+    NotEquals,
+    GreaterThan,
+    LessThan,
+    GreaterOrEquals,
+    LessOrEquals,
+    Distance,
     Exclamation,
-
-    Deg,
-    Rad,
+    Ampersand,
+    Tilde,
+    ShiftLeft,
+    ShiftRight,
+    Xor,
+
+    Unit,
+    To,
+
+    If,
+    Then,
+    Else,
+    And,
+    Or,
+    Not,
 
     Pipe,
     OpenCeil,
@@ -26,16 +51,22 @@ pub enum TokenKind {
     ClosedFloor,
     OpenParenthesis,
     ClosedParenthesis,
+    OpenBracket,
+    ClosedBracket,
     Comma,
+    Semicolon,
 
     EOF,
 }
 
+/// The start and end byte offsets of a part of the input, eg. `&input[span.0..span.1]` is the text of a token.
+pub type Span = (usize, usize);
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Token {
     pub kind: TokenKind,
     pub value: String,
-    pub span: (usize, usize),
+    pub span: Span,
 }
 
 pub struct Lexer<'a> {
@@ -62,26 +93,44 @@ impl<'a> Lexer<'a> {
             }
         }
 
+        // The lexer counts characters, so convert the spans to byte offsets, which can be used to slice the source.
+        let offsets: Vec<usize> = source
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain(std::iter::once(source.len()))
+            .collect();
+        let to_offset = |index: usize| offsets.get(index).copied().unwrap_or(source.len());
+        for token in &mut tokens {
+            token.span = (to_offset(token.span.0), to_offset(token.span.1));
+        }
+
         tokens
     }
 
     fn next(&mut self) -> Token {
-        let mut c = if let Some(c) = self.peek() {
+        if let Some(unterminated) = self.skip_whitespace_and_comments() {
+            return unterminated;
+        }
+        let c = if let Some(c) = self.peek() {
             *c
         } else {
             return build(TokenKind::EOF, "", (self.index, self.index));
         };
 
-        while c == ' ' || c == '\t' || c == '\r' || c == '\n' {
-            if let None = self.advance() {
-                return build(TokenKind::EOF, "", (self.index, self.index));
-            }
+        if c.is_ascii_digit() {
+            return self.next_number_literal();
+        }
 
-            c = *self.peek().unwrap();
+        if c == '"' {
+            return self.next_text();
         }
 
-        if c.is_digit(10) {
-            return self.next_number_literal();
+        // A standalone underscore refers to the last answer. Otherwise it's part of eg. `x_1`.
+        if c == '_'
+            && !matches!(self.chars.clone().nth(1), Some(next) if is_valid_identifier(Some(&next)) || next.is_ascii_digit())
+        {
+            self.advance();
+            return build(TokenKind::Identifier, "_", (self.index - 1, self.index));
         }
 
         if is_valid_identifier(Some(&c)) {
@@ -94,6 +143,7 @@ impl<'a> Lexer<'a> {
             '-' => build(TokenKind::Minus, "", span),
             '*' => build(TokenKind::Star, "", span),
             '/' => build(TokenKind::Slash, "", span),
+            '%' => build(TokenKind::Percent, "", span),
             '^' => build(TokenKind::Power, "", span),
             '|' => build(TokenKind::Pipe, "", span),
             '⌈' => build(TokenKind::OpenCeil, "", span),
@@ -102,39 +152,191 @@ impl<'a> Lexer<'a> {
             '⌋' => build(TokenKind::ClosedFloor, "", span),
             '(' => build(TokenKind::OpenParenthesis, "", span),
             ')' => build(TokenKind::ClosedParenthesis, "", span),
+            '[' => build(TokenKind::OpenBracket, "", span),
+            ']' => build(TokenKind::ClosedBracket, "", span),
             '=' => build(TokenKind::Equals, "", span),
+            '>' => build(TokenKind::GreaterThan, "", span),
+            '<' => build(TokenKind::LessThan, "", span),
             '!' => build(TokenKind::Exclamation, "", span),
+            '&' => build(TokenKind::Ampersand, "", span),
+            '~' => build(TokenKind::Tilde, "", span),
             ',' => build(TokenKind::Comma, "", span),
-            _ => build(TokenKind::Unknown, "", span),
+            ';' => build(TokenKind::Semicolon, "", span),
+            _ => build(TokenKind::Unknown, &c.to_string(), span),
         };
 
         self.advance();
 
+        // Tokens consisting of three characters.
+        if c == '<' && self.peek() == Some(&'-') && self.chars.clone().nth(1) == Some('>') {
+            self.advance();
+            self.advance();
+            return build(TokenKind::Distance, "", (span.0, span.1 + 2));
+        }
+
+        // Tokens consisting of two characters.
+        let long_kind = match (c, self.peek()) {
+            ('!', Some('=')) | ('<', Some('>')) => Some(TokenKind::NotEquals),
+            ('=', Some('=')) => Some(TokenKind::EqualsEquals),
+            ('>', Some('=')) => Some(TokenKind::GreaterOrEquals),
+            ('<', Some('=')) => Some(TokenKind::LessOrEquals),
+            ('<', Some('<')) => Some(TokenKind::ShiftLeft),
+            ('>', Some('>')) => Some(TokenKind::ShiftRight),
+            ('/', Some('/')) => Some(TokenKind::DoubleSlash),
+            _ => None,
+        };
+
+        if let Some(kind) = long_kind {
+            self.advance();
+            return build(kind, "", (span.0, span.1 + 1));
+        }
+
         token
     }
 
     fn next_number_literal(&mut self) -> Token {
+        if let Some(token) = self.next_radix_literal() {
+            return token;
+        }
+
         let start = self.index;
         let mut end = start;
         let mut value = String::new();
+        let mut ends_with_whitespace = false;
 
-        loop {
-            let c = if let Some(c) = self.peek() {
-                *c
-            } else {
+        while let Some(&c) = self.peek() {
+            if !c.is_ascii_digit() && c != '.' && c != '_' && !c.is_whitespace() {
                 break;
-            };
+            }
 
-            if !c.is_digit(10) && c != '.' && !c.is_whitespace() {
+            // An underscore after whitespace is the last answer, eg. in `2 _`, rather than a separator.
+            if c == '_' && ends_with_whitespace {
                 break;
             }
 
+            // Whitespace is allowed inside number literals (eg. `1 000`), but isn't part of the value.
+            ends_with_whitespace = c.is_whitespace();
+            if !ends_with_whitespace {
+                value.push(c);
+            }
+
             end += 1;
-            value.push(c);
             self.advance();
         }
 
-        build(TokenKind::Literal, &value, (start, end))
+        // Underscores are only allowed between digits, eg. `1_000_000`.
+        if value.starts_with('_')
+            || value.ends_with('_')
+            || value.contains("_.")
+            || value.contains("._")
+        {
+            return build(TokenKind::Unknown, &value, (start, end));
+        }
+
+        // The exponent has to come directly after the digits, eg. `1.5e-10`.
+        if !ends_with_whitespace {
+            if let Some(exponent) = self.next_exponent() {
+                end += exponent.len();
+                value.push_str(&exponent);
+            }
+        }
+
+        build(TokenKind::Literal, &value.replace('_', ""), (start, end))
+    }
+
+    /// The exponent of a number in scientific notation, eg. `e-10` in `1.5e-10`.
+    /// `None` is returned if the `e` isn't followed by digits, eg. for `2e`, which is 2 times Euler's number.
+    fn next_exponent(&mut self) -> Option<String> {
+        let mut chars = self.chars.clone();
+        let mut exponent = match chars.next() {
+            Some(c) if c == 'e' || c == 'E' => c.to_string(),
+            _ => return None,
+        };
+
+        if let Some(sign) = chars.clone().next().filter(|c| *c == '+' || *c == '-') {
+            exponent.push(sign);
+            chars.next();
+        }
+
+        let digits: String = chars.take_while(|c| c.is_ascii_digit()).collect();
+        if digits.is_empty() {
+            return None;
+        }
+
+        exponent.push_str(&digits);
+        for _ in 0..exponent.len() {
+            self.advance();
+        }
+
+        Some(exponent)
+    }
+
+    /// A hexadecimal, binary or octal integer, eg. `0xff`, `0b1010` or `0o17`.
+    /// The value of the token is the integer in base 10, so that it can be parsed like other literals.
+    /// `None` is returned if there is no prefix followed by a letter or digit, eg. for `0x`, which is `0 * x`.
+    /// Letters and digits that aren't digits in the base make the literal an `Unknown` token, eg. `0b102` or `0xg`.
+    fn next_radix_literal(&mut self) -> Option<Token> {
+        let mut chars = self.chars.clone();
+        if chars.next() != Some('0') {
+            return None;
+        }
+
+        let radix = match chars.next() {
+            Some('x') | Some('X') => 16,
+            Some('b') | Some('B') => 2,
+            Some('o') | Some('O') => 8,
+            _ => return None,
+        };
+        if !matches!(chars.next(), Some(c) if c.is_ascii_alphanumeric()) {
+            return None;
+        }
+
+        let start = self.index;
+        self.advance();
+        self.advance();
+
+        let mut digits = String::new();
+        while let Some(&c) = self.peek() {
+            if !c.is_ascii_alphanumeric() && c != '_' {
+                break;
+            }
+
+            digits.push(c);
+            self.advance();
+        }
+
+        // Like in decimal literals, underscores are only allowed between digits, eg. `0xffff_ffff`.
+        if digits.ends_with('_') || !digits.chars().all(|c| c.is_digit(radix) || c == '_') {
+            return Some(build(TokenKind::Unknown, &digits, (start, self.index)));
+        }
+
+        let value = Integer::from_str_radix(&digits.replace('_', ""), radix as i32).ok()?;
+
+        Some(build(
+            TokenKind::Literal,
+            &value.to_string(),
+            (start, self.index),
+        ))
+    }
+
+    /// Text within quotes, eg. `"kinetic energy"`. The value doesn't include the quotes.
+    fn next_text(&mut self) -> Token {
+        let start = self.index;
+        let mut value = String::new();
+        self.advance();
+
+        while let Some(c) = self.peek() {
+            let c = *c;
+            self.advance();
+            if c == '"' {
+                return build(TokenKind::Text, &value, (start, self.index));
+            }
+
+            value.push(c);
+        }
+
+        // The closing quote is missing.
+        build(TokenKind::Unknown, &value, (start, self.index))
     }
 
     fn next_identifier(&mut self) -> Token {
@@ -146,13 +348,14 @@ impl<'a> Lexer<'a> {
         while is_valid_identifier(self.peek()) {
             let c = *self.peek().unwrap();
 
-            // If the current character is an underscore, expect a number next.
-            // This is to allow the notation like the following: x_1
-            if c == '_' {
+            // If the current character is an underscore followed by a number, the number is a subscript,
+            // eg. x_1. Otherwise, the underscore is part of the name, eg. pi_approx.
+            if c == '_' && matches!(self.chars.clone().nth(1), Some(next) if next.is_ascii_digit())
+            {
                 self.advance();
                 let num = self.next_number_literal().value;
                 value.push('_');
-                value.push_str(&num.trim_end()); // Trim, since the number_literal function allows whitespace, which identifiers should not contain.
+                value.push_str(num.trim_end()); // Trim, since the number_literal function allows whitespace, which identifiers should not contain.
                 break;
             }
 
@@ -168,14 +371,61 @@ impl<'a> Lexer<'a> {
         }
 
         let kind = match value.as_ref() {
-            "deg" | "°" => TokenKind::Deg,
-            "rad" => TokenKind::Rad,
+            "unit" => TokenKind::Unit,
+            "to" => TokenKind::To,
+            "if" => TokenKind::If,
+            "then" => TokenKind::Then,
+            "else" => TokenKind::Else,
+            "and" => TokenKind::And,
+            "or" => TokenKind::Or,
+            "not" => TokenKind::Not,
+            "xor" => TokenKind::Xor,
             _ => TokenKind::Identifier,
         };
 
         build(kind, &value, (start, end))
     }
 
+    /// Comments are either to the end of the line, eg. `# radius`, or within `/*` and `*/`.
+    /// `//` is integer division, so it doesn't start a comment.
+    /// A block comment without an end, eg. `/* radius`, results in an unknown token for the `/*`.
+    fn skip_whitespace_and_comments(&mut self) -> Option<Token> {
+        while let Some(&c) = self.peek() {
+            // Any unicode whitespace, such as non-breaking spaces, which are common when copy-pasting.
+            if c.is_whitespace() {
+                self.advance();
+            } else if c == '#' {
+                while matches!(self.peek(), Some(&c) if c != '\n') {
+                    self.advance();
+                }
+            } else if c == '/' && self.chars.clone().nth(1) == Some('*') {
+                let start = self.index;
+                self.advance();
+                self.advance();
+
+                let mut previous = None;
+                let mut terminated = false;
+                while let Some(&c) = self.peek() {
+                    self.advance();
+                    if previous == Some('*') && c == '/' {
+                        terminated = true;
+                        break;
+                    }
+
+                    previous = Some(c);
+                }
+
+                if !terminated {
+                    return Some(build(TokenKind::Unknown, "/*", (start, start + 2)));
+                }
+            } else {
+                break;
+            }
+        }
+
+        None
+    }
+
     fn peek(&mut self) -> Option<&char> {
         self.chars.peek()
     }
@@ -186,7 +436,67 @@ impl<'a> Lexer<'a> {
     }
 }
 
-fn build(kind: TokenKind, value: &str, span: (usize, usize)) -> Token {
+/// Cache mapping input strings to the tokens they were lexed into.
+/// Since lexing does not depend on any context, the input string alone is enough as the key.
+pub struct TokenCache {
+    capacity: usize,
+    entries: HashMap<String, Vec<Token>>,
+    /// The cached inputs, from the least to the most recently used.
+    order: VecDeque<String>,
+}
+
+impl TokenCache {
+    pub fn new(capacity: usize) -> Self {
+        TokenCache {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Get the tokens of the input from the cache, or lex it if it isn't cached.
+    pub fn lex(&mut self, source: &str) -> Vec<Token> {
+        if let Some(tokens) = self.entries.get(source) {
+            if let Some(index) = self.order.iter().position(|input| input == source) {
+                let input = self.order.remove(index).unwrap();
+                self.order.push_back(input);
+            }
+
+            return tokens.clone();
+        }
+
+        let tokens = Lexer::lex(source);
+        if self.capacity > 0 {
+            self.entries.insert(source.to_string(), tokens.clone());
+            self.order.push_back(source.to_string());
+            self.evict();
+        }
+
+        tokens
+    }
+
+    /// The cached inputs and their tokens.
+    #[cfg(feature = "profiling")]
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &Vec<Token>)> {
+        self.entries.iter()
+    }
+
+    /// Remove the least recently used entries until the cache fits within its capacity.
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+fn build(kind: TokenKind, value: &str, span: Span) -> Token {
     Token {
         kind,
         value: value.to_string(),
@@ -196,7 +506,7 @@ fn build(kind: TokenKind, value: &str, span: (usize, usize)) -> Token {
 
 fn is_valid_identifier(c: Option<&char>) -> bool {
     if let Some(c) = c {
-        regex::Regex::new(r"[^\s\n\r0-9\+-/\*\^!\(\)=\.,|⌊⌋⌈⌉]")
+        regex::Regex::new(r"[^\s\n\r0-9\+-/\*\^!\(\)\[\]=\.,<>|&~%⌊⌋⌈⌉#$@;:?{}\\`]")
             .unwrap()
             .is_match(&c.to_string())
     } else {
@@ -219,7 +529,7 @@ mod tests {
 
     #[test]
     fn test_token_kinds() {
-        let tokens = Lexer::lex("+-*/^()|=!,");
+        let tokens = Lexer::lex("+-*/^()[]|=!,;");
         let expected = vec![
             TokenKind::Plus,
             TokenKind::Minus,
@@ -228,16 +538,179 @@ mod tests {
             TokenKind::Power,
             TokenKind::OpenParenthesis,
             TokenKind::ClosedParenthesis,
+            TokenKind::OpenBracket,
+            TokenKind::ClosedBracket,
             TokenKind::Pipe,
             TokenKind::Equals,
             TokenKind::Exclamation,
             TokenKind::Comma,
+            TokenKind::Semicolon,
+            TokenKind::EOF,
+        ];
+
+        match_tokens(tokens, expected);
+    }
+
+    #[test]
+    fn test_distance() {
+        let tokens = Lexer::lex("3<->7 <- >");
+        let expected = vec![
+            TokenKind::Literal,
+            TokenKind::Distance,
+            TokenKind::Literal,
+            TokenKind::LessThan,
+            TokenKind::Minus,
+            TokenKind::GreaterThan,
+            TokenKind::EOF,
+        ];
+
+        assert_eq!(tokens[1].span, (1, 4));
+        match_tokens(tokens, expected);
+    }
+
+    #[test]
+    fn test_keywords() {
+        let tokens = Lexer::lex("if x then 1 else iff");
+        let expected = vec![
+            TokenKind::If,
+            TokenKind::Identifier,
+            TokenKind::Then,
+            TokenKind::Literal,
+            TokenKind::Else,
+            TokenKind::Identifier,
+            TokenKind::EOF,
+        ];
+
+        assert_eq!(tokens[5].value, "iff");
+        match_tokens(tokens, expected);
+    }
+
+    #[test_case("0xff", "255")]
+    #[test_case("0XFF", "255" ; "uppercase")]
+    #[test_case("0b1010", "10")]
+    #[test_case("0o17", "15")]
+    #[test_case("0xffff_ffff", "4294967295")]
+    #[test_case("0x1_0000_0000_0000_0000", "18446744073709551616")]
+    fn test_radix_literals(input: &str, expected: &str) {
+        let tokens = Lexer::lex(input);
+
+        assert_eq!(tokens[0].kind, TokenKind::Literal);
+        assert_eq!(tokens[0].value, expected);
+        assert_eq!(tokens[0].span, (0, input.len()));
+        assert_eq!(tokens[1].kind, TokenKind::EOF);
+    }
+
+    #[test_case("0b102" ; "binary")]
+    #[test_case("0o9" ; "octal")]
+    #[test_case("0xg" ; "hexadecimal")]
+    #[test_case("0xff_" ; "trailing underscore")]
+    fn test_invalid_radix_literals(input: &str) {
+        let tokens = Lexer::lex(input);
+
+        assert_eq!(tokens[0].kind, TokenKind::Unknown);
+        assert_eq!(tokens[0].span, (0, input.len()));
+        assert_eq!(tokens[1].kind, TokenKind::EOF);
+    }
+
+    #[test]
+    fn test_radix_prefix_without_digits() {
+        let tokens = Lexer::lex("0x + 0b");
+        let expected = vec![
+            TokenKind::Literal,
+            TokenKind::Identifier,
+            TokenKind::Plus,
+            TokenKind::Literal,
+            TokenKind::Identifier,
+            TokenKind::EOF,
+        ];
+
+        match_tokens(tokens, expected);
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let tokens = Lexer::lex("~1 & 2 | 3 xor 4 << 5 >> 6 <= 7");
+        let expected = vec![
+            TokenKind::Tilde,
+            TokenKind::Literal,
+            TokenKind::Ampersand,
+            TokenKind::Literal,
+            TokenKind::Pipe,
+            TokenKind::Literal,
+            TokenKind::Xor,
+            TokenKind::Literal,
+            TokenKind::ShiftLeft,
+            TokenKind::Literal,
+            TokenKind::ShiftRight,
+            TokenKind::Literal,
+            TokenKind::LessOrEquals,
+            TokenKind::Literal,
+            TokenKind::EOF,
+        ];
+
+        match_tokens(tokens, expected);
+    }
+
+    #[test]
+    fn test_division_operators() {
+        let tokens = Lexer::lex("7 % 2 // 3 / 4");
+        let expected = vec![
+            TokenKind::Literal,
+            TokenKind::Percent,
+            TokenKind::Literal,
+            TokenKind::DoubleSlash,
+            TokenKind::Literal,
+            TokenKind::Slash,
+            TokenKind::Literal,
+            TokenKind::EOF,
+        ];
+
+        match_tokens(tokens, expected);
+    }
+
+    #[test]
+    fn test_unit_keywords() {
+        let tokens = Lexer::lex("unit km = 1000 m to mi");
+        let expected = vec![
+            TokenKind::Unit,
+            TokenKind::Identifier,
+            TokenKind::Equals,
+            TokenKind::Literal,
+            TokenKind::Identifier,
+            TokenKind::To,
+            TokenKind::Identifier,
+            TokenKind::EOF,
+        ];
+
+        match_tokens(tokens, expected);
+    }
+
+    #[test]
+    fn test_boolean_operators() {
+        let tokens = Lexer::lex("not x and y or z");
+        let expected = vec![
+            TokenKind::Not,
+            TokenKind::Identifier,
+            TokenKind::And,
+            TokenKind::Identifier,
+            TokenKind::Or,
+            TokenKind::Identifier,
             TokenKind::EOF,
         ];
 
         match_tokens(tokens, expected);
     }
 
+    #[test_case("!=" ; "exclamation equals")]
+    #[test_case("<>" ; "angle brackets")]
+    fn test_not_equals(input: &str) {
+        let tokens = Lexer::lex(input);
+        let expected = vec![TokenKind::NotEquals, TokenKind::EOF];
+
+        assert_eq!(tokens[0].span, (0, 2));
+        match_tokens(tokens, expected);
+    }
+
     #[test_case("1")]
     #[test_case("24")]
     #[test_case("56.4")]
@@ -249,6 +722,146 @@ mod tests {
         match_tokens(tokens, expected);
     }
 
+    #[test_case("1.5e-10", "1.5e-10")]
+    #[test_case("3E8", "3E8" ; "uppercase")]
+    #[test_case("2e+5", "2e+5")]
+    fn test_scientific_notation(input: &str, expected: &str) {
+        let tokens = Lexer::lex(input);
+
+        assert_eq!(&tokens[0].value, expected);
+        assert_eq!(tokens[0].span, (0, input.len()));
+        match_tokens(tokens, vec![TokenKind::Literal, TokenKind::EOF]);
+    }
+
+    #[test_case("2e", vec![TokenKind::Literal, TokenKind::Identifier, TokenKind::EOF])]
+    #[test_case("2e+x", vec![TokenKind::Literal, TokenKind::Identifier, TokenKind::Plus, TokenKind::Identifier, TokenKind::EOF] ; "sign without digits")]
+    #[test_case("2 e5", vec![TokenKind::Literal, TokenKind::Identifier, TokenKind::Literal, TokenKind::EOF] ; "whitespace before exponent")]
+    fn test_euler_after_number(input: &str, expected: Vec<TokenKind>) {
+        match_tokens(Lexer::lex(input), expected);
+    }
+
+    #[test_case("1_000", "1000")]
+    #[test_case("3.141_592_653", "3.141592653")]
+    fn test_number_literal_separator(input: &str, expected: &str) {
+        let tokens = Lexer::lex(input);
+
+        assert_eq!(&tokens[0].value, expected);
+        match_tokens(tokens, vec![TokenKind::Literal, TokenKind::EOF]);
+    }
+
+    #[test_case("\"kinetic energy\"", TokenKind::Text, "kinetic energy" ; "text")]
+    #[test_case("\"unterminated", TokenKind::Unknown, "unterminated" ; "unterminated")]
+    fn test_text(input: &str, kind: TokenKind, value: &str) {
+        let tokens = Lexer::lex(input);
+
+        assert_eq!(&tokens[0].value, value);
+        assert_eq!(tokens[0].span, (0, input.len()));
+        match_tokens(tokens, vec![kind, TokenKind::EOF]);
+    }
+
+    #[test_case("==", TokenKind::EqualsEquals)]
+    #[test_case(">", TokenKind::GreaterThan)]
+    #[test_case("<", TokenKind::LessThan)]
+    #[test_case(">=", TokenKind::GreaterOrEquals)]
+    #[test_case("<=", TokenKind::LessOrEquals)]
+    fn test_comparison(input: &str, kind: TokenKind) {
+        match_tokens(Lexer::lex(input), vec![kind, TokenKind::EOF]);
+    }
+
+    #[test_case("pi_approx" ; "prelude function")]
+    #[test_case("x_y" ; "letter after underscore")]
+    fn test_underscore_in_identifier(input: &str) {
+        let tokens = Lexer::lex(input);
+
+        assert_eq!(&tokens[0].value, input);
+        match_tokens(tokens, vec![TokenKind::Identifier, TokenKind::EOF]);
+    }
+
+    #[test]
+    fn test_last_answer() {
+        let tokens = Lexer::lex("_*1_000 x_1");
+
+        assert_eq!(&tokens[0].value, "_");
+        assert_eq!(&tokens[2].value, "1000");
+        assert_eq!(&tokens[3].value, "x_1");
+        match_tokens(
+            tokens,
+            vec![
+                TokenKind::Identifier,
+                TokenKind::Star,
+                TokenKind::Literal,
+                TokenKind::Identifier,
+                TokenKind::EOF,
+            ],
+        );
+    }
+
+    #[test_case("1_")]
+    #[test_case("1_.5" ; "before decimal point")]
+    #[test_case("1._5" ; "after decimal point")]
+    fn test_number_literal_invalid_separator(input: &str) {
+        let tokens = Lexer::lex(input);
+
+        assert_eq!(tokens[0].kind, TokenKind::Unknown);
+    }
+
+    #[test_case("$" ; "dollar sign")]
+    #[test_case("@" ; "at sign")]
+    #[test_case("?" ; "question mark")]
+    #[test_case("." ; "lone decimal point")]
+    fn test_invalid_character(input: &str) {
+        let tokens = Lexer::lex(input);
+
+        assert_eq!(&tokens[0].value, input);
+        match_tokens(tokens, vec![TokenKind::Unknown, TokenKind::EOF]);
+    }
+
+    #[test_case("1 + 2 # three", vec![TokenKind::Literal, TokenKind::Plus, TokenKind::Literal, TokenKind::EOF] ; "line comment")]
+    #[test_case("x # comment\ny", vec![TokenKind::Identifier, TokenKind::Identifier, TokenKind::EOF] ; "line comment before newline")]
+    #[test_case("1 /* one * two */ + 2", vec![TokenKind::Literal, TokenKind::Plus, TokenKind::Literal, TokenKind::EOF] ; "block comment")]
+    #[test_case("1 /* unterminated", vec![TokenKind::Literal, TokenKind::Unknown, TokenKind::EOF] ; "unterminated block comment")]
+    #[test_case("1 /* ends with a star *", vec![TokenKind::Literal, TokenKind::Unknown, TokenKind::EOF] ; "unterminated block comment ending with a star")]
+    #[test_case("6 // 4", vec![TokenKind::Literal, TokenKind::DoubleSlash, TokenKind::Literal, TokenKind::EOF] ; "integer division")]
+    fn test_comments(input: &str, expected: Vec<TokenKind>) {
+        match_tokens(Lexer::lex(input), expected);
+    }
+
+    #[test_case(" " ; "space")]
+    #[test_case("  \t" ; "tab")]
+    fn test_only_whitespace(input: &str) {
+        match_tokens(Lexer::lex(input), vec![TokenKind::EOF]);
+    }
+
+    #[test_case("1\u{a0}+\u{a0}2")] // Non-breaking space
+    #[test_case("1\u{2009}+\u{2009}2")] // Thin space
+    #[test_case("\u{202f}1+\u{202f}2\u{202f}")] // Narrow no-break space
+    fn test_unicode_whitespace(input: &str) {
+        let tokens = Lexer::lex(input);
+        let expected = vec![
+            TokenKind::Literal,
+            TokenKind::Plus,
+            TokenKind::Literal,
+            TokenKind::EOF,
+        ];
+
+        assert_eq!(&tokens[0].value, "1");
+        assert_eq!(&tokens[2].value, "2");
+        match_tokens(tokens, expected);
+    }
+
+    #[test]
+    fn test_span_byte_offsets() {
+        let input = "π + ⌈2⌉";
+        let tokens = Lexer::lex(input);
+        let spans: Vec<(usize, usize)> = tokens.iter().map(|token| token.span).collect();
+
+        assert_eq!(
+            spans,
+            vec![(0, 2), (3, 4), (5, 8), (8, 9), (9, 12), (12, 12)]
+        );
+        assert_eq!(&input[tokens[2].span.0..tokens[2].span.1], "⌈");
+    }
+
     #[test_case("x")]
     #[test_case("xy")]
     fn test_identifier(input: &str) {
@@ -272,4 +885,35 @@ mod tests {
 
         match_tokens(tokens, expected);
     }
+
+    #[test]
+    fn test_token_cache() {
+        let mut cache = TokenCache::new(2);
+        let tokens = cache.lex("2sin(x) + 3");
+
+        assert_eq!(cache.entries.len(), 1);
+        assert_eq!(cache.lex("2sin(x) + 3"), tokens);
+        assert_eq!(tokens, Lexer::lex("2sin(x) + 3"));
+    }
+
+    #[test]
+    fn test_token_cache_capacity() {
+        let mut cache = TokenCache::new(2);
+        cache.lex("1");
+        cache.lex("2");
+        cache.lex("3");
+
+        assert_eq!(cache.entries.len(), 2);
+        assert!(!cache.entries.contains_key("1"));
+
+        // "2" was used less recently than "3".
+        cache.lex("3");
+        cache.lex("4");
+        assert!(cache.entries.contains_key("3"));
+        assert!(!cache.entries.contains_key("2"));
+
+        cache.set_capacity(0);
+        cache.lex("4");
+        assert!(cache.entries.is_empty());
+    }
 }
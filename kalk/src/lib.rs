@@ -1,7 +1,31 @@
+#![cfg_attr(test, allow(clippy::unused_unit))] // Triggered by the code generated by `test_case`.
 pub mod ast;
+#[cfg(feature = "cache")]
+mod cache;
+mod calculus;
+mod complex;
+mod diagnostic;
+mod exact;
+mod explain;
+pub mod hooks;
 mod interpreter;
+mod interval;
 mod lexer;
+mod linalg;
+mod macros;
+pub mod output;
 pub mod parser;
 mod prelude;
+#[cfg(feature = "profiling")]
+pub mod profile;
+mod recognize;
+pub mod simplify;
+mod solver;
 mod symbol_table;
 mod test_helpers;
+mod types;
+
+pub use ast::{Expr, Stmt};
+pub use interpreter::Value;
+pub use lexer::{Span, TokenKind};
+pub use symbol_table::{SymbolTable, SymbolTableIter};
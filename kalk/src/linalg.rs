@@ -0,0 +1,306 @@
+use crate::parser::CalcError;
+use rug::float::Special;
+use rug::Float;
+
+/// A matrix, as a list of rows that all have the same length.
+pub type Matrix = Vec<Vec<Float>>;
+
+/// The amount of rows and columns in the matrix.
+pub fn size(matrix: &[Vec<Float>]) -> (usize, usize) {
+    (matrix.len(), matrix.first().map_or(0, |row| row.len()))
+}
+
+pub fn identity(precision: u32, size: usize) -> Matrix {
+    (0..size)
+        .map(|i| {
+            (0..size)
+                .map(|j| Float::with_val(precision, (i == j) as u32))
+                .collect()
+        })
+        .collect()
+}
+
+pub fn transpose(matrix: &[Vec<Float>]) -> Matrix {
+    let (rows, columns) = size(matrix);
+
+    (0..columns)
+        .map(|j| (0..rows).map(|i| matrix[i][j].clone()).collect())
+        .collect()
+}
+
+/// Multiply two matrices, where the amount of columns in the left one
+/// has to be the same as the amount of rows in the right one.
+pub fn multiply(
+    precision: u32,
+    left: &[Vec<Float>],
+    right: &[Vec<Float>],
+) -> Result<Matrix, CalcError> {
+    let (rows, inner) = size(left);
+    let (right_rows, columns) = size(right);
+    if inner != right_rows {
+        return Err(CalcError::Message(format!(
+            "Can't multiply a {}×{} matrix by a {}×{} matrix.",
+            rows, inner, right_rows, columns
+        )));
+    }
+
+    Ok(left
+        .iter()
+        .map(|row| {
+            (0..columns)
+                .map(|j| {
+                    row.iter()
+                        .zip(right)
+                        .fold(Float::with_val(precision, 0), |sum, (x, right_row)| {
+                            sum + Float::with_val(precision, x * &right_row[j])
+                        })
+                })
+                .collect()
+        })
+        .collect())
+}
+
+/// Calculate the determinant with Gaussian elimination. It is NaN if any of the elements are.
+pub fn determinant(precision: u32, matrix: &[Vec<Float>]) -> Result<Float, CalcError> {
+    let size = square_size(matrix)?;
+    if contains_nan(matrix) {
+        return Ok(Float::with_val(precision, Special::Nan));
+    }
+
+    let mut rows = matrix.to_vec();
+    let mut determinant = Float::with_val(precision, 1);
+    for i in 0..size {
+        let pivot = match find_pivot(&rows, i) {
+            Some(pivot) => pivot,
+            None => return Ok(Float::with_val(precision, 0)),
+        };
+        if pivot != i {
+            rows.swap(pivot, i);
+            determinant = -determinant;
+        }
+
+        determinant *= &rows[i][i];
+        let pivot_row = rows[i].clone();
+        for row in rows.iter_mut().skip(i + 1) {
+            let factor = Float::with_val(precision, &row[i] / &pivot_row[i]);
+            for (x, pivot_x) in row.iter_mut().zip(&pivot_row).skip(i) {
+                *x -= Float::with_val(precision, &factor * pivot_x);
+            }
+        }
+    }
+
+    Ok(determinant)
+}
+
+/// Calculate the inverse with Gauss-Jordan elimination. Every element is NaN if any of the elements are.
+pub fn inverse(precision: u32, matrix: &[Vec<Float>]) -> Result<Matrix, CalcError> {
+    let size = square_size(matrix)?;
+    if contains_nan(matrix) {
+        return Ok(vec![
+            vec![Float::with_val(precision, Special::Nan); size];
+            size
+        ]);
+    }
+
+    let mut rows = matrix.to_vec();
+    let mut inverse = identity(precision, size);
+    for i in 0..size {
+        let pivot = find_pivot(&rows, i)
+            .ok_or_else(|| CalcError::Message(String::from("The matrix isn't invertible.")))?;
+        rows.swap(pivot, i);
+        inverse.swap(pivot, i);
+
+        let divisor = rows[i][i].clone();
+        for x in rows[i].iter_mut().chain(inverse[i].iter_mut()) {
+            *x /= &divisor;
+        }
+
+        let (pivot_row, pivot_inverse_row) = (rows[i].clone(), inverse[i].clone());
+        for j in (0..size).filter(|j| *j != i) {
+            let factor = rows[j][i].clone();
+            for (x, pivot_x) in rows[j].iter_mut().zip(&pivot_row) {
+                *x -= Float::with_val(precision, &factor * pivot_x);
+            }
+            for (x, pivot_x) in inverse[j].iter_mut().zip(&pivot_inverse_row) {
+                *x -= Float::with_val(precision, &factor * pivot_x);
+            }
+        }
+    }
+
+    Ok(inverse)
+}
+
+/// Multiply the matrix by itself `exponent` times. A negative exponent gives a power of the inverse.
+/// The exponent has to be an integer that fits in 32 bits, not counting the sign.
+pub fn power(precision: u32, matrix: &[Vec<Float>], exponent: &Float) -> Result<Matrix, CalcError> {
+    let size = square_size(matrix)?;
+    if !exponent.is_integer() {
+        return Err(CalcError::Message(String::from(
+            "A matrix can only be raised to an integer power.",
+        )));
+    }
+
+    let mut remaining = exponent
+        .to_integer()
+        .and_then(|exponent| exponent.abs().to_u32())
+        .ok_or_else(|| {
+            CalcError::Message(format!(
+                "A matrix can't be raised to the power of {}, since it is too large.",
+                exponent
+            ))
+        })?;
+    let mut base = if exponent.is_sign_negative() {
+        inverse(precision, matrix)?
+    } else {
+        matrix.to_vec()
+    };
+    let mut result = identity(precision, size);
+
+    // Exponentiation by squaring.
+    while remaining > 0 {
+        if remaining % 2 == 1 {
+            result = multiply(precision, &result, &base)?;
+        }
+
+        remaining /= 2;
+        if remaining > 0 {
+            base = multiply(precision, &base, &base)?;
+        }
+    }
+
+    Ok(result)
+}
+
+fn square_size(matrix: &[Vec<Float>]) -> Result<usize, CalcError> {
+    match size(matrix) {
+        (rows, columns) if rows == columns => Ok(rows),
+        (rows, columns) => Err(CalcError::Message(format!(
+            "Expected a square matrix, but got a {}×{} matrix.",
+            rows, columns
+        ))),
+    }
+}
+
+fn contains_nan(matrix: &[Vec<Float>]) -> bool {
+    matrix.iter().flatten().any(Float::is_nan)
+}
+
+/// Find the row at or below `column` with the largest element in the column, if it isn't zero.
+/// Elements that are NaN, eg. from `inf - inf` during the elimination, are skipped.
+fn find_pivot(rows: &[Vec<Float>], column: usize) -> Option<usize> {
+    let (index, row) = rows
+        .iter()
+        .enumerate()
+        .skip(column)
+        .filter(|(_, row)| !row[column].is_nan())
+        .max_by(|(_, a), (_, b)| a[column].cmp_abs(&b[column]).unwrap())?;
+
+    if row[column].is_zero() {
+        None
+    } else {
+        Some(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(rows: &[&[f64]]) -> Matrix {
+        rows.iter()
+            .map(|row| row.iter().map(|x| Float::with_val(53, *x)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_multiply() {
+        let a = matrix(&[&[1f64, 2f64], &[3f64, 4f64]]);
+        let b = matrix(&[&[5f64], &[6f64]]);
+
+        assert_eq!(multiply(53, &a, &b).unwrap(), matrix(&[&[17f64], &[39f64]]));
+        assert_eq!(
+            multiply(53, &b, &a),
+            Err(CalcError::Message(String::from(
+                "Can't multiply a 2×1 matrix by a 2×2 matrix."
+            )))
+        );
+    }
+
+    #[test]
+    fn test_transpose() {
+        let a = matrix(&[&[1f64, 2f64, 3f64]]);
+
+        assert_eq!(transpose(&a), matrix(&[&[1f64], &[2f64], &[3f64]]));
+    }
+
+    #[test]
+    fn test_determinant() {
+        let a = matrix(&[
+            &[0f64, 2f64, 1f64],
+            &[1f64, 1f64, 0f64],
+            &[2f64, 0f64, 3f64],
+        ]);
+        let singular = matrix(&[&[1f64, 2f64], &[2f64, 4f64]]);
+
+        assert_eq!(determinant(53, &a).unwrap(), -8f64);
+        assert_eq!(determinant(53, &singular).unwrap(), 0f64);
+        assert!(determinant(53, &matrix(&[&[1f64, 2f64]])).is_err());
+    }
+
+    #[test]
+    fn test_inverse() {
+        let a = matrix(&[&[2f64, 1f64], &[1f64, 1f64]]);
+        let singular = matrix(&[&[1f64, 2f64], &[2f64, 4f64]]);
+
+        assert_eq!(
+            inverse(53, &a).unwrap(),
+            matrix(&[&[1f64, -1f64], &[-1f64, 2f64]])
+        );
+        assert_eq!(
+            inverse(53, &singular),
+            Err(CalcError::Message(String::from(
+                "The matrix isn't invertible."
+            )))
+        );
+    }
+
+    #[test]
+    fn test_nan() {
+        let a = matrix(&[&[f64::NAN, 1f64], &[1f64, 1f64]]);
+
+        assert!(determinant(53, &a).unwrap().is_nan());
+        assert!(inverse(53, &a).unwrap().iter().flatten().all(Float::is_nan));
+    }
+
+    #[test]
+    fn test_power() {
+        let a = matrix(&[&[1f64, 1f64], &[1f64, 0f64]]);
+        let exponent = |x: f64| Float::with_val(53, x);
+
+        assert_eq!(
+            power(53, &a, &exponent(10f64)).unwrap(),
+            matrix(&[&[89f64, 55f64], &[55f64, 34f64]])
+        );
+        assert_eq!(power(53, &a, &exponent(0f64)).unwrap(), identity(53, 2));
+        assert_eq!(
+            power(53, &a, &exponent(-1f64)).unwrap(),
+            matrix(&[&[0f64, 1f64], &[1f64, -1f64]])
+        );
+        assert!(power(53, &a, &exponent(0.5)).is_err());
+        assert!(power(53, &a, &exponent(1e10)).is_err());
+        assert!(power(53, &a, &exponent(-1e10)).is_err());
+        assert!(power(53, &a, &exponent(f64::INFINITY)).is_err());
+    }
+
+    #[test]
+    fn test_power_not_square() {
+        let a = matrix(&[&[1f64, 2f64, 3f64], &[4f64, 5f64, 6f64]]);
+
+        assert_eq!(
+            power(53, &a, &Float::with_val(53, 2)),
+            Err(CalcError::Message(String::from(
+                "Expected a square matrix, but got a 2×3 matrix."
+            )))
+        );
+    }
+}
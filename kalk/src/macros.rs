@@ -0,0 +1,124 @@
+use crate::parser::CalcError;
+use std::collections::HashMap;
+
+/// Expanding the macros may make the input at most this many bytes longer, since a few macros can expand
+/// to exponentially long input, eg. when `B` is `A A`, `C` is `B B` and so on.
+const MAX_EXPANSION_LENGTH: usize = 1 << 16;
+
+/// Replace the names of macros in the input with their expansions, until there is nothing left to expand.
+///
+/// A name is only replaced if it isn't part of a longer name, eg. `SQ` in `SQRT`.
+/// `CalcError::CircularDependency` is returned if a macro expands to itself, directly or through other macros,
+/// and `CalcError::Message` if the expansion gets too long.
+pub fn expand(input: &str, macros: &HashMap<String, String>) -> Result<String, CalcError> {
+    let mut output = String::with_capacity(input.len());
+    let max_length = input.len() + MAX_EXPANSION_LENGTH;
+    expand_into(&mut output, input, macros, &mut Vec::new(), max_length)?;
+
+    Ok(output)
+}
+
+/// Append the input to the output with its macros expanded. Like in the C preprocessor, `active` contains the macros
+/// that are currently being expanded, so that a macro referring to itself is noticed as soon as it happens.
+fn expand_into<'a>(
+    output: &mut String,
+    input: &str,
+    macros: &'a HashMap<String, String>,
+    active: &mut Vec<&'a str>,
+    max_length: usize,
+) -> Result<(), CalcError> {
+    let mut rest = input;
+
+    while let Some((pos, name)) = find_next(rest, macros) {
+        output.push_str(&rest[..pos]);
+        if active.contains(&name.as_str()) {
+            return Err(CalcError::CircularDependency(name.clone()));
+        }
+
+        active.push(name);
+        expand_into(output, &macros[name], macros, active, max_length)?;
+        active.pop();
+
+        if output.len() > max_length {
+            return Err(CalcError::Message(format!(
+                "The expansion of the macro '{}' is too long.",
+                name
+            )));
+        }
+        rest = &rest[pos + name.len()..];
+    }
+    output.push_str(rest);
+
+    Ok(())
+}
+
+/// Find the first macro name in the input that isn't part of a longer name.
+fn find_next<'a>(input: &str, macros: &'a HashMap<String, String>) -> Option<(usize, &'a String)> {
+    macros
+        .keys()
+        .filter_map(|name| {
+            input
+                .match_indices(name.as_str())
+                .find(|(pos, _)| is_separate(input, *pos, name.len()))
+                .map(|(pos, _)| (pos, name))
+        })
+        // Prefer the longest name if several start at the same position.
+        .min_by_key(|(pos, name)| (*pos, usize::MAX - name.len()))
+}
+
+fn is_separate(input: &str, pos: usize, len: usize) -> bool {
+    // Digits are allowed before the name, so that eg. `2SQ` works like `2x`.
+    let before = input[..pos].chars().next_back();
+    let after = input[pos + len..].chars().next();
+
+    !matches!(before, Some(c) if c.is_alphabetic() || c == '_')
+        && !matches!(after, Some(c) if c.is_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn macros(definitions: &[(&str, &str)]) -> HashMap<String, String> {
+        definitions
+            .iter()
+            .map(|(name, expansion)| (name.to_string(), expansion.to_string()))
+            .collect()
+    }
+
+    #[test_case("x SQ", "x ^2")]
+    #[test_case("2SQ + SQRT", "2^2 + SQRT")]
+    #[test_case("SQ4", "SQ4" ; "part of a longer name")]
+    #[test_case("x FOURTH", "x ^2^2" ; "recursive")]
+    fn test_expand(input: &str, expected: &str) {
+        let macros = macros(&[("SQ", "^2"), ("FOURTH", "SQSQ"), ("SQSQ", "^2^2")]);
+
+        assert_eq!(expand(input, &macros).unwrap(), expected);
+    }
+
+    #[test_case(&[("A", "A A")] ; "self-referential")]
+    #[test_case(&[("A", "B"), ("B", "A")] ; "mutually recursive")]
+    #[test_case(&[("A", "1 + B"), ("B", "C"), ("C", "2A")] ; "indirectly recursive")]
+    fn test_circular(definitions: &[(&str, &str)]) {
+        let macros = macros(definitions);
+
+        assert!(matches!(
+            expand("x A", &macros),
+            Err(CalcError::CircularDependency(name)) if macros.contains_key(&name)
+        ));
+    }
+
+    #[test]
+    fn test_too_long() {
+        // Every macro expands to two of the next one, so `M0` would be 2^20 `x`'s.
+        let mut definitions: Vec<(String, String)> = (0..20)
+            .map(|i| (format!("M{}", i), format!("M{} M{}", i + 1, i + 1)))
+            .collect();
+        definitions.push((String::from("M20"), String::from("x")));
+        let macros = definitions.into_iter().collect();
+
+        assert!(matches!(expand("M0", &macros), Err(CalcError::Message(_))));
+        assert!(expand("M10", &macros).is_ok());
+    }
+}
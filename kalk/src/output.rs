@@ -0,0 +1,241 @@
+use rug::Float;
+
+/// The amount of decimal digits per bit of precision, log10(2).
+const DIGITS_PER_BIT: f64 = std::f64::consts::LOG10_2;
+
+/// How the exponent of a number is chosen when formatting it, see `FormatOptions::set_notation`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Notation {
+    /// Fixed notation, unless the number is very large or very small, eg. `123.45` and `1.5*10^12`.
+    Auto,
+    /// Never an exponent, eg. `1500000000000`.
+    Fixed,
+    /// One digit before the decimal point, eg. `1.2345*10^2`.
+    Scientific,
+    /// An exponent that is a multiple of 3, eg. `123.45` and `1.5*10^12`.
+    Engineering,
+}
+
+/// Options for formatting answers as text, eg. with `KalkValue::format`.
+///
+/// ```
+/// use kalk::output::{FormatOptions, Notation};
+/// use rug::Float;
+///
+/// let options = FormatOptions::new()
+///     .set_significant_digits(3)
+///     .set_notation(Notation::Scientific);
+/// assert_eq!(options.format_number(&Float::with_val(53, 1234.5)), "1.23*10^3");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatOptions {
+    significant_digits: Option<usize>,
+    notation: Notation,
+    digit_separator: Option<char>,
+    trim_trailing_zeros: bool,
+}
+
+impl FormatOptions {
+    pub fn new() -> Self {
+        FormatOptions {
+            significant_digits: None,
+            notation: Notation::Auto,
+            digit_separator: None,
+            trim_trailing_zeros: true,
+        }
+    }
+
+    /// Round numbers to this many significant digits (at least 1).
+    /// By default, numbers get as many digits as their precision can represent, eg. 15 for 53 bits.
+    pub fn set_significant_digits(mut self, digits: usize) -> Self {
+        self.significant_digits = Some(digits.max(1));
+
+        self
+    }
+
+    /// The default is `Notation::Auto`.
+    pub fn set_notation(mut self, notation: Notation) -> Self {
+        self.notation = notation;
+
+        self
+    }
+
+    /// Separate the digits before the decimal point into groups of three, eg. `1 000 000` with a space.
+    /// This is disabled by default.
+    pub fn set_digit_separator(mut self, separator: char) -> Self {
+        self.digit_separator = Some(separator);
+
+        self
+    }
+
+    /// Remove zeros at the end of the decimals, eg. `1.500` becomes `1.5` and `2.000` becomes `2`.
+    /// This is enabled by default.
+    pub fn set_trim_trailing_zeros(mut self, enabled: bool) -> Self {
+        self.trim_trailing_zeros = enabled;
+
+        self
+    }
+
+    /// Format a number, with the exponent written as `*10^`, eg. `1.5*10^12`.
+    pub fn format_number(&self, value: &Float) -> String {
+        if value.is_nan() {
+            return String::from("NaN");
+        }
+
+        if value.is_infinite() {
+            return String::from(if value.is_sign_negative() {
+                "-∞"
+            } else {
+                "∞"
+            });
+        }
+
+        let significant_digits = self
+            .significant_digits
+            .unwrap_or_else(|| ((value.prec() as f64 * DIGITS_PER_BIT) as usize).max(1));
+        let (negative, digits, exp) = value.to_sign_string_exp(10, Some(significant_digits));
+        let sign = if negative && !value.is_zero() {
+            "-"
+        } else {
+            ""
+        };
+
+        // The value is 0.digits * 10^exp, so the exponent with one digit before the decimal point is exp - 1.
+        let exponent = if value.is_zero() {
+            0
+        } else {
+            exp.unwrap_or_default() - 1
+        };
+        let shown_exponent = match self.notation {
+            Notation::Fixed => 0,
+            Notation::Auto if (-7..=7).contains(&exponent) => 0,
+            Notation::Auto | Notation::Scientific => exponent,
+            Notation::Engineering => exponent.div_euclid(3) * 3,
+        };
+
+        let integer_digits = (exponent - shown_exponent + 1) as isize;
+        let number = self.place_decimal_point(&digits, integer_digits);
+        if shown_exponent == 0 {
+            format!("{}{}", sign, number)
+        } else {
+            format!("{}{}*10^{}", sign, number, shown_exponent)
+        }
+    }
+
+    /// Put the decimal point after the first `integer_digits` digits, padding with zeros if needed.
+    fn place_decimal_point(&self, digits: &str, integer_digits: isize) -> String {
+        let (integer_part, fraction_part) = if integer_digits <= 0 {
+            let zeros = "0".repeat(integer_digits.unsigned_abs());
+            (String::from("0"), format!("{}{}", zeros, digits))
+        } else if integer_digits as usize >= digits.len() {
+            let zeros = "0".repeat(integer_digits as usize - digits.len());
+            (format!("{}{}", digits, zeros), String::new())
+        } else {
+            let (integer_part, fraction_part) = digits.split_at(integer_digits as usize);
+            (integer_part.to_string(), fraction_part.to_string())
+        };
+
+        let integer_part = match self.digit_separator {
+            Some(separator) => group_digits(&integer_part, separator),
+            None => integer_part,
+        };
+        let fraction_part = if self.trim_trailing_zeros {
+            fraction_part.trim_end_matches('0')
+        } else {
+            &fraction_part
+        };
+
+        if fraction_part.is_empty() {
+            integer_part
+        } else {
+            format!("{}.{}", integer_part, fraction_part)
+        }
+    }
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Insert the separator between every group of three digits, counting from the right.
+fn group_digits(digits: &str, separator: char) -> String {
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(separator);
+        }
+
+        grouped.push(digit);
+    }
+
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(0.1, "0.1")]
+    #[test_case(-2.5, "-2.5")]
+    #[test_case(100f64, "100")]
+    #[test_case(0f64, "0")]
+    #[test_case(1e20, "1*10^20")]
+    #[test_case(123456789012f64, "1.23456789012*10^11")]
+    #[test_case(0.00000001234, "1.234*10^-8")]
+    #[test_case(0.000001234, "0.000001234")]
+    #[test_case(f64::INFINITY, "∞")]
+    fn test_format_default(value: f64, expected: &str) {
+        let options = FormatOptions::new();
+
+        assert_eq!(options.format_number(&Float::with_val(53, value)), expected);
+    }
+
+    #[test_case(Notation::Fixed, 1.5e12, "1500000000000")]
+    #[test_case(Notation::Fixed, 1.5e-9, "0.0000000015" ; "fixed small")]
+    #[test_case(Notation::Scientific, 123.45, "1.2345*10^2")]
+    #[test_case(Notation::Scientific, 1f64, "1" ; "scientific without exponent")]
+    #[test_case(Notation::Engineering, 123450f64, "123.45*10^3")]
+    #[test_case(Notation::Engineering, 0.0015, "1.5*10^-3" ; "engineering small")]
+    fn test_notation(notation: Notation, value: f64, expected: &str) {
+        let options = FormatOptions::new().set_notation(notation);
+
+        assert_eq!(options.format_number(&Float::with_val(53, value)), expected);
+    }
+
+    #[test]
+    fn test_significant_digits() {
+        let options = FormatOptions::new().set_significant_digits(3);
+
+        assert_eq!(
+            options.format_number(&Float::with_val(53, 2f64 / 3f64)),
+            "0.667"
+        );
+        assert_eq!(options.format_number(&Float::with_val(53, 9.996)), "10");
+        assert_eq!(
+            options.format_number(&Float::with_val(53, 123456)),
+            "123000"
+        );
+    }
+
+    #[test]
+    fn test_trailing_zeros() {
+        let options = FormatOptions::new()
+            .set_significant_digits(4)
+            .set_trim_trailing_zeros(false);
+
+        assert_eq!(options.format_number(&Float::with_val(53, 1.5)), "1.500");
+        assert_eq!(options.format_number(&Float::with_val(53, 2)), "2.000");
+    }
+
+    #[test_case(1234567f64, "1,234,567")]
+    #[test_case(-123456.5, "-123,456.5")]
+    #[test_case(123f64, "123")]
+    fn test_digit_separator(value: f64, expected: &str) {
+        let options = FormatOptions::new().set_digit_separator(',');
+
+        assert_eq!(options.format_number(&Float::with_val(53, value)), expected);
+    }
+}
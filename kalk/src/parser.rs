@@ -1,10 +1,56 @@
+#[cfg(feature = "cache")]
+use crate::cache::Cache;
+#[cfg(feature = "profiling")]
+use crate::profile::{self, MemoryProfile};
 use crate::{
     ast::{Expr, Stmt},
-    interpreter,
-    lexer::{Lexer, Token, TokenKind},
+    calculus, diagnostic, exact, explain,
+    hooks::{Callback, Callbacks, EvalEvent},
+    interpreter::{self, Value},
+    interval,
+    lexer::{Lexer, Span, Token, TokenCache, TokenKind},
+    macros,
+    output::FormatOptions,
+    prelude, recognize, simplify,
     symbol_table::SymbolTable,
+    types,
 };
-use rug::Float;
+use rayon::prelude::*;
+use rug::float::Round;
+use rug::{Complex, Float, Rational};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::Arc;
+
+/// The default upper limit for the precision, in bits. This is about 300 000 decimal digits.
+pub const DEFAULT_MAX_PRECISION: u32 = 1 << 20;
+
+/// The default number of intervals that integrals are split into, see `Context::set_integration_steps`.
+pub const DEFAULT_INTEGRATION_STEPS: u32 = 1000;
+
+/// The default number of nested calls to user-defined functions that are allowed, see `Context::set_recursion_limit`.
+pub const DEFAULT_RECURSION_LIMIT: u32 = 256;
+
+/// The default base that `Context::format_value` formats integers in, see `Context::set_output_base`.
+pub const DEFAULT_OUTPUT_BASE: u32 = 10;
+
+/// Answers are only suggested to be shown as fractions if the denominator is at most this large.
+const MAX_FRACTION_DENOMINATOR: u32 = 1000;
+
+/// Words that have a meaning in the syntax, other than built-in functions and constants.
+const KEYWORDS: [&str; 9] = [
+    "unit", "to", "if", "then", "else", "and", "or", "not", "xor",
+];
+
+/// The first line of the files written by `Context::save_state`.
+const STATE_HEADER: &str =
+    "# Definitions saved by kalk, one per line, after the ones they depend on.";
+
+/// The error message of `Context::eval_n_times` when the input doesn't end in an expression.
+const NOT_AN_EXPRESSION: &str = "The input has to end in an expression to be evaluated n times.";
 
 /// Struct containing the current state of the parser. It stores user-defined functions and variables.
 /// # Examples
@@ -17,8 +63,26 @@ use rug::Float;
 pub struct Context {
     tokens: Vec<Token>,
     pos: usize,
+    /// Whether the parser is inside of `|x|`, where a pipe ends the group rather than being a bitwise or.
+    in_abs_group: bool,
     symbol_table: SymbolTable,
     angle_unit: Unit,
+    decimal_mode: bool,
+    integer_mode: bool,
+    rounding: RoundingMode,
+    auto_simplify: bool,
+    max_precision: u32,
+    /// Used instead of the precision given to the eval functions, see `Context::global_precision_guard`.
+    global_precision: Option<u32>,
+    integration_steps: u32,
+    recursion_limit: u32,
+    output_base: u32,
+    operation_count: usize,
+    token_cache: TokenCache,
+    macros: HashMap<String, String>,
+    callbacks: Callbacks,
+    #[cfg(feature = "cache")]
+    cache: Cache,
 }
 
 impl Context {
@@ -26,8 +90,24 @@ impl Context {
         Context {
             tokens: Vec::new(),
             pos: 0,
+            in_abs_group: false,
             symbol_table: SymbolTable::new(),
             angle_unit: Unit::Radians,
+            decimal_mode: false,
+            integer_mode: false,
+            rounding: RoundingMode::Nearest,
+            auto_simplify: false,
+            max_precision: DEFAULT_MAX_PRECISION,
+            global_precision: None,
+            integration_steps: DEFAULT_INTEGRATION_STEPS,
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
+            output_base: DEFAULT_OUTPUT_BASE,
+            operation_count: 0,
+            token_cache: TokenCache::new(0),
+            macros: HashMap::new(),
+            callbacks: Vec::new(),
+            #[cfg(feature = "cache")]
+            cache: Cache::new(),
         }
     }
 
@@ -36,502 +116,4859 @@ impl Context {
 
         self
     }
-}
-
-impl Default for Context {
-    fn default() -> Self {
-        Self::new()
-    }
-}
 
-/// Mathematical unit used in calculations.
-#[derive(Debug, Clone, PartialEq)]
-pub enum Unit {
-    Radians,
-    Degrees,
-}
+    /// Calculate with exact decimal numbers where possible, eg. `0.1 + 0.2` becomes exactly `0.3`
+    /// rather than the closest binary floating point number to `0.30000000000000004`.
+    /// Functions, constants and non-integer powers are still calculated with floating point numbers,
+    /// and so is arithmetic whose result would be too large to store exactly, eg. `(10^1000)^1000`.
+    /// This is disabled by default.
+    pub fn set_decimal_mode(mut self, enabled: bool) -> Self {
+        self.decimal_mode = enabled;
 
-/// Error that occured during parsing or evaluation.
-#[derive(Debug, Clone, PartialEq)]
-pub enum CalcError {
-    IncorrectAmountOfArguments(usize, String, usize),
-    InvalidNumberLiteral(String),
-    InvalidOperator,
-    InvalidUnit,
-    UnexpectedToken(TokenKind),
-    UndefinedFn(String),
-    UndefinedVar(String),
-    Unknown,
-}
+        self
+    }
 
-/// Evaluate expressions/declarations and return the answer.
-///
-/// `None` will be returned if the last statement is a declaration.
-pub fn eval(
-    context: &mut Context,
-    input: &str,
-    precision: u32,
-) -> Result<Option<Float>, CalcError> {
-    let statements = parse(context, input)?;
+    /// Only allow integers, and calculate `+ - * / ^` exactly rather than with floating point numbers.
+    /// The precision given when evaluating is the maximum amount of bits a result may have.
+    ///
+    /// `CalcError::NotDivisible` is returned for divisions that don't result in an integer, eg. `7/2`,
+    /// `CalcError::Overflow` if a result needs too many bits, and `CalcError::NotAnInteger`
+    /// for other non-integer values, eg. `sqrt(2)`. This is disabled by default.
+    pub fn set_integer_mode(mut self, enabled: bool) -> Self {
+        self.integer_mode = enabled;
 
-    let mut interpreter =
-        interpreter::Context::new(&mut context.symbol_table, &context.angle_unit, precision);
-    interpreter.interpret(statements)
-}
+        self
+    }
 
-/// Parse expressions/declarations and return a syntax tree.
-///
-/// `None` will be returned if the last statement is a declaration.
-pub fn parse(context: &mut Context, input: &str) -> Result<Vec<Stmt>, CalcError> {
-    context.tokens = Lexer::lex(input);
-    context.pos = 0;
+    /// The direction to round in when the result of an operation or a literal can't be represented exactly
+    /// at the precision. Built-in functions always round to the nearest value. This is `RoundingMode::Nearest` by default.
+    pub fn set_precision_rounding(mut self, mode: RoundingMode) -> Self {
+        self.rounding = mode;
 
-    let mut statements: Vec<Stmt> = Vec::new();
-    while !is_at_end(context) {
-        statements.push(parse_stmt(context)?);
+        self
     }
 
-    Ok(statements)
-}
+    /// Simplify the input algebraically after it has been parsed, eg. `x * 1 + 0` becomes `x`.
+    /// Only what is known to keep the same value is simplified, so eg. `x - x` is left as it is
+    /// while `x` isn't defined, and products of matrices aren't reordered. This is disabled by default.
+    pub fn auto_simplify(mut self, enable: bool) -> Self {
+        self.auto_simplify = enable;
 
-fn parse_stmt(context: &mut Context) -> Result<Stmt, CalcError> {
-    if match_token(context, TokenKind::Identifier) {
-        return Ok(match peek_next(context).kind {
-            TokenKind::Equals => parse_var_decl_stmt(context)?,
-            TokenKind::OpenParenthesis => parse_identifier_stmt(context)?,
-            _ => Stmt::Expr(Box::new(parse_expr(context)?)),
-        });
+        self
     }
 
-    Ok(Stmt::Expr(Box::new(parse_expr(context)?)))
-}
+    /// Set the highest precision (in bits) that may be used for evaluation.
+    /// Evaluating with a higher precision returns `CalcError::PrecisionTooHigh`,
+    /// instead of trying to allocate numbers that might not fit in memory.
+    /// The default is `DEFAULT_MAX_PRECISION`.
+    pub fn set_max_precision(mut self, max_precision: u32) -> Self {
+        self.max_precision = max_precision;
 
-fn parse_identifier_stmt(context: &mut Context) -> Result<Stmt, CalcError> {
-    let began_at = context.pos;
-    let primary = parse_primary(context)?; // Since function declarations and function calls look the same at first, simply parse a "function call", and re-use the data.
+        self
+    }
 
-    // If `primary` is followed by an equal sign, it is a function declaration.
-    if let TokenKind::Equals = peek(context).kind {
-        advance(context);
-        let expr = parse_expr(context)?;
+    /// Set the number of intervals that integrals, eg. `∫(0, 1, x^2)`, are split into.
+    /// More steps give a more accurate result, but take longer to evaluate.
+    /// An odd number is rounded up, since Simpson's rule needs pairs of intervals.
+    /// The default is `DEFAULT_INTEGRATION_STEPS`.
+    pub fn set_integration_steps(mut self, steps: u32) -> Self {
+        self.integration_steps = steps.max(2) + steps % 2;
 
-        // Use the "function call" expression that was parsed, and put its values into a function declaration statement instead.
-        if let Expr::FnCall(identifier, parameters) = primary {
-            let mut parameter_identifiers = Vec::new();
+        self
+    }
 
-            // All the "arguments" are expected to be parsed as variables,
-            // since parameter definitions look the same as variable references.
-            // Extract these.
-            for parameter in parameters {
-                if let Expr::Var(parameter_identifier) = parameter {
-                    parameter_identifiers.push(parameter_identifier);
-                }
-            }
+    /// Set how many calls to user-defined functions may be nested, eg. when a function calls itself.
+    /// `CalcError::RecursionLimitReached` is returned if a call would go deeper than this.
+    /// The default is `DEFAULT_RECURSION_LIMIT`.
+    pub fn set_recursion_limit(mut self, limit: u32) -> Self {
+        self.recursion_limit = limit;
 
-            let fn_decl = Stmt::FnDecl(identifier.clone(), parameter_identifiers, Box::new(expr));
+        self
+    }
 
-            // Insert the function declaration into the symbol table during parsing
-            // so that the parser can find out if particular functions exist.
-            context
-                .symbol_table
-                .insert(&format!("{}()", identifier), fn_decl.clone());
+    /// Set the base (2 to 36) that `Context::format_value` formats integers in, eg. 16 for hexadecimal.
+    /// A base outside of this range is clamped to it.
+    /// The default is `DEFAULT_OUTPUT_BASE`.
+    pub fn set_output_base(mut self, base: u32) -> Self {
+        self.output_base = base.clamp(2, 36);
 
-            return Ok(fn_decl);
-        }
+        self
+    }
 
-        Err(CalcError::Unknown)
-    } else {
-        // It is a function call, not a function declaration.
-        // Redo the parsing for this specific part.
-        context.pos = began_at;
-        Ok(Stmt::Expr(Box::new(parse_expr(context)?)))
+    pub fn output_base(&self) -> u32 {
+        self.output_base
     }
-}
 
-fn parse_var_decl_stmt(context: &mut Context) -> Result<Stmt, CalcError> {
-    let identifier = advance(context).clone();
-    advance(context); // Equal sign
-    let expr = parse_expr(context)?;
+    /// Format an integer in the output base, see `Context::set_output_base`, eg. 255 becomes `0xff` in base 16.
+    /// Base 2, 8 and 16 get the same prefix as literals in those bases, eg. `0b1010` or `0o17`.
+    ///
+    /// `None` is returned if the value isn't an integer, since only integers can be formatted in other bases.
+    pub fn format_value(&self, value: &Float) -> Option<String> {
+        if !value.is_integer() {
+            return None;
+        }
 
-    Ok(Stmt::VarDecl(identifier.value, Box::new(expr)))
-}
+        let integer = value.to_integer()?;
+        let prefix = match self.output_base {
+            2 => "0b",
+            8 => "0o",
+            16 => "0x",
+            _ => "",
+        };
+        let sign = if integer < 0 { "-" } else { "" };
+        let digits = integer.abs().to_string_radix(self.output_base as i32);
 
-fn parse_expr(context: &mut Context) -> Result<Expr, CalcError> {
-    Ok(parse_sum(context)?)
-}
+        Some(format!("{}{}{}", sign, prefix, digits))
+    }
 
-fn parse_sum(context: &mut Context) -> Result<Expr, CalcError> {
-    let mut left = parse_factor(context)?;
+    /// Remember the tokens of the `size` most recently lexed inputs,
+    /// so that they don't need to be lexed again when the same input is evaluated again.
+    /// This is disabled (0) by default.
+    pub fn set_lexer_cache_size(mut self, size: usize) -> Self {
+        self.token_cache.set_capacity(size);
 
-    while match_token(context, TokenKind::Plus) || match_token(context, TokenKind::Minus) {
-        let op = peek(context).kind.clone();
-        advance(context);
-        let right = parse_factor(context)?;
+        self
+    }
 
-        left = Expr::Binary(Box::new(left), op, Box::new(right));
+    /// Define a macro, which is replaced by its expansion in the input before it is parsed,
+    /// eg. after `macro_define("SQ", "^2")`, `3 SQ` becomes `3 ^2`.
+    /// Unlike functions, macros are plain text substitutions, and may expand to other macros.
+    pub fn macro_define(&mut self, name: &str, expansion: &str) {
+        self.macros.insert(name.into(), expansion.into());
     }
 
-    Ok(left)
-}
+    /// Call `f` whenever `event` happens during evaluation, eg. for profiling or logging.
+    /// An empty name in the event means that `f` is called for every name,
+    /// eg. `EvalEvent::BeforeFnCall(String::new())` for every function call.
+    /// If `f` returns an error, the evaluation stops with that error, eg. to block calls to a function.
+    pub fn register_callback(&mut self, event: EvalEvent, f: Callback) {
+        self.callbacks.push((event, f.into()));
+    }
 
-fn parse_factor(context: &mut Context) -> Result<Expr, CalcError> {
-    let mut left = parse_unary(context)?;
+    pub fn unregister_all_callbacks(&mut self) {
+        self.callbacks.clear();
+    }
 
-    while match_token(context, TokenKind::Star)
-        || match_token(context, TokenKind::Slash)
-        || match_token(context, TokenKind::Identifier)
-        || match_token(context, TokenKind::Literal)
+    /// Make a function implemented in Rust callable from expressions, eg. to look something up in a database.
+    /// It is given the arguments as numbers, and replaces any user-defined function with the same name.
+    ///
+    /// ```
+    /// use kalk::parser::{self, CalcError, Context};
+    /// use rug::Float;
+    ///
+    /// let mut context = Context::new();
+    /// context
+    ///     .register_native_fn("lookup", |args: &[Float]| match args {
+    ///         [id] if *id == 1 => Ok(Float::with_val(53, 42)),
+    ///         [id] => Err(CalcError::Message(format!("No row with the id {}.", id))),
+    ///         _ => Err(CalcError::IncorrectAmountOfArguments(1, "lookup".into(), args.len())),
+    ///     })
+    ///     .unwrap();
+    ///
+    /// assert_eq!(parser::eval(&mut context, "lookup(1) + 1", 53).unwrap().unwrap(), 43);
+    /// ```
+    ///
+    /// Native functions can't be saved by `save_state`, and only take real numbers,
+    /// so they can't be used by `eval_complex` or with vectors.
+    pub fn register_native_fn<F>(&mut self, name: &str, f: F) -> Result<(), CalcError>
+    where
+        F: Fn(&[Float]) -> Result<Float, CalcError> + Send + Sync + 'static,
     {
-        // If the next token is an identifier, assume it's multiplication. Eg. 3y
-        let op = match peek(context).kind {
-            TokenKind::Identifier | TokenKind::Literal => TokenKind::Star,
-            _ => advance(context).kind.clone(),
-        };
+        check_name(name)?;
+        if prelude::DOCS.contains_key(name)
+            || prelude::UNARY_FUNCS.contains_key(name)
+            || prelude::BINARY_FUNCS.contains_key(name)
+        {
+            return Err(CalcError::Message(format!(
+                "'{}' is a built-in function.",
+                name
+            )));
+        }
 
-        let right = parse_unary(context)?;
-        left = Expr::Binary(Box::new(left), op, Box::new(right));
-    }
+        self.symbol_table.insert_native_fn(name, Arc::new(f));
 
-    Ok(left)
-}
+        Ok(())
+    }
 
-fn parse_unary(context: &mut Context) -> Result<Expr, CalcError> {
-    if match_token(context, TokenKind::Minus) {
-        let op = advance(context).kind.clone();
-        let expr = Box::new(parse_unary(context)?);
-        return Ok(Expr::Unary(op, expr));
+    /// The user-defined variables and functions, eg. to see how many there are.
+    pub fn symbol_table(&self) -> &SymbolTable {
+        &self.symbol_table
     }
 
-    Ok(parse_exponent(context)?)
-}
+    /// Parse the input and return its syntax tree, without evaluating it.
+    ///
+    /// The input is parsed with a copy of the context, so the functions and units it declares aren't added to the context.
+    /// They are still known in the rest of the input, eg. `f(3)` is a call in `f(x) = 2x; f(3)`.
+    pub fn get_ast(&self, input: &str) -> Result<Vec<Stmt>, CalcError> {
+        parse(&mut self.snapshot(), input)
+    }
 
-fn parse_exponent(context: &mut Context) -> Result<Expr, CalcError> {
-    let left = parse_factorial(context)?;
+    /// Evaluate an expression that has already been parsed, eg. by `get_ast`, or built by hand.
+    pub fn evaluate_expr(&mut self, expr: &Expr, precision: u32) -> Result<Float, CalcError> {
+        let mut interpreter = self.interpreter(precision)?;
+        let result = interpreter.interpret(vec![Stmt::Expr(Box::new(expr.clone()))]);
+        self.operation_count = interpreter.operation_count();
 
-    if match_token(context, TokenKind::Power) {
-        let op = advance(context).kind.clone();
-        let right = Box::new(parse_exponent(context)?);
-        return Ok(Expr::Binary(Box::new(left), op, right));
+        // The statement is an expression, so it always has a value.
+        result.map(|value| value.unwrap())
     }
 
-    Ok(left)
-}
-
-fn parse_factorial(context: &mut Context) -> Result<Expr, CalcError> {
-    let expr = parse_primary(context)?;
+    /// Evaluate statements that have already been parsed, eg. by `parser::parse`, like `eval_value` does.
+    /// `None` will be returned if the last statement is a declaration.
+    pub fn evaluate_statements(
+        &mut self,
+        statements: Vec<Stmt>,
+        precision: u32,
+    ) -> Result<Option<Value>, CalcError> {
+        let mut interpreter = self.interpreter(precision)?;
+        let result = interpreter.interpret_value(statements);
+        self.operation_count = interpreter.operation_count();
 
-    Ok(if match_token(context, TokenKind::Exclamation) {
-        advance(context);
-        Expr::Unary(TokenKind::Exclamation, Box::new(expr))
-    } else {
-        expr
-    })
-}
+        result
+    }
 
-fn parse_primary(context: &mut Context) -> Result<Expr, CalcError> {
-    let expr = match peek(context).kind {
-        TokenKind::OpenParenthesis => parse_group(context)?,
-        TokenKind::Pipe | TokenKind::OpenCeil | TokenKind::OpenFloor => parse_group_fn(context)?,
-        TokenKind::Identifier => parse_identifier(context)?,
-        _ => Expr::Literal(advance(context).value.clone()),
-    };
+    /// Parse the input and return how deeply nested its syntax tree is, eg. 1 for `1` and 2 for `1 + 2`.
+    ///
+    /// The deepest statement is used if the input contains several of them.
+    /// The input is parsed with a copy of the context, so the functions and units it declares aren't added to the context.
+    pub fn ast_depth(&self, input: &str) -> Result<usize, CalcError> {
+        let depth = parse(&mut self.snapshot(), input)?
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::VarDecl(_, expr) | Stmt::FnDecl(_, _, expr) | Stmt::Expr(expr) => {
+                    expr_depth(expr)
+                }
+                Stmt::UnitDecl(_, definition) => definition.as_deref().map_or(0, expr_depth),
+            })
+            .max()
+            .unwrap_or(0);
 
-    if !is_at_end(context) && peek(context).kind.is_unit() {
-        Ok(Expr::Unit(Box::new(expr), advance(context).kind.clone()))
-    } else {
-        Ok(expr)
+        Ok(depth)
     }
-}
 
-fn parse_group(context: &mut Context) -> Result<Expr, CalcError> {
-    advance(context);
-    let group_expr = Expr::Group(Box::new(parse_expr(context)?));
-    consume(context, TokenKind::ClosedParenthesis)?;
+    /// Parse the input and return the number of expression nodes in its syntax tree,
+    /// as a measure of how complex it is. Eg. `1 + 2` has three: the two literals and the sum.
+    /// The input is parsed with a copy of the context, so the functions and units it declares aren't added to the context.
+    pub fn count_ast_nodes(&self, input: &str) -> Result<usize, CalcError> {
+        let count = parse(&mut self.snapshot(), input)?
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::VarDecl(_, expr) | Stmt::FnDecl(_, _, expr) | Stmt::Expr(expr) => {
+                    count_expr_nodes(expr)
+                }
+                Stmt::UnitDecl(_, definition) => definition.as_deref().map_or(0, count_expr_nodes),
+            })
+            .sum();
 
-    Ok(group_expr)
-}
+        Ok(count)
+    }
 
-fn parse_group_fn(context: &mut Context) -> Result<Expr, CalcError> {
-    let name = match &advance(context).kind {
-        TokenKind::Pipe => "abs",
-        TokenKind::OpenCeil => "ceil",
-        TokenKind::OpenFloor => "floor",
-        _ => panic!("Unexpected parsing error."),
-    };
+    /// Define a variable for each of the given names and values, eg. `import_variables(&[("a".into(), 1.5)])`.
+    ///
+    /// The values are stored as the shortest decimal number that represents the `f64`,
+    /// and evaluated at the precision that is used when evaluating.
+    ///
+    /// Returns `CalcError::InvalidNumberLiteral` if any of the values isn't finite, like `define_var`.
+    /// Nothing is defined in that case.
+    pub fn import_variables(&mut self, vars: &[(String, f64)]) -> Result<&mut Self, CalcError> {
+        if let Some((_, value)) = vars.iter().find(|(_, value)| !value.is_finite()) {
+            return Err(CalcError::InvalidNumberLiteral(value.to_string()));
+        }
 
-    let expr = parse_expr(context)?;
-    advance(context);
+        for (identifier, value) in vars {
+            let literal = Box::new(Expr::Literal(value.abs().to_string()));
+            let expr = if value.is_sign_negative() {
+                Box::new(Expr::Unary(TokenKind::Minus, literal))
+            } else {
+                literal
+            };
 
-    Ok(Expr::FnCall(name.to_string(), vec![expr]))
-}
+            self.symbol_table
+                .insert(Stmt::VarDecl(identifier.clone(), expr));
+        }
 
-fn parse_identifier(context: &mut Context) -> Result<Expr, CalcError> {
-    let identifier = advance(context).clone();
+        Ok(self)
+    }
 
-    // Eg. sqrt64
-    if match_token(context, TokenKind::Literal) {
-        // If there is a function with this name, parse it as a function, with the next token as the argument.
-        if context.symbol_table.contains_fn(&identifier.value) {
-            let parameter = Expr::Literal(advance(context).value.clone());
-            return Ok(Expr::FnCall(identifier.value, vec![parameter]));
+    /// Define a variable with a value from the host application, eg. `define_var("temperature", 21.5)`,
+    /// rather than building a declaration like `temperature = 21.5` as text.
+    /// The value may be an `f64`, a `Float` or a `Value`, eg. a vector.
+    ///
+    /// Returns `CalcError::InvalidNumberLiteral` if the value isn't finite.
+    pub fn define_var<V: Into<Value>>(&mut self, name: &str, value: V) -> Result<(), CalcError> {
+        check_name(name)?;
+        let value = value.into();
+        if !value.is_finite() {
+            return Err(CalcError::InvalidNumberLiteral(
+                value.format(&FormatOptions::default()),
+            ));
         }
+
+        self.symbol_table.insert(Stmt::VarDecl(
+            name.into(),
+            Box::new(interpreter::value_to_expr(value)),
+        ));
+
+        Ok(())
     }
 
-    // Eg. sqrt(64)
-    if match_token(context, TokenKind::OpenParenthesis) {
-        advance(context);
+    /// Define a function from its parameters and the expression of its body,
+    /// eg. `define_fn("area", &["width", "height"], "width * height")`.
+    ///
+    /// The parameters are known while the body is parsed, so they may be longer than one letter.
+    pub fn define_fn(&mut self, name: &str, params: &[&str], body: &str) -> Result<(), CalcError> {
+        check_name(name)?;
+        for param in params {
+            check_name(param)?;
+        }
 
-        let mut parameters = Vec::new();
-        parameters.push(parse_expr(context)?);
+        // The parameters are defined as variables for now, so that eg. `width` isn't parsed as `w*i*d*t*h`.
+        let placeholders: Vec<(String, Float)> = params
+            .iter()
+            .map(|param| (param.to_string(), Float::new(53)))
+            .collect();
+        let mut statements = self.with_variables(&placeholders, |context| parse(context, body))?;
+        let expr = match statements.pop() {
+            Some(Stmt::Expr(expr)) if statements.is_empty() => expr,
+            _ => {
+                return Err(CalcError::Message(format!(
+                    "The body of {} must be a single expression.",
+                    name
+                )))
+            }
+        };
 
-        while match_token(context, TokenKind::Comma) {
-            advance(context);
-            parameters.push(parse_expr(context)?);
+        self.symbol_table.insert(Stmt::FnDecl(
+            name.into(),
+            params.iter().map(|param| param.to_string()).collect(),
+            expr,
+        ));
+
+        Ok(())
+    }
+
+    /// Get the value of a variable, eg. one that was declared by evaluating `x = 3` or by `define_var`.
+    pub fn get_var(&mut self, name: &str, precision: u32) -> Result<Value, CalcError> {
+        if !self.symbol_table.contains_var(name) {
+            return Err(CalcError::UndefinedVar(name.into()));
         }
 
-        consume(context, TokenKind::ClosedParenthesis)?;
+        let mut interpreter = self.interpreter(precision)?;
+        let result =
+            interpreter.interpret_value(vec![Stmt::Expr(Box::new(Expr::Var(name.into())))]);
+        self.operation_count = interpreter.operation_count();
 
-        return Ok(Expr::FnCall(identifier.value, parameters));
+        // The statement is an expression, so it always has a value.
+        result.map(|value| value.unwrap())
     }
 
-    // Eg. x
-    if context.symbol_table.contains_var(&identifier.value) {
-        Ok(Expr::Var(identifier.value))
-    } else {
-        let mut chars = identifier.value.chars();
-        let mut left = Expr::Var(chars.next().unwrap().to_string());
+    /// Get the names of the functions and then the variables that a user-defined function refers to directly,
+    /// in the order they appear. Eg. `f(x) = sin(x) + g(x)` depends on `sin`, `g` and `x`.
+    pub fn function_dependencies(&self, name: &str) -> Result<Vec<String>, CalcError> {
+        match self.symbol_table.get_fn(name) {
+            Some(Stmt::FnDecl(_, _, body)) => {
+                let mut functions = Vec::new();
+                let mut variables = Vec::new();
+                collect_dependencies(body, &mut functions, &mut variables);
+                functions.append(&mut variables);
 
-        // Turn each individual character into its own variable reference.
-        // This parses eg `xy` as `x*y` instead of *one* variable.
-        for c in chars {
-            left = Expr::Binary(
-                Box::new(left),
-                TokenKind::Star,
-                Box::new(Expr::Var(c.to_string())),
-            );
+                Ok(functions)
+            }
+            _ => Err(CalcError::UndefinedFn(name.into())),
         }
-
-        Ok(left)
     }
-}
 
-fn peek(context: &mut Context) -> &Token {
-    &context.tokens[context.pos]
-}
+    /// Get the names of all the user-defined variables and functions,
+    /// sorted so that every definition comes after the definitions it depends on.
+    ///
+    /// `CalcError::CircularDependency` is returned if definitions depend on each other.
+    pub fn topological_sort_definitions(&self) -> Result<Vec<String>, CalcError> {
+        Ok(self
+            .sorted_definitions(false)?
+            .into_iter()
+            .map(|(identifier, _)| identifier)
+            .collect())
+    }
 
-fn peek_next(context: &mut Context) -> &Token {
-    &context.tokens[context.pos + 1]
-}
+    /// The user-defined variables and functions, together with whether they are functions,
+    /// in the order of `topological_sort_definitions`. If cycles are allowed, eg. for recursive functions,
+    /// the definitions in a cycle are in alphabetical order instead.
+    fn sorted_definitions(&self, allow_cycles: bool) -> Result<Vec<(String, bool)>, CalcError> {
+        let vars = self
+            .symbol_table
+            .vars()
+            .map(|(identifier, _)| (identifier, false));
+        let fns = self
+            .symbol_table
+            .fns()
+            .map(|(identifier, _)| (identifier, true));
+        let mut definitions: Vec<(&String, bool)> = vars.chain(fns).collect();
+        definitions.sort();
 
-fn previous(context: &mut Context) -> &Token {
-    &context.tokens[context.pos - 1]
-}
+        let mut visited = HashMap::new();
+        let mut sorted = Vec::new();
+        for (identifier, is_fn) in definitions {
+            self.visit_definition(identifier, is_fn, allow_cycles, &mut visited, &mut sorted)?;
+        }
 
-fn match_token(context: &mut Context, kind: TokenKind) -> bool {
-    if is_at_end(context) {
-        return false;
+        Ok(sorted)
     }
 
-    peek(context).kind == kind
-}
+    /// Depth-first search for `topological_sort_definitions`.
+    /// `visited` is false for definitions that are still being visited, and true for finished ones.
+    fn visit_definition(
+        &self,
+        identifier: &str,
+        is_fn: bool,
+        allow_cycles: bool,
+        visited: &mut HashMap<(String, bool), bool>,
+        sorted: &mut Vec<(String, bool)>,
+    ) -> Result<(), CalcError> {
+        let key = (identifier.to_string(), is_fn);
+        match visited.get(&key) {
+            Some(true) => return Ok(()),
+            Some(false) if allow_cycles => return Ok(()),
+            Some(false) => return Err(CalcError::CircularDependency(identifier.into())),
+            None => (),
+        }
 
-fn advance(context: &mut Context) -> &Token {
-    context.pos += 1;
-    previous(context)
+        visited.insert(key.clone(), false);
+
+        let mut functions = Vec::new();
+        let mut variables = Vec::new();
+        let definition = if is_fn {
+            self.symbol_table.get_fn(identifier)
+        } else {
+            self.symbol_table.get_var(identifier)
+        };
+        match definition {
+            Some(Stmt::VarDecl(_, expr)) => {
+                collect_dependencies(expr, &mut functions, &mut variables)
+            }
+            Some(Stmt::FnDecl(_, parameters, body)) => {
+                collect_dependencies(body, &mut functions, &mut variables);
+                variables.retain(|variable| !parameters.contains(variable));
+            }
+            _ => (),
+        }
+
+        for function in functions {
+            if self.symbol_table.get_fn(&function).is_some() {
+                self.visit_definition(&function, true, allow_cycles, visited, sorted)?;
+            }
+        }
+
+        for variable in variables {
+            if self.symbol_table.get_var(&variable).is_some() {
+                self.visit_definition(&variable, false, allow_cycles, visited, sorted)?;
+            }
+        }
+
+        visited.insert(key, true);
+        sorted.push((identifier.into(), is_fn));
+
+        Ok(())
+    }
+
+    /// Get the names of the built-in functions, constants and units, user-defined functions, variables and units,
+    /// and keywords that could be what `partial` is the beginning of, eg. `si` gives `sin` and `sinh`.
+    ///
+    /// Names that start with `partial` come first, followed by names that contain its characters in order,
+    /// eg. `asin` for `si`. Shorter names come before longer ones.
+    pub fn repl_completions(&self, partial: &str) -> Vec<String> {
+        let user_defined = self
+            .symbol_table
+            .vars()
+            .chain(self.symbol_table.fns())
+            .chain(self.symbol_table.units())
+            .map(|(identifier, _)| identifier.as_str())
+            .chain(self.symbol_table.native_fns().map(String::as_str));
+        let mut names: Vec<&str> = prelude::DOCS
+            .keys()
+            .copied()
+            .chain(prelude::UNITS.keys().copied())
+            .chain(KEYWORDS.iter().copied())
+            .chain(user_defined)
+            .collect();
+        names.sort_by_key(|name| (name.chars().count(), *name));
+        names.dedup();
+
+        let (prefix_matches, other): (Vec<&str>, Vec<&str>) = names
+            .into_iter()
+            .partition(|name| name.starts_with(partial));
+        let fuzzy_matches = other.into_iter().filter(|name| {
+            let mut chars = name.chars();
+            partial.chars().all(|c| chars.any(|x| x == c))
+        });
+
+        prefix_matches
+            .into_iter()
+            .chain(fuzzy_matches)
+            .map(String::from)
+            .collect()
+    }
+
+    /// Get every name that can be used in an expression, ie. the built-in functions and constants,
+    /// and the user-defined functions and variables, together with what kind of name it is.
+    ///
+    /// The names are sorted alphabetically. A name that is both a function and a variable is listed twice.
+    pub fn list_all_names(&self) -> Vec<(String, NameKind)> {
+        let builtin = prelude::DOCS.keys().map(|name| {
+            let kind = if prelude::CONSTANTS.contains_key(name) {
+                NameKind::Constant
+            } else {
+                NameKind::BuiltinFn
+            };
+
+            (name.to_string(), kind)
+        });
+        let user_fns = self.symbol_table.fns().map(|(identifier, stmt)| {
+            let arity = match stmt {
+                Stmt::FnDecl(_, parameters, _) => parameters.len(),
+                _ => 0,
+            };
+
+            (identifier.clone(), NameKind::UserFn(arity))
+        });
+        let user_vars = self
+            .symbol_table
+            .vars()
+            .map(|(identifier, _)| (identifier.clone(), NameKind::UserVar));
+        let native_fns = self
+            .symbol_table
+            .native_fns()
+            .map(|identifier| (identifier.clone(), NameKind::NativeFn));
+
+        let mut names: Vec<(String, NameKind)> = builtin
+            .chain(user_fns)
+            .chain(user_vars)
+            .chain(native_fns)
+            .collect();
+        names.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        names
+    }
+
+    /// Estimate how much memory is used by the definitions, the lexed tokens and the numbers
+    /// that are kept between evaluations, eg. to find out why a long-running session keeps growing.
+    #[cfg(feature = "profiling")]
+    pub fn profile_memory(&self) -> MemoryProfile {
+        let cached_tokens: usize = self
+            .token_cache
+            .entries()
+            .map(|(input, tokens)| input.capacity() + profile::tokens_bytes(tokens))
+            .sum();
+
+        let floats = self.symbol_table.last_answer().into_iter();
+        #[cfg(feature = "cache")]
+        let floats = floats.chain(self.cache.values());
+
+        MemoryProfile {
+            symbol_table_bytes: profile::symbol_table_bytes(&self.symbol_table),
+            token_buffer_bytes: profile::tokens_bytes(&self.tokens) + cached_tokens,
+            total_float_bytes: floats.map(profile::float_bytes).sum(),
+        }
+    }
+
+    /// Forget every user-defined variable and function, while keeping the built-in ones and the configuration.
+    pub fn reset_user_definitions(&mut self) {
+        self.symbol_table.clear_user_definitions();
+    }
+
+    /// Save the user-defined variables, functions and units to a file, eg. to keep them for the next session of a REPL.
+    /// They can be loaded again with `load_state`.
+    ///
+    /// The file contains a declaration per line, eg. `f(x) = 2 * x`, after the declarations it depends on.
+    /// `CalcError::WriteFile` is returned if the file can't be written.
+    pub fn save_state<P: AsRef<Path>>(&self, path: P) -> Result<(), CalcError> {
+        let mut lines = vec![String::from(STATE_HEADER)];
+        lines.extend(self.sorted_units().into_iter().map(|unit| unit.to_string()));
+        for (identifier, is_fn) in self.sorted_definitions(true)? {
+            let definition = if is_fn {
+                self.symbol_table.get_fn(&identifier)
+            } else {
+                self.symbol_table.get_var(&identifier)
+            };
+            lines.extend(definition.map(|stmt| stmt.to_string()));
+        }
+
+        let path = path.as_ref();
+        fs::write(path, lines.join("\n") + "\n")
+            .map_err(|err| CalcError::WriteFile(format!("{}: {}", path.display(), err)))
+    }
+
+    /// Load the definitions in a file written by `save_state`. They are added to the current definitions,
+    /// replacing the ones with the same names. The file is evaluated like in `eval_file`.
+    ///
+    /// The declarations are only stored, not calculated, so this works with any precision the context allows,
+    /// eg. after `set_max_precision(32)` or inside a `global_precision_guard`.
+    pub fn load_state<P: AsRef<Path>>(&mut self, path: P) -> Result<(), CalcError> {
+        let precision = self.implicit_precision(53);
+        eval_file_at(self, path, precision)?;
+
+        Ok(())
+    }
+
+    /// The declarations of the user-defined units, where every unit comes after the unit it is defined in terms of.
+    fn sorted_units(&self) -> Vec<&Stmt> {
+        let mut units: Vec<(&String, &Stmt)> = self.symbol_table.units().collect();
+        units.sort_by_key(|(identifier, _)| *identifier);
+
+        let mut sorted: Vec<(&String, &Stmt)> = Vec::new();
+        while sorted.len() < units.len() {
+            let sorted_count = sorted.len();
+            for &(identifier, stmt) in &units {
+                if sorted
+                    .iter()
+                    .any(|(sorted_identifier, _)| *sorted_identifier == identifier)
+                {
+                    continue;
+                }
+
+                let base_unit = match stmt {
+                    Stmt::UnitDecl(_, Some(definition)) => match &**definition {
+                        Expr::Unit(_, base_unit) => Some(base_unit),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                // Built-in units are always declared.
+                let is_ready = base_unit.map_or(true, |base_unit| {
+                    self.symbol_table.get_unit(base_unit).is_none()
+                        || sorted
+                            .iter()
+                            .any(|(sorted_identifier, _)| *sorted_identifier == base_unit)
+                });
+                if is_ready {
+                    sorted.push((identifier, stmt));
+                }
+            }
+
+            // Units that were redefined in terms of each other, eg. `unit m = 0.001 km` after `unit km = 1000 m`.
+            if sorted.len() == sorted_count {
+                for &(identifier, stmt) in &units {
+                    if !sorted
+                        .iter()
+                        .any(|(sorted_identifier, _)| *sorted_identifier == identifier)
+                    {
+                        sorted.push((identifier, stmt));
+                    }
+                }
+            }
+        }
+
+        sorted.into_iter().map(|(_, stmt)| stmt).collect()
+    }
+
+    /// Get the documentation of a built-in function or constant, eg. `help("sin")`.
+    pub fn help(&self, name: &str) -> Option<String> {
+        prelude::DOCS.get(name).map(|doc| doc.to_string())
+    }
+
+    /// Format an error with the part of the input that caused it, eg.
+    ///
+    /// ```text
+    /// error: expected ')'
+    ///   |
+    ///   | (1 + 2
+    ///   | -     ^ expected ')'
+    ///   |
+    ///   = note: the '(' marked with '-' is never closed
+    /// ```
+    pub fn format_error(&self, err: &CalcError, input: &str) -> String {
+        diagnostic::format_error(err, input)
+    }
+
+    /// Find out which type of value the input will result in, without evaluating it.
+    ///
+    /// `CalcError::Unknown` is returned if it can't be known before evaluation,
+    /// eg. if it contains names that aren't defined yet, and the error that evaluating it would result in
+    /// if it combines values of types that can't be combined, eg. a vector and a matrix in `[1, 2] + [[1, 2]]`.
+    /// The input is parsed with a copy of the context, so the functions and units it declares aren't added to the context.
+    pub fn check_type_consistency(&self, input: &str) -> Result<KalkType, CalcError> {
+        let mut context = self.snapshot();
+        let statements = parse(&mut context, input)?;
+        let expr = match statements.last() {
+            Some(Stmt::Expr(expr)) | Some(Stmt::VarDecl(_, expr)) => expr,
+            _ => return Err(CalcError::Unknown),
+        };
+
+        types::check(expr, &context.symbol_table)?.ok_or(CalcError::Unknown)
+    }
+
+    /// Differentiate the input with respect to the variable, and return the derivative without evaluating it,
+    /// eg. `x^3` becomes `3 * (x ^ 2)`.
+    ///
+    /// The derivative can also be evaluated directly with `diff(expr, x)` or `diff(expr, x, at)`.
+    pub fn derivative(&mut self, input: &str, variable: &str) -> Result<Expr, CalcError> {
+        match parse(self, input)?.last() {
+            Some(Stmt::Expr(expr)) => {
+                calculus::derivative(&self.symbol_table, &self.angle_unit, expr, variable)
+            }
+            _ => Err(CalcError::Message(String::from(
+                "Only expressions can be differentiated.",
+            ))),
+        }
+    }
+
+    /// Describe the input in plain English, eg. `x^2 + 1` becomes "x squared plus 1".
+    /// This is meant for screen readers and educational tools.
+    /// The input is parsed with a copy of the context, so the functions and units it declares aren't added to the context.
+    pub fn explain(&self, input: &str) -> Result<String, CalcError> {
+        let statements = parse(&mut self.snapshot(), input)?;
+
+        Ok(explain::explain(&statements))
+    }
+
+    /// Evaluate expressions/declarations and return the answer together with the unit it is in, if any.
+    ///
+    /// `None` will be returned if the last statement is a declaration.
+    pub fn eval_with_units(
+        &mut self,
+        input: &str,
+        precision: u32,
+    ) -> Result<Option<(Float, Option<String>)>, CalcError> {
+        let statements = parse(self, input)?;
+        let mut interpreter = self.interpreter(precision)?;
+        let result = interpreter.interpret_with_unit(statements);
+        self.operation_count = interpreter.operation_count();
+
+        result
+    }
+
+    /// Evaluate expressions/declarations and return the answer together with a hint
+    /// about whether it should be displayed as an integer, a fraction or a decimal number.
+    ///
+    /// `None` will be returned if the last statement is a declaration.
+    pub fn eval_with_display_hint(
+        &mut self,
+        input: &str,
+        precision: u32,
+    ) -> Result<Option<(Float, DisplayHint)>, CalcError> {
+        Ok(eval(self, input, precision)?.map(|result| {
+            let hint = display_hint(&result);
+
+            (result, hint)
+        }))
+    }
+
+    /// Evaluate expressions/declarations, and replace the answer with a "nice" value if it is within `tolerance` of one,
+    /// eg. `1.5707963` becomes π/2. Integers, simple fractions and simple fractions of common constants
+    /// (π, e, √2, √3, ln 2 and ϕ) are recognised.
+    ///
+    /// `None` will be returned if the last statement is a declaration.
+    pub fn eval_with_tolerance(
+        &mut self,
+        input: &str,
+        precision: u32,
+        tolerance: f64,
+    ) -> Result<Option<Float>, CalcError> {
+        Ok(eval(self, input, precision)?
+            .map(|result| recognize::nice_value(&result, tolerance).unwrap_or(result)))
+    }
+
+    /// Evaluate expressions/declarations, where the answer may be a complex number, eg. `sqrt(-1)` or `(1 + 2i) * (3 - i)`.
+    /// `i` is the imaginary unit, unless a variable called `i` has been declared.
+    ///
+    /// This is the same as `eval_value`, but vectors result in `CalcError::UnexpectedVector`.
+    /// Only arithmetic and a few functions support complex numbers, see `KalkValue`.
+    /// `None` will be returned if the last statement is a declaration.
+    pub fn eval_complex(
+        &mut self,
+        input: &str,
+        precision: u32,
+    ) -> Result<Option<KalkValue>, CalcError> {
+        Ok(match self.eval_value(input, precision)? {
+            Some(Value::Scalar(value)) => Some(KalkValue::from(value)),
+            Some(Value::Complex(value)) => Some(KalkValue::from(value)),
+            Some(Value::Vector(_)) | Some(Value::Matrix(_)) => {
+                return Err(CalcError::UnexpectedVector)
+            }
+            None => None,
+        })
+    }
+
+    /// Evaluate expressions/declarations, and keep the answer as an exact fraction if it can be calculated exactly,
+    /// eg. `1/3 + 1/3` is exactly 2/3. Arithmetic with integer exponents, `%` and `//` on rational numbers, and variables
+    /// defined as such, are exact. Anything else, eg. `sqrt(2)` or `pi/2`, results in `ExactValue::Float`.
+    /// `None` will be returned if the last statement is a declaration.
+    pub fn eval_exact(
+        &mut self,
+        input: &str,
+        precision: u32,
+    ) -> Result<Option<ExactValue>, CalcError> {
+        let statements = parse(self, input)?;
+        let last_expr = match statements.last() {
+            Some(Stmt::Expr(expr)) => Some(expr.clone()),
+            _ => None,
+        };
+
+        let mut interpreter = self.interpreter(precision)?;
+        let result = interpreter.interpret(statements);
+        self.operation_count = interpreter.operation_count();
+
+        let value = match result? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        Ok(Some(
+            match last_expr.and_then(|expr| exact::eval(&self.symbol_table, &expr)) {
+                Some(rational) => ExactValue::Rational(rational),
+                None => ExactValue::Float(value),
+            },
+        ))
+    }
+
+    /// Evaluate expressions/declarations, where the answer may also be a vector, eg. `[1, 2, 3] * 2` or `(1, 2) + (3, 4)`.
+    ///
+    /// Operators and built-in functions are applied element-wise to vectors.
+    /// The other eval functions return `CalcError::UnexpectedVector` if the answer is a vector.
+    /// `None` will be returned if the last statement is a declaration.
+    pub fn eval_value(&mut self, input: &str, precision: u32) -> Result<Option<Value>, CalcError> {
+        let statements = parse(self, input)?;
+
+        self.evaluate_statements(statements, precision)
+    }
+
+    /// Evaluate expressions/declarations and return the answer together with an estimate of how many
+    /// significant bits were lost to cancellation, ie. when adding numbers of opposite sign and similar magnitude.
+    ///
+    /// Eg. `1e100 - 1e100 + 1` loses every bit, since the 1 is far too small to be represented next to 1e100.
+    /// The estimate is taken from the addition or subtraction that lost the most bits, and is at most the precision.
+    /// `None` will be returned if the last statement is a declaration.
+    pub fn eval_checking_precision_loss(
+        &mut self,
+        input: &str,
+        precision: u32,
+    ) -> Result<(Option<Float>, usize), CalcError> {
+        let statements = parse(self, input)?;
+        let mut interpreter = self.interpreter(precision)?;
+        let result = interpreter.interpret(statements);
+        let lost_bits = interpreter.lost_bits();
+        self.operation_count = interpreter.operation_count();
+
+        Ok((result?, lost_bits))
+    }
+
+    /// Evaluate conditions, eg. `x > 0`, and return whether each of them is true, ie. not zero.
+    ///
+    /// Every condition is parsed first, and then evaluated by the same interpreter.
+    /// The first error that occurs is returned, and declarations are not allowed.
+    pub fn eval_boolean_array(
+        &mut self,
+        conditions: &[&str],
+        precision: u32,
+    ) -> Result<Vec<bool>, CalcError> {
+        let mut parsed = Vec::with_capacity(conditions.len());
+        for condition in conditions {
+            let statements = parse(self, condition)?;
+            if !matches!(statements.last(), Some(Stmt::Expr(_))) {
+                return Err(CalcError::Message(format!(
+                    "'{}' is not a condition.",
+                    condition
+                )));
+            }
+
+            parsed.push(statements);
+        }
+
+        let mut interpreter = self.interpreter(precision)?;
+        let mut results = Vec::with_capacity(parsed.len());
+        let mut error = None;
+        for statements in parsed {
+            match interpreter.interpret(statements) {
+                Ok(value) => results.push(value.is_some_and(|value| !value.is_zero())),
+                Err(err) => {
+                    error = Some(err);
+                    break;
+                }
+            }
+        }
+        self.operation_count = interpreter.operation_count();
+
+        match error {
+            Some(err) => Err(err),
+            None => Ok(results),
+        }
+    }
+
+    /// Evaluate expressions/declarations with interval arithmetic, and return the lower and upper bound
+    /// of the answer, eg. to see how much rounding errors may have affected it.
+    ///
+    /// Literals are given an uncertainty of half a unit in the last place, which is then propagated
+    /// through the operations. Only arithmetic and a few functions are supported (`sqrt`, `exp`, `ln` and `abs`),
+    /// otherwise `CalcError::Message` is returned.
+    /// `None` will be returned if the last statement is a declaration.
+    pub fn eval_interval(
+        &mut self,
+        input: &str,
+        precision: u32,
+    ) -> Result<Option<(Float, Float)>, CalcError> {
+        let precision = self.checked_precision(precision)?;
+        let statements = parse(self, input)?;
+        let mut result = None;
+        for stmt in statements {
+            result = match stmt {
+                Stmt::Expr(expr) => Some(interval::eval(&self.symbol_table, &expr, precision)?),
+                // Functions and units are added to the symbol table by the parser.
+                Stmt::VarDecl(_, _) => {
+                    self.symbol_table.insert(stmt);
+                    None
+                }
+                Stmt::FnDecl(_, _, _) | Stmt::UnitDecl(_, _) => None,
+            };
+        }
+
+        Ok(result)
+    }
+
+    /// Evaluate expressions/declarations and return the answer as an `f64`.
+    ///
+    /// `None` will be returned if the last statement is a declaration.
+    /// `CalcError::Overflow` is returned if the answer is too large for an `f64`.
+    /// It is calculated with the precision of an `f64`, unless the context allows less or has a global precision.
+    pub fn eval_returning_f64(&mut self, input: &str) -> Result<Option<f64>, CalcError> {
+        let precision = self.implicit_precision(53);
+        let result = match eval(self, input, precision)? {
+            Some(result) => result,
+            None => return Ok(None),
+        };
+
+        let value = result.to_f64();
+        if value.is_infinite() && result.is_finite() {
+            return Err(CalcError::Overflow);
+        }
+
+        Ok(Some(value))
+    }
+
+    /// Evaluate expressions/declarations and return the answer as an `i64`.
+    ///
+    /// `None` will be returned if the last statement is a declaration.
+    /// `CalcError::InvalidNumberLiteral` is returned if the answer isn't an integer,
+    /// and `CalcError::Overflow` if it is too large for an `i64`, or for the precision if the context allows less than 64 bits.
+    pub fn eval_returning_i64(&mut self, input: &str) -> Result<Option<i64>, CalcError> {
+        // Enough precision to represent every `i64` exactly, unless the context allows less or has a global precision.
+        let precision = self.implicit_precision(128);
+        let result = match eval(self, input, precision)? {
+            Some(result) => result,
+            None => return Ok(None),
+        };
+
+        if result.is_infinite() {
+            return Err(CalcError::Overflow);
+        }
+
+        if !result.is_integer() {
+            return Err(CalcError::InvalidNumberLiteral(result.to_string()));
+        }
+
+        match result.to_integer().and_then(|integer| integer.to_i64()) {
+            // Larger integers may have been rounded to another one, eg. `2^62 + 1` at 32 bits.
+            Some(value) if precision < 64 && value.unsigned_abs() > 1 << precision => {
+                Err(CalcError::Overflow)
+            }
+            Some(value) => Ok(Some(value)),
+            None => Err(CalcError::Overflow),
+        }
+    }
+
+    /// Evaluate the input `n` times, with the variable `var` set to 0, 1, 2, ..., n - 1,
+    /// and return the results. Eg. `eval_n_times("sin(i * pi / 10)", 20, "i", 53)`.
+    ///
+    /// Any existing definition of `var` is restored afterwards. It is an error (`CalcError::Message`) if the input
+    /// doesn't end in an expression, eg. if it only declares a variable, so there is one result for each `n`.
+    pub fn eval_n_times(
+        &mut self,
+        input: &str,
+        n: usize,
+        var: &str,
+        precision: u32,
+    ) -> Result<Vec<Float>, CalcError> {
+        let previous_definition = self.symbol_table.get_var(var).cloned();
+        let results = self.eval_with_counter(input, n, var, precision);
+
+        match previous_definition {
+            Some(stmt) => self.symbol_table.insert(stmt),
+            None => {
+                self.symbol_table.remove_var(var);
+            }
+        }
+
+        results
+    }
+
+    fn eval_with_counter(
+        &mut self,
+        input: &str,
+        n: usize,
+        var: &str,
+        precision: u32,
+    ) -> Result<Vec<Float>, CalcError> {
+        let set_counter = |context: &mut Context, i: usize| {
+            let value = Box::new(Expr::Literal(i.to_string()));
+            context
+                .symbol_table
+                .insert(Stmt::VarDecl(var.into(), value));
+        };
+
+        // Define the variable before parsing, so that eg. `ab` isn't parsed as `a*b`.
+        set_counter(self, 0);
+        let statements = parse(self, input)?;
+
+        let mut results = Vec::new();
+        for i in 0..n {
+            set_counter(self, i);
+            match self.interpreter(precision)?.interpret(statements.clone())? {
+                Some(result) => results.push(result),
+                None => return Err(CalcError::Message(String::from(NOT_AN_EXPRESSION))),
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Call a user-defined function with arguments that have already been calculated,
+    /// eg. `substitute_and_eval("f", &[x], 53)` rather than evaluating `f(x)` from a string.
+    pub fn substitute_and_eval(
+        &mut self,
+        fn_name: &str,
+        args: &[Float],
+        precision: u32,
+    ) -> Result<Float, CalcError> {
+        if self.symbol_table.get_fn(fn_name).is_none() {
+            return Err(CalcError::UndefinedFn(fn_name.into()));
+        }
+
+        let mut arguments = Vec::with_capacity(args.len());
+        for arg in args {
+            if !arg.is_finite() {
+                return Err(CalcError::InvalidNumberLiteral(arg.to_string()));
+            }
+
+            arguments.push(interpreter::float_to_expr(arg));
+        }
+
+        self.evaluate_expr(&Expr::FnCall(fn_name.into(), arguments), precision)
+    }
+
+    /// Define the given variables while `f` is run, and then restore the previous definitions,
+    /// eg. to evaluate something for hypothetical values without changing the context permanently.
+    /// The definitions are also restored if `f` panics.
+    pub fn with_variables<F, R>(&mut self, vars: &[(String, Float)], f: F) -> R
+    where
+        F: FnOnce(&mut Context) -> R,
+    {
+        let previous = vars
+            .iter()
+            .map(|(identifier, _)| {
+                (
+                    identifier.clone(),
+                    self.symbol_table.get_var(identifier).cloned(),
+                )
+            })
+            .collect();
+        for (identifier, value) in vars {
+            self.symbol_table.insert(Stmt::VarDecl(
+                identifier.clone(),
+                Box::new(interpreter::float_to_expr(value)),
+            ));
+        }
+
+        let mut scope = VariableScope {
+            context: self,
+            previous,
+        };
+
+        f(&mut scope)
+    }
+
+    /// Evaluate the input once for every value, with the variable `var` set to that value.
+    /// The input is only parsed once, and the evaluations run in parallel, each with its own copy
+    /// of the symbol table, so the context itself is not changed.
+    /// The results are in the same order as the values.
+    pub fn parallel_sweep(
+        &self,
+        input: &str,
+        var: &str,
+        values: &[Float],
+        precision: u32,
+    ) -> Vec<Result<Option<Float>, CalcError>> {
+        // The variable has to be declared while parsing, so that eg. `xy` is parsed as `x * y`.
+        let mut parsed = self.snapshot();
+        parsed.symbol_table.insert(Stmt::VarDecl(
+            var.into(),
+            Box::new(Expr::Literal(String::from("0"))),
+        ));
+        let statements = match parse(&mut parsed, input) {
+            Ok(statements) => statements,
+            Err(err) => return vec![Err(err); values.len()],
+        };
+
+        values
+            .par_iter()
+            .map(|value| {
+                let mut symbol_table = parsed.symbol_table.clone();
+                symbol_table.insert(Stmt::VarDecl(
+                    var.into(),
+                    Box::new(interpreter::float_to_expr(value)),
+                ));
+
+                parsed
+                    .interpreter_for(&mut symbol_table, precision)?
+                    .interpret(statements.clone())
+            })
+            .collect()
+    }
+
+    /// Evaluate the input, but only allow it to refer to the given variables, functions and units,
+    /// eg. to evaluate input from someone else without giving access to everything in the context.
+    ///
+    /// Built-in functions, constants and units need to be allowed as well, and so do the names of parameters,
+    /// the variable of `sum` and `abs`, `ceil` and `floor` for `|x|`, `⌈x⌉` and `⌊x⌋`.
+    /// The names are checked when they are looked up, so this includes the ones used by allowed variables and functions,
+    /// and `CalcError::UndefinedVar`, `CalcError::UndefinedFn` or `CalcError::UndefinedUnit` is returned for any other name.
+    /// Declarations in the input are only made in a copy of the context, so the context itself is not changed.
+    pub fn eval_in_sandbox(
+        &mut self,
+        input: &str,
+        precision: u32,
+        allowed_names: &[&str],
+    ) -> Result<Option<Float>, CalcError> {
+        let mut sandbox = self.snapshot();
+        let statements = parse(&mut sandbox, input)?;
+        sandbox.symbol_table.set_allowed_names(allowed_names);
+
+        let mut interpreter = sandbox.interpreter(precision)?;
+        let result = interpreter.interpret(statements);
+        self.operation_count = interpreter.operation_count();
+
+        result
+    }
+
+    /// Parse the input now, but evaluate it later, when the returned closure is called.
+    ///
+    /// Syntax errors are returned right away. The closure evaluates the input with a copy of the definitions
+    /// as they were when this was called, so later changes to the context don't affect the result.
+    /// The input is parsed with the copy as well, so the functions and units it declares aren't added to the context.
+    pub fn eval_lazy(
+        &self,
+        input: &str,
+        precision: u32,
+    ) -> Result<impl FnOnce() -> Result<Option<Float>, CalcError>, CalcError> {
+        let mut context = self.snapshot();
+        let statements = parse(&mut context, input)?;
+
+        Ok(move || context.interpreter(precision)?.interpret(statements))
+    }
+
+    /// A copy of the definitions and settings, without any cached results.
+    fn snapshot(&self) -> Context {
+        Context {
+            symbol_table: self.symbol_table.clone(),
+            angle_unit: self.angle_unit.clone(),
+            decimal_mode: self.decimal_mode,
+            integer_mode: self.integer_mode,
+            rounding: self.rounding.clone(),
+            auto_simplify: self.auto_simplify,
+            max_precision: self.max_precision,
+            global_precision: self.global_precision,
+            integration_steps: self.integration_steps,
+            recursion_limit: self.recursion_limit,
+            output_base: self.output_base,
+            macros: self.macros.clone(),
+            callbacks: self.callbacks.clone(),
+            ..Context::new()
+        }
+    }
+
+    /// Evaluate everything at `precision`, regardless of the precision given to the eval functions,
+    /// until the returned guard is dropped. The previous precision is then restored.
+    ///
+    /// The guard dereferences to the context, eg. `eval(&mut guard, "1/3", 53)` is evaluated at 256 bits
+    /// after `let mut guard = context.global_precision_guard(256);`.
+    pub fn global_precision_guard(&mut self, precision: u32) -> PrecisionGuard<'_> {
+        let previous = self.global_precision.replace(precision);
+
+        PrecisionGuard {
+            context: self,
+            previous,
+        }
+    }
+
+    /// The number of operations (binary operations, unary operations and function calls)
+    /// performed during the last evaluation. Results that are re-used from the cache are counted as if they were
+    /// evaluated again, and in decimal mode, an expression that is calculated exactly counts as one operation.
+    pub fn operation_count(&self) -> usize {
+        self.operation_count
+    }
+
+    fn interpreter(&mut self, precision: u32) -> Result<interpreter::Context<'_>, CalcError> {
+        let precision = self.checked_precision(precision)?;
+        let mut interpreter =
+            interpreter::Context::new(&mut self.symbol_table, &self.angle_unit, precision);
+        interpreter.set_decimal_mode(self.decimal_mode);
+        interpreter.set_integer_mode(self.integer_mode);
+        interpreter.set_rounding(self.rounding.to_round());
+        interpreter.set_integration_steps(self.integration_steps);
+        interpreter.set_recursion_limit(self.recursion_limit);
+        interpreter.set_callbacks(&self.callbacks);
+        #[cfg(feature = "cache")]
+        interpreter.set_cache(&mut self.cache);
+
+        Ok(interpreter)
+    }
+
+    /// An interpreter like the one from `interpreter`, but for another symbol table, and without the cache.
+    fn interpreter_for<'a>(
+        &'a self,
+        symbol_table: &'a mut SymbolTable,
+        precision: u32,
+    ) -> Result<interpreter::Context<'a>, CalcError> {
+        let precision = self.checked_precision(precision)?;
+        let mut interpreter = interpreter::Context::new(symbol_table, &self.angle_unit, precision);
+        interpreter.set_decimal_mode(self.decimal_mode);
+        interpreter.set_integer_mode(self.integer_mode);
+        interpreter.set_rounding(self.rounding.to_round());
+        interpreter.set_integration_steps(self.integration_steps);
+        interpreter.set_recursion_limit(self.recursion_limit);
+        interpreter.set_callbacks(&self.callbacks);
+
+        Ok(interpreter)
+    }
+
+    /// The precision for evaluating when none is given, eg. in `eval_returning_f64`: the global precision
+    /// if there is one, otherwise `preferred`, but never more than the context allows.
+    fn implicit_precision(&self, preferred: u32) -> u32 {
+        self.global_precision
+            .unwrap_or(preferred)
+            .min(self.max_precision)
+    }
+
+    /// The precision to evaluate at, ie. the global precision if one is set.
+    fn checked_precision(&self, precision: u32) -> Result<u32, CalcError> {
+        let precision = self.global_precision.unwrap_or(precision);
+        if precision > self.max_precision {
+            return Err(CalcError::PrecisionTooHigh(precision));
+        }
+
+        Ok(precision)
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Restores the variables that were replaced by `Context::with_variables` when dropped.
+struct VariableScope<'a> {
+    context: &'a mut Context,
+    previous: Vec<(String, Option<Stmt>)>,
+}
+
+impl Deref for VariableScope<'_> {
+    type Target = Context;
+
+    fn deref(&self) -> &Context {
+        self.context
+    }
+}
+
+impl DerefMut for VariableScope<'_> {
+    fn deref_mut(&mut self) -> &mut Context {
+        self.context
+    }
+}
+
+impl Drop for VariableScope<'_> {
+    fn drop(&mut self) {
+        // In reverse, in case the same name was given more than once.
+        for (identifier, previous) in self.previous.drain(..).rev() {
+            match previous {
+                Some(stmt) => self.context.symbol_table.insert(stmt),
+                None => {
+                    self.context.symbol_table.remove_var(&identifier);
+                }
+            }
+        }
+    }
+}
+
+/// Restores the precision that was replaced by `Context::global_precision_guard` when dropped.
+pub struct PrecisionGuard<'a> {
+    context: &'a mut Context,
+    previous: Option<u32>,
+}
+
+impl Deref for PrecisionGuard<'_> {
+    type Target = Context;
+
+    fn deref(&self) -> &Context {
+        self.context
+    }
+}
+
+impl DerefMut for PrecisionGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Context {
+        self.context
+    }
+}
+
+impl Drop for PrecisionGuard<'_> {
+    fn drop(&mut self) {
+        self.context.global_precision = self.previous;
+    }
+}
+
+/// Angle unit used in calculations.
+///
+/// Values tagged with an angle unit, eg. `30deg` or `0.5rad`, are converted to the angle unit of the context
+/// before they are used, which means they can be mixed freely: `30deg + 0.5rad`.
+/// Numbers without a unit are assumed to already be in the angle unit of the context.
+/// Values in other units are converted to the base unit they are declared in terms of, see `Stmt::UnitDecl`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Unit {
+    Radians,
+    Degrees,
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unit::Radians => write!(f, "rad"),
+            Unit::Degrees => write!(f, "deg"),
+        }
+    }
+}
+
+/// Direction to round numbers in, see `Context::set_precision_rounding`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoundingMode {
+    /// Round to the nearest number, and to the even one if it is exactly in between.
+    Nearest,
+    TowardZero,
+    /// Round toward positive infinity.
+    Up,
+    /// Round toward negative infinity.
+    Down,
+}
+
+impl RoundingMode {
+    fn to_round(&self) -> Round {
+        match self {
+            RoundingMode::Nearest => Round::Nearest,
+            RoundingMode::TowardZero => Round::Zero,
+            RoundingMode::Up => Round::Up,
+            RoundingMode::Down => Round::Down,
+        }
+    }
+}
+
+/// Suggestion for how an answer should be displayed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisplayHint {
+    /// The answer is a whole number, eg. `6/3`.
+    Integer,
+    /// The answer is a fraction with a small denominator, eg. `1/4`.
+    Fraction,
+    /// The answer is best shown as a decimal number, eg. `sqrt(2)`.
+    Decimal,
+}
+
+/// What kind of name something is, see `Context::list_all_names`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NameKind {
+    BuiltinFn,
+    /// A user-defined function with the given number of parameters.
+    UserFn(usize),
+    UserVar,
+    /// A function registered by the host application, see `Context::register_native_fn`.
+    NativeFn,
+    Constant,
+}
+
+/// A complex number, see `Context::eval_complex`.
+///
+/// Arithmetic, `sqrt`, `exp`, `ln`, `sin`, `cos`, `tan`, `abs`, `re`, `im`, `arg` and `conj` support complex numbers.
+/// The other built-in functions can only be given real numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KalkValue {
+    real: Float,
+    imaginary: Float,
 }
 
-fn consume(context: &mut Context, kind: TokenKind) -> Result<&Token, CalcError> {
-    if match_token(context, kind.clone()) {
-        return Ok(advance(context));
+impl KalkValue {
+    pub fn real(&self) -> &Float {
+        &self.real
+    }
+
+    pub fn imaginary(&self) -> &Float {
+        &self.imaginary
+    }
+
+    /// Whether the imaginary part is zero.
+    pub fn is_real(&self) -> bool {
+        self.imaginary.is_zero()
+    }
+
+    /// Express a real number as a simple fraction, or a simple fraction of π or e, eg. `0.5` is `1/2` and `1.5707963` is `π/2`.
+    /// `None` is returned if no such fraction is close enough, or if the number isn't real.
+    pub fn estimate_fraction(&self) -> Option<String> {
+        if !self.is_real() {
+            return None;
+        }
+
+        recognize::estimate_fraction(&self.real)
+    }
+
+    /// Format the number as text, eg. `1.5`, `2i` or `1 - 2.5i`.
+    pub fn format(&self, options: &FormatOptions) -> String {
+        if self.is_real() {
+            return options.format_number(&self.real);
+        }
+
+        let imaginary = options.format_number(&self.imaginary.clone().abs());
+        if self.real.is_zero() {
+            let sign = if self.imaginary.is_sign_negative() {
+                "-"
+            } else {
+                ""
+            };
+
+            return format!("{}{}i", sign, imaginary);
+        }
+
+        let sign = if self.imaginary.is_sign_negative() {
+            "-"
+        } else {
+            "+"
+        };
+
+        format!(
+            "{} {} {}i",
+            options.format_number(&self.real),
+            sign,
+            imaginary
+        )
+    }
+}
+
+impl From<Float> for KalkValue {
+    fn from(real: Float) -> Self {
+        let imaginary = Float::with_val(real.prec(), 0);
+
+        KalkValue { real, imaginary }
+    }
+}
+
+impl From<Complex> for KalkValue {
+    fn from(value: Complex) -> Self {
+        let (real, imaginary) = value.into_real_imag();
+
+        // Negative zero is equal to zero, but confusing to get as an answer.
+        let without_negative_zero = |x: Float| if x.is_zero() { x.abs() } else { x };
+
+        KalkValue {
+            real: without_negative_zero(real),
+            imaginary: without_negative_zero(imaginary),
+        }
+    }
+}
+
+/// The answer of `Context::eval_exact`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExactValue {
+    /// An answer that was calculated exactly, eg. 2/3.
+    Rational(Rational),
+    /// An answer that couldn't be calculated exactly, eg. `sqrt(2)`.
+    Float(Float),
+}
+
+impl ExactValue {
+    /// Format the value as text. Fractions are written as `numerator/denominator`, eg. `-2/3`, or as an integer.
+    pub fn format(&self, options: &FormatOptions) -> String {
+        match self {
+            ExactValue::Rational(value) if *value.denom() == 1 => value.numer().to_string(),
+            ExactValue::Rational(value) => format!("{}/{}", value.numer(), value.denom()),
+            ExactValue::Float(value) => options.format_number(value),
+        }
+    }
+}
+
+/// The type of value an expression evaluates to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KalkType {
+    Scalar,
+    Vector,
+    Matrix,
+    /// A complex number that isn't real, eg. `sqrt(-1)`.
+    Complex,
+}
+
+/// Error that occured during parsing or evaluation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalcError {
+    CircularDependency(String),
+    /// There was nothing where a value was expected, eg. at the end of `1 +` or inside `()`.
+    EmptyInput(Span),
+    /// Values in different units were added, subtracted or compared, eg. `3 m + 4 s`,
+    /// or a value was converted to a unit of another kind, eg. `5 m to s`.
+    IncompatibleUnits(String, String),
+    IncorrectAmountOfArguments(usize, String, usize),
+    /// A character that isn't part of the syntax, eg. `#`.
+    InvalidCharacter(char, Span),
+    InvalidNumberLiteral(String),
+    InvalidOperator,
+    InvalidStep,
+    InvalidUnit,
+    /// An error on a line of a file, see `eval_file`. Lines are counted from 1.
+    Line(usize, Box<CalcError>),
+    /// An error without a variant of its own, eg. from `CalcError::from(String::from("..."))`.
+    Message(String),
+    /// A value that isn't an integer was used or calculated in integer mode, eg. `sqrt(2)`, see `Context::set_integer_mode`.
+    NotAnInteger(String),
+    NotDivisible,
+    Overflow,
+    PrecisionTooHigh(u32),
+    /// A file couldn't be read, eg. by `eval_file`. The string is the path followed by the reason,
+    /// eg. `lib.kalk: No such file or directory (os error 2)`.
+    ReadFile(String),
+    /// Calls to a user-defined function were nested deeper than the recursion limit, see `Context::set_recursion_limit`.
+    RecursionLimitReached(String),
+    /// The parser expected a token of this kind, or didn't expect it in the case of `Equals` and `Unknown`.
+    /// The span is where in the input it happened, see `CalcError::span`.
+    UnexpectedToken(TokenKind, Span),
+    /// The input ended before a group was closed, eg. `(1 + 2` or `⌈2.5`.
+    /// The kind is the token that would close it, and the span is where the group starts.
+    UnterminatedGroup(TokenKind, Span),
+    /// A vector was used where only a number is allowed, eg. in `sqrt` of two arguments.
+    UnexpectedVector,
+    UndefinedFn(String),
+    UndefinedUnit(String),
+    UndefinedVar(String),
+    Unknown,
+    /// A file couldn't be written, eg. by `Context::save_state`. The string is the path followed by the reason.
+    WriteFile(String),
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalcError::CircularDependency(name) => write!(f, "Circular dependency: '{}'.", name),
+            CalcError::EmptyInput(_) => write!(f, "Expected a value."),
+            CalcError::IncompatibleUnits(left, right) => {
+                write!(f, "Incompatible units: '{}' and '{}'.", left, right)
+            }
+            CalcError::IncorrectAmountOfArguments(expected, func, got) => write!(
+                f,
+                "Expected {} arguments for function {}, but got {}.",
+                expected, func, got
+            ),
+            CalcError::InvalidCharacter(c, _) => write!(f, "Invalid character: '{}'.", c),
+            CalcError::InvalidNumberLiteral(x) => write!(f, "Invalid number literal: '{}'.", x),
+            CalcError::InvalidOperator => write!(f, "Invalid operator."),
+            CalcError::InvalidStep => write!(
+                f,
+                "Invalid step, it needs to lead from the start to the end."
+            ),
+            CalcError::InvalidUnit => write!(f, "Invalid unit."),
+            CalcError::Line(line, err) => write!(f, "Line {}: {}", line, err),
+            CalcError::Message(message) => write!(f, "{}", message),
+            CalcError::NotAnInteger(x) => write!(f, "Expected an integer, but got {}.", x),
+            CalcError::NotDivisible => write!(f, "The division doesn't result in an integer."),
+            CalcError::Overflow => write!(f, "Too big to process."),
+            CalcError::PrecisionTooHigh(precision) => {
+                write!(f, "The precision {} is too high.", precision)
+            }
+            CalcError::ReadFile(reason) => write!(f, "Couldn't read the file {}.", reason),
+            CalcError::RecursionLimitReached(name) => write!(
+                f,
+                "The recursion limit was reached when calling function '{}'.",
+                name
+            ),
+            CalcError::UnexpectedToken(TokenKind::Equals, _) => {
+                write!(f, "Expected a name to declare before '=', eg. 'x = 5'.")
+            }
+            CalcError::UnexpectedToken(kind, _) => write!(f, "Unexpected token: '{:?}'.", kind),
+            CalcError::UnterminatedGroup(kind, _) => {
+                write!(f, "Expected '{}' to close the group.", closing_symbol(kind))
+            }
+            CalcError::UnexpectedVector => write!(f, "Expected a number, but got a vector."),
+            CalcError::UndefinedFn(name) => write!(f, "Undefined function: '{}'.", name),
+            CalcError::UndefinedUnit(name) => write!(f, "Undefined unit: '{}'.", name),
+            CalcError::UndefinedVar(name) => write!(f, "Undefined variable: '{}'.", name),
+            CalcError::Unknown => write!(f, "Unknown error."),
+            CalcError::WriteFile(reason) => write!(f, "Couldn't write the file {}.", reason),
+        }
+    }
+}
+
+impl CalcError {
+    /// The byte offsets in the input where the error happened, if known, eg. `(4, 5)` for the `)` in `2 * )`.
+    /// For input with macros, this refers to the input after the macros have been expanded,
+    /// and for `CalcError::Line`, to the line.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CalcError::Line(_, err) => err.span(),
+            CalcError::EmptyInput(span)
+            | CalcError::InvalidCharacter(_, span)
+            | CalcError::UnexpectedToken(_, span)
+            | CalcError::UnterminatedGroup(_, span) => Some(*span),
+            _ => None,
+        }
+    }
+
+    /// A short description of the error, eg. "expected ')'", meant to be shown next to its span.
+    pub fn message(&self) -> String {
+        diagnostic::describe(self)
+    }
+}
+
+/// The symbol of a token that closes a group, eg. `)`.
+pub(crate) fn closing_symbol(kind: &TokenKind) -> &'static str {
+    match kind {
+        TokenKind::ClosedParenthesis => ")",
+        TokenKind::ClosedBracket => "]",
+        TokenKind::ClosedCeil => "⌉",
+        TokenKind::ClosedFloor => "⌋",
+        TokenKind::Pipe => "|",
+        _ => "?",
+    }
+}
+
+impl From<CalcError> for String {
+    fn from(err: CalcError) -> Self {
+        err.to_string()
+    }
+}
+
+impl From<String> for CalcError {
+    fn from(message: String) -> Self {
+        CalcError::Message(message)
+    }
+}
+
+/// Evaluate expressions/declarations and return the answer.
+///
+/// `None` will be returned if the last statement is a declaration.
+pub fn eval(
+    context: &mut Context,
+    input: &str,
+    precision: u32,
+) -> Result<Option<Float>, CalcError> {
+    let statements = parse(context, input)?;
+    let mut interpreter = context.interpreter(precision)?;
+    let result = interpreter.interpret(statements);
+    context.operation_count = interpreter.operation_count();
+
+    result
+}
+
+/// Evaluate a file of declarations and expressions, one statement per line, eg. a library of formulas.
+///
+/// The lines are evaluated in order, with a precision of 53 bits, and the declarations stay in the context.
+/// The answers of the lines that are expressions are returned together with their line numbers, counted from 1.
+/// Empty lines and lines with only a comment are skipped, but block comments can't span several lines.
+///
+/// ```
+/// use kalk::parser;
+/// let mut context = parser::Context::new();
+/// let path = std::env::temp_dir().join("kalk_eval_file_example.kalk");
+/// std::fs::write(&path, "# circles\narea(r) = pi r^2\n\narea(1)").unwrap();
+/// let answers = parser::eval_file(&mut context, &path).unwrap();
+/// assert_eq!(answers.len(), 1);
+/// assert_eq!(answers[0].0, 4);
+/// ```
+///
+/// The first line that fails is returned as `CalcError::Line`, and a file that can't be read as `CalcError::ReadFile`.
+pub fn eval_file<P: AsRef<Path>>(
+    context: &mut Context,
+    path: P,
+) -> Result<Vec<(usize, Value)>, CalcError> {
+    eval_file_at(context, path, 53)
+}
+
+fn eval_file_at<P: AsRef<Path>>(
+    context: &mut Context,
+    path: P,
+    precision: u32,
+) -> Result<Vec<(usize, Value)>, CalcError> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+        .map_err(|err| CalcError::ReadFile(format!("{}: {}", path.display(), err)))?;
+
+    let mut answers = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        match context.eval_value(line, precision) {
+            Ok(Some(value)) => answers.push((index + 1, value)),
+            Ok(None) => (),
+            Err(err) => return Err(CalcError::Line(index + 1, Box::new(err))),
+        }
+    }
+
+    Ok(answers)
+}
+
+/// Collect the names of the functions and variables in the expression, without duplicates.
+fn collect_dependencies(expr: &Expr, functions: &mut Vec<String>, variables: &mut Vec<String>) {
+    fn add(names: &mut Vec<String>, name: &str) {
+        if !names.iter().any(|x| x == name) {
+            names.push(name.into());
+        }
+    }
+
+    match expr {
+        Expr::Binary(left, _, right) | Expr::Equation(left, right) => {
+            collect_dependencies(left, functions, variables);
+            collect_dependencies(right, functions, variables);
+        }
+        Expr::Unary(_, expr)
+        | Expr::Unit(expr, _)
+        | Expr::Conversion(expr, _)
+        | Expr::Group(expr)
+        | Expr::Annotated(expr, _) => collect_dependencies(expr, functions, variables),
+        Expr::Var(identifier) => add(variables, identifier),
+        Expr::FnCall(identifier, expressions) => {
+            add(functions, identifier);
+            for expr in expressions {
+                collect_dependencies(expr, functions, variables);
+            }
+        }
+        Expr::Vector(elements) => {
+            for expr in elements {
+                collect_dependencies(expr, functions, variables);
+            }
+        }
+        Expr::Conditional(condition, then, otherwise) => {
+            collect_dependencies(condition, functions, variables);
+            collect_dependencies(then, functions, variables);
+            collect_dependencies(otherwise, functions, variables);
+        }
+        Expr::Literal(_) => (),
+    }
+}
+
+fn display_hint(value: &Float) -> DisplayHint {
+    if !value.is_finite() {
+        return DisplayHint::Decimal;
+    }
+
+    if value.is_integer() {
+        return DisplayHint::Integer;
+    }
+
+    // Allow for the rounding errors of a few operations, eg. `0.1 + 0.2` is still 3/10.
+    let tolerance = Float::with_val(value.prec(), Float::i_exp(1, 8 - value.prec() as i32));
+    for denominator in 2..=MAX_FRACTION_DENOMINATOR {
+        let scaled = Float::with_val(value.prec(), value * denominator);
+        let distance = Float::with_val(value.prec(), &scaled - scaled.clone().round());
+        if distance.abs() <= Float::with_val(value.prec(), scaled.abs() * &tolerance) {
+            return DisplayHint::Fraction;
+        }
+    }
+
+    DisplayHint::Decimal
+}
+
+fn count_expr_nodes(expr: &Expr) -> usize {
+    let children = match expr {
+        Expr::Binary(left, _, right) | Expr::Equation(left, right) => {
+            count_expr_nodes(left) + count_expr_nodes(right)
+        }
+        Expr::Unary(_, expr)
+        | Expr::Unit(expr, _)
+        | Expr::Conversion(expr, _)
+        | Expr::Group(expr)
+        | Expr::Annotated(expr, _) => count_expr_nodes(expr),
+        Expr::FnCall(_, expressions) | Expr::Vector(expressions) => {
+            expressions.iter().map(count_expr_nodes).sum()
+        }
+        Expr::Conditional(condition, then, otherwise) => {
+            count_expr_nodes(condition) + count_expr_nodes(then) + count_expr_nodes(otherwise)
+        }
+        Expr::Var(_) | Expr::Literal(_) => 0,
+    };
+
+    children + 1
+}
+
+fn expr_depth(expr: &Expr) -> usize {
+    let children_depth = match expr {
+        Expr::Binary(left, _, right) | Expr::Equation(left, right) => {
+            expr_depth(left).max(expr_depth(right))
+        }
+        Expr::Unary(_, expr)
+        | Expr::Unit(expr, _)
+        | Expr::Conversion(expr, _)
+        | Expr::Group(expr)
+        | Expr::Annotated(expr, _) => expr_depth(expr),
+        Expr::FnCall(_, expressions) | Expr::Vector(expressions) => {
+            expressions.iter().map(expr_depth).max().unwrap_or(0)
+        }
+        Expr::Conditional(condition, then, otherwise) => expr_depth(condition)
+            .max(expr_depth(then))
+            .max(expr_depth(otherwise)),
+        Expr::Var(_) | Expr::Literal(_) => 0,
+    };
+
+    children_depth + 1
+}
+
+/// Make sure that a name given to `Context::define_var` or `Context::define_fn` can be written in an expression,
+/// ie. that it is a single identifier, eg. `x_1` but not `2x` or `a b`, and isn't a constant like `π`.
+fn check_name(name: &str) -> Result<(), CalcError> {
+    let tokens = Lexer::lex(name);
+    let is_identifier = match tokens.as_slice() {
+        [token, eof] => {
+            token.kind == TokenKind::Identifier && token.value == name && eof.kind == TokenKind::EOF
+        }
+        _ => false,
+    };
+
+    if is_identifier && !prelude::CONSTANTS.contains_key(name) {
+        Ok(())
+    } else {
+        Err(CalcError::Message(format!(
+            "'{}' is not a valid name.",
+            name
+        )))
+    }
+}
+
+/// Parse expressions/declarations and return a syntax tree.
+///
+/// `None` will be returned if the last statement is a declaration.
+pub fn parse(context: &mut Context, input: &str) -> Result<Vec<Stmt>, CalcError> {
+    context.tokens = if context.macros.is_empty() {
+        context.token_cache.lex(input)
+    } else {
+        let expanded = macros::expand(input, &context.macros)?;
+        context.token_cache.lex(&expanded)
+    };
+    context.pos = 0;
+    context.in_abs_group = false;
+
+    let mut statements: Vec<Stmt> = Vec::new();
+    while !is_at_end(context) {
+        // Statements can be separated by semicolons, eg. `x = 3; y = 4; x * y`.
+        // Empty statements, eg. after a trailing semicolon, are skipped.
+        if match_token(context, TokenKind::Semicolon) {
+            advance(context);
+            continue;
+        }
+
+        statements.push(parse_stmt(context)?);
+    }
+
+    if context.auto_simplify {
+        statements = statements
+            .into_iter()
+            .map(|stmt| simplify::simplify_stmt_for_eval(stmt, &context.symbol_table))
+            .collect();
+    }
+
+    Ok(statements)
+}
+
+/// Parse expressions/declarations and simplify them algebraically, eg. `2x + x * x + 1 + 1` becomes `2 * x + x ^ 2 + 2`.
+/// This is done regardless of `Context::auto_simplify`. Variables that aren't defined are treated as numbers,
+/// which means eg. `x / x` becomes 1 even though it is undefined for x = 0.
+pub fn parse_and_simplify(context: &mut Context, input: &str) -> Result<Vec<Stmt>, CalcError> {
+    Ok(parse(context, input)?
+        .into_iter()
+        .map(|stmt| simplify::simplify_stmt(stmt, &context.symbol_table))
+        .collect())
+}
+
+fn parse_stmt(context: &mut Context) -> Result<Stmt, CalcError> {
+    if match_token(context, TokenKind::Unit) {
+        return parse_unit_decl_stmt(context);
+    }
+
+    if match_token(context, TokenKind::Identifier) {
+        return Ok(match peek_next(context).kind {
+            TokenKind::Equals => parse_var_decl_stmt(context)?,
+            TokenKind::OpenParenthesis => parse_identifier_stmt(context)?,
+            _ => Stmt::Expr(Box::new(parse_equation(context)?)),
+        });
+    }
+
+    Ok(Stmt::Expr(Box::new(parse_equation(context)?)))
+}
+
+fn parse_identifier_stmt(context: &mut Context) -> Result<Stmt, CalcError> {
+    let began_at = context.pos;
+    let primary = parse_primary(context)?; // Since function declarations and function calls look the same at first, simply parse a "function call", and re-use the data.
+
+    // If `primary` is followed by an equal sign, it is a function declaration.
+    if let TokenKind::Equals = peek(context).kind {
+        advance(context);
+        let expr = parse_expr(context)?;
+
+        // Use the "function call" expression that was parsed, and put its values into a function declaration statement instead.
+        if let Expr::FnCall(identifier, parameters) = primary {
+            let mut parameter_identifiers = Vec::new();
+
+            // All the "arguments" are expected to be parsed as variables,
+            // since parameter definitions look the same as variable references.
+            // Extract these.
+            for parameter in parameters {
+                if let Expr::Var(parameter_identifier) = parameter {
+                    parameter_identifiers.push(parameter_identifier);
+                }
+            }
+
+            let fn_decl = Stmt::FnDecl(identifier.clone(), parameter_identifiers, Box::new(expr));
+
+            // Insert the function declaration into the symbol table during parsing
+            // so that the parser can find out if particular functions exist.
+            context.symbol_table.insert(fn_decl.clone());
+
+            return Ok(fn_decl);
+        }
+
+        Err(CalcError::Unknown)
+    } else {
+        // It is a function call, not a function declaration.
+        // Redo the parsing for this specific part.
+        context.pos = began_at;
+        Ok(Stmt::Expr(Box::new(parse_equation(context)?)))
+    }
+}
+
+fn parse_var_decl_stmt(context: &mut Context) -> Result<Stmt, CalcError> {
+    let identifier = advance(context).clone();
+    advance(context); // Equal sign
+    let expr = parse_expr(context)?;
+
+    Ok(Stmt::VarDecl(identifier.value, Box::new(expr)))
+}
+
+/// Eg. `unit m`, or `unit km = 1000 m`, where the definition is an amount of a unit that has already been declared.
+fn parse_unit_decl_stmt(context: &mut Context) -> Result<Stmt, CalcError> {
+    advance(context);
+    let identifier = consume(context, TokenKind::Identifier)?.value.clone();
+
+    // The built-in units can't be redefined.
+    if prelude::UNITS.contains_key(identifier.as_str()) {
+        return Err(CalcError::InvalidUnit);
+    }
+
+    let definition = if match_token(context, TokenKind::Equals) {
+        advance(context);
+        match parse_expr(context)? {
+            expr @ Expr::Unit(_, _) => Some(Box::new(expr)),
+            _ => return Err(CalcError::InvalidUnit),
+        }
+    } else {
+        None
+    };
+
+    let unit_decl = Stmt::UnitDecl(identifier, definition);
+
+    // Like functions, units are inserted into the symbol table during parsing,
+    // so that the parser can tell eg. `5 km` apart from `5 * km`.
+    context.symbol_table.insert(unit_decl.clone());
+
+    Ok(unit_decl)
+}
+
+/// An expression, or an equation if it is followed by an equal sign, eg. `2x + 3 = 11`.
+fn parse_equation(context: &mut Context) -> Result<Expr, CalcError> {
+    let left = parse_expr(context)?;
+
+    if match_token(context, TokenKind::Equals) {
+        advance(context);
+        let right = parse_expr(context)?;
+
+        return Ok(Expr::Equation(Box::new(left), Box::new(right)));
+    }
+
+    Ok(left)
+}
+
+fn parse_expr(context: &mut Context) -> Result<Expr, CalcError> {
+    // Declarations are handled by `parse_stmt`, so this is eg. `= 5`, which is missing a name.
+    if match_token(context, TokenKind::Equals) {
+        return Err(CalcError::UnexpectedToken(
+            TokenKind::Equals,
+            current_span(context),
+        ));
+    }
+
+    if match_token(context, TokenKind::If) {
+        return parse_conditional(context);
+    }
+
+    parse_conversion(context)
+}
+
+/// Eg. `if x < 0 then -x else x`. The else branch may be another conditional.
+fn parse_conditional(context: &mut Context) -> Result<Expr, CalcError> {
+    advance(context);
+    let condition = parse_expr(context)?;
+    consume(context, TokenKind::Then)?;
+    let then = parse_expr(context)?;
+    consume(context, TokenKind::Else)?;
+    let otherwise = parse_expr(context)?;
+
+    Ok(Expr::Conditional(
+        Box::new(condition),
+        Box::new(then),
+        Box::new(otherwise),
+    ))
+}
+
+/// Eg. `5 km to mi`.
+fn parse_conversion(context: &mut Context) -> Result<Expr, CalcError> {
+    let mut left = parse_or(context)?;
+
+    while match_token(context, TokenKind::To) {
+        advance(context);
+        let unit = consume(context, TokenKind::Identifier)?.value.clone();
+        if !context.symbol_table.contains_unit(&unit) {
+            return Err(CalcError::UndefinedUnit(unit));
+        }
+
+        left = Expr::Conversion(Box::new(left), unit);
+    }
+
+    Ok(left)
+}
+
+fn parse_or(context: &mut Context) -> Result<Expr, CalcError> {
+    let mut left = parse_and(context)?;
+
+    while match_token(context, TokenKind::Or) {
+        let op = advance(context).kind.clone();
+        let right = parse_and(context)?;
+
+        left = Expr::Binary(Box::new(left), op, Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_and(context: &mut Context) -> Result<Expr, CalcError> {
+    let mut left = parse_not(context)?;
+
+    while match_token(context, TokenKind::And) {
+        let op = advance(context).kind.clone();
+        let right = parse_not(context)?;
+
+        left = Expr::Binary(Box::new(left), op, Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_not(context: &mut Context) -> Result<Expr, CalcError> {
+    if match_token(context, TokenKind::Not) {
+        let op = advance(context).kind.clone();
+        let expr = Box::new(parse_not(context)?);
+        return Ok(Expr::Unary(op, expr));
+    }
+
+    parse_comparison(context)
+}
+
+fn parse_comparison(context: &mut Context) -> Result<Expr, CalcError> {
+    let mut left = parse_bitwise_or(context)?;
+
+    while match_token(context, TokenKind::EqualsEquals)
+        || match_token(context, TokenKind::NotEquals)
+        || match_token(context, TokenKind::GreaterThan)
+        || match_token(context, TokenKind::LessThan)
+        || match_token(context, TokenKind::GreaterOrEquals)
+        || match_token(context, TokenKind::LessOrEquals)
+    {
+        let op = advance(context).kind.clone();
+        let right = parse_bitwise_or(context)?;
+
+        left = Expr::Binary(Box::new(left), op, Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_bitwise_or(context: &mut Context) -> Result<Expr, CalcError> {
+    let mut left = parse_xor(context)?;
+
+    while match_token(context, TokenKind::Pipe) && !context.in_abs_group {
+        let op = advance(context).kind.clone();
+        let right = parse_xor(context)?;
+
+        left = Expr::Binary(Box::new(left), op, Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_xor(context: &mut Context) -> Result<Expr, CalcError> {
+    let mut left = parse_bitwise_and(context)?;
+
+    while match_token(context, TokenKind::Xor) {
+        let op = advance(context).kind.clone();
+        let right = parse_bitwise_and(context)?;
+
+        left = Expr::Binary(Box::new(left), op, Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_bitwise_and(context: &mut Context) -> Result<Expr, CalcError> {
+    let mut left = parse_shift(context)?;
+
+    while match_token(context, TokenKind::Ampersand) {
+        let op = advance(context).kind.clone();
+        let right = parse_shift(context)?;
+
+        left = Expr::Binary(Box::new(left), op, Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_shift(context: &mut Context) -> Result<Expr, CalcError> {
+    let mut left = parse_distance(context)?;
+
+    while match_token(context, TokenKind::ShiftLeft) || match_token(context, TokenKind::ShiftRight)
+    {
+        let op = advance(context).kind.clone();
+        let right = parse_distance(context)?;
+
+        left = Expr::Binary(Box::new(left), op, Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_distance(context: &mut Context) -> Result<Expr, CalcError> {
+    let mut left = parse_sum(context)?;
+
+    while match_token(context, TokenKind::Distance) {
+        let op = advance(context).kind.clone();
+        let right = parse_sum(context)?;
+
+        left = Expr::Binary(Box::new(left), op, Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_sum(context: &mut Context) -> Result<Expr, CalcError> {
+    let mut left = parse_factor(context)?;
+
+    while match_token(context, TokenKind::Plus) || match_token(context, TokenKind::Minus) {
+        let op = peek(context).kind.clone();
+        advance(context);
+        let right = parse_factor(context)?;
+
+        left = Expr::Binary(Box::new(left), op, Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_factor(context: &mut Context) -> Result<Expr, CalcError> {
+    let mut left = parse_unary(context)?;
+
+    while match_token(context, TokenKind::Star)
+        || match_token(context, TokenKind::Slash)
+        || match_token(context, TokenKind::DoubleSlash)
+        || match_token(context, TokenKind::Percent)
+        || match_token(context, TokenKind::Identifier)
+        || match_token(context, TokenKind::Literal)
+    {
+        // If the next token is an identifier, assume it's multiplication. Eg. 3y
+        let op = match peek(context).kind {
+            TokenKind::Identifier | TokenKind::Literal => TokenKind::Star,
+            _ => advance(context).kind.clone(),
+        };
+
+        let right = parse_unary(context)?;
+        left = Expr::Binary(Box::new(left), op, Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_unary(context: &mut Context) -> Result<Expr, CalcError> {
+    if match_token(context, TokenKind::Minus) || match_token(context, TokenKind::Tilde) {
+        let op = advance(context).kind.clone();
+        let expr = Box::new(parse_unary(context)?);
+        return Ok(Expr::Unary(op, expr));
+    }
+
+    parse_exponent(context)
+}
+
+fn parse_exponent(context: &mut Context) -> Result<Expr, CalcError> {
+    let left = parse_factorial(context)?;
+
+    if match_token(context, TokenKind::Power) {
+        let op = advance(context).kind.clone();
+        let right = Box::new(parse_exponent(context)?);
+        return Ok(Expr::Binary(Box::new(left), op, right));
+    }
+
+    Ok(left)
+}
+
+fn parse_factorial(context: &mut Context) -> Result<Expr, CalcError> {
+    let expr = parse_primary(context)?;
+
+    Ok(if match_token(context, TokenKind::Exclamation) {
+        advance(context);
+        Expr::Unary(TokenKind::Exclamation, Box::new(expr))
+    } else {
+        expr
+    })
+}
+
+fn parse_primary(context: &mut Context) -> Result<Expr, CalcError> {
+    if is_at_end(context) {
+        return Err(CalcError::EmptyInput(current_span(context)));
+    }
+
+    let expr = match peek(context).kind {
+        TokenKind::OpenParenthesis => parse_group(context)?,
+        TokenKind::OpenBracket => parse_vector(context)?,
+        TokenKind::Pipe | TokenKind::OpenCeil | TokenKind::OpenFloor => parse_group_fn(context)?,
+        TokenKind::Identifier => parse_identifier(context)?,
+        TokenKind::Unknown => return Err(unknown_token_error(peek(context))),
+        // Eg. `()` or `f(1, )`
+        TokenKind::ClosedParenthesis
+        | TokenKind::ClosedBracket
+        | TokenKind::ClosedCeil
+        | TokenKind::ClosedFloor
+        | TokenKind::Comma
+        | TokenKind::Semicolon => return Err(CalcError::EmptyInput(current_span(context))),
+        _ => Expr::Literal(advance(context).value.clone()),
+    };
+
+    // Eg. 30deg or 5 km
+    if match_token(context, TokenKind::Identifier)
+        && context
+            .symbol_table
+            .contains_unit(&context.tokens[context.pos].value)
+    {
+        Ok(Expr::Unit(Box::new(expr), advance(context).value.clone()))
+    } else {
+        Ok(expr)
+    }
+}
+
+fn parse_group(context: &mut Context) -> Result<Expr, CalcError> {
+    let opening = advance(context).span;
+
+    // Eg. |(1 | 2)|, where the pipe inside the parentheses is a bitwise or.
+    let in_abs_group = context.in_abs_group;
+    context.in_abs_group = false;
+    let expr = parse_expr(context)?;
+    context.in_abs_group = in_abs_group;
+
+    // Eg. (1, 2, 3)
+    if match_token(context, TokenKind::Comma) {
+        let elements = parse_vector_elements(context, expr, TokenKind::ClosedParenthesis, opening)?;
+
+        return Ok(Expr::Vector(elements));
+    }
+
+    let group_expr = Expr::Group(Box::new(expr));
+    consume_closing(context, TokenKind::ClosedParenthesis, opening)?;
+
+    Ok(group_expr)
+}
+
+fn parse_vector(context: &mut Context) -> Result<Expr, CalcError> {
+    let opening = advance(context).span;
+    let first = parse_expr(context)?;
+    let elements = parse_vector_elements(context, first, TokenKind::ClosedBracket, opening)?;
+
+    Ok(Expr::Vector(elements))
+}
+
+/// Parse the rest of the comma separated elements of a vector, and the closing token.
+fn parse_vector_elements(
+    context: &mut Context,
+    first: Expr,
+    closing: TokenKind,
+    opening: Span,
+) -> Result<Vec<Expr>, CalcError> {
+    let mut elements = vec![first];
+    while match_token(context, TokenKind::Comma) {
+        advance(context);
+        elements.push(parse_expr(context)?);
+    }
+    consume_closing(context, closing, opening)?;
+
+    Ok(elements)
+}
+
+fn parse_group_fn(context: &mut Context) -> Result<Expr, CalcError> {
+    let opening = advance(context).clone();
+    let (name, closing) = match opening.kind {
+        TokenKind::Pipe => ("abs", TokenKind::Pipe),
+        TokenKind::OpenCeil => ("ceil", TokenKind::ClosedCeil),
+        TokenKind::OpenFloor => ("floor", TokenKind::ClosedFloor),
+        kind => return Err(CalcError::UnexpectedToken(kind, opening.span)),
+    };
+
+    let in_abs_group = context.in_abs_group;
+    context.in_abs_group = name == "abs";
+    let expr = parse_expr(context)?;
+    context.in_abs_group = in_abs_group;
+    consume_closing(context, closing, opening.span)?;
+
+    Ok(Expr::FnCall(name.to_string(), vec![expr]))
+}
+
+fn parse_identifier(context: &mut Context) -> Result<Expr, CalcError> {
+    let identifier = advance(context).clone();
+
+    // Eg. sqrt64
+    if match_token(context, TokenKind::Literal) {
+        // If there is a function with this name, parse it as a function, with the next token as the argument.
+        if context.symbol_table.contains_fn(&identifier.value) {
+            let parameter = Expr::Literal(advance(context).value.clone());
+            return Ok(Expr::FnCall(identifier.value, vec![parameter]));
+        }
+    }
+
+    // Eg. sin x
+    // Like above, the function is applied before any operator, so `sin x^2` is `(sin x)^2`.
+    if match_token(context, TokenKind::Identifier)
+        && context.symbol_table.contains_fn(&identifier.value)
+    {
+        let parameter = parse_primary(context)?;
+        return Ok(Expr::FnCall(identifier.value, vec![parameter]));
+    }
+
+    // Eg. sqrt(64)
+    if match_token(context, TokenKind::OpenParenthesis) {
+        let opening = advance(context).span;
+
+        let mut parameters = Vec::new();
+        parameters.push(parse_expr(context)?);
+
+        // Eg. note(x^2, "kinetic energy")
+        if identifier.value == "note" {
+            consume(context, TokenKind::Comma)?;
+            let annotation = consume(context, TokenKind::Text)?.value.clone();
+            consume_closing(context, TokenKind::ClosedParenthesis, opening)?;
+
+            return Ok(Expr::Annotated(
+                Box::new(parameters.pop().unwrap()),
+                annotation,
+            ));
+        }
+
+        while match_token(context, TokenKind::Comma) {
+            advance(context);
+            parameters.push(parse_expr(context)?);
+        }
+
+        consume_closing(context, TokenKind::ClosedParenthesis, opening)?;
+
+        return Ok(Expr::FnCall(identifier.value, parameters));
+    }
+
+    // Eg. x
+    if context.symbol_table.contains_var(&identifier.value) {
+        Ok(Expr::Var(identifier.value))
+    } else {
+        let mut chars = identifier.value.chars();
+        let mut left = Expr::Var(chars.next().unwrap().to_string());
+
+        // Turn each individual character into its own variable reference.
+        // This parses eg `xy` as `x*y` instead of *one* variable.
+        for c in chars {
+            left = Expr::Binary(
+                Box::new(left),
+                TokenKind::Star,
+                Box::new(Expr::Var(c.to_string())),
+            );
+        }
+
+        Ok(left)
+    }
+}
+
+fn peek(context: &mut Context) -> &Token {
+    &context.tokens[context.pos]
+}
+
+fn peek_next(context: &mut Context) -> &Token {
+    &context.tokens[context.pos + 1]
+}
+
+fn previous(context: &mut Context) -> &Token {
+    &context.tokens[context.pos - 1]
+}
+
+fn match_token(context: &mut Context, kind: TokenKind) -> bool {
+    if is_at_end(context) {
+        return false;
+    }
+
+    peek(context).kind == kind
+}
+
+fn advance(context: &mut Context) -> &Token {
+    context.pos += 1;
+    previous(context)
+}
+
+fn consume(context: &mut Context, kind: TokenKind) -> Result<&Token, CalcError> {
+    if match_token(context, kind.clone()) {
+        return Ok(advance(context));
+    }
+
+    Err(CalcError::UnexpectedToken(kind, current_span(context)))
+}
+
+/// Consume the token that closes a group, eg. `)`. If the input ends before it, the group is unterminated.
+fn consume_closing(context: &mut Context, kind: TokenKind, opening: Span) -> Result<(), CalcError> {
+    if match_token(context, kind.clone()) {
+        advance(context);
+
+        return Ok(());
+    }
+
+    if is_at_end(context) {
+        Err(CalcError::UnterminatedGroup(kind, opening))
+    } else {
+        Err(CalcError::UnexpectedToken(kind, current_span(context)))
+    }
+}
+
+/// Unknown tokens are either a character that isn't part of the syntax, eg. `#`,
+/// or something malformed, eg. text without a closing quote.
+fn unknown_token_error(token: &Token) -> CalcError {
+    let mut chars = token.value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if token.span.1 - token.span.0 == c.len_utf8() => {
+            CalcError::InvalidCharacter(c, token.span)
+        }
+        _ => CalcError::UnexpectedToken(TokenKind::Unknown, token.span),
+    }
+}
+
+/// The span of the current token, or of the end of the input if all the tokens have been consumed.
+fn current_span(context: &Context) -> Span {
+    context
+        .tokens
+        .get(context.pos)
+        .or_else(|| context.tokens.last())
+        .map_or((0, 0), |token| token.span)
+}
+
+fn is_at_end(context: &mut Context) -> bool {
+    context.pos >= context.tokens.len() || peek(context).kind == TokenKind::EOF
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Unit;
+    use super::*;
+    use crate::lexer::{Token, TokenKind::*};
+    use crate::test_helpers::*;
+    use test_case::test_case;
+
+    fn parse_with_context(context: &mut Context, tokens: Vec<Token>) -> Result<Stmt, CalcError> {
+        context.tokens = tokens;
+
+        parse_stmt(context)
+    }
+
+    fn parse(tokens: Vec<Token>) -> Result<Stmt, CalcError> {
+        let mut context = Context::new();
+        context.tokens = tokens;
+
+        parse_stmt(&mut context)
+    }
+
+    #[test]
+    fn test_var() {
+        // x
+        let tokens = vec![token(Identifier, "x"), token(EOF, "")];
+
+        assert_eq!(parse(tokens).unwrap(), Stmt::Expr(var("x")));
+    }
+
+    #[test]
+    fn test_binary() {
+        // 1+2*(3-4/5)
+        let tokens = vec![
+            token(Literal, "1"),
+            token(Plus, ""),
+            token(Literal, "2"),
+            token(Star, ""),
+            token(OpenParenthesis, ""),
+            token(Literal, "3"),
+            token(Minus, ""),
+            token(Literal, "4"),
+            token(Slash, ""),
+            token(Literal, "5"),
+            token(ClosedParenthesis, ""),
+        ];
+
+        assert_eq!(
+            parse(tokens).unwrap(),
+            Stmt::Expr(binary(
+                literal("1"),
+                Plus,
+                binary(
+                    literal("2"),
+                    Star,
+                    group(binary(
+                        literal("3"),
+                        Minus,
+                        binary(literal("4"), Slash, literal("5"))
+                    ))
+                )
+            ))
+        );
+    }
+
+    #[test_case(vec![token(Equals, ""), token(Literal, "5")] ; "at the start")]
+    #[test_case(vec![token(Literal, "2"), token(Star, ""), token(OpenParenthesis, ""), token(Equals, "")] ; "in a group")]
+    fn test_leading_equals(tokens: Vec<Token>) {
+        assert_eq!(
+            parse(tokens),
+            Err(CalcError::UnexpectedToken(Equals, (0, 0)))
+        );
+    }
+
+    #[test]
+    fn test_distance() {
+        // 1+2<->3
+        let tokens = vec![
+            token(Literal, "1"),
+            token(Plus, ""),
+            token(Literal, "2"),
+            token(Distance, ""),
+            token(Literal, "3"),
+        ];
+
+        assert_eq!(
+            parse(tokens).unwrap(),
+            Stmt::Expr(binary(
+                binary(literal("1"), Plus, literal("2")),
+                Distance,
+                literal("3")
+            ))
+        );
+    }
+
+    #[test]
+    fn test_pow() {
+        let tokens = vec![
+            token(Literal, "1"),
+            token(Star, ""),
+            token(Literal, "2"),
+            token(Power, ""),
+            token(Literal, "3"),
+            token(Power, ""),
+            token(Literal, "4"),
+            token(Plus, ""),
+            token(Literal, "5"),
+        ];
+
+        assert_eq!(
+            parse(tokens).unwrap(),
+            Stmt::Expr(binary(
+                binary(
+                    literal("1"),
+                    Star,
+                    binary(
+                        literal("2"),
+                        Power,
+                        binary(literal("3"), Power, literal("4")),
+                    ),
+                ),
+                Plus,
+                literal("5")
+            )),
+        );
+    }
+
+    #[test_case("deg")]
+    #[test_case("rad")]
+    #[test_case("°" ; "degree sign")]
+    fn test_unary(angle_unit: &str) {
+        let tokens = vec![
+            token(Minus, ""),
+            token(Literal, "1"),
+            token(Identifier, angle_unit),
+        ];
+
+        assert_eq!(
+            parse(tokens).unwrap(),
+            Stmt::Expr(unary(
+                Minus,
+                Box::new(Expr::Unit(literal("1"), angle_unit.into()))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_var_decl() {
+        let tokens = vec![
+            token(Identifier, "x"),
+            token(Equals, ""),
+            token(Literal, "1"),
+            token(Plus, ""),
+            token(Literal, "2"),
+        ];
+
+        assert_eq!(
+            parse(tokens).unwrap(),
+            Stmt::VarDecl(String::from("x"), binary(literal("1"), Plus, literal("2")))
+        );
+    }
+
+    #[test]
+    fn test_fn_decl() {
+        let tokens = vec![
+            token(Identifier, "f"),
+            token(OpenParenthesis, ""),
+            token(Identifier, "x"),
+            token(ClosedParenthesis, ""),
+            token(Equals, ""),
+            token(Literal, "1"),
+            token(Plus, ""),
+            token(Literal, "2"),
+        ];
+
+        assert_eq!(
+            parse(tokens).unwrap(),
+            Stmt::FnDecl(
+                String::from("f"),
+                vec![String::from("x")],
+                binary(literal("1"), Plus, literal("2"))
+            )
+        );
+    }
+
+    #[test]
+    fn test_fn_call() {
+        let tokens = vec![
+            token(Identifier, "f"),
+            token(OpenParenthesis, ""),
+            token(Literal, "1"),
+            token(Plus, ""),
+            token(Literal, "2"),
+            token(ClosedParenthesis, ""),
+            token(Plus, ""),
+            token(Literal, "3"),
+        ];
+
+        let mut context = Context::new();
+
+        // Add the function to the symbol table first, in order to prevent errors.
+        context.symbol_table.insert(Stmt::FnDecl(
+            String::from("f"),
+            vec![String::from("x")],
+            literal("1"),
+        ));
+
+        assert_eq!(
+            parse_with_context(&mut context, tokens).unwrap(),
+            Stmt::Expr(binary(
+                Box::new(Expr::FnCall(
+                    String::from("f"),
+                    vec![*binary(literal("1"), Plus, literal("2"))]
+                )),
+                Plus,
+                literal("3")
+            ))
+        );
+    }
+
+    #[test]
+    fn test_note() {
+        // note(x^2, "kinetic energy")
+        let tokens = vec![
+            token(Identifier, "note"),
+            token(OpenParenthesis, ""),
+            token(Identifier, "x"),
+            token(Power, ""),
+            token(Literal, "2"),
+            token(Comma, ""),
+            token(Text, "kinetic energy"),
+            token(ClosedParenthesis, ""),
+            token(EOF, ""),
+        ];
+
+        assert_eq!(
+            parse(tokens).unwrap(),
+            Stmt::Expr(Box::new(Expr::Annotated(
+                binary(var("x"), Power, literal("2")),
+                String::from("kinetic energy")
+            )))
+        );
+    }
+
+    #[test_case("note(2 * 3, \"area\") + 1", 7f64)]
+    #[test_case("note(note(2, \"a\"), \"b\")", 2f64)]
+    fn test_note_eval(input: &str, expected: f64) {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_eval_with_tolerance() {
+        let mut context = Context::new();
+        let half_pi = Float::with_val(53, rug::float::Constant::Pi) / 2;
+
+        assert_eq!(
+            context
+                .eval_with_tolerance("1.5707963", 53, 1e-6)
+                .unwrap()
+                .unwrap(),
+            half_pi
+        );
+        assert_eq!(
+            context
+                .eval_with_tolerance("1.2345678", 53, 1e-9)
+                .unwrap()
+                .unwrap(),
+            1.2345678
+        );
+    }
+
+    #[test]
+    fn test_list_all_names() {
+        let mut context = Context::new();
+        eval(&mut context, "f(x, y) = x + y", 53).unwrap();
+        eval(&mut context, "x = 2", 53).unwrap();
+        let names = context.list_all_names();
+
+        assert!(names.contains(&(String::from("f"), NameKind::UserFn(2))));
+        assert!(names.contains(&(String::from("x"), NameKind::UserVar)));
+        assert!(names.contains(&(String::from("sin"), NameKind::BuiltinFn)));
+        assert!(names.contains(&(String::from("sum"), NameKind::BuiltinFn)));
+        assert!(names.contains(&(String::from("pi"), NameKind::Constant)));
+        assert!(names.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+    }
+
+    #[test_case("1e100 - 1e100 + 1", 53)]
+    #[test_case("1 - 0.75", 2)]
+    #[test_case("1 + 2 * 3", 0)]
+    #[test_case("5 - 4.9", 6)]
+    fn test_eval_checking_precision_loss(input: &str, expected: usize) {
+        let mut context = Context::new();
+        let (_, lost_bits) = context.eval_checking_precision_loss(input, 53).unwrap();
+
+        assert_eq!(lost_bits, expected);
+    }
+
+    #[test]
+    fn test_global_precision_guard() {
+        let mut context = Context::new();
+        {
+            let mut guard = context.global_precision_guard(256);
+            assert_eq!(eval(&mut guard, "1/3", 53).unwrap().unwrap().prec(), 256);
+
+            {
+                let mut inner = guard.global_precision_guard(128);
+                assert!(eval(&mut inner, "1 + y", 53).is_err());
+            }
+
+            assert_eq!(eval(&mut guard, "1/3", 53).unwrap().unwrap().prec(), 256);
+        }
+
+        assert_eq!(eval(&mut context, "1/3", 53).unwrap().unwrap().prec(), 53);
+    }
+
+    #[test_case("2 > 1", 1f64)]
+    #[test_case("2 < 1", 0f64)]
+    #[test_case("1 + 1 == 2", 1f64)]
+    #[test_case("3 != 3", 0f64)]
+    #[test_case("3 <> 4", 1f64)]
+    #[test_case("2 >= 2", 1f64)]
+    #[test_case("3 <= 2", 0f64)]
+    fn test_comparison(input: &str, expected: f64) {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test_case("2x + 3 = 11", 4f64)]
+    #[test_case("x + 3 = 7", 4f64)]
+    #[test_case("x^2 = 2", 2f64.sqrt())]
+    #[test_case("y/2 = a", 10f64 ; "declared variable")]
+    fn test_equation(input: &str, expected: f64) {
+        let mut context = Context::new();
+        eval(&mut context, "a = 5", 53).unwrap();
+        let result = eval(&mut context, input, 53).unwrap().unwrap();
+
+        assert_approx_eq!(&result, &Float::with_val(53, expected), 4);
+    }
+
+    #[test_case("x + y = 3")]
+    #[test_case("1 = 2")]
+    #[test_case("x^2 = -1")]
+    fn test_equation_error(input: &str) {
+        let mut context = Context::new();
+
+        assert!(matches!(
+            eval(&mut context, input, 53),
+            Err(CalcError::Message(_))
+        ));
+    }
+
+    #[test]
+    fn test_boolean_precedence() {
+        // not a or b and c < d
+        let tokens = vec![
+            token(Not, ""),
+            token(Identifier, "a"),
+            token(Or, ""),
+            token(Identifier, "b"),
+            token(And, ""),
+            token(Identifier, "c"),
+            token(LessThan, ""),
+            token(Identifier, "d"),
+        ];
+
+        assert_eq!(
+            parse(tokens).unwrap(),
+            Stmt::Expr(binary(
+                unary(Not, var("a")),
+                Or,
+                binary(var("b"), And, binary(var("c"), LessThan, var("d")))
+            ))
+        );
+    }
+
+    #[test_case("1 < 2 and 2 < 3", 1f64)]
+    #[test_case("1 < 2 and 3 < 2", 0f64)]
+    #[test_case("0 or 2", 1f64)]
+    #[test_case("0 or 0", 0f64)]
+    #[test_case("not 0", 1f64)]
+    #[test_case("not 5", 0f64)]
+    #[test_case("not 1 == 2", 1f64 ; "not of comparison")]
+    #[test_case("1 or 0 and 0", 1f64 ; "and before or")]
+    #[test_case("(1 or 0) and 0", 0f64 ; "grouped")]
+    fn test_boolean(input: &str, expected: f64) {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_conditional() {
+        let tokens = vec![
+            token(If, ""),
+            token(Identifier, "x"),
+            token(LessThan, ""),
+            token(Literal, "0"),
+            token(Then, ""),
+            token(Minus, ""),
+            token(Identifier, "x"),
+            token(Else, ""),
+            token(Identifier, "x"),
+        ];
+
+        assert_eq!(
+            parse(tokens).unwrap(),
+            Stmt::Expr(Box::new(Expr::Conditional(
+                binary(var("x"), LessThan, literal("0")),
+                unary(Minus, var("x")),
+                var("x"),
+            )))
+        );
+    }
+
+    #[test_case("if 2 > 1 then 3 else 4", 3f64)]
+    #[test_case("if 0 then 3 else 4", 4f64)]
+    #[test_case("if 0 then 1 else if 0.5 then 2 else 3", 2f64 ; "else if")]
+    #[test_case("2 * (if 1 == 1 then 3 else 4)", 6f64 ; "in a group")]
+    #[test_case("if 1 then 2 else y", 2f64 ; "only the chosen branch is evaluated")]
+    #[test_case("f(x) = if x < 0 then -x else x\nf(-3) + f(4)", 7f64 ; "in a function")]
+    fn test_conditional(input: &str, expected: f64) {
+        let mut context = Context::new();
+        let mut result = None;
+        for line in input.lines() {
+            result = eval(&mut context, line, 53).unwrap();
+        }
+
+        assert_eq!(result.unwrap(), expected);
+    }
+
+    #[test_case("fib(10)", 55f64)]
+    #[test_case("count(200)", 200f64 ; "deep")]
+    #[test_case("count(3) + n", 4f64 ; "parameters are only defined in the call")]
+    #[test_case("fib(count(3) + 1) * 2", 6f64 ; "nested")]
+    fn test_recursion(input: &str, expected: f64) {
+        let mut context = Context::new();
+        eval(
+            &mut context,
+            "fib(n) = if n < 2 then n else fib(n - 1) + fib(n - 2)",
+            53,
+        )
+        .unwrap();
+        eval(
+            &mut context,
+            "count(n) = if n <= 0 then 0 else 1 + count(n - 1)",
+            53,
+        )
+        .unwrap();
+        eval(&mut context, "n = 1", 53).unwrap();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        let mut context = Context::new().set_recursion_limit(10);
+        eval(
+            &mut context,
+            "count(n) = if n <= 0 then 0 else 1 + count(n - 1)",
+            53,
+        )
+        .unwrap();
+
+        assert_eq!(eval(&mut context, "count(9)", 53).unwrap().unwrap(), 9f64);
+        assert_eq!(
+            eval(&mut context, "count(10)", 53),
+            Err(CalcError::RecursionLimitReached(String::from("count")))
+        );
+    }
+
+    #[test]
+    fn test_infinite_recursion() {
+        let mut context = Context::new();
+        eval(&mut context, "f(x) = f(x) + 1", 53).unwrap();
+
+        assert_eq!(
+            eval(&mut context, "f(1)", 53),
+            Err(CalcError::RecursionLimitReached(String::from("f")))
+        );
+        // The parameters of the calls that failed aren't left behind.
+        assert_eq!(
+            eval(&mut context, "x", 53),
+            Err(CalcError::UndefinedVar(String::from("x")))
+        );
+    }
+
+    #[test_case("if 1 then 2", TokenKind::Else, (11, 11))]
+    #[test_case("if 1 else 2", TokenKind::Then, (5, 9))]
+    fn test_conditional_error(input: &str, expected: TokenKind, span: Span) {
+        let mut context = Context::new();
+
+        assert_eq!(
+            eval(&mut context, input, 53),
+            Err(CalcError::UnexpectedToken(expected, span))
+        );
+    }
+
+    #[test_case("π + (2]", ClosedParenthesis, (7, 8) ; "after unicode")]
+    #[test_case("√(2 + 3]", ClosedParenthesis, (9, 10) ; "function call")]
+    #[test_case("3 + \"text", Unknown, (4, 9) ; "unterminated text")]
+    #[test_case("3 + 4 /* four", Unknown, (6, 8) ; "unterminated comment")]
+    #[test_case("1 + 0b102", Unknown, (4, 9) ; "invalid binary digit")]
+    fn test_unexpected_token_span(input: &str, kind: TokenKind, span: Span) {
+        let mut context = Context::new();
+        let err = eval(&mut context, input, 53).unwrap_err();
+
+        assert_eq!(err.span(), Some(span));
+        assert_eq!(err, CalcError::UnexpectedToken(kind, span));
+    }
+
+    #[test_case("π + (2", CalcError::UnterminatedGroup(ClosedParenthesis, (5, 6)) ; "parenthesis")]
+    #[test_case("√(2 + 3", CalcError::UnterminatedGroup(ClosedParenthesis, (3, 4)) ; "function call")]
+    #[test_case("[1, 2", CalcError::UnterminatedGroup(ClosedBracket, (0, 1)) ; "vector")]
+    #[test_case("⌈2.5", CalcError::UnterminatedGroup(ClosedCeil, (0, 3)) ; "ceil")]
+    #[test_case("|1 - 2", CalcError::UnterminatedGroup(Pipe, (0, 1)) ; "abs")]
+    #[test_case("⌈2.5⌋", CalcError::UnexpectedToken(ClosedCeil, (6, 9)) ; "mismatched")]
+    #[test_case("1 @ 2", CalcError::InvalidCharacter('@', (2, 3)) ; "invalid character")]
+    #[test_case("1 +", CalcError::EmptyInput((3, 3)) ; "missing operand")]
+    #[test_case("⌈", CalcError::EmptyInput((3, 3)) ; "only opening")]
+    #[test_case("()", CalcError::EmptyInput((1, 2)) ; "empty group")]
+    #[test_case("max(1, )", CalcError::EmptyInput((7, 8)) ; "empty argument")]
+    #[test_case("1 +; 2", CalcError::EmptyInput((3, 4)) ; "before semicolon")]
+    fn test_malformed_input(input: &str, expected: CalcError) {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, input, 53), Err(expected));
+    }
+
+    #[test_case("x = 3; y = 4; x * y", 12f64 ; "declarations")]
+    #[test_case("f(x) = 2x; g(x) = f(x) + 1; g(3)", 7f64 ; "functions")]
+    #[test_case("x = 2; y = x + 1; y * 2", 6f64 ; "in order")]
+    #[test_case("1; 2;; 3;", 3f64 ; "empty statements")]
+    #[test_case("x = 3; (x + 1) * 2", 8f64 ; "group")]
+    fn test_semicolons(input: &str, expected: f64) {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test_case("x = 3; # the radius\n 2x", 6f64 ; "line comment")]
+    #[test_case("2 /* two */ * 3 /* three */", 6f64 ; "block comment")]
+    #[test_case("7 // 2 # integer division", 3f64 ; "after integer division")]
+    fn test_comments(input: &str, expected: f64) {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    fn write_script(name: &str, content: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, content).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn test_eval_file() {
+        let path = write_script(
+            "kalk_test_eval_file.kalk",
+            "# Formulas\nk = 3\nf(x) = k x # scaled\n\nf(2)\n[1, 2] * k\n",
+        );
+        let mut context = Context::new();
+        let answers = eval_file(&mut context, &path).unwrap();
+
+        assert_eq!(answers.len(), 2);
+        assert_eq!(answers[0].0, 5);
+        assert_eq!(answers[0].1, Value::Scalar(Float::with_val(53, 6)));
+        assert_eq!(answers[1].0, 6);
+        assert_eq!(
+            answers[1].1,
+            Value::Vector(vec![Float::with_val(53, 3), Float::with_val(53, 6)])
+        );
+
+        // The declarations stay in the context.
+        assert_eq!(eval(&mut context, "f(k)", 53).unwrap().unwrap(), 9f64);
+    }
+
+    #[test]
+    fn test_eval_file_error() {
+        let path = write_script("kalk_test_eval_file_error.kalk", "x = 2\nx + (1\nx");
+        let mut context = Context::new();
+        let err = eval_file(&mut context, &path).unwrap_err();
+
+        assert_eq!(
+            err,
+            CalcError::Line(
+                2,
+                Box::new(CalcError::UnterminatedGroup(ClosedParenthesis, (4, 5)))
+            )
+        );
+        assert_eq!(err.span(), Some((4, 5)));
+
+        let missing = std::env::temp_dir().join("kalk_test_missing_file.kalk");
+        assert!(matches!(
+            eval_file(&mut context, &missing),
+            Err(CalcError::ReadFile(_))
+        ));
+    }
+
+    #[test]
+    fn test_semicolon_last_declaration() {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, "x = 3; y = x;", 53), Ok(None));
+        assert_eq!(eval(&mut context, "y", 53).unwrap().unwrap(), 3f64);
+    }
+
+    #[test]
+    fn test_semicolon_error_in_earlier_statement() {
+        let mut context = Context::new();
+
+        assert_eq!(
+            eval(&mut context, "foo(1); 2", 53),
+            Err(CalcError::UndefinedFn(String::from("foo")))
+        );
+        assert_eq!(
+            context.eval_value("foo(1); [2]", 53),
+            Err(CalcError::UndefinedFn(String::from("foo")))
+        );
+    }
+
+    #[test_case("" ; "empty")]
+    #[test_case("   " ; "spaces")]
+    #[test_case("\u{a0}" ; "non-breaking space")]
+    fn test_empty_input(input: &str) {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, input, 53), Ok(None));
+    }
+
+    #[test]
+    fn test_eval_boolean_array() {
+        let mut context = Context::new();
+        eval(&mut context, "x = 3", 53).unwrap();
+
+        assert_eq!(
+            context
+                .eval_boolean_array(&["x > 0", "x < 10", "x != 5", "x - 3"], 53)
+                .unwrap(),
+            vec![true, true, true, false]
+        );
+        assert_eq!(
+            context.eval_boolean_array(&["x > 0", "y > 0"], 53),
+            Err(CalcError::UndefinedVar(String::from("y")))
+        );
+        assert!(matches!(
+            context.eval_boolean_array(&["x = 2"], 53),
+            Err(CalcError::Message(_))
+        ));
+    }
+
+    #[test_case("30deg + 30deg", 60f64, Some("deg"))]
+    #[test_case("2 * 0.5rad", 57.29577951308232, Some("deg"))]
+    #[test_case("asin(1)", 90f64, Some("deg"))]
+    #[test_case("abs(-30°)", 30f64, Some("deg"))]
+    #[test_case("sin(90deg)", 1f64, None)]
+    #[test_case("2 + 3", 5f64, None)]
+    fn test_eval_with_units(input: &str, expected_value: f64, expected_unit: Option<&str>) {
+        let mut context = Context::new().set_angle_unit(Unit::Degrees);
+        let (value, unit) = context.eval_with_units(input, 53).unwrap().unwrap();
+
+        assert_approx_eq!(&value, &Float::with_val(53, expected_value), 2);
+        assert_eq!(unit.as_deref(), expected_unit);
+    }
+
+    #[test]
+    fn test_eval_with_units_var() {
+        let mut context = Context::new();
+        context.eval_with_units("x = 30deg", 53).unwrap();
+        context.eval_with_units("f(y) = 2y", 53).unwrap();
+        let (_, unit) = context.eval_with_units("f(x)", 53).unwrap().unwrap();
+
+        assert_eq!(unit.as_deref(), Some("rad"));
+    }
+
+    #[test_case("5 km", 5000f64, Some("m"))]
+    #[test_case("5 km to mi", 3.1068559611866697, Some("mi"))]
+    #[test_case("1 mi + 1 km to km", 2.609344, Some("km"))]
+    #[test_case("3000 to km", 3f64, Some("km"))]
+    #[test_case("180deg to rad", std::f64::consts::PI, Some("rad"))]
+    #[test_case("2 km * 3 to mi to km", 6f64, Some("km"))]
+    fn test_unit_conversion(input: &str, expected_value: f64, expected_unit: Option<&str>) {
+        let mut context = Context::new();
+        eval(&mut context, "unit m", 53).unwrap();
+        eval(&mut context, "unit km = 1000 m", 53).unwrap();
+        eval(&mut context, "unit mi = 1.609344 km", 53).unwrap();
+        let (value, unit) = context.eval_with_units(input, 53).unwrap().unwrap();
+
+        assert_approx_eq!(&value, &Float::with_val(53, expected_value), 8);
+        assert_eq!(unit.as_deref(), expected_unit);
+    }
+
+    #[test_case("3 m + 4 s", Err(("m", "s")))]
+    #[test_case("3 s - 1 km", Err(("s", "m")))]
+    #[test_case("1 km < 2 s", Err(("m", "s")))]
+    #[test_case("x + 1 s", Err(("m", "s")) ; "variable")]
+    #[test_case("[1, 2] m + [3, 4] s", Err(("m", "s")) ; "vectors")]
+    #[test_case("3 m + 4 km", Ok(4003f64))]
+    #[test_case("3 m + 1", Ok(4f64))]
+    #[test_case("2 m * 3 s", Ok(6f64))]
+    fn test_dimensional_analysis(input: &str, expected: Result<f64, (&str, &str)>) {
+        let mut context = Context::new();
+        eval(&mut context, "unit m", 53).unwrap();
+        eval(&mut context, "unit s", 53).unwrap();
+        eval(&mut context, "unit km = 1000 m", 53).unwrap();
+        eval(&mut context, "x = 3 km", 53).unwrap();
+
+        let result = context.eval_value(input, 53).map(|value| match value {
+            Some(Value::Scalar(x)) => x.to_f64(),
+            _ => f64::NAN,
+        });
+        let expected = expected
+            .map_err(|(left, right)| CalcError::IncompatibleUnits(left.into(), right.into()));
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_unit_errors() {
+        let mut context = Context::new();
+        eval(&mut context, "unit m", 53).unwrap();
+        eval(&mut context, "unit s", 53).unwrap();
+        eval(&mut context, "unit a = 2 m", 53).unwrap();
+        eval(&mut context, "unit b = 2 a", 53).unwrap();
+        eval(&mut context, "unit a = 2 b", 53).unwrap();
+
+        assert_eq!(
+            eval(&mut context, "5 m to s", 53),
+            Err(CalcError::IncompatibleUnits(
+                String::from("m"),
+                String::from("s")
+            ))
+        );
+        assert_eq!(
+            eval(&mut context, "5 m to ly", 53),
+            Err(CalcError::UndefinedUnit(String::from("ly")))
+        );
+        assert_eq!(
+            eval(&mut context, "1 a", 53),
+            Err(CalcError::CircularDependency(String::from("a")))
+        );
+        assert_eq!(
+            eval(&mut context, "unit x = 5", 53),
+            Err(CalcError::InvalidUnit)
+        );
+        assert_eq!(
+            eval(&mut context, "unit deg = 2 rad", 53),
+            Err(CalcError::InvalidUnit)
+        );
+    }
+
+    #[test_case("0xff + 0b1", 256f64)]
+    #[test_case("0o17 * 2", 30f64)]
+    #[test_case("0x10^2", 256f64)]
+    fn test_radix_literals(input: &str, expected: f64) {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test_case("1.5e3 + 1", 1501f64)]
+    #[test_case("2e-2", 0.02)]
+    #[test_case("2e - 2 * e", 0f64 ; "euler")]
+    #[test_case("2e3x", 4000f64 ; "implicit multiplication")]
+    fn test_scientific_notation(input: &str, expected: f64) {
+        let mut context = Context::new();
+        eval(&mut context, "x = 2", 53).unwrap();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test_case("7 % 3", 1f64)]
+    #[test_case("-7 % 3", 2f64 ; "negative dividend")]
+    #[test_case("7 % -3", -2f64 ; "negative divisor")]
+    #[test_case("7.5 % 2", 1.5f64)]
+    #[test_case("7 // 2", 3f64)]
+    #[test_case("-7 // 2", -4f64 ; "negative floor division")]
+    #[test_case("2 + 7 // 2 * 3", 11f64 ; "same precedence as multiplication")]
+    #[test_case("10 % 4 ^ 2", 10f64 ; "power before modulo")]
+    fn test_modulo(input: &str, expected: f64) {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test_case("6 & 3", 2f64)]
+    #[test_case("6 | 3", 7f64)]
+    #[test_case("6 xor 3", 5f64)]
+    #[test_case("1 << 4", 16f64)]
+    #[test_case("-16 >> 2", -4f64)]
+    #[test_case("~5", -6f64)]
+    #[test_case("1 | 2 & 3 << 1", 3f64 ; "precedence")]
+    #[test_case("1 + 1 << 2", 8f64 ; "arithmetic before shift")]
+    #[test_case("|-3| | 4", 7f64 ; "after abs")]
+    #[test_case("|(1 | 2) - 5|", 2f64 ; "inside abs")]
+    fn test_bitwise(input: &str, expected: f64) {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test_case("1.5 & 1")]
+    #[test_case("~0.5")]
+    #[test_case("1 << -1")]
+    #[test_case("1 << 4000000000" ; "too large shift")]
+    #[test_case("(1 << 1000000) << 1000000" ; "too large result")]
+    fn test_bitwise_error(input: &str) {
+        let mut context = Context::new();
+
+        assert!(matches!(
+            eval(&mut context, input, 53),
+            Err(CalcError::Message(_))
+        ));
+    }
+
+    #[test_case("0.1 + 0.2", "0.3")]
+    #[test_case("sqrt(-4)", "2i")]
+    #[test_case("-i/2", "-0.5i" ; "negative imaginary")]
+    #[test_case("1 - 2.5i", "1 - 2.5i")]
+    fn test_format_complex(input: &str, expected: &str) {
+        let mut context = Context::new();
+        let value = context.eval_complex(input, 53).unwrap().unwrap();
+
+        assert_eq!(value.format(&FormatOptions::new()), expected);
+    }
+
+    #[test_case("simplify(x^2 * x)", 27f64)]
+    #[test_case("simplify(x) + 1", 4f64 ; "in an expression")]
+    fn test_simplify_fn(input: &str, expected: f64) {
+        let mut context = Context::new();
+        eval(&mut context, "x = 3", 53).unwrap();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test_case("1/4 + 1/4", Some("1/2"))]
+    #[test_case("asin(1)", Some("π/2"))]
+    #[test_case("sqrt(2)", None)]
+    #[test_case("sqrt(-1)", None ; "complex")]
+    fn test_estimate_fraction(input: &str, expected: Option<&str>) {
+        let mut context = Context::new();
+        let value = context.eval_complex(input, 53).unwrap().unwrap();
+
+        assert_eq!(value.estimate_fraction().as_deref(), expected);
+    }
+
+    #[test_case(16, 255f64, Some("0xff"))]
+    #[test_case(16, -255f64, Some("-0xff") ; "negative")]
+    #[test_case(2, 10f64, Some("0b1010"))]
+    #[test_case(8, 15f64, Some("0o17"))]
+    #[test_case(10, 255f64, Some("255"))]
+    #[test_case(36, 35f64, Some("z"))]
+    #[test_case(16, 2.5, None)]
+    fn test_output_base(base: u32, value: f64, expected: Option<&str>) {
+        let context = Context::new().set_output_base(base);
+        let formatted = context.format_value(&Float::with_val(53, value));
+
+        assert_eq!(formatted.as_deref(), expected);
+    }
+
+    #[test]
+    fn test_lexer_cache() {
+        let mut context = Context::new().set_lexer_cache_size(8);
+        eval(&mut context, "x = 2", 53).unwrap();
+        eval(&mut context, "x + 1", 53).unwrap();
+        let tokens = context.tokens.clone();
+
+        assert_eq!(eval(&mut context, "x + 1", 53).unwrap().unwrap(), 3);
+        assert_eq!(context.tokens, tokens);
+    }
+
+    #[test_case(RoundingMode::Nearest, 0.34375, 0.34375)]
+    #[test_case(RoundingMode::Up, 0.34375, 0.34375)]
+    #[test_case(RoundingMode::Down, 0.3125, 0.3125)]
+    #[test_case(RoundingMode::TowardZero, 0.3125, 0.3125)]
+    fn test_precision_rounding(mode: RoundingMode, third: f64, literal: f64) {
+        // 1/3 is between 0.3125 (0.0101 in binary) and 0.34375 (0.01011) with 4 bits.
+        let mut context = Context::new().set_precision_rounding(mode);
+
+        assert_eq!(eval(&mut context, "1/3", 4).unwrap().unwrap(), third);
+        assert_eq!(eval(&mut context, "0.33", 4).unwrap().unwrap(), literal);
+    }
+
+    // With the `cache` feature, these results would otherwise be re-used after the settings they depend on change.
+    #[test]
+    fn test_settings_change_result() {
+        let mut context = Context::new();
+        assert_approx_eq!(
+            &eval(&mut context, "sin(90)+0", 53).unwrap().unwrap(),
+            &Float::with_val(53, 90).sin(),
+            1
+        );
+        context = context.set_angle_unit(Unit::Degrees);
+        assert_eq!(eval(&mut context, "sin(90)+0", 53).unwrap().unwrap(), 1);
+
+        assert_eq!(eval(&mut context, "1/3", 4).unwrap().unwrap(), 0.34375);
+        context = context.set_precision_rounding(RoundingMode::Down);
+        assert_eq!(eval(&mut context, "1/3", 4).unwrap().unwrap(), 0.3125);
+    }
+
+    #[test]
+    fn test_integer_mode() {
+        let mut context = Context::new().set_integer_mode(true);
+
+        assert_eq!(
+            eval(&mut context, "(2^10 - 24) / 8 * 3", 53)
+                .unwrap()
+                .unwrap(),
+            375
+        );
+        // 2^100 doesn't fit in a 53-bit float, but the result is calculated exactly with enough bits.
+        assert_eq!(
+            eval(&mut context, "2^100 + 1", 128).unwrap().unwrap(),
+            Float::with_val(
+                128,
+                Float::parse("1267650600228229401496703205377").unwrap()
+            )
+        );
+        assert_eq!(eval(&mut context, "7/2", 53), Err(CalcError::NotDivisible));
+        assert_eq!(eval(&mut context, "2^100", 53), Err(CalcError::Overflow));
+        assert_eq!(
+            eval(&mut context, "1.5 * 2", 53),
+            Err(CalcError::NotAnInteger(String::from("1.5000000000000000")))
+        );
+        assert!(matches!(
+            eval(&mut context, "sqrt(2)", 53),
+            Err(CalcError::NotAnInteger(_))
+        ));
+    }
+
+    #[test_case("1/3 + 1/3", "2/3")]
+    #[test_case("0.1 + 0.2", "3/10")]
+    #[test_case("(2/3)^(-2) * 4", "9")]
+    #[test_case("-7 % 3 + 1/2", "5/2" ; "modulo")]
+    #[test_case("x * 3", "1/2" ; "variable")]
+    #[test_case("sqrt(4) / 3", "0.666666666666667" ; "function")]
+    #[test_case("1/0", "∞" ; "division by zero")]
+    fn test_eval_exact(input: &str, expected: &str) {
+        let mut context = Context::new();
+        context.eval_exact("x = 1/6", 53).unwrap();
+        let value = context.eval_exact(input, 53).unwrap().unwrap();
+
+        assert_eq!(value.format(&FormatOptions::new()), expected);
+    }
+
+    #[test]
+    fn test_decimal_mode() {
+        let mut context = Context::new().set_decimal_mode(true);
+        let mut float_context = Context::new();
+        let expected = Float::with_val(53, Float::parse("0.3").unwrap());
+
+        assert_eq!(
+            eval(&mut context, "0.1 + 0.2", 53).unwrap().unwrap(),
+            expected
+        );
+        assert_ne!(
+            eval(&mut float_context, "0.1 + 0.2", 53).unwrap().unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_decimal_mode_too_large() {
+        let mut context = Context::new().set_decimal_mode(true);
+        let mut float_context = Context::new();
+
+        // Too large to calculate exactly, so it is calculated with floating point numbers instead.
+        assert_eq!(
+            eval(&mut context, "(10^1000)^1000", 53).unwrap().unwrap(),
+            eval(&mut float_context, "(10^1000)^1000", 53)
+                .unwrap()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decimal_mode_callbacks() {
+        use crate::hooks::EvalEvent::*;
+        use std::sync::{Arc, Mutex};
+
+        let mut context = Context::new().set_decimal_mode(true);
+        eval(&mut context, "x = 0.1", 53).unwrap();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        for event in [BeforeVarAccess(String::new()), ValueComputed(0f64)] {
+            let log = Arc::clone(&log);
+            context.register_callback(
+                event,
+                Box::new(move |view| {
+                    log.lock().unwrap().push(view.event().clone());
+                    Ok(())
+                }),
+            );
+        }
+
+        eval(&mut context, "x + 0.2", 53).unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![BeforeVarAccess("x".into()), ValueComputed(0.3)]
+        );
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let calls: Vec<String> = prelude::UNARY_FUNCS
+            .keys()
+            .map(|name| format!("{}(0.5)", name))
+            .chain(
+                prelude::BINARY_FUNCS
+                    .keys()
+                    .map(|name| format!("{}(0.5, 2)", name)),
+            )
+            .chain(prelude::CONSTANTS.keys().map(|name| name.to_string()))
+            .collect();
+        let mut inputs: Vec<&str> = calls.iter().map(String::as_str).collect();
+        inputs.extend(&["sum(1, 10, 2n + 1)", "5.2!", "1/3 + 30deg", "1/0"]);
+
+        eval_all_deterministic(&inputs);
+    }
+
+    #[test]
+    fn test_eval_returning_f64() {
+        let mut context = Context::new();
+
+        assert_eq!(context.eval_returning_f64("1.5 * 3"), Ok(Some(4.5)));
+        assert_eq!(context.eval_returning_f64("x = 2"), Ok(None));
+        assert_eq!(
+            context.eval_returning_f64("10^400"),
+            Err(CalcError::Overflow)
+        );
+
+        let mut limited = Context::new().set_max_precision(24);
+        assert_eq!(
+            limited.eval_returning_f64("1/3"),
+            Ok(Some((1f32 / 3f32) as f64))
+        );
+        assert_eq!(
+            limited.eval_returning_f64("10^400"),
+            Err(CalcError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_eval_returning_i64() {
+        let mut context = Context::new();
+
+        assert_eq!(context.eval_returning_i64("2^10"), Ok(Some(1024)));
+        assert_eq!(context.eval_returning_i64("2^63 - 1"), Ok(Some(i64::MAX)));
+        assert_eq!(context.eval_returning_i64("x = 2"), Ok(None));
+        assert!(matches!(
+            context.eval_returning_i64("2.5"),
+            Err(CalcError::InvalidNumberLiteral(_))
+        ));
+        assert_eq!(context.eval_returning_i64("2^63"), Err(CalcError::Overflow));
+
+        let mut limited = Context::new().set_max_precision(32);
+        assert_eq!(limited.eval_returning_i64("-2^32"), Ok(Some(-(1 << 32))));
+        assert_eq!(
+            limited.eval_returning_i64("2^62 + 1"),
+            Err(CalcError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_import_variables() {
+        let mut context = Context::new();
+        context
+            .import_variables(&[("a".to_string(), 1.5), ("b".to_string(), 2.5)])
+            .unwrap();
+        assert_eq!(eval(&mut context, "a+b", 53).unwrap().unwrap(), 4);
+
+        context
+            .import_variables(&[("b".to_string(), -0.25)])
+            .unwrap();
+        assert_eq!(eval(&mut context, "a+b", 53).unwrap().unwrap(), 1.25);
+    }
+
+    #[test_case(f64::NAN, "NaN")]
+    #[test_case(f64::INFINITY, "inf")]
+    #[test_case(f64::NEG_INFINITY, "-inf")]
+    fn test_import_variables_not_finite(value: f64, literal: &str) {
+        let mut context = Context::new();
+        let result = context
+            .import_variables(&[("a".to_string(), 1.5), ("b".to_string(), value)])
+            .map(|_| ());
+
+        assert_eq!(
+            result,
+            Err(CalcError::InvalidNumberLiteral(literal.to_string()))
+        );
+        assert!(context.symbol_table().get_var("a").is_none());
+    }
+
+    #[test_case("sin x^2", "(sin(x))^2")]
+    #[test_case("sin2^2", "(sin(2))^2")]
+    #[test_case("2sin x", "2sin(x)")]
+    #[test_case("sqrt x + 1", "sqrt(x) + 1")]
+    fn test_fn_without_parentheses(input: &str, equivalent: &str) {
+        let mut context = Context::new();
+        eval(&mut context, "x = 2", 53).unwrap();
+
+        assert_eq!(
+            eval(&mut context, input, 53).unwrap(),
+            eval(&mut context, equivalent, 53).unwrap()
+        );
+    }
+
+    #[test_case("1_000 + 1_000", 2000f64)]
+    #[test_case("1_234.567_89", 1234.56789)]
+    fn test_number_literal_separator(input: &str, expected: f64) {
+        let mut context = Context::new();
+        let result = eval(&mut context, input, 53).unwrap().unwrap();
+
+        assert_approx_eq!(&result, &Float::with_val(53, expected), 0);
+    }
+
+    #[test]
+    fn test_error_conversion() {
+        fn eval_to_string_err(input: &str) -> Result<String, String> {
+            let mut context = Context::new();
+            let result = eval(&mut context, input, 53)?.unwrap();
+
+            Ok(result.to_string())
+        }
+
+        assert_eq!(
+            eval_to_string_err("1 + y"),
+            Err(String::from("Undefined variable: 'y'."))
+        );
+        assert_eq!(
+            CalcError::from(String::from("Not supported.")),
+            CalcError::Message(String::from("Not supported."))
+        );
+        assert_eq!(
+            CalcError::Message(String::from("Not supported.")).to_string(),
+            "Not supported."
+        );
+    }
+
+    #[test]
+    fn test_last_answer() {
+        let mut context = Context::new();
+        assert_eq!(
+            eval(&mut context, "_ * 3", 53),
+            Err(CalcError::UndefinedVar(String::from("_")))
+        );
+
+        eval(&mut context, "2+2", 53).unwrap();
+        assert_eq!(eval(&mut context, "_ * 3", 53).unwrap().unwrap(), 12);
+        assert_eq!(eval(&mut context, "1_000 + _", 53).unwrap().unwrap(), 1012);
+
+        // The underscore isn't a separator after whitespace.
+        eval(&mut context, "2+2", 53).unwrap();
+        assert_eq!(eval(&mut context, "2 _", 53).unwrap().unwrap(), 8);
+    }
+
+    #[test]
+    fn test_var_using_last_answer() {
+        let mut context = Context::new();
+        eval(&mut context, "2+2", 53).unwrap();
+        eval(&mut context, "y = _ + 1", 53).unwrap();
+        assert_eq!(eval(&mut context, "y*2", 53).unwrap().unwrap(), 10);
+
+        // `y` is now 11, since the last answer changed.
+        assert_eq!(eval(&mut context, "y*2", 53).unwrap().unwrap(), 22);
+    }
+
+    #[test]
+    fn test_ans() {
+        let mut context = Context::new();
+        assert_eq!(
+            eval(&mut context, "ans * 2", 53),
+            Err(CalcError::UndefinedVar(String::from("ans")))
+        );
+
+        eval(&mut context, "3 + 4", 53).unwrap();
+        assert_eq!(eval(&mut context, "ans * 2", 53).unwrap().unwrap(), 14);
+        assert_eq!(eval(&mut context, "ans + 1", 53).unwrap().unwrap(), 15);
+        assert_eq!(eval(&mut context, "2ans", 53).unwrap().unwrap(), 30);
+
+        // Declarations and errors don't change it.
+        eval(&mut context, "x = 100", 53).unwrap();
+        assert!(eval(&mut context, "1 +", 53).is_err());
+        assert_eq!(eval(&mut context, "ans", 53).unwrap().unwrap(), 30);
+
+        // A variable called `ans` hides the last answer.
+        eval(&mut context, "ans = 5", 53).unwrap();
+        assert_eq!(eval(&mut context, "ans", 53).unwrap().unwrap(), 5);
+        assert_eq!(eval(&mut context, "_", 53).unwrap().unwrap(), 5);
+    }
+
+    #[test]
+    fn test_ans_complex() {
+        let mut context = Context::new();
+        eval(&mut context, "2", 53).unwrap();
+        let result = context.eval_complex("ans * i", 53).unwrap().unwrap();
+
+        assert_eq!(result.real(), &0f64);
+        assert_eq!(result.imaginary(), &2f64);
+    }
+
+    #[test]
+    fn test_number_literal_invalid_separator() {
+        let mut context = Context::new();
+
+        assert!(eval(&mut context, "1_ + 2", 53).is_err());
+    }
+
+    #[test_case("1+2*3", 2)]
+    #[test_case("-sqrt(4)!", 3)]
+    #[test_case("2", 0)]
+    #[test_case("f(x) = 2x; f(1+2)", 3 ; "user function")]
+    fn test_operation_count(input: &str, expected: usize) {
+        let mut context = Context::new();
+        eval(&mut context, input, 53).unwrap();
+
+        assert_eq!(context.operation_count(), expected);
+    }
+
+    #[test]
+    fn test_get_ast() {
+        let context = Context::new();
+
+        assert_eq!(
+            context.get_ast("x = 1 + 2").unwrap(),
+            vec![var_decl("x", binary(literal("1"), Plus, literal("2")))]
+        );
+
+        // `f` is known after its declaration has been parsed, so this is a call rather than a multiplication.
+        let statements = context.get_ast("f(x) = 2x; f(3)").unwrap();
+        assert_eq!(
+            statements.last(),
+            Some(&Stmt::Expr(fn_call("f", vec![*literal("3")])))
+        );
+        // The declaration isn't added to the context.
+        assert!(context.symbol_table().get_fn("f").is_none());
+    }
+
+    #[test_case("1/4", DisplayHint::Fraction)]
+    #[test_case("-2/3", DisplayHint::Fraction)]
+    #[test_case("0.1 + 0.2", DisplayHint::Fraction)]
+    #[test_case("6/3", DisplayHint::Integer)]
+    #[test_case("sqrt(2)", DisplayHint::Decimal)]
+    #[test_case("pi", DisplayHint::Decimal)]
+    fn test_display_hint(input: &str, expected: DisplayHint) {
+        let mut context = Context::new();
+        let (_, hint) = context.eval_with_display_hint(input, 53).unwrap().unwrap();
+
+        assert_eq!(hint, expected);
+    }
+
+    #[test]
+    fn test_callbacks() {
+        use crate::hooks::EvalEvent::*;
+        use std::sync::{Arc, Mutex};
+
+        let mut context = Context::new();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        for event in [
+            BeforeFnCall(String::new()),
+            AfterFnCall("cos".into()),
+            BeforeVarAccess(String::new()),
+        ] {
+            let log = Arc::clone(&log);
+            context.register_callback(
+                event,
+                Box::new(move |view| {
+                    log.lock().unwrap().push(view.event().clone());
+                    Ok(())
+                }),
+            );
+        }
+
+        eval(&mut context, "sin(1) + cos(pi)", 53).unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                BeforeFnCall("sin".into()),
+                BeforeFnCall("cos".into()),
+                BeforeVarAccess("pi".into()),
+                AfterFnCall("cos".into()),
+            ]
+        );
+
+        context.unregister_all_callbacks();
+        eval(&mut context, "sin(1)", 53).unwrap();
+        assert_eq!(log.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_blocking_callback() {
+        let mut context = Context::new();
+        context.register_callback(
+            EvalEvent::BeforeFnCall("sqrt".into()),
+            Box::new(|_| Err(CalcError::Message(String::from("sqrt is blocked.")))),
+        );
+
+        assert_eq!(eval(&mut context, "sin(0) + 1", 53).unwrap().unwrap(), 1);
+        assert_eq!(
+            eval(&mut context, "1 + sqrt(4)", 53),
+            Err(CalcError::Message(String::from("sqrt is blocked.")))
+        );
+        assert_eq!(
+            context.eval_value("sqrt([4, 9])", 53),
+            Err(CalcError::Message(String::from("sqrt is blocked.")))
+        );
+    }
+
+    #[test]
+    fn test_value_computed_callback() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let mut context = Context::new();
+        let count = Arc::new(AtomicUsize::new(0));
+        let count_clone = Arc::clone(&count);
+        context.register_callback(
+            EvalEvent::ValueComputed(0f64),
+            Box::new(move |_| {
+                count_clone.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }),
+        );
+
+        // Two literals and the sum.
+        eval(&mut context, "1 + 2", 53).unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_value_computed_callback_fn_call() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let mut context = Context::new();
+        eval(&mut context, "f(x) = x", 53).unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+        let count_clone = Arc::clone(&count);
+        context.register_callback(
+            EvalEvent::ValueComputed(0f64),
+            Box::new(move |_| {
+                count_clone.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }),
+        );
+
+        // Two literals and the sum, the parameter and its value, and the result of the call.
+        eval(&mut context, "f(1+2)", 53).unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    fn test_symbol_table() {
+        let mut context = Context::new();
+        eval(&mut context, "x = 1", 53).unwrap();
+        eval(&mut context, "f(x) = 2x", 53).unwrap();
+
+        assert_eq!(context.symbol_table().len(), 2);
+    }
+
+    #[test]
+    fn test_macro_define() {
+        let mut context = Context::new();
+        context.macro_define("CUBE", "^3");
+        context.macro_define("NEXT", "+ 1");
+
+        assert_eq!(eval(&mut context, "2 CUBE", 53).unwrap().unwrap(), 8);
+        assert_eq!(eval(&mut context, "2 CUBE NEXT", 53).unwrap().unwrap(), 9);
+
+        context.macro_define("A", "A A");
+        assert_eq!(
+            eval(&mut context, "2 A", 53),
+            Err(CalcError::CircularDependency(String::from("A")))
+        );
+    }
+
+    #[test]
+    fn test_evaluate_expr() {
+        let mut context = Context::new();
+        eval(&mut context, "f(x) = 2x", 53).unwrap();
+        let sum = binary(literal("3"), Plus, literal("4"));
+        let call = fn_call("f", vec![*var("y")]);
+
+        assert_eq!(context.evaluate_expr(&sum, 53).unwrap(), 7);
+        assert_eq!(
+            context.evaluate_expr(&call, 53),
+            Err(CalcError::UndefinedVar(String::from("y")))
+        );
+    }
+
+    #[test]
+    fn test_eval_in_sandbox() {
+        let mut context = Context::new();
+        eval(&mut context, "x = 4", 53).unwrap();
+        eval(&mut context, "f(a) = a + x", 53).unwrap();
+        let allowed = ["x", "f", "sqrt", "a", "g"];
+
+        assert_eq!(
+            context
+                .eval_in_sandbox("sqrt(x) + f(1)", 53, &allowed)
+                .unwrap()
+                .unwrap(),
+            7
+        );
+        assert_eq!(
+            context.eval_in_sandbox("sin(x)", 53, &allowed),
+            Err(CalcError::UndefinedFn(String::from("sin")))
+        );
+        assert_eq!(
+            context.eval_in_sandbox("x * pi", 53, &allowed),
+            Err(CalcError::UndefinedVar(String::from("pi")))
+        );
+        assert_eq!(
+            context.eval_in_sandbox("g(a) = a - y; g(1)", 53, &allowed),
+            Err(CalcError::UndefinedVar(String::from("y")))
+        );
+
+        // Declarations don't leave the sandbox.
+        context.eval_in_sandbox("g(a) = 2a", 53, &allowed).unwrap();
+        assert!(context.symbol_table().get_fn("g").is_none());
+    }
+
+    #[test]
+    fn test_eval_in_sandbox_dependencies() {
+        let mut context = Context::new();
+        eval(&mut context, "x = 4", 53).unwrap();
+        eval(&mut context, "y = x + 1", 53).unwrap();
+        eval(&mut context, "f(a) = sin(a)", 53).unwrap();
+        eval(&mut context, "unit m", 53).unwrap();
+        eval(&mut context, "unit km = 1000 m", 53).unwrap();
+
+        // The names used by allowed variables, functions and units have to be allowed as well.
+        assert_eq!(
+            context.eval_in_sandbox("f(1)", 53, &["f", "a"]),
+            Err(CalcError::UndefinedFn(String::from("sin")))
+        );
+        assert_eq!(
+            context.eval_in_sandbox("y", 53, &["y"]),
+            Err(CalcError::UndefinedVar(String::from("x")))
+        );
+        assert_eq!(
+            context.eval_in_sandbox("2 km", 53, &["km"]),
+            Err(CalcError::UndefinedUnit(String::from("m")))
+        );
+        assert_eq!(
+            context
+                .eval_in_sandbox("y + 2 km", 53, &["y", "x", "km", "m"])
+                .unwrap()
+                .unwrap(),
+            2005
+        );
+    }
+
+    #[test]
+    fn test_eval_lazy() {
+        let mut context = Context::new();
+        eval(&mut context, "x = 2", 53).unwrap();
+        let lazy: Vec<_> = (0..1000)
+            .map(|i| context.eval_lazy(&format!("{}x + 1", i), 53).unwrap())
+            .collect();
+
+        // The definitions are copied when the expressions are parsed.
+        eval(&mut context, "x = 3", 53).unwrap();
+
+        for (i, f) in lazy.into_iter().enumerate() {
+            assert_eq!(f().unwrap().unwrap(), 2 * i + 1);
+        }
+        assert!(context.eval_lazy("2 * (1 + 3", 53).is_err());
+    }
+
+    #[test]
+    fn test_eval_lazy_declarations() {
+        let mut context = Context::new();
+        let lazy = context
+            .eval_lazy("unit m; g(x) = 2x; g(3) + g(1)", 53)
+            .unwrap();
+
+        // The declarations are only made in the copy of the context.
+        assert!(context.symbol_table().get_fn("g").is_none());
+        assert!(context.symbol_table().get_unit("m").is_none());
+        assert_eq!(lazy().unwrap().unwrap(), 8);
+        assert!(context.symbol_table().get_fn("g").is_none());
+        assert!(context.eval_value("g(1)", 53).is_err());
+    }
+
+    #[test]
+    fn test_parallel_sweep() {
+        let mut context = Context::new();
+        eval(&mut context, "a = 2", 53).unwrap();
+        let values: Vec<Float> = (0..100).map(|i| Float::with_val(53, i - 50)).collect();
+
+        let results = context.parallel_sweep("a*x^2 + 1", "x", &values, 53);
+        let declarations = context.parallel_sweep("y = x", "x", &values, 53);
+
+        assert_eq!(results.len(), values.len());
+        for (result, value) in results.into_iter().zip(values) {
+            assert_eq!(result.unwrap().unwrap(), 2 * value.square() + 1);
+        }
+        assert!(declarations.iter().all(|result| *result == Ok(None)));
+        assert!(!context.symbol_table.contains_var("x"));
+        assert!(!context.symbol_table.contains_var("y"));
+    }
+
+    #[test]
+    fn test_parallel_sweep_many_values() {
+        let mut context = Context::new();
+        eval(&mut context, "f(t) = 2t", 53).unwrap();
+        eval(&mut context, "y = 3", 53).unwrap();
+        eval(&mut context, "7", 53).unwrap();
+        let values: Vec<Float> = (0..10000).map(|i| Float::with_val(53, i)).collect();
+
+        // Every evaluation starts with the same last answer, regardless of the order they run in.
+        let results = context.parallel_sweep("xy + _ + f(x)", "x", &values, 53);
+
+        assert_eq!(results.len(), values.len());
+        for (result, value) in results.into_iter().zip(values) {
+            assert_eq!(result.unwrap().unwrap(), 5 * value + 7);
+        }
+    }
+
+    #[test]
+    fn test_parallel_sweep_errors() {
+        let context = Context::new();
+        let values = [Float::with_val(53, 1), Float::with_val(53, 2)];
+        let results = context.parallel_sweep("x + z", "x", &values, 53);
+
+        assert_eq!(results.len(), 2);
+        assert!(results
+            .iter()
+            .all(|result| *result == Err(CalcError::UndefinedVar(String::from("z")))));
+    }
+
+    #[test]
+    fn test_with_variables() {
+        let mut context = Context::new();
+        eval(&mut context, "x = 1", 53).unwrap();
+        let vars = [
+            (String::from("x"), Float::with_val(53, 10)),
+            (String::from("y"), Float::with_val(53, -2)),
+        ];
+
+        let result = context.with_variables(&vars, |context| eval(context, "xy", 53));
+
+        assert_eq!(result.unwrap().unwrap(), -20);
+        assert_eq!(eval(&mut context, "x", 53).unwrap().unwrap(), 1);
+        assert_eq!(
+            eval(&mut context, "y", 53),
+            Err(CalcError::UndefinedVar(String::from("y")))
+        );
+    }
+
+    #[test]
+    fn test_substitute_and_eval() {
+        let mut context = Context::new();
+        eval(&mut context, "f(x, y) = x^2 + y", 53).unwrap();
+        let args = [Float::with_val(53, 1.5), Float::with_val(53, -0.25)];
+
+        assert_eq!(context.substitute_and_eval("f", &args, 53).unwrap(), 2);
+        assert_eq!(
+            context.substitute_and_eval("f", &args[..1], 53),
+            Err(CalcError::IncorrectAmountOfArguments(2, "f".into(), 1))
+        );
+        assert_eq!(
+            context.substitute_and_eval("sqrt", &args[..1], 53),
+            Err(CalcError::UndefinedFn("sqrt".into()))
+        );
+    }
+
+    #[test]
+    fn test_define_var() {
+        let mut context = Context::new();
+        context.define_var("temperature", 21.5).unwrap();
+        context.define_var("x", Float::with_val(53, -2)).unwrap();
+        context
+            .define_var(
+                "v",
+                Value::Vector(vec![Float::with_val(53, 1), Float::with_val(53, 2)]),
+            )
+            .unwrap();
+
+        assert_eq!(
+            eval(&mut context, "temperature + x", 53).unwrap().unwrap(),
+            19.5
+        );
+        assert_eq!(
+            context.eval_value("v * x", 53).unwrap().unwrap(),
+            Value::Vector(vec![Float::with_val(53, -2), Float::with_val(53, -4)])
+        );
+    }
+
+    #[test_case("2x" ; "starts with a digit")]
+    #[test_case("a b" ; "two identifiers")]
+    #[test_case("x+1" ; "expression")]
+    #[test_case("" ; "empty")]
+    #[test_case("π" ; "constant")]
+    fn test_define_var_invalid_name(name: &str) {
+        let mut context = Context::new();
+
+        assert!(matches!(
+            context.define_var(name, 1.0),
+            Err(CalcError::Message(_))
+        ));
+        assert_eq!(
+            context.define_fn(name, &["x"], "x"),
+            context.define_var(name, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_define_var_not_finite() {
+        let mut context = Context::new();
+
+        assert_eq!(
+            context.define_var("x", f64::NAN),
+            Err(CalcError::InvalidNumberLiteral(String::from("NaN")))
+        );
+        assert!(!context.symbol_table.contains_var("x"));
+    }
+
+    #[test]
+    fn test_define_fn() {
+        let mut context = Context::new();
+        context
+            .define_fn("area", &["width", "height"], "width * height")
+            .unwrap();
+        context.define_fn("double", &["x"], "2x").unwrap();
+
+        assert_eq!(eval(&mut context, "area(3, 4)", 53).unwrap().unwrap(), 12);
+        assert_eq!(
+            eval(&mut context, "double(area(1, 2))", 53)
+                .unwrap()
+                .unwrap(),
+            4
+        );
+
+        // The parameters are only defined while the body is parsed.
+        assert!(!context.symbol_table.contains_var("width"));
+        assert!(!context.symbol_table.contains_var("x"));
+    }
+
+    #[test_case("x = 2" ; "declaration")]
+    #[test_case("x; x" ; "two expressions")]
+    #[test_case("" ; "empty")]
+    fn test_define_fn_invalid_body(body: &str) {
+        let mut context = Context::new();
+
+        assert!(matches!(
+            context.define_fn("f", &["x"], body),
+            Err(CalcError::Message(_))
+        ));
+        assert!(!context.symbol_table.contains_fn("f"));
+    }
+
+    #[test]
+    fn test_native_fn() {
+        let mut context = Context::new();
+        context
+            .register_native_fn("lookup", |args: &[Float]| {
+                Ok(args
+                    .iter()
+                    .fold(Float::with_val(53, 0), |sum, x| sum + x.clone() * 10))
+            })
+            .unwrap();
+        eval(&mut context, "f(x) = lookup(x, 1) + 1", 53).unwrap();
+
+        assert_eq!(eval(&mut context, "lookup(2)", 53).unwrap().unwrap(), 20);
+        assert_eq!(eval(&mut context, "lookup 2 + 1", 53).unwrap().unwrap(), 21);
+        assert_eq!(
+            eval(&mut context, "lookup()", 53),
+            Err(CalcError::EmptyInput((7, 8)))
+        );
+        assert_eq!(
+            eval(&mut context, "f(lookup(1))", 53).unwrap().unwrap(),
+            111
+        );
+        assert_eq!(
+            context.eval_value("lookup(1, 2)", 53).unwrap().unwrap(),
+            Value::Scalar(Float::with_val(53, 30))
+        );
+        assert!(context
+            .list_all_names()
+            .contains(&(String::from("lookup"), NameKind::NativeFn)));
+    }
+
+    #[test]
+    fn test_native_fn_error() {
+        let mut context = Context::new();
+        context
+            .register_native_fn("half", |args: &[Float]| match args {
+                [x] => Ok(Float::with_val(53, x / 2)),
+                _ => Err(CalcError::IncorrectAmountOfArguments(
+                    1,
+                    "half".into(),
+                    args.len(),
+                )),
+            })
+            .unwrap();
+
+        assert_eq!(
+            eval(&mut context, "half(1, 2)", 53),
+            Err(CalcError::IncorrectAmountOfArguments(1, "half".into(), 2))
+        );
+        assert_eq!(
+            eval(&mut context, "half(y)", 53),
+            Err(CalcError::UndefinedVar("y".into()))
+        );
+    }
+
+    #[test]
+    fn test_native_fn_replaces_user_fn() {
+        let mut context = Context::new();
+        let one = |_: &[Float]| Ok(Float::with_val(53, 1));
+        eval(&mut context, "f(x) = 2", 53).unwrap();
+
+        context.register_native_fn("f", one).unwrap();
+        assert_eq!(eval(&mut context, "f(0)", 53).unwrap().unwrap(), 1);
+        assert!(context.symbol_table.get_fn("f").is_none());
+
+        eval(&mut context, "f(x) = 3", 53).unwrap();
+        assert_eq!(eval(&mut context, "f(0)", 53).unwrap().unwrap(), 3);
+        assert_eq!(context.symbol_table.native_fns().count(), 0);
+
+        assert!(matches!(
+            context.register_native_fn("sin", one),
+            Err(CalcError::Message(_))
+        ));
+        assert!(matches!(
+            context.register_native_fn("2f", one),
+            Err(CalcError::Message(_))
+        ));
+    }
+
+    #[test]
+    fn test_native_fn_not_cached() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let mut context = Context::new();
+        context
+            .register_native_fn("next", move |_: &[Float]| {
+                Ok(Float::with_val(
+                    53,
+                    counter.fetch_add(1, Ordering::SeqCst) + 1,
+                ))
+            })
+            .unwrap();
+
+        assert_eq!(eval(&mut context, "next(0) * 2", 53).unwrap().unwrap(), 2);
+        assert_eq!(eval(&mut context, "next(0) * 2", 53).unwrap().unwrap(), 4);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_native_fn_nested_call() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let mut context = Context::new();
+        context
+            .register_native_fn("next", move |arguments: &[Float]| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(arguments[0].clone() + 1)
+            })
+            .unwrap();
+
+        assert_eq!(eval(&mut context, "next(next(0))", 53).unwrap().unwrap(), 2);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_get_var() {
+        let mut context = Context::new();
+        eval(&mut context, "x = 3; y = x^2", 53).unwrap();
+        context.define_var("z", 0.5).unwrap();
+
+        assert_eq!(
+            context.get_var("y", 53).unwrap(),
+            Value::Scalar(Float::with_val(53, 9))
+        );
+        assert_eq!(context.get_var("z", 53).unwrap(), Value::from(0.5));
+        assert_eq!(
+            context.get_var("w", 53),
+            Err(CalcError::UndefinedVar(String::from("w")))
+        );
+    }
+
+    #[test_case("1", 1)]
+    #[test_case("1 + 2", 3)]
+    #[test_case("f(1, 2, 3)", 4)]
+    #[test_case("-(x)!", 4)]
+    #[test_case("y = 2sin(x)", 4)]
+    fn test_count_ast_nodes(input: &str, expected: usize) {
+        let mut context = Context::new();
+        eval(&mut context, "f(a, b, c) = a + b + c", 53).unwrap();
+
+        assert_eq!(context.count_ast_nodes(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_count_ast_nodes_without_declaring() {
+        let context = Context::new();
+
+        assert_eq!(context.count_ast_nodes("g(x) = 2x; g(1)").unwrap(), 5);
+        assert!(context.symbol_table().get_fn("g").is_none());
+    }
+
+    #[test_case("1", 1)]
+    #[test_case("1 + 2", 2)]
+    #[test_case("((1))", 3)]
+    #[test_case("x = sqrt(1 + 2)", 3)]
+    #[test_case("-(2 * 3)", 4)]
+    fn test_ast_depth(input: &str, expected: usize) {
+        let context = Context::new();
+
+        assert_eq!(context.ast_depth(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_ast_depth_without_declaring() {
+        let context = Context::new();
+
+        assert_eq!(context.ast_depth("f(x) = 2x; unit m").unwrap(), 2);
+        assert!(context.symbol_table().get_fn("f").is_none());
+        assert!(context.symbol_table().get_unit("m").is_none());
+    }
+
+    #[test_case("equiv(x^2 - 1, (x - 1) * (x + 1))", 1)]
+    #[test_case("equiv(sin(x)^2 + cos(x)^2, 1)", 1)]
+    #[test_case("equiv(xy, yx)", 1)]
+    #[test_case("equiv(x^2, 2x)", 0)]
+    #[test_case("equiv(x - y, 0)", 0)]
+    #[test_case("equiv(2 + 2, 4)", 1)]
+    #[test_case("equiv(sqrt(x), x)", 0 ; "only one defined")]
+    #[test_case("equiv(sqrt(x) * sqrt(x), sqrt(x)^2)", 1 ; "both undefined for some")]
+    fn test_equiv(input: &str, expected: i32) {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_equiv_undefined_everywhere() {
+        let mut context = Context::new();
+
+        assert!(eval(&mut context, "equiv(f(x), f(x))", 53).is_err());
+    }
+
+    #[test_case("∫(0, 1, x^2)", 1f64 / 3f64)]
+    #[test_case("∫(0, 3, 2x + 1)", 12f64)]
+    #[test_case("integrate(1, 2, 1/t, t)", 2f64.ln())]
+    #[test_case("integrate(2, 0, x^3, x)", -4f64 ; "reversed bounds")]
+    #[test_case("∫(0, π, sin(x))", 2f64)]
+    fn test_integrate(input: &str, expected: f64) {
+        let mut context = Context::new();
+        let result = eval(&mut context, input, 53).unwrap().unwrap();
+
+        // Simpson's rule is only accurate to about 12 digits with the default number of steps.
+        assert_approx_eq!(
+            &Float::with_val(40, result),
+            &Float::with_val(40, expected),
+            1
+        );
+    }
+
+    fn vector(values: &[f64]) -> Value {
+        Value::Vector(values.iter().map(|x| Float::with_val(53, *x)).collect())
+    }
+
+    #[test_case("[1, 2, 3] * 2", vector(&[2f64, 4f64, 6f64]))]
+    #[test_case("(1, 2) + (3, 4)", vector(&[4f64, 6f64]) ; "parentheses")]
+    #[test_case("1 - [1, 2]", vector(&[0f64, -1f64]))]
+    #[test_case("-[1, 0]", vector(&[-1f64, 0f64]))]
+    #[test_case("sqrt([4, 9])", vector(&[2f64, 3f64]))]
+    #[test_case("max([1, 5], [4, 2])", vector(&[4f64, 5f64]) ; "binary function")]
+    #[test_case("(1 + 2) * 3", Value::Scalar(Float::with_val(53, 9)) ; "scalar")]
+    fn test_eval_value(input: &str, expected: Value) {
+        let mut context = Context::new();
+
+        assert_eq!(context.eval_value(input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test_case("x^2 = 4", 2f64)]
+    #[test_case("sqrt(x) = 3", 9f64)]
+    fn test_eval_value_equation(input: &str, expected: f64) {
+        let mut context = Context::new();
+        let result = match context.eval_value(input, 53).unwrap().unwrap() {
+            Value::Scalar(x) => x,
+            value => panic!("Expected a scalar, but got {:?}.", value),
+        };
+
+        assert_approx_eq!(&result, &Float::with_val(53, expected), 4);
+    }
+
+    #[test]
+    fn test_vector_of_fn_calls() {
+        let mut context = Context::new();
+        eval(&mut context, "f(x) = x^2 + 1", 53).unwrap();
+
+        assert_eq!(
+            context
+                .eval_value("[f(0), f(1), f(2)]", 53)
+                .unwrap()
+                .unwrap(),
+            vector(&[1f64, 2f64, 5f64])
+        );
+    }
+
+    #[test_case("len([1, 2, 3])", 3f64)]
+    #[test_case("sum((1, 2, 3))", 6f64)]
+    #[test_case("prod([2, 3, 4])", 24f64)]
+    #[test_case("avg([1, 2, 3, 4])", 2.5)]
+    #[test_case("min([3, -1, 2]) + max([3, -1, 2])", 2f64)]
+    #[test_case("sum([1, 2] * [3, 4])", 11f64 ; "dot product")]
+    fn test_vector_fn(input: &str, expected: f64) {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test_case("mean(1, 2, 3, 4)", 2.5)]
+    #[test_case("mean([1, 2], 3)", 2f64 ; "vector and number")]
+    #[test_case("median([5, 1, 3])", 3f64)]
+    #[test_case("median([[4, 1], [3, 2]])", 2.5 ; "matrix")]
+    #[test_case("mode(3, 1, 3, 2)", 3f64)]
+    #[test_case("variance(2, 4, 4, 4, 5, 5, 7, 9)", 32f64 / 7f64)]
+    #[test_case("stdev([1, 3, 5])", 2f64)]
+    #[test_case("percentile([1, 2, 3, 4], 25)", 1.75)]
+    #[test_case("percentile(4, 1, 3, 2, 100)", 4f64 ; "variadic")]
+    #[test_case("2mean(x, 3)", 5f64 ; "variable")]
+    #[test_case("avg([2, 4])", 3f64)]
+    #[test_case("weighted_mean([1, 2, 3], [3, 1, 1])", 1.6)]
+    #[test_case("weighted_mean([[1, 2], [3, 4]], [1, 0, 0, 1])", 2.5 ; "weighted matrix")]
+    fn test_stat_fn(input: &str, expected: f64) {
+        let mut context = Context::new();
+        eval(&mut context, "x = 2", 53).unwrap();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test_case("weighted_mean([1, 2], [1, 2, 3])")]
+    #[test_case("weighted_mean([1, 2], [1, -1])")]
+    #[test_case("weighted_mean([1, 2])")]
+    fn test_weighted_mean_error(input: &str) {
+        let mut context = Context::new();
+
+        assert!(eval(&mut context, input, 53).is_err());
+    }
+
+    #[test_case("nCr(5, 2) + nPr(5, 2)", 30f64)]
+    #[test_case("binom(6, 3)", 20f64)]
+    #[test_case("nCr(52, 5)", 2598960f64 ; "poker hands")]
+    fn test_combinatorics(input: &str, expected: f64) {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test_case("gcd(12, 18) + lcm(4, 6)", 18f64)]
+    #[test_case("isprime(97) + isprime(91)", 1f64)]
+    #[test_case("nextprime(13)", 17f64)]
+    #[test_case("totient(36)", 12f64)]
+    #[test_case("len(factor(360))", 6f64 ; "factor")]
+    #[test_case("prod(factor(360))", 360f64 ; "product of factors")]
+    fn test_number_theory(input: &str, expected: f64) {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
     }
 
-    Err(CalcError::UnexpectedToken(kind))
-}
+    #[test]
+    fn test_factor() {
+        let mut context = Context::new();
 
-fn is_at_end(context: &mut Context) -> bool {
-    context.pos >= context.tokens.len() || peek(context).kind == TokenKind::EOF
-}
+        assert_eq!(
+            context.eval_value("factor(12)", 53).unwrap().unwrap(),
+            vector(&[2f64, 2f64, 3f64])
+        );
+        assert_eq!(
+            eval(&mut context, "factor(12)", 53),
+            Err(CalcError::UnexpectedVector)
+        );
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::lexer::{Token, TokenKind::*};
-    use crate::test_helpers::*;
-    use test_case::test_case;
+    #[test]
+    fn test_contfrac() {
+        let mut context = Context::new();
 
-    fn parse_with_context(context: &mut Context, tokens: Vec<Token>) -> Result<Stmt, CalcError> {
-        context.tokens = tokens;
+        assert_eq!(
+            context.eval_value("contfrac(pi, 5)", 53).unwrap().unwrap(),
+            vector(&[3f64, 7f64, 15f64, 1f64, 292f64])
+        );
+        assert_eq!(
+            context
+                .eval_value("contfrac(0.75, 5)", 53)
+                .unwrap()
+                .unwrap(),
+            vector(&[0f64, 1f64, 3f64])
+        );
+        assert_eq!(
+            eval(&mut context, "len(contfrac(43/30, 10))", 53)
+                .unwrap()
+                .unwrap(),
+            4f64
+        );
+    }
 
-        parse_stmt(context)
+    #[test]
+    fn test_pi_approx() {
+        let mut context = Context::new();
+        let result = eval(&mut context, "pi_approx(10)", 53).unwrap().unwrap();
+
+        assert_approx_eq!(&result, &Float::with_val(53, rug::float::Constant::Pi), 1);
+        assert!(eval(&mut context, "pi_approx(10^9)", 53)
+            .unwrap()
+            .unwrap()
+            .is_nan());
     }
 
-    fn parse(tokens: Vec<Token>) -> Result<Stmt, CalcError> {
+    #[test]
+    fn test_e_approx() {
         let mut context = Context::new();
-        context.tokens = tokens;
+        let result = eval(&mut context, "e_approx(40)", 53).unwrap().unwrap();
 
-        parse_stmt(&mut context)
+        assert_approx_eq!(&result, &Float::with_val(53, 1).exp(), 1);
+        assert!(eval(&mut context, "e_approx(4 * 10^9)", 53)
+            .unwrap()
+            .unwrap()
+            .is_nan());
     }
 
     #[test]
-    fn test_var() {
-        // x
-        let tokens = vec![token(Identifier, "x"), token(EOF, "")];
+    fn test_ncr_exact() {
+        let mut context = Context::new();
+        let result = eval(&mut context, "nCr(100, 50)", 53).unwrap().unwrap();
 
-        assert_eq!(parse(tokens).unwrap(), Stmt::Expr(var("x")));
+        assert_eq!(
+            context.format_value(&result).unwrap(),
+            "100891344545564193334812497256"
+        );
+    }
+
+    #[test_case(
+        "[1, 2] + [1, 2, 3]",
+        CalcError::Message(String::from("The vectors have different lengths: 2 and 3."))
+    )]
+    #[test_case("[[1, 2], 3]", CalcError::Message(String::from("The rows of a matrix must be vectors of the same length.")) ; "mixed")]
+    #[test_case("[[[1]]]", CalcError::UnexpectedVector ; "nested matrix")]
+    #[test_case(
+        "[[1, 2]] + [[1], [2]]",
+        CalcError::Message(String::from("The matrices have different sizes: 1×2 and 2×1."))
+    )]
+    #[test_case(
+        "[[1, 2]] + [1, 2]",
+        CalcError::Message(String::from("A vector and a matrix can only be multiplied."))
+    )]
+    #[test_case(
+        "[[1, 2], [3, 4]]^0.5",
+        CalcError::Message(String::from("A matrix can only be raised to an integer power."))
+    )]
+    #[test_case("sum(1, 3, [n, n])", CalcError::UnexpectedVector ; "special function")]
+    #[test_case(
+        "[1, 2] * i",
+        CalcError::Message(String::from("Vectors and matrices can only contain real numbers."))
+    )]
+    fn test_eval_value_error(input: &str, expected: CalcError) {
+        let mut context = Context::new();
+
+        assert_eq!(context.eval_value(input, 53), Err(expected));
     }
 
     #[test]
-    fn test_binary() {
-        // 1+2*(3-4/5)
-        let tokens = vec![
-            token(Literal, "1"),
-            token(Plus, ""),
-            token(Literal, "2"),
-            token(Star, ""),
-            token(OpenParenthesis, ""),
-            token(Literal, "3"),
-            token(Minus, ""),
-            token(Literal, "4"),
-            token(Slash, ""),
-            token(Literal, "5"),
-            token(ClosedParenthesis, ""),
-        ];
+    fn test_eval_value_variables() {
+        let mut context = Context::new();
+        context.eval_value("v = [1, 2]", 53).unwrap();
+        context.eval_value("A = [[0, 1], [1, 0]]", 53).unwrap();
+        context.eval_value("f(x) = x^2", 53).unwrap();
 
         assert_eq!(
-            parse(tokens).unwrap(),
-            Stmt::Expr(binary(
-                literal("1"),
-                Plus,
-                binary(
-                    literal("2"),
-                    Star,
-                    group(binary(
-                        literal("3"),
-                        Minus,
-                        binary(literal("4"), Slash, literal("5"))
-                    ))
-                )
-            ))
+            context.eval_value("f(v) + v", 53).unwrap().unwrap(),
+            vector(&[2f64, 6f64])
+        );
+        assert_eq!(
+            context.eval_value("A * v", 53).unwrap().unwrap(),
+            vector(&[2f64, 1f64])
         );
     }
 
+    fn matrix(rows: &[&[f64]]) -> Value {
+        Value::Matrix(
+            rows.iter()
+                .map(|row| row.iter().map(|x| Float::with_val(53, *x)).collect())
+                .collect(),
+        )
+    }
+
+    #[test_case("[[1, 2], [3, 4]] * [[5, 6], [7, 8]]", matrix(&[&[19f64, 22f64], &[43f64, 50f64]]))]
+    #[test_case("((1, 2), (3, 4)) + 1", matrix(&[&[2f64, 3f64], &[4f64, 5f64]]) ; "parentheses")]
+    #[test_case("[[1, 2], [3, 4]] * [1, 1]", vector(&[3f64, 7f64]) ; "matrix times vector")]
+    #[test_case("[1, 1] * [[1, 2], [3, 4]]", vector(&[4f64, 6f64]) ; "vector times matrix")]
+    #[test_case("[[1, 1], [1, 0]]^5", matrix(&[&[8f64, 5f64], &[5f64, 3f64]]))]
+    #[test_case("[[2, 1], [1, 1]]^(-1)", matrix(&[&[1f64, -1f64], &[-1f64, 2f64]]) ; "inverse power")]
+    #[test_case("inv([[2, 1], [1, 1]])", matrix(&[&[1f64, -1f64], &[-1f64, 2f64]]))]
+    #[test_case("transpose([[1, 2, 3], [4, 5, 6]])", matrix(&[&[1f64, 4f64], &[2f64, 5f64], &[3f64, 6f64]]))]
+    #[test_case("transpose([1, 2])", matrix(&[&[1f64], &[2f64]]) ; "transpose vector")]
+    #[test_case("abs([[-1, 2], [3, -4]])", matrix(&[&[1f64, 2f64], &[3f64, 4f64]]))]
+    #[test_case("identity(3)", matrix(&[&[1f64, 0f64, 0f64], &[0f64, 1f64, 0f64], &[0f64, 0f64, 1f64]]))]
+    #[test_case("zeros(2, 3)", matrix(&[&[0f64, 0f64, 0f64], &[0f64, 0f64, 0f64]]))]
+    #[test_case("ones(2, 2)", matrix(&[&[1f64, 1f64], &[1f64, 1f64]]))]
+    #[test_case("2 * identity(2) + ones(2, 2)", matrix(&[&[3f64, 1f64], &[1f64, 3f64]]) ; "combined")]
+    fn test_eval_value_matrix(input: &str, expected: Value) {
+        let mut context = Context::new();
+
+        assert_eq!(context.eval_value(input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test_case("det([[1, 2], [3, 4]])", -2f64)]
+    #[test_case("det(2)", 2f64)]
+    #[test_case("inv(4)", 0.25)]
+    #[test_case("sum([[1, 2], [3, 4]])", 10f64)]
+    fn test_matrix_fn(input: &str, expected: f64) {
+        let mut context = Context::new();
+
+        assert_eq!(eval(&mut context, input, 53).unwrap().unwrap(), expected);
+    }
+
+    #[test_case("identity(0)")]
+    #[test_case("identity(1.5)")]
+    #[test_case("zeros(2, -1)")]
+    #[test_case("ones(2)")]
+    #[test_case("zeros(2^20, 2)" ; "too many elements")]
+    fn test_matrix_constructor_error(input: &str) {
+        let mut context = Context::new();
+
+        assert!(context.eval_value(input, 53).is_err());
+    }
+
     #[test]
-    fn test_pow() {
-        let tokens = vec![
-            token(Literal, "1"),
-            token(Star, ""),
-            token(Literal, "2"),
-            token(Power, ""),
-            token(Literal, "3"),
-            token(Power, ""),
-            token(Literal, "4"),
-            token(Plus, ""),
-            token(Literal, "5"),
-        ];
+    fn test_matrix_power_error() {
+        let mut context = Context::new();
 
         assert_eq!(
-            parse(tokens).unwrap(),
-            Stmt::Expr(binary(
-                binary(
-                    literal("1"),
-                    Star,
-                    binary(
-                        literal("2"),
-                        Power,
-                        binary(literal("3"), Power, literal("4")),
-                    ),
-                ),
-                Plus,
-                literal("5")
-            )),
+            context.eval_value("[[1, 2, 3], [4, 5, 6]]^2", 53),
+            Err(CalcError::Message(String::from(
+                "Expected a square matrix, but got a 2×3 matrix."
+            )))
         );
+        assert!(context.eval_value("[[1, 1], [1, 0]]^(2^40)", 53).is_err());
     }
 
-    #[test_case(Deg)]
-    #[test_case(Rad)]
-    fn test_unary(angle_unit: TokenKind) {
-        let tokens = vec![
-            token(Minus, ""),
-            token(Literal, "1"),
-            token(angle_unit.clone(), ""),
-        ];
+    #[test]
+    fn test_unexpected_vector() {
+        let mut context = Context::new();
 
         assert_eq!(
-            parse(tokens).unwrap(),
-            Stmt::Expr(unary(Minus, Box::new(Expr::Unit(literal("1"), angle_unit))))
+            eval(&mut context, "[1, 2] * 2", 53),
+            Err(CalcError::UnexpectedVector)
         );
     }
 
     #[test]
-    fn test_var_decl() {
-        let tokens = vec![
-            token(Identifier, "x"),
-            token(Equals, ""),
-            token(Literal, "1"),
-            token(Plus, ""),
-            token(Literal, "2"),
-        ];
+    fn test_integration_steps() {
+        let mut coarse = Context::new().set_integration_steps(1);
+        let mut fine = Context::new().set_integration_steps(1000);
+        let expected = Float::with_val(53, 1).exp() - 1;
+        let result = eval(&mut coarse, "∫(0, 1, exp(x))", 53).unwrap().unwrap();
+        assert!(Float::with_val(53, &result - &expected).abs() > 1e-4);
+
+        let result = eval(&mut fine, "∫(0, 1, exp(x))", 53).unwrap().unwrap();
+        assert_approx_eq!(
+            &Float::with_val(40, result),
+            &Float::with_val(40, expected),
+            1
+        );
+    }
+
+    #[test]
+    fn test_function_dependencies() {
+        let mut context = Context::new();
+        eval(&mut context, "g(y) = 2y", 53).unwrap();
+        eval(&mut context, "f(x) = sin(x) + g(x) * x", 53).unwrap();
 
         assert_eq!(
-            parse(tokens).unwrap(),
-            Stmt::VarDecl(String::from("x"), binary(literal("1"), Plus, literal("2")))
+            context.function_dependencies("f").unwrap(),
+            vec!["sin", "g", "x"]
+        );
+        assert_eq!(context.function_dependencies("g").unwrap(), vec!["y"]);
+        assert_eq!(
+            context.function_dependencies("h"),
+            Err(CalcError::UndefinedFn("h".into()))
         );
     }
 
     #[test]
-    fn test_fn_decl() {
-        let tokens = vec![
-            token(Identifier, "f"),
-            token(OpenParenthesis, ""),
-            token(Identifier, "x"),
-            token(ClosedParenthesis, ""),
-            token(Equals, ""),
-            token(Literal, "1"),
-            token(Plus, ""),
-            token(Literal, "2"),
-        ];
+    fn test_topological_sort_definitions() {
+        let mut context = Context::new();
+        eval(&mut context, "c = b * a", 53).unwrap();
+        eval(&mut context, "b = f(a) + 1", 53).unwrap();
+        eval(&mut context, "f(x) = 2x", 53).unwrap();
+        eval(&mut context, "a = 1", 53).unwrap();
 
         assert_eq!(
-            parse(tokens).unwrap(),
-            Stmt::FnDecl(
-                String::from("f"),
-                vec![String::from("x")],
-                binary(literal("1"), Plus, literal("2"))
-            )
+            context.topological_sort_definitions().unwrap(),
+            vec!["a", "f", "b", "c"]
         );
     }
 
     #[test]
-    fn test_fn_call() {
-        let tokens = vec![
-            token(Identifier, "f"),
-            token(OpenParenthesis, ""),
-            token(Literal, "1"),
-            token(Plus, ""),
-            token(Literal, "2"),
-            token(ClosedParenthesis, ""),
-            token(Plus, ""),
-            token(Literal, "3"),
-        ];
+    fn test_topological_sort_definitions_cycle() {
+        let mut context = Context::new();
+        eval(&mut context, "a = b", 53).unwrap();
+        eval(&mut context, "b = a", 53).unwrap();
+
+        assert!(matches!(
+            context.topological_sort_definitions(),
+            Err(CalcError::CircularDependency(_))
+        ));
+    }
 
+    #[test]
+    fn test_save_and_load_state() {
+        let path = std::env::temp_dir().join("kalk_test_state.kalk");
         let mut context = Context::new();
+        for input in [
+            "zeta = 20",
+            "hours = 8",
+            "alpha = zeta * hours",
+            "scaled(x) = zeta x",
+            "fib(n) = if n < 2 then n else fib(n - 1) + fib(n - 2)",
+            "unit m",
+            "unit km = 1000 m",
+            "unit mi = 1.609344 km",
+            "distance = 2 mi",
+        ] {
+            eval(&mut context, input, 53).unwrap();
+        }
+        context.save_state(&path).unwrap();
 
-        // Add the function to the symbol table first, in order to prevent errors.
-        context.symbol_table.set(
-            "f()",
-            Stmt::FnDecl(String::from("f"), vec![String::from("x")], literal("1")),
+        let mut loaded = Context::new();
+        loaded.load_state(&path).unwrap();
+        assert_eq!(eval(&mut loaded, "alpha", 53).unwrap().unwrap(), 160f64);
+        assert_eq!(eval(&mut loaded, "scaled(2)", 53).unwrap().unwrap(), 40f64);
+        assert_eq!(eval(&mut loaded, "fib(10)", 53).unwrap().unwrap(), 55f64);
+        assert_eq!(
+            eval(&mut loaded, "distance", 53).unwrap().unwrap(),
+            3218.688
+        );
+        assert_eq!(loaded.symbol_table().var_count(), 4);
+        assert_eq!(loaded.symbol_table().fn_count(), 2);
+
+        let mut limited = Context::new().set_max_precision(32);
+        limited.load_state(&path).unwrap();
+        assert_eq!(eval(&mut limited, "alpha", 32).unwrap().unwrap(), 160f64);
+
+        let mut guarded = Context::new();
+        let mut guard = guarded.global_precision_guard(256);
+        guard.load_state(&path).unwrap();
+        assert_eq!(eval(&mut guard, "alpha", 53).unwrap().unwrap(), 160f64);
+    }
+
+    #[test]
+    fn test_save_state_error() {
+        let path = std::env::temp_dir()
+            .join("kalk_test_missing_directory")
+            .join("state.kalk");
+        let context = Context::new();
+
+        assert!(matches!(
+            context.save_state(&path),
+            Err(CalcError::WriteFile(_))
+        ));
+    }
+
+    #[test]
+    fn test_repl_completions() {
+        let mut context = Context::new();
+        eval(&mut context, "sigma(x) = 2x", 53).unwrap();
+        eval(&mut context, "size = 3", 53).unwrap();
+        let completions = context.repl_completions("si");
+
+        assert_eq!(completions[..4], ["sin", "sinh", "size", "sigma"]);
+        assert!(completions.contains(&String::from("asin")));
+        assert!(!completions.contains(&String::from("cos")));
+        assert_eq!(
+            context.repl_completions("ra"),
+            vec!["rad", "frac", "variance", "integrate", "transpose"]
         );
+    }
+
+    #[test_case("if", "if")]
+    #[test_case("th", "then")]
+    #[test_case("el", "else")]
+    #[test_case("an", "and")]
+    #[test_case("or", "or")]
+    #[test_case("no", "not")]
+    #[test_case("xo", "xor")]
+    fn test_keyword_completions(partial: &str, expected: &str) {
+        assert_eq!(Context::new().repl_completions(partial)[0], expected);
+    }
+
+    #[test]
+    fn test_reset_user_definitions() {
+        let mut context = Context::new();
+        eval(&mut context, "x = 2", 53).unwrap();
+        eval(&mut context, "f(y) = 3y", 53).unwrap();
+        context.reset_user_definitions();
 
         assert_eq!(
-            parse_with_context(&mut context, tokens).unwrap(),
-            Stmt::Expr(binary(
-                Box::new(Expr::FnCall(
-                    String::from("f"),
-                    vec![*binary(literal("1"), Plus, literal("2"))]
-                )),
-                Plus,
-                literal("3")
-            ))
+            eval(&mut context, "x", 53),
+            Err(CalcError::UndefinedVar("x".into()))
+        );
+        assert_eq!(
+            eval(&mut context, "f(1)", 53),
+            Err(CalcError::UndefinedFn("f".into()))
+        );
+        assert_eq!(eval(&mut context, "max(1, 2)", 53).unwrap().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_precision_too_high() {
+        let mut context = Context::new();
+        let mut limited_context = Context::new().set_max_precision(64);
+
+        assert_eq!(
+            eval(&mut context, "1 + 1", u32::MAX),
+            Err(CalcError::PrecisionTooHigh(u32::MAX))
+        );
+        assert_eq!(
+            eval(&mut limited_context, "1 + 1", 128),
+            Err(CalcError::PrecisionTooHigh(128))
+        );
+        assert_eq!(eval(&mut limited_context, "1 + 1", 64).unwrap().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_eval_n_times() {
+        let mut context = Context::new();
+        eval(&mut context, "i = 100", 53).unwrap();
+        let results = context.eval_n_times("2i + 1", 4, "i", 53).unwrap();
+        let results_ab = context.eval_n_times("ab^2", 3, "ab", 53).unwrap();
+
+        assert_eq!(results, vec![1, 3, 5, 7]);
+        assert_eq!(results_ab, vec![0, 1, 4]);
+        assert_eq!(eval(&mut context, "i", 53).unwrap().unwrap(), 100);
+        assert_eq!(
+            eval(&mut context, "ab", 53),
+            Err(CalcError::UndefinedVar("a".into()))
+        );
+        assert_eq!(
+            context.eval_n_times("y = 2i", 3, "i", 53),
+            Err(CalcError::Message(String::from(NOT_AN_EXPRESSION)))
+        );
+        assert_eq!(eval(&mut context, "i", 53).unwrap().unwrap(), 100);
+    }
+
+    #[test]
+    fn test_help() {
+        let context = Context::new();
+
+        assert!(!context.help("sin").unwrap().is_empty());
+        assert_eq!(context.help("√"), context.help("sqrt"));
+        assert_eq!(context.help("unknown"), None);
+    }
+
+    #[test]
+    fn test_check_type_consistency() {
+        let mut context = Context::new();
+        eval(&mut context, "f(x) = 2x", 53).unwrap();
+
+        assert_eq!(
+            context.check_type_consistency("f(3) + sin(π)"),
+            Ok(KalkType::Scalar)
+        );
+        assert_eq!(
+            context.check_type_consistency("y = 3"),
+            Ok(KalkType::Scalar)
+        );
+        assert_eq!(
+            context.check_type_consistency("f(z)"),
+            Err(CalcError::Unknown)
+        );
+        assert_eq!(
+            context.check_type_consistency("sqrt(-1)"),
+            Ok(KalkType::Complex)
+        );
+        assert_eq!(
+            context.check_type_consistency("[1, 2] + [[1, 2], [3, 4]]"),
+            Err(CalcError::Message(String::from(
+                "A vector and a matrix can only be multiplied."
+            )))
+        );
+        assert_eq!(
+            context.check_type_consistency("g(x) = [x, 1]; g(2) * [[1, 2], [3, 4]]"),
+            Ok(KalkType::Vector)
         );
+        assert!(context.symbol_table().get_fn("g").is_none());
     }
 }
@@ -11,7 +11,7 @@ pub const CONSTANTS: phf::Map<&'static str, &'static str> = phf::phf_map! {
     "ϕ" => "1.61803398",
 };
 
-use crate::parser::Unit;
+use crate::parser::{CalcError, Unit};
 use funcs::*;
 pub const UNARY_FUNCS: phf::Map<&'static str, UnaryFuncInfo> = phf::phf_map! {
     "cos" => UnaryFuncInfo(cos, Trig),
@@ -41,28 +41,184 @@ pub const UNARY_FUNCS: phf::Map<&'static str, UnaryFuncInfo> = phf::phf_map! {
     "atanh" => UnaryFuncInfo(atanh, InverseTrig),
 
     "abs" => UnaryFuncInfo(abs, Other),
+    "arg" => UnaryFuncInfo(arg, Other),
     "cbrt" => UnaryFuncInfo(cbrt, Other),
     "ceil" => UnaryFuncInfo(ceil, Other),
+    "conj" => UnaryFuncInfo(conj, Other),
+    "e_approx" => UnaryFuncInfo(e_approx, Other),
     "exp" => UnaryFuncInfo(exp, Other),
     "floor" => UnaryFuncInfo(floor, Other),
     "frac" => UnaryFuncInfo(frac, Other),
     "gamma" => UnaryFuncInfo(gamma, Other),
     "Γ" => UnaryFuncInfo(gamma, Other),
+    "im" => UnaryFuncInfo(im, Other),
+    "isprime" => UnaryFuncInfo(isprime, Other),
     "log" => UnaryFuncInfo(log, Other),
     "ln" => UnaryFuncInfo(ln, Other),
+    "nextprime" => UnaryFuncInfo(nextprime, Other),
+    "pi_approx" => UnaryFuncInfo(pi_approx, Other),
+    "re" => UnaryFuncInfo(re, Other),
     "round" => UnaryFuncInfo(round, Other),
     "sqrt" => UnaryFuncInfo(sqrt, Other),
     "√" => UnaryFuncInfo(sqrt, Other),
+    "totient" => UnaryFuncInfo(totient, Other),
     "trunc" => UnaryFuncInfo(trunc, Other),
 };
 pub const BINARY_FUNCS: phf::Map<&'static str, BinaryFuncInfo> = phf::phf_map! {
+    "gcd" => BinaryFuncInfo(gcd, Other),
+    "lcm" => BinaryFuncInfo(lcm, Other),
     "max" => BinaryFuncInfo(max, Other),
     "min" => BinaryFuncInfo(min, Other),
+    "mod" => BinaryFuncInfo(modulo, Other),
+    "nCr" => BinaryFuncInfo(ncr, Other),
+    "binom" => BinaryFuncInfo(ncr, Other),
+    "nPr" => BinaryFuncInfo(npr, Other),
     "hyp" => BinaryFuncInfo(hyp, Other),
+    "ilog" => BinaryFuncInfo(ilog, Other),
     "log" => BinaryFuncInfo(logx, Other),
     "sqrt" => BinaryFuncInfo(nth_sqrt, Other),
 };
 
+/// Statistics of a list of numbers, eg. `median(3, 1, 2)`. The interpreter gives vectors and matrices
+/// as their elements, so `median([3, 1, 2])` is the same.
+pub const STAT_FUNCS: phf::Map<&'static str, fn(Vec<Float>) -> Float> = phf::phf_map! {
+    "mean" => mean,
+    "median" => median,
+    "mode" => mode,
+    "percentile" => percentile,
+    "stdev" => stdev,
+    "variance" => variance,
+};
+
+/// Statistics of two lists of numbers that belong together, eg. `weighted_mean([1, 2], [3, 1])`.
+/// Like for `STAT_FUNCS`, the interpreter gives vectors and matrices as their elements.
+pub const PAIRED_STAT_FUNCS: phf::Map<
+    &'static str,
+    fn(Vec<Float>, Vec<Float>) -> Result<Float, CalcError>,
+> = phf::phf_map! {
+    "weighted_mean" => weighted_mean,
+};
+
+/// Functions of a number that result in a vector, eg. `factor(12)`, which is `[2, 2, 3]`.
+pub const VECTOR_VALUED_FUNCS: phf::Map<&'static str, fn(Float) -> Vec<Float>> = phf::phf_map! {
+    "factor" => factor,
+};
+
+/// Functions of two numbers that result in a vector, eg. `contfrac(pi, 3)`, which is `[3, 7, 15]`.
+pub const BINARY_VECTOR_VALUED_FUNCS: phf::Map<&'static str, fn(Float, Float) -> Vec<Float>> = phf::phf_map! {
+    "contfrac" => contfrac,
+};
+
+/// Functions where the result is in the same unit as the argument.
+const UNIT_PRESERVING_FUNCS: [&str; 9] = [
+    "abs", "ceil", "floor", "frac", "max", "min", "mod", "round", "trunc",
+];
+
+/// Built-in units. Units of the same kind are defined in terms of the same base unit,
+/// which for angles is radians. Other units can be declared with eg. `unit km = 1000 m`.
+pub const UNITS: phf::Map<&'static str, UnitInfo> = phf::phf_map! {
+    "rad" => UnitInfo(radian, "rad"),
+    "deg" => UnitInfo(degree, "rad"),
+    "°" => UnitInfo(degree, "rad"),
+};
+
+pub const DOCS: phf::Map<&'static str, &'static str> = phf::phf_map! {
+    "cos" => "cos(x): cosine of x in the current angle unit",
+    "cosec" => "cosec(x): cosecant of x in the current angle unit",
+    "cosech" => "cosech(x): hyperbolic cosecant of x",
+    "cosh" => "cosh(x): hyperbolic cosine of x",
+    "cot" => "cot(x): cotangent of x in the current angle unit",
+    "coth" => "coth(x): hyperbolic cotangent of x",
+    "sec" => "sec(x): secant of x in the current angle unit",
+    "sech" => "sech(x): hyperbolic secant of x",
+    "sin" => "sin(x): sine of x in the current angle unit",
+    "sinh" => "sinh(x): hyperbolic sine of x",
+    "tan" => "tan(x): tangent of x in the current angle unit",
+    "tanh" => "tanh(x): hyperbolic tangent of x",
+
+    "acos" => "acos(x): inverse cosine of x, in the current angle unit",
+    "acosec" => "acosec(x): inverse cosecant of x, in the current angle unit",
+    "acosech" => "acosech(x): inverse hyperbolic cosecant of x",
+    "acosh" => "acosh(x): inverse hyperbolic cosine of x",
+    "acot" => "acot(x): inverse cotangent of x, in the current angle unit",
+    "acoth" => "acoth(x): inverse hyperbolic cotangent of x",
+    "asec" => "asec(x): inverse secant of x, in the current angle unit",
+    "asech" => "asech(x): inverse hyperbolic secant of x",
+    "asin" => "asin(x): inverse sine of x, in the current angle unit",
+    "asinh" => "asinh(x): inverse hyperbolic sine of x",
+    "atan" => "atan(x): inverse tangent of x, in the current angle unit",
+    "atanh" => "atanh(x): inverse hyperbolic tangent of x",
+
+    "abs" => "abs(x) or |x|: absolute value of x",
+    "arg" => "arg(z): the angle of the complex number z, in radians",
+    "avg" => "avg(v): the average of the elements of the vector v, the same as mean(v)",
+    "binom" => "binom(n, k) or nCr(n, k): the binomial coefficient, ie. the number of ways to choose k of n things when the order doesn't matter. It is calculated exactly, eg. binom(100, 50) is 100891344545564193334812497256, and is NaN if it would have more than about a million bits",
+    "cbrt" => "cbrt(x): cube root of x",
+    "ceil" => "ceil(x) or ⌈x⌉: smallest integer greater than or equal to x",
+    "conj" => "conj(z): complex conjugate of z",
+    "contfrac" => "contfrac(x, n): the first n coefficients of the continued fraction of x, eg. contfrac(pi, 5) is [3, 7, 15, 1, 292]. There are fewer if the fraction ends earlier, eg. contfrac(0.75, 5) is [0, 1, 3], including for numbers that only differ from the fraction by rounding, eg. contfrac(1/3, 5) is [0, 3]. It is [NaN] if x isn't finite or n isn't a positive integer",
+    "e_approx" => "e_approx(n): e to n bits of precision, calculated with the series sum(1/k!, k, 0, ∞). n can be at most 2^20, otherwise the result is NaN",
+    "det" => "det(A): determinant of the square matrix A, eg. det([[1, 2], [3, 4]]) is -2",
+    "diff" => "diff(expr, x): the derivative of expr with respect to x. diff(expr, x, a): the same, at x = a",
+    "equiv" => "equiv(a, b): 1 if the expressions a and b are equivalent, otherwise 0. This is checked by comparing them at a number of sample points, so it is not a proof",
+    "exp" => "exp(x): e to the power of x",
+    "factor" => "factor(n): the prime factors of the integer n, with repetitions, in increasing order. Eg. factor(12) is [2, 2, 3]. It is [NaN] if n has several very large prime factors, eg. for 2^128 + 1, since they would take too long to find",
+    "floor" => "floor(x) or ⌊x⌋: largest integer less than or equal to x",
+    "frac" => "frac(x): fractional part of x",
+    "gamma" => "gamma(x) or Γ(x): gamma function of x",
+    "Γ" => "gamma(x) or Γ(x): gamma function of x",
+    "gcd" => "gcd(a, b): greatest common divisor of the integers a and b",
+    "hyp" => "hyp(x, y): hypotenuse of a right triangle with the legs x and y",
+    "identity" => "identity(n): the n×n identity matrix, with ones on the diagonal and zeros everywhere else",
+    "im" => "im(z): imaginary part of the complex number z",
+    "ilog" => "ilog(b, n): the base b logarithm of the integer n, rounded down. Eg. ilog(10, 12345) is 4",
+    "isprime" => "isprime(n): 1 if n is a prime number, otherwise 0. Large numbers are tested probabilistically, with a negligible chance of error",
+    "inv" => "inv(A): inverse of the square matrix A. A^(-1) is the same",
+    "integrate" => "integrate(a, b, expr, x) or ∫(a, b, expr, x): integral of expr from x = a to x = b. x is the variable if it is left out, eg. ∫(0, 1, x^2)",
+    "∫" => "integrate(a, b, expr, x) or ∫(a, b, expr, x): integral of expr from x = a to x = b. x is the variable if it is left out, eg. ∫(0, 1, x^2)",
+    "log" => "log(x): base 10 logarithm of x. log(x, b): base b logarithm of x",
+    "lcm" => "lcm(a, b): least common multiple of the integers a and b",
+    "len" => "len(v): the number of elements of the vector v, eg. len([1, 2, 3]) is 3",
+    "ln" => "ln(x): natural logarithm of x",
+    "max" => "max(x, y): the largest of x and y. max(v): the largest element of the vector v",
+    "mean" => "mean(x, y, ...) or mean(v): the arithmetic mean of the numbers, or of the elements of the vector v",
+    "median" => "median(x, y, ...) or median(v): the middle number when sorted, or the mean of the two middle ones if there is an even number of them",
+    "min" => "min(x, y): the smallest of x and y. min(v): the smallest element of the vector v",
+    "mode" => "mode(x, y, ...) or mode(v): the most common number. The smallest one is chosen if several are equally common",
+    "mod" => "mod(x, y): x modulo y, with the same sign as y. Eg. mod(-1, 2π) is in the range [0, 2π)",
+    "nCr" => "nCr(n, k) or binom(n, k): the binomial coefficient, ie. the number of ways to choose k of n things when the order doesn't matter. It is calculated exactly, eg. nCr(100, 50) is 100891344545564193334812497256, and is NaN if it would have more than about a million bits",
+    "nPr" => "nPr(n, k): the number of ways to choose k of n things in order, ie. n! / (n - k)!. It is calculated exactly, and is NaN if it would have more than about a million bits",
+    "nextprime" => "nextprime(x): the smallest prime number greater than x",
+    "note" => "note(expr, \"text\"): expr, with a description that is shown when explaining it",
+    "ones" => "ones(m, n): an m×n matrix where every element is 1",
+    "percentile" => "percentile(v, p): the pth percentile of the elements of v, interpolated linearly between them. Eg. percentile([1, 2, 3, 4], 50) is 2.5, the median",
+    "pi_approx" => "pi_approx(n): π to n decimal digits, calculated with the Chudnovsky algorithm. n can be at most about 315000, otherwise the result is NaN",
+    "prod" => "prod(start, end, expr) or ∏(start, end, expr): product of expr for every integer n from start to end. prod(start, end, step, expr) and prod(k, start, end, expr) work like for sum",
+    "∏" => "prod(start, end, expr) or ∏(start, end, expr): product of expr for every integer n from start to end. prod(start, end, step, expr) and prod(k, start, end, expr) work like for sum",
+    "re" => "re(z): real part of the complex number z",
+    "round" => "round(x): x rounded to the nearest integer",
+    "simplify" => "simplify(expr): expr, but the calculator shows it simplified algebraically instead of its value, eg. simplify(x + x) is 2 * x",
+    "sqrt" => "sqrt(x) or √x: square root of x. sqrt(x, n): nth root of x",
+    "√" => "sqrt(x) or √x: square root of x. sqrt(x, n): nth root of x",
+    "stdev" => "stdev(x, y, ...) or stdev(v): the sample standard deviation of the numbers, ie. the square root of the variance",
+    "sum" => "sum(start, end, expr) or Σ(start, end, expr): sum of expr for every integer n from start to end. sum(start, end, step, expr): the same, but n increases by step. sum(k, start, end, expr): the same as the first, but with k instead of n, if k isn't a variable",
+    "Σ" => "sum(start, end, expr) or Σ(start, end, expr): sum of expr for every integer n from start to end. sum(start, end, step, expr): the same, but n increases by step. sum(k, start, end, expr): the same as the first, but with k instead of n, if k isn't a variable",
+    "totient" => "totient(n): Euler's totient function, ie. how many of the integers from 1 to n have no common divisor with n other than 1. It is NaN if n can't be factorized, see factor",
+    "transpose" => "transpose(A): the matrix A with its rows as columns. A vector is turned into a column",
+    "trunc" => "trunc(x): integer part of x",
+    "variance" => "variance(x, y, ...) or variance(v): the sample variance of the numbers, ie. the sum of their squared distances from the mean divided by one less than how many there are",
+    "weighted_mean" => "weighted_mean(v, w): the mean of the elements of v, where each one counts as much as the element of w at the same position, ie. Σ(v * w) / Σw",
+    "zeros" => "zeros(m, n): an m×n matrix where every element is 0",
+
+    "pi" => "pi or π: ratio of the circumference of a circle to its diameter, 3.14159265...",
+    "π" => "pi or π: ratio of the circumference of a circle to its diameter, 3.14159265...",
+    "e" => "e: Euler's number, 2.71828182...",
+    "tau" => "tau or τ: ratio of the circumference of a circle to its radius, 6.28318530...",
+    "τ" => "tau or τ: ratio of the circumference of a circle to its radius, 6.28318530...",
+    "phi" => "phi or ϕ: the golden ratio, 1.61803398...",
+    "ϕ" => "phi or ϕ: the golden ratio, 1.61803398...",
+};
+
 enum FuncType {
     Trig,
     InverseTrig,
@@ -99,19 +255,80 @@ impl BinaryFuncInfo {
     }
 }
 
-pub fn call_unary_func(name: &str, x: Float, angle_unit: &Unit) -> Option<Float> {
-    if let Some(func_info) = UNARY_FUNCS.get(name) {
-        Some(func_info.call(x, &angle_unit))
-    } else {
-        None
+/// A function that gives the size of the unit in the base unit at a precision, and the name of the base unit.
+pub struct UnitInfo(fn(u32) -> Float, &'static str);
+
+impl UnitInfo {
+    pub fn factor(&self, precision: u32) -> Float {
+        (self.0)(precision)
+    }
+
+    pub fn base(&self) -> &'static str {
+        self.1
     }
 }
 
+fn radian(precision: u32) -> Float {
+    Float::with_val(precision, 1)
+}
+
+fn degree(precision: u32) -> Float {
+    special_funcs::to_radians(Float::with_val(precision, 1))
+}
+
+pub fn call_unary_func(name: &str, x: Float, angle_unit: &Unit) -> Option<Float> {
+    UNARY_FUNCS
+        .get(name)
+        .map(|func_info| func_info.call(x, angle_unit))
+}
+
 pub fn call_binary_func(name: &str, x: Float, y: Float, angle_unit: &Unit) -> Option<Float> {
-    if let Some(func_info) = BINARY_FUNCS.get(name) {
-        Some(func_info.call(x, y, angle_unit))
+    BINARY_FUNCS
+        .get(name)
+        .map(|func_info| func_info.call(x, y, angle_unit))
+}
+
+pub fn call_stat_func(name: &str, values: Vec<Float>) -> Option<Float> {
+    STAT_FUNCS.get(name).map(|func| func(values))
+}
+
+pub fn call_paired_stat_func(
+    name: &str,
+    values: Vec<Float>,
+    weights: Vec<Float>,
+) -> Option<Result<Float, CalcError>> {
+    PAIRED_STAT_FUNCS
+        .get(name)
+        .map(|func| func(values, weights))
+}
+
+pub fn call_vector_valued_func(name: &str, x: Float) -> Option<Vec<Float>> {
+    VECTOR_VALUED_FUNCS.get(name).map(|func| func(x))
+}
+
+pub fn call_binary_vector_valued_func(name: &str, x: Float, y: Float) -> Option<Vec<Float>> {
+    BINARY_VECTOR_VALUED_FUNCS.get(name).map(|func| func(x, y))
+}
+
+/// Find out which unit the result of a prelude function is in, given the unit of its argument.
+pub fn get_result_unit(
+    name: &str,
+    argument_unit: Option<String>,
+    angle_unit: &Unit,
+) -> Option<String> {
+    let func_type = if let Some(UnaryFuncInfo(_, func_type)) = UNARY_FUNCS.get(name) {
+        func_type
+    } else if let Some(BinaryFuncInfo(_, func_type)) = BINARY_FUNCS.get(name) {
+        func_type
     } else {
-        None
+        return None;
+    };
+
+    match func_type {
+        FuncType::Trig => None,
+        FuncType::InverseTrig => Some(angle_unit.to_string()),
+        FuncType::Other if UNIT_PRESERVING_FUNCS.contains(&name) => argument_unit,
+        FuncType::Other => None,
     }
 }
 
@@ -130,6 +347,7 @@ fn from_angle_unit(x: Float, angle_unit: &Unit) -> Float {
 }
 
 pub mod special_funcs {
+    use rug::float::Constant;
     use rug::Float;
 
     pub fn factorial(x: Float) -> Float {
@@ -137,17 +355,35 @@ pub mod special_funcs {
     }
 
     pub fn to_degrees(x: Float) -> Float {
-        Float::with_val(53, x.to_f64().to_degrees())
+        let pi = Float::with_val(x.prec(), Constant::Pi);
+        x * 180 / pi
     }
 
     pub fn to_radians(x: Float) -> Float {
-        Float::with_val(53, x.to_f64().to_radians())
+        let pi = Float::with_val(x.prec(), Constant::Pi);
+        x * pi / 180
+    }
+
+    /// The `%` operator, which is the same as `mod(x, y)`.
+    pub fn modulo(x: Float, y: Float) -> Float {
+        super::funcs::modulo(x, y)
+    }
+
+    /// The `//` operator, which rounds the quotient down, so that `x = y * (x // y) + x % y`.
+    pub fn floor_division(x: Float, y: Float) -> Float {
+        let remainder = super::funcs::modulo(x.clone(), y.clone());
+
+        ((x - remainder) / y).round()
     }
 }
 
 mod funcs {
+    use crate::parser::CalcError;
+    use rug::float::{Constant, Special};
+    use rug::integer::IsPrime;
     use rug::ops::Pow;
-    use rug::Float;
+    use rug::{Float, Integer, Rational};
+    use std::cmp::Ordering;
 
     pub fn abs(x: Float) -> Float {
         x.abs()
@@ -201,6 +437,17 @@ mod funcs {
         x.atanh()
     }
 
+    /// The angle of a real number is 0 if it is positive and π if it is negative.
+    pub fn arg(x: Float) -> Float {
+        if x < 0 {
+            Float::with_val(x.prec(), Constant::Pi)
+        } else if x.is_nan() {
+            x
+        } else {
+            Float::with_val(x.prec(), 0)
+        }
+    }
+
     pub fn cbrt(x: Float) -> Float {
         x.cbrt()
     }
@@ -209,6 +456,10 @@ mod funcs {
         x.ceil()
     }
 
+    pub fn conj(x: Float) -> Float {
+        x
+    }
+
     pub fn cos(x: Float) -> Float {
         x.cos()
     }
@@ -233,6 +484,32 @@ mod funcs {
         x.clone().cosh() / x.sinh()
     }
 
+    /// The largest precision, in bits, that `e_approx` and `pi_approx` calculate their results with.
+    /// It is the same as the default maximum precision of a context, `parser::DEFAULT_MAX_PRECISION`.
+    const MAX_APPROX_PRECISION: u32 = crate::parser::DEFAULT_MAX_PRECISION;
+
+    /// NaN is returned if more than `MAX_APPROX_PRECISION` bits are asked for.
+    pub fn e_approx(x: Float) -> Float {
+        let precision = x.to_u32_saturating().unwrap_or(0).max(1);
+        // Extra bits to make up for the rounding errors of the additions.
+        let working_precision = match precision.checked_add(32) {
+            Some(working_precision) if precision <= MAX_APPROX_PRECISION => working_precision,
+            _ => return Float::with_val(x.prec(), Special::Nan),
+        };
+        let mut sum = Float::with_val(working_precision, 1);
+        let mut term = Float::with_val(working_precision, 1);
+        let mut k = 1u32;
+
+        // Stop when the terms no longer affect the sum at this precision.
+        while term.get_exp().unwrap_or(i32::MIN) > -(working_precision as i32) {
+            term /= k;
+            sum += &term;
+            k += 1;
+        }
+
+        Float::with_val(precision, sum)
+    }
+
     pub fn exp(x: Float) -> Float {
         x.exp()
     }
@@ -249,10 +526,35 @@ mod funcs {
         x.gamma()
     }
 
+    pub fn im(x: Float) -> Float {
+        Float::with_val(x.prec(), 0)
+    }
+
     pub fn hyp(x: Float, y: Float) -> Float {
         x.hypot(&y)
     }
 
+    /// Floor of the base `b` logarithm of `x`, calculated exactly with integers.
+    /// NaN is returned if the arguments aren't integers with `b > 1` and `x > 0`.
+    pub fn ilog(b: Float, x: Float) -> Float {
+        let precision = x.prec();
+        if !b.is_integer() || !x.is_integer() || b <= 1 || x <= 0 {
+            return Float::with_val(precision, Special::Nan);
+        }
+
+        let b = b.to_integer().unwrap();
+        let x = x.to_integer().unwrap();
+
+        let mut result = 0u32;
+        let mut power = b.clone();
+        while power <= x {
+            power *= &b;
+            result += 1;
+        }
+
+        Float::with_val(precision, result)
+    }
+
     pub fn log(x: Float) -> Float {
         x.log10()
     }
@@ -265,6 +567,122 @@ mod funcs {
         x.ln()
     }
 
+    pub fn mean(values: Vec<Float>) -> Float {
+        let count = values.len();
+        match sum(values) {
+            Some(sum) => sum / count as u64,
+            None => Float::with_val(53, Special::Nan),
+        }
+    }
+
+    pub fn median(values: Vec<Float>) -> Float {
+        let values = sorted(values);
+        let middle = values.len() / 2;
+        if values.is_empty() {
+            Float::with_val(53, Special::Nan)
+        } else if values.len() % 2 == 1 {
+            values[middle].clone()
+        } else {
+            Float::with_val(values[middle].prec(), &values[middle - 1] + &values[middle]) / 2
+        }
+    }
+
+    /// The most common value. Of the values that are equally common, the smallest is chosen.
+    pub fn mode(values: Vec<Float>) -> Float {
+        let values = sorted(values);
+        let mut mode = None;
+        let mut mode_count = 0;
+        let mut start = 0;
+        // Equal values are next to each other, since they are sorted.
+        for end in 1..=values.len() {
+            if end == values.len() || values[end] != values[start] {
+                if end - start > mode_count {
+                    mode = Some(&values[start]);
+                    mode_count = end - start;
+                }
+
+                start = end;
+            }
+        }
+
+        mode.cloned()
+            .unwrap_or_else(|| Float::with_val(53, Special::Nan))
+    }
+
+    /// The last value is the percentile, between 0 and 100, and the others are the data.
+    /// Like the median, it is interpolated linearly between the two closest values.
+    pub fn percentile(mut values: Vec<Float>) -> Float {
+        let p = match values.pop() {
+            Some(p) if !values.is_empty() && (0..=100).contains(&p) => p,
+            _ => return Float::with_val(53, Special::Nan),
+        };
+        let values = sorted(values);
+        let rank: Float = p / 100 * (values.len() - 1) as u64;
+        let lower = rank.to_f64().floor() as usize;
+        let upper = (lower + 1).min(values.len() - 1);
+        let fraction = rank - lower as u64;
+        let difference = Float::with_val(values[upper].prec(), &values[upper] - &values[lower]);
+
+        fraction * difference + &values[lower]
+    }
+
+    pub fn stdev(values: Vec<Float>) -> Float {
+        variance(values).sqrt()
+    }
+
+    /// The sample variance, so it is undefined for a single value.
+    pub fn variance(values: Vec<Float>) -> Float {
+        let count = values.len() as u64;
+        let mean = mean(values.clone());
+        let squares = values.into_iter().map(|x| (x - &mean).square()).collect();
+
+        match sum(squares) {
+            Some(sum) => sum / (count - 1),
+            None => mean,
+        }
+    }
+
+    /// The mean where every value counts as much as its weight, Σ(v * w) / Σw.
+    pub fn weighted_mean(values: Vec<Float>, weights: Vec<Float>) -> Result<Float, CalcError> {
+        if values.len() != weights.len() {
+            return Err(CalcError::Message(format!(
+                "weighted_mean needs as many weights as values, but got {} values and {} weights.",
+                values.len(),
+                weights.len()
+            )));
+        }
+
+        let precision = values.first().map_or(53, Float::prec);
+        let mut total = Float::with_val(precision, 0);
+        let mut total_weight = Float::with_val(precision, 0);
+        for (value, weight) in values.iter().zip(&weights) {
+            total += Float::with_val(precision, value * weight);
+            total_weight += weight;
+        }
+
+        if total_weight.is_zero() {
+            return Err(CalcError::Message(String::from(
+                "The weights of weighted_mean add up to zero.",
+            )));
+        }
+
+        Ok(total / total_weight)
+    }
+
+    fn sum(values: Vec<Float>) -> Option<Float> {
+        let mut iter = values.into_iter();
+        let first = iter.next()?;
+
+        Some(iter.fold(first, |sum, x| sum + x))
+    }
+
+    /// NaN is sorted like any other value, since it has no order.
+    fn sorted(mut values: Vec<Float>) -> Vec<Float> {
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+        values
+    }
+
     pub fn max(x: Float, y: Float) -> Float {
         x.max(&y)
     }
@@ -273,6 +691,51 @@ mod funcs {
         x.min(&y)
     }
 
+    /// NaN is returned if more digits than fit in `MAX_APPROX_PRECISION` bits are asked for.
+    pub fn pi_approx(x: Float) -> Float {
+        let digits = x.to_u32_saturating().unwrap_or(0).max(1);
+        let bits = (f64::from(digits) * std::f64::consts::LOG2_10).ceil() + 8.0;
+        if bits > f64::from(MAX_APPROX_PRECISION) {
+            return Float::with_val(x.prec(), Special::Nan);
+        }
+
+        let precision = bits as u32;
+        // Every term of the series adds about 14 correct digits.
+        let terms = digits / 14 + 1;
+        let (_, q, t) = chudnovsky(0, terms + 1);
+
+        let sqrt = Float::with_val(precision, 10005).sqrt();
+        sqrt * 426880 * q / t
+    }
+
+    /// Binary splitting of the Chudnovsky series, for the terms in the range `a..b`.
+    /// Returns P(a, b), Q(a, b) and T(a, b).
+    fn chudnovsky(a: u32, b: u32) -> (Integer, Integer, Integer) {
+        if b - a == 1 {
+            if a == 0 {
+                return (Integer::from(1), Integer::from(1), Integer::from(13591409));
+            }
+
+            let k = u64::from(a);
+            let p = Integer::from(6 * k - 5) * (2 * k - 1) * (6 * k - 1);
+            let q = Integer::from(k).pow(3) * 10939058860032000u64;
+            let t: Integer = &p * (Integer::from(545140134) * k + 13591409);
+
+            // The terms alternate in sign.
+            return if a % 2 == 1 { (p, q, -t) } else { (p, q, t) };
+        }
+
+        let m = (a + b) / 2;
+        let (p1, q1, t1) = chudnovsky(a, m);
+        let (p2, q2, t2) = chudnovsky(m, b);
+
+        (Integer::from(&p1 * &p2), q1 * &q2, t1 * q2 + p1 * t2)
+    }
+
+    pub fn re(x: Float) -> Float {
+        x
+    }
+
     pub fn round(x: Float) -> Float {
         x.round()
     }
@@ -297,6 +760,282 @@ mod funcs {
         x.sqrt()
     }
 
+    /// Floored modulo, which means the result has the same sign as the divisor.
+    pub fn modulo(x: Float, y: Float) -> Float {
+        let remainder = Float::with_val(x.prec(), &x % &y);
+        if remainder.is_zero() || remainder.is_sign_negative() == y.is_sign_negative() {
+            return remainder;
+        }
+
+        let result = remainder + &y;
+
+        // Adding a tiny remainder can round to the divisor itself, which is outside of the range.
+        if result == y {
+            Float::with_val(x.prec(), 0)
+        } else {
+            result
+        }
+    }
+
+    /// The binomial coefficient, calculated exactly with integers.
+    /// NaN is returned if the arguments aren't integers with `n >= 0` and `k >= 0`, and 0 if `k > n`.
+    pub fn ncr(n: Float, k: Float) -> Float {
+        let precision = n.prec();
+        match combinatorics_args(&n, &k, false) {
+            Some((n, k)) => exact_float(n.binomial(k), precision),
+            None => invalid_or_zero(&n, &k),
+        }
+    }
+
+    /// The number of k-permutations of n, `n! / (n - k)!`, calculated exactly with integers.
+    /// The arguments are checked like for `ncr`.
+    pub fn npr(n: Float, k: Float) -> Float {
+        let precision = n.prec();
+        match combinatorics_args(&n, &k, true) {
+            Some((n, k)) => exact_float(
+                n.binomial(k) * Integer::from(Integer::factorial(k)),
+                precision,
+            ),
+            None => invalid_or_zero(&n, &k),
+        }
+    }
+
+    /// The arguments of `ncr` and `npr` as integers, if they are valid, `k <= n`,
+    /// and the result has at most `MAX_COMBINATORICS_BITS` bits.
+    /// For combinations, k is replaced by `n - k` if that is smaller, since the result is the same.
+    fn combinatorics_args(n: &Float, k: &Float, permutations: bool) -> Option<(Integer, u32)> {
+        if !n.is_integer() || !k.is_integer() || *n < 0 || *k < 0 || k > n {
+            return None;
+        }
+
+        let n = n.to_integer()?;
+        let mut k = k.to_integer()?;
+        if !permutations {
+            let rest = Integer::from(&n - &k);
+            k = k.min(rest);
+        }
+        let k = k.to_u32()?;
+        if combinatorics_bits(&n, k, permutations) > MAX_COMBINATORICS_BITS {
+            return None;
+        }
+
+        Some((n, k))
+    }
+
+    /// Results of `ncr` and `npr` with more bits than this are NaN, since eg. `nCr(10000000, 5000000)`
+    /// would take seconds to calculate.
+    const MAX_COMBINATORICS_BITS: f64 = MAX_APPROX_PRECISION as f64;
+
+    /// An upper bound for the number of bits in the number of k-permutations of n, `n^k`,
+    /// or in the number of k-combinations, `(e * n / k)^k`.
+    fn combinatorics_bits(n: &Integer, k: u32, permutations: bool) -> f64 {
+        let log2_n = Float::with_val(64, n).log2().to_f64();
+        if permutations {
+            f64::from(k) * log2_n
+        } else if k == 0 {
+            0.0
+        } else {
+            f64::from(k) * (log2_n - f64::from(k).log2() + std::f64::consts::LOG2_E)
+        }
+    }
+
+    fn invalid_or_zero(n: &Float, k: &Float) -> Float {
+        if n.is_integer() && k.is_integer() && *n >= 0 && k > n {
+            Float::with_val(n.prec(), 0)
+        } else {
+            Float::with_val(n.prec(), Special::Nan)
+        }
+    }
+
+    /// The integer as a float, with enough precision to represent it exactly, but at least `precision`.
+    fn exact_float(x: Integer, precision: u32) -> Float {
+        Float::with_val(x.significant_bits().max(precision), x)
+    }
+
+    /// The prime factors of an integer, with repetitions, in increasing order.
+    /// 1 has itself as the only factor, and `[NaN]` is returned if `x` isn't a positive integer,
+    /// or if it couldn't be factorized within `MAX_POLLARD_RHO_STEPS`.
+    pub fn factor(x: Float) -> Vec<Float> {
+        let precision = x.prec();
+        match positive_integer(&x) {
+            Some(n) if n == 1 => vec![Float::with_val(precision, 1)],
+            Some(n) => match prime_factors(n, MAX_POLLARD_RHO_STEPS) {
+                Some(factors) => factors
+                    .into_iter()
+                    .map(|factor| exact_float(factor, precision))
+                    .collect(),
+                None => vec![Float::with_val(precision, Special::Nan)],
+            },
+            None => vec![Float::with_val(precision, Special::Nan)],
+        }
+    }
+
+    /// The coefficients of the continued fraction of `x`, at most `depth` of them. `x` is taken as the
+    /// exact fraction it represents, and the expansion ends at the first convergent that rounds to `x`,
+    /// since the coefficients after that would only describe the rounding error.
+    pub fn contfrac(x: Float, depth: Float) -> Vec<Float> {
+        let precision = x.prec();
+        let (mut rest, depth) = match (x.to_rational(), positive_integer(&depth)) {
+            (Some(rest), Some(depth)) => (rest, depth.to_usize().unwrap_or(usize::MAX)),
+            _ => return vec![Float::with_val(precision, Special::Nan)],
+        };
+
+        // The numerators and denominators of the last two convergents.
+        let (mut numerator, mut previous_numerator) = (Integer::from(1), Integer::from(0));
+        let (mut denominator, mut previous_denominator) = (Integer::from(0), Integer::from(1));
+        let mut coefficients = Vec::new();
+        while coefficients.len() < depth {
+            let (fraction, coefficient) = rest.fract_floor(Integer::new());
+            previous_numerator += Integer::from(&coefficient * &numerator);
+            previous_denominator += Integer::from(&coefficient * &denominator);
+            std::mem::swap(&mut numerator, &mut previous_numerator);
+            std::mem::swap(&mut denominator, &mut previous_denominator);
+            coefficients.push(exact_float(coefficient, precision));
+
+            let convergent = Rational::from((numerator.clone(), denominator.clone()));
+            if fraction == 0 || Float::with_val(precision, convergent) == x {
+                break;
+            }
+
+            rest = fraction.recip();
+        }
+
+        coefficients
+    }
+
+    /// NaN is returned if the arguments aren't integers.
+    pub fn gcd(x: Float, y: Float) -> Float {
+        let precision = x.prec();
+        match (x.to_integer(), y.to_integer()) {
+            (Some(a), Some(b)) if x.is_integer() && y.is_integer() => {
+                exact_float(a.gcd(&b), precision)
+            }
+            _ => Float::with_val(precision, Special::Nan),
+        }
+    }
+
+    /// The result is 1 for primes and 0 for everything else, including numbers that aren't integers.
+    pub fn isprime(x: Float) -> Float {
+        let is_prime = match positive_integer(&x) {
+            Some(n) => n.is_probably_prime(PRIMALITY_TEST_ROUNDS) != IsPrime::No,
+            None => false,
+        };
+
+        Float::with_val(x.prec(), is_prime as u8)
+    }
+
+    /// NaN is returned if the arguments aren't integers.
+    pub fn lcm(x: Float, y: Float) -> Float {
+        let precision = x.prec();
+        match (x.to_integer(), y.to_integer()) {
+            (Some(a), Some(b)) if x.is_integer() && y.is_integer() => {
+                exact_float(a.lcm(&b), precision)
+            }
+            _ => Float::with_val(precision, Special::Nan),
+        }
+    }
+
+    pub fn nextprime(x: Float) -> Float {
+        let precision = x.prec();
+        match x.floor().to_integer() {
+            Some(n) => exact_float(n.next_prime(), precision),
+            None => Float::with_val(precision, Special::Nan),
+        }
+    }
+
+    /// Euler's totient function, calculated from the distinct prime factors `p` of `n` as `n * ∏(1 - 1/p)`.
+    /// NaN is returned if `x` isn't a positive integer, or if it couldn't be factorized, like for `factor`.
+    pub fn totient(x: Float) -> Float {
+        let precision = x.prec();
+        let (n, mut factors) = match positive_integer(&x) {
+            Some(n) => match prime_factors(n.clone(), MAX_POLLARD_RHO_STEPS) {
+                Some(factors) => (n, factors),
+                None => return Float::with_val(precision, Special::Nan),
+            },
+            None => return Float::with_val(precision, Special::Nan),
+        };
+
+        factors.dedup();
+        let result = factors
+            .into_iter()
+            .fold(n, |result, p| result / &p * (p - 1u32));
+
+        exact_float(result, precision)
+    }
+
+    /// The number of Miller-Rabin rounds for numbers that are too large to be tested deterministically.
+    const PRIMALITY_TEST_ROUNDS: u32 = 30;
+
+    fn positive_integer(x: &Float) -> Option<Integer> {
+        if x.is_integer() && *x >= 1 {
+            x.to_integer()
+        } else {
+            None
+        }
+    }
+
+    /// The largest number of steps Pollard's rho algorithm may take for one factorization,
+    /// since it can take very long for numbers with only large prime factors, eg. `2^128 + 1`.
+    const MAX_POLLARD_RHO_STEPS: u32 = 1 << 20;
+
+    /// Factorize `n`, which is at least 2, by trial division with small numbers,
+    /// and then with Pollard's rho algorithm for what remains.
+    /// `None` is returned if that takes more than `max_steps` steps, which is `MAX_POLLARD_RHO_STEPS` outside of tests.
+    pub fn prime_factors(mut n: Integer, max_steps: u32) -> Option<Vec<Integer>> {
+        let mut factors = Vec::new();
+        for d in 2u32..1000 {
+            while n.is_divisible_u(d) {
+                n /= d;
+                factors.push(Integer::from(d));
+            }
+        }
+
+        let mut steps_left = max_steps;
+        let mut remaining = vec![n];
+        while let Some(n) = remaining.pop() {
+            if n == 1 {
+                continue;
+            }
+
+            if n.is_probably_prime(PRIMALITY_TEST_ROUNDS) != IsPrime::No {
+                factors.push(n);
+            } else {
+                let divisor = pollard_rho(&n, &mut steps_left)?;
+                remaining.push(Integer::from(&n / &divisor));
+                remaining.push(divisor);
+            }
+        }
+
+        factors.sort();
+
+        Some(factors)
+    }
+
+    /// Find a divisor of the composite number `n` other than 1 and `n`,
+    /// by looking for a cycle in the sequence `x² + c mod n`. A new `c` is tried if it fails.
+    /// `None` is returned if no divisor was found before running out of steps.
+    fn pollard_rho(n: &Integer, steps_left: &mut u32) -> Option<Integer> {
+        let mut c = 1u32;
+        loop {
+            let next = |x: &Integer| (Integer::from(x * x) + c) % n;
+            let mut x = Integer::from(2);
+            let mut y = Integer::from(2);
+            let mut divisor = Integer::from(1);
+            while divisor == 1 {
+                *steps_left = steps_left.checked_sub(1)?;
+                x = next(&x);
+                y = next(&next(&y));
+                divisor = Integer::from(&x - &y).abs().gcd(n);
+            }
+
+            if divisor != *n {
+                return Some(divisor);
+            }
+
+            c += 1;
+        }
+    }
+
     pub fn nth_sqrt(x: Float, n: Float) -> Float {
         x.pow(Float::with_val(1, 1) / n)
     }
@@ -313,3 +1052,370 @@ mod funcs {
         x.trunc()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+    use rug::Integer;
+    use std::f64::consts::TAU;
+    use test_case::test_case;
+
+    #[test]
+    fn test_docs() {
+        // Functions that are evaluated by the interpreter rather than through the tables.
+        let special = [
+            "avg",
+            "det",
+            "diff",
+            "equiv",
+            "identity",
+            "integrate",
+            "∫",
+            "inv",
+            "len",
+            "note",
+            "ones",
+            "prod",
+            "∏",
+            "simplify",
+            "sum",
+            "Σ",
+            "transpose",
+            "zeros",
+        ];
+        let names = UNARY_FUNCS
+            .keys()
+            .chain(BINARY_FUNCS.keys())
+            .chain(STAT_FUNCS.keys())
+            .chain(PAIRED_STAT_FUNCS.keys())
+            .chain(VECTOR_VALUED_FUNCS.keys())
+            .chain(BINARY_VECTOR_VALUED_FUNCS.keys())
+            .chain(CONSTANTS.keys())
+            .chain(special.iter());
+
+        for name in names {
+            assert!(DOCS.contains_key(name), "No documentation for '{}'.", name);
+        }
+    }
+
+    #[test_case(-1f64, TAU - 1f64)]
+    #[test_case(7f64, 7f64 - TAU)]
+    #[test_case(-20f64, 4f64 * TAU - 20f64)]
+    #[test_case(0.5f64, 0.5f64)]
+    #[test_case(-1e-20, 0f64 ; "rounded to a whole turn")]
+    fn test_mod_principal_angle(angle: f64, expected: f64) {
+        let two_pi: Float = Float::with_val(53, rug::float::Constant::Pi) * 2;
+        let result = funcs::modulo(Float::with_val(53, angle), two_pi.clone());
+
+        // The result should be in the principal range, and differ from the angle by a whole number of turns.
+        assert!(result >= 0 && result < two_pi);
+        assert_approx_eq!(&result, &Float::with_val(53, expected), 4);
+    }
+
+    #[test_case(7f64, -3f64, -2f64 ; "negative divisor")]
+    #[test_case(-7f64, 3f64, 2f64 ; "negative dividend")]
+    #[test_case(7f64, 3f64, 1f64)]
+    #[test_case(-6f64, 3f64, 0f64)]
+    fn test_mod_sign(x: f64, y: f64, expected: f64) {
+        let result = funcs::modulo(Float::with_val(53, x), Float::with_val(53, y));
+
+        assert_eq!(result, expected);
+    }
+
+    #[test_case(7f64, 2f64, 3f64)]
+    #[test_case(-7f64, 2f64, -4f64 ; "negative dividend")]
+    #[test_case(7f64, -2f64, -4f64 ; "negative divisor")]
+    #[test_case(-7f64, -2f64, 3f64 ; "both negative")]
+    #[test_case(7.5f64, 2f64, 3f64 ; "fraction")]
+    fn test_floor_division(x: f64, y: f64, expected: f64) {
+        let result = special_funcs::floor_division(Float::with_val(53, x), Float::with_val(53, y));
+
+        assert_eq!(result, expected);
+    }
+
+    #[test_case(10f64, 12345f64, 4f64)]
+    #[test_case(10f64, 1000f64, 3f64)]
+    #[test_case(10f64, 999f64, 2f64)]
+    #[test_case(10f64, 1001f64, 3f64)]
+    #[test_case(2f64, 1f64, 0f64)]
+    #[test_case(2f64, 1024f64, 10f64)]
+    #[test_case(2f64, 1023f64, 9f64)]
+    fn test_ilog(b: f64, x: f64, expected: f64) {
+        let result = funcs::ilog(Float::with_val(53, b), Float::with_val(53, x));
+
+        assert_eq!(result, expected);
+    }
+
+    #[test_case(1f64, 10f64 ; "base one")]
+    #[test_case(10f64, 0f64 ; "zero")]
+    #[test_case(10f64, 12.5f64 ; "fraction")]
+    #[test_case(2.5f64, 10f64 ; "fractional base")]
+    fn test_ilog_invalid(b: f64, x: f64) {
+        assert!(funcs::ilog(Float::with_val(53, b), Float::with_val(53, x)).is_nan());
+    }
+
+    #[test]
+    fn test_weighted_mean() {
+        let values = floats(&[1f64, 2f64, 3f64]);
+
+        assert_eq!(
+            funcs::weighted_mean(values.clone(), floats(&[3f64, 1f64, 1f64])).unwrap(),
+            1.6
+        );
+        assert_eq!(
+            funcs::weighted_mean(values.clone(), floats(&[1f64, 1f64])),
+            Err(CalcError::Message(String::from(
+                "weighted_mean needs as many weights as values, but got 3 values and 2 weights."
+            )))
+        );
+        assert_eq!(
+            funcs::weighted_mean(values, floats(&[1f64, -2f64, 1f64])),
+            Err(CalcError::Message(String::from(
+                "The weights of weighted_mean add up to zero."
+            )))
+        );
+    }
+
+    #[test_case(5f64, 2f64, 10f64)]
+    #[test_case(5f64, 0f64, 1f64 ; "none chosen")]
+    #[test_case(5f64, 5f64, 1f64 ; "all chosen")]
+    #[test_case(5f64, 6f64, 0f64 ; "more than n")]
+    #[test_case(0f64, 0f64, 1f64 ; "zero")]
+    #[test_case(1e15f64, 999999999999999f64, 1e15f64 ; "almost all of many")]
+    fn test_ncr(n: f64, k: f64, expected: f64) {
+        assert_eq!(
+            funcs::ncr(Float::with_val(53, n), Float::with_val(53, k)),
+            expected
+        );
+    }
+
+    #[test_case(5f64, 2f64, 20f64)]
+    #[test_case(5f64, 0f64, 1f64 ; "none chosen")]
+    #[test_case(5f64, 5f64, 120f64 ; "all chosen")]
+    #[test_case(5f64, 6f64, 0f64 ; "more than n")]
+    fn test_npr(n: f64, k: f64, expected: f64) {
+        assert_eq!(
+            funcs::npr(Float::with_val(53, n), Float::with_val(53, k)),
+            expected
+        );
+    }
+
+    #[test_case(2.5f64, 1f64 ; "fractional n")]
+    #[test_case(5f64, 1.5f64 ; "fractional k")]
+    #[test_case(-5f64, 2f64 ; "negative n")]
+    #[test_case(5f64, -2f64 ; "negative k")]
+    #[test_case(10000000f64, 5000000f64 ; "too many bits")]
+    #[test_case(1e300f64, 5000f64 ; "large n")]
+    fn test_combinatorics_invalid(n: f64, k: f64) {
+        assert!(funcs::ncr(Float::with_val(53, n), Float::with_val(53, k)).is_nan());
+        assert!(funcs::npr(Float::with_val(53, n), Float::with_val(53, k)).is_nan());
+    }
+
+    #[test]
+    fn test_ncr_exact() {
+        let expected = Integer::from_str_radix("100891344545564193334812497256", 10).unwrap();
+        let result = funcs::ncr(Float::with_val(53, 100), Float::with_val(53, 50));
+
+        assert_eq!(result.to_integer().unwrap(), expected);
+        assert_eq!(
+            funcs::npr(Float::with_val(53, 30), Float::with_val(53, 30))
+                .to_integer()
+                .unwrap(),
+            Integer::from(Integer::factorial(30))
+        );
+    }
+
+    #[test_case(12f64, 18f64, 6f64, 36f64)]
+    #[test_case(-4f64, 6f64, 2f64, 12f64 ; "negative")]
+    #[test_case(7f64, 0f64, 7f64, 0f64 ; "zero")]
+    fn test_gcd_lcm(x: f64, y: f64, gcd: f64, lcm: f64) {
+        assert_eq!(
+            funcs::gcd(Float::with_val(53, x), Float::with_val(53, y)),
+            gcd
+        );
+        assert_eq!(
+            funcs::lcm(Float::with_val(53, x), Float::with_val(53, y)),
+            lcm
+        );
+    }
+
+    #[test]
+    fn test_gcd_lcm_invalid() {
+        assert!(funcs::gcd(Float::with_val(53, 1.5), Float::with_val(53, 3)).is_nan());
+        assert!(funcs::lcm(Float::with_val(53, 3), Float::with_val(53, 1.5)).is_nan());
+    }
+
+    #[test_case(2f64, true)]
+    #[test_case(97f64, true)]
+    #[test_case(91f64, false)]
+    #[test_case(1f64, false ; "one")]
+    #[test_case(-7f64, false ; "negative")]
+    #[test_case(7.5f64, false ; "fraction")]
+    fn test_isprime(x: f64, expected: bool) {
+        assert_eq!(funcs::isprime(Float::with_val(53, x)), expected as u8);
+    }
+
+    #[test_case(13f64, 17f64)]
+    #[test_case(2.5f64, 3f64 ; "fraction")]
+    #[test_case(-10f64, 2f64 ; "negative")]
+    fn test_nextprime(x: f64, expected: f64) {
+        assert_eq!(funcs::nextprime(Float::with_val(53, x)), expected);
+    }
+
+    #[test_case(1f64, 1f64)]
+    #[test_case(36f64, 12f64)]
+    #[test_case(97f64, 96f64 ; "prime")]
+    fn test_totient(x: f64, expected: f64) {
+        assert_eq!(funcs::totient(Float::with_val(53, x)), expected);
+    }
+
+    #[test]
+    fn test_prime_factors_too_slow() {
+        // 2^128 + 1 is the product of two primes that are both too large for Pollard's rho algorithm.
+        let n = Integer::from(Integer::u_pow_u(2, 128)) + 1;
+        // A product of two primes above the trial division limit, that only takes a few steps.
+        let small = Integer::from(1009u32 * 1013u32);
+
+        assert_eq!(funcs::prime_factors(n, 1 << 10), None);
+        assert_eq!(
+            funcs::prime_factors(small, 1 << 10),
+            Some(vec![Integer::from(1009), Integer::from(1013)])
+        );
+    }
+
+    #[test]
+    #[ignore = "takes all of the MAX_POLLARD_RHO_STEPS, which is slow"]
+    fn test_factor_too_slow() {
+        let x: Float = Float::with_val(256, Float::u_pow_u(2, 128)) + 1;
+
+        assert!(funcs::factor(x.clone())[0].is_nan());
+        assert!(funcs::totient(x).is_nan());
+    }
+
+    #[test_case(12f64, &[2f64, 2f64, 3f64])]
+    #[test_case(1f64, &[1f64] ; "one")]
+    #[test_case(600851475143f64, &[71f64, 839f64, 1471f64, 6857f64] ; "large")]
+    fn test_factor(x: f64, expected: &[f64]) {
+        assert_eq!(funcs::factor(Float::with_val(53, x)), floats(expected));
+    }
+
+    #[test_case(0f64 ; "zero")]
+    #[test_case(-12f64 ; "negative")]
+    #[test_case(1.5f64 ; "fraction")]
+    fn test_factor_invalid(x: f64) {
+        let factors = funcs::factor(Float::with_val(53, x));
+
+        assert!(factors.len() == 1 && factors[0].is_nan());
+    }
+
+    #[test]
+    fn test_factor_big() {
+        // Both factors are larger than the ones found by trial division, so Pollard's rho is needed.
+        let n = Integer::from(1_000_000_007u64) * Integer::from(1_000_000_009u64);
+        let factors: Vec<Integer> = funcs::factor(Float::with_val(128, n))
+            .iter()
+            .map(|x| x.to_integer().unwrap())
+            .collect();
+
+        assert_eq!(factors, [1_000_000_007u64, 1_000_000_009u64]);
+        assert_eq!(
+            funcs::totient(Float::with_val(128, 1_000_000_016_000_000_063u64)),
+            1_000_000_014_000_000_048u64
+        );
+    }
+
+    #[test_case(0.75, 5f64, &[0f64, 1f64, 3f64] ; "ends early")]
+    #[test_case(-0.75, 5f64, &[-1f64, 4f64] ; "negative")]
+    #[test_case(1f64 / 3f64, 5f64, &[0f64, 3f64] ; "rounded")]
+    #[test_case(2.5, 1f64, &[2f64] ; "one coefficient")]
+    fn test_contfrac(x: f64, depth: f64, expected: &[f64]) {
+        assert_eq!(
+            funcs::contfrac(Float::with_val(53, x), Float::with_val(53, depth)),
+            floats(expected)
+        );
+    }
+
+    #[test]
+    fn test_contfrac_pi() {
+        let pi = Float::with_val(53, rug::float::Constant::Pi);
+
+        assert_eq!(
+            funcs::contfrac(pi, Float::with_val(53, 5)),
+            floats(&[3f64, 7f64, 15f64, 1f64, 292f64])
+        );
+    }
+
+    #[test_case(f64::INFINITY, 5f64 ; "infinite")]
+    #[test_case(0.75, 0f64 ; "zero depth")]
+    #[test_case(0.75, 1.5 ; "fractional depth")]
+    fn test_contfrac_invalid(x: f64, depth: f64) {
+        let coefficients = funcs::contfrac(Float::with_val(53, x), Float::with_val(53, depth));
+
+        assert!(coefficients.len() == 1 && coefficients[0].is_nan());
+    }
+
+    fn floats(values: &[f64]) -> Vec<Float> {
+        values.iter().map(|x| Float::with_val(53, x)).collect()
+    }
+
+    #[test_case(&[3f64, 1f64, 2f64], 2f64 ; "odd")]
+    #[test_case(&[4f64, 1f64, 3f64, 2f64], 2.5 ; "even")]
+    #[test_case(&[-1f64], -1f64 ; "one")]
+    fn test_median(values: &[f64], expected: f64) {
+        assert_eq!(funcs::median(floats(values)), expected);
+    }
+
+    #[test_case(&[1f64, 2f64, 2f64, 3f64], 2f64 ; "most common")]
+    #[test_case(&[3f64, 1f64, 3f64, 1f64, 2f64], 1f64 ; "tie")]
+    #[test_case(&[5f64, 4f64], 4f64 ; "all unique")]
+    fn test_mode(values: &[f64], expected: f64) {
+        assert_eq!(funcs::mode(floats(values)), expected);
+    }
+
+    #[test_case(&[10f64, 20f64, 30f64, 0f64], 10f64 ; "minimum")]
+    #[test_case(&[10f64, 20f64, 30f64, 100f64], 30f64 ; "maximum")]
+    #[test_case(&[30f64, 10f64, 20f64, 75f64], 25f64 ; "interpolated")]
+    #[test_case(&[7f64, 50f64], 7f64 ; "one value")]
+    fn test_percentile(values: &[f64], expected: f64) {
+        assert_eq!(funcs::percentile(floats(values)), expected);
+    }
+
+    #[test_case(&[1f64, 2f64, 101f64] ; "above 100")]
+    #[test_case(&[1f64, 2f64, -1f64] ; "negative")]
+    #[test_case(&[50f64] ; "no values")]
+    fn test_percentile_invalid(values: &[f64]) {
+        assert!(funcs::percentile(floats(values)).is_nan());
+    }
+
+    #[test]
+    fn test_variance() {
+        let values = floats(&[2f64, 4f64, 4f64, 4f64, 5f64, 5f64, 7f64, 9f64]);
+
+        assert_eq!(funcs::variance(values.clone()), 32f64 / 7f64);
+        assert_eq!(funcs::stdev(values), (32f64 / 7f64).sqrt());
+        assert!(funcs::variance(floats(&[1f64])).is_nan());
+    }
+
+    #[test]
+    fn test_e_approx() {
+        let expected = Float::with_val(200, 1).exp();
+
+        assert_eq!(funcs::e_approx(Float::with_val(53, 200)), expected);
+        assert_eq!(funcs::e_approx(Float::with_val(53, 200)).prec(), 200);
+        assert!(funcs::e_approx(Float::with_val(53, 4e9)).is_nan());
+        assert!(funcs::e_approx(Float::with_val(53, u32::MAX)).is_nan());
+    }
+
+    #[test]
+    fn test_pi_approx() {
+        let pi = "3.1415926535897932384626433832795028841971693993751058209749445923078164062862089986280348253421170679";
+        let result = funcs::pi_approx(Float::with_val(53, 100));
+        // The digits above are only enough for 332 bits.
+        let result = Float::with_val(332, result);
+        let expected = Float::with_val(332, Float::parse(pi).unwrap());
+
+        assert_approx_eq!(&result, &expected, 2);
+        assert!(funcs::pi_approx(Float::with_val(53, 1e9)).is_nan());
+    }
+}
@@ -0,0 +1,104 @@
+use crate::ast::{Expr, Stmt};
+use crate::lexer::Token;
+use crate::symbol_table::SymbolTable;
+use rug::Float;
+use std::mem::size_of;
+
+/// An estimate of how much memory a context uses, see `Context::profile_memory`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryProfile {
+    /// The declarations of the user-defined variables and functions.
+    pub symbol_table_bytes: usize,
+    /// The tokens of the last input and of the inputs in the lexer cache.
+    pub token_buffer_bytes: usize,
+    /// The numbers that are kept between evaluations, ie. the last answer and cached results.
+    pub total_float_bytes: usize,
+}
+
+pub(crate) fn symbol_table_bytes(symbol_table: &SymbolTable) -> usize {
+    symbol_table
+        .vars()
+        .chain(symbol_table.fns())
+        .map(|(identifier, stmt)| identifier.capacity() + stmt_bytes(stmt))
+        .sum()
+}
+
+pub(crate) fn tokens_bytes(tokens: &[Token]) -> usize {
+    tokens
+        .iter()
+        .map(|token| size_of::<Token>() + token.value.capacity())
+        .sum()
+}
+
+pub(crate) fn float_bytes(value: &Float) -> usize {
+    // The mantissa is stored in 64-bit limbs.
+    size_of::<Float>() + (value.prec() as usize + 63) / 64 * 8
+}
+
+fn stmt_bytes(stmt: &Stmt) -> usize {
+    size_of::<Stmt>()
+        + match stmt {
+            Stmt::VarDecl(identifier, expr) => identifier.capacity() + expr_bytes(expr),
+            Stmt::FnDecl(identifier, parameters, expr) => {
+                identifier.capacity()
+                    + parameters
+                        .iter()
+                        .map(|parameter| size_of::<String>() + parameter.capacity())
+                        .sum::<usize>()
+                    + expr_bytes(expr)
+            }
+            Stmt::UnitDecl(identifier, definition) => {
+                identifier.capacity() + definition.as_deref().map_or(0, expr_bytes)
+            }
+            Stmt::Expr(expr) => expr_bytes(expr),
+        }
+}
+
+fn expr_bytes(expr: &Expr) -> usize {
+    size_of::<Expr>()
+        + match expr {
+            Expr::Binary(left, _, right) | Expr::Equation(left, right) => {
+                expr_bytes(left) + expr_bytes(right)
+            }
+            Expr::Unary(_, expr) | Expr::Group(expr) => expr_bytes(expr),
+            Expr::Unit(expr, unit) | Expr::Conversion(expr, unit) => {
+                expr_bytes(expr) + unit.capacity()
+            }
+            Expr::Annotated(expr, annotation) => expr_bytes(expr) + annotation.capacity(),
+            Expr::Var(identifier) | Expr::Literal(identifier) => identifier.capacity(),
+            Expr::FnCall(identifier, expressions) => {
+                identifier.capacity() + expressions.iter().map(expr_bytes).sum::<usize>()
+            }
+            Expr::Vector(elements) => elements.iter().map(expr_bytes).sum(),
+            Expr::Conditional(condition, then, otherwise) => {
+                expr_bytes(condition) + expr_bytes(then) + expr_bytes(otherwise)
+            }
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{self, Context};
+
+    #[test]
+    fn test_float_bytes() {
+        assert_eq!(float_bytes(&Float::new(53)), size_of::<Float>() + 8);
+        assert_eq!(float_bytes(&Float::new(256)), size_of::<Float>() + 32);
+    }
+
+    #[test]
+    fn test_grows_with_definitions() {
+        let mut context = Context::new();
+        let empty = context.profile_memory();
+        parser::eval(&mut context, "f(x) = x^2 + 1", 53).unwrap();
+        parser::eval(&mut context, "f(2)", 53).unwrap();
+        let profile = context.profile_memory();
+
+        assert_eq!(empty.symbol_table_bytes, 0);
+        assert_eq!(empty.total_float_bytes, 0);
+        assert!(profile.symbol_table_bytes > 0);
+        assert!(profile.token_buffer_bytes > 0);
+        assert!(profile.total_float_bytes >= float_bytes(&Float::new(53)));
+    }
+}
@@ -0,0 +1,180 @@
+use rug::float::Constant;
+use rug::{Float, Integer};
+
+/// The largest denominator tried when looking for a fraction, eg. 3/7.
+const MAX_DENOMINATOR: u32 = 1000;
+
+/// The largest denominator tried when looking for a fraction of a constant, eg. 3π/4.
+/// It is kept small, since almost any value is close to some fraction of a constant with a large denominator.
+const MAX_CONSTANT_DENOMINATOR: u32 = 12;
+
+/// Find a "nice" value that is within `tolerance` of `value`: an integer, a simple fraction
+/// or a simple fraction of a known constant, eg. `1.5707963` is π/2.
+///
+/// The candidates with the smallest denominator are preferred, and plain fractions before constants.
+/// The nice value is calculated at the precision of `value`.
+pub fn nice_value(value: &Float, tolerance: f64) -> Option<Float> {
+    if !value.is_finite() {
+        return None;
+    }
+
+    let precision = value.prec();
+    let constants = constants(precision);
+    for denominator in 1..=MAX_DENOMINATOR {
+        for constant in &constants {
+            if denominator > MAX_CONSTANT_DENOMINATOR && *constant != 1 {
+                continue;
+            }
+
+            // The closest multiple of constant / denominator.
+            let numerator = (Float::with_val(precision, value * denominator) / constant).round();
+            let candidate = Float::with_val(precision, numerator * constant / denominator);
+            let distance = Float::with_val(precision, &candidate - value).abs();
+            if distance <= tolerance {
+                return Some(candidate);
+            }
+        }
+    }
+
+    None
+}
+
+/// How close a fraction p/q has to be to be accepted by `estimate_fraction`, as a multiple of 1/q².
+/// Any number is within 1/q² of some fraction with the denominator q, so this has to be much smaller.
+const FRACTION_TOLERANCE: f64 = 1e-6;
+
+/// Express `value` as a simple fraction, or a simple fraction of π or e, eg. `0.5` is `1/2` and `1.5707963` is `π/2`.
+/// An integer is written without a denominator, eg. `2` or `2π`.
+///
+/// The fraction is found with continued fractions, and only accepted if it is much closer than
+/// fractions with the same denominator usually are, so that eg. `sin(1)` isn't written as a fraction with a large denominator.
+pub fn estimate_fraction(value: &Float) -> Option<String> {
+    if !value.is_finite() {
+        return None;
+    }
+
+    if value.is_zero() {
+        return Some(String::from("0"));
+    }
+
+    let precision = value.prec();
+    let sign = if value.is_sign_negative() { "-" } else { "" };
+    let constants = [
+        (Float::with_val(precision, 1), ""),
+        (Float::with_val(precision, Constant::Pi), "π"),
+        (Float::with_val(precision, 1).exp(), "e"),
+    ];
+    for (constant, name) in &constants {
+        let x = Float::with_val(precision, value / constant).abs();
+        if let Some((numerator, denominator)) = continued_fraction(&x) {
+            let numerator = match (numerator.to_u32(), *name) {
+                (Some(1), name) if !name.is_empty() => String::new(),
+                _ => numerator.to_string(),
+            };
+            let fraction = if denominator == 1 {
+                format!("{}{}{}", sign, numerator, name)
+            } else {
+                format!("{}{}{}/{}", sign, numerator, name, denominator)
+            };
+
+            return Some(fraction);
+        }
+    }
+
+    None
+}
+
+/// The first convergent p/q of the continued fraction of the positive number `x` that is within
+/// `FRACTION_TOLERANCE / q²` of it, with q at most `MAX_DENOMINATOR`.
+fn continued_fraction(x: &Float) -> Option<(Integer, Integer)> {
+    let precision = x.prec();
+    let (mut numerator, mut previous_numerator) = (Integer::from(1), Integer::from(0));
+    let (mut denominator, mut previous_denominator) = (Integer::from(0), Integer::from(1));
+    let mut remainder = x.clone();
+    while remainder.is_finite() {
+        let term = remainder.clone().floor().to_integer()?;
+        let next_numerator = Integer::from(&term * &numerator) + &previous_numerator;
+        let next_denominator = Integer::from(&term * &denominator) + &previous_denominator;
+        if next_denominator > MAX_DENOMINATOR {
+            return None;
+        }
+
+        previous_numerator = std::mem::replace(&mut numerator, next_numerator);
+        previous_denominator = std::mem::replace(&mut denominator, next_denominator);
+
+        let fraction = Float::with_val(precision, &numerator) / &denominator;
+        let error = Float::with_val(precision, x - fraction).abs()
+            * Integer::from(denominator.square_ref());
+        if error <= FRACTION_TOLERANCE {
+            return Some((numerator, denominator));
+        }
+
+        remainder = (remainder - term).recip();
+    }
+
+    None
+}
+
+fn constants(precision: u32) -> Vec<Float> {
+    vec![
+        Float::with_val(precision, 1),
+        Float::with_val(precision, Constant::Pi),
+        Float::with_val(precision, 1).exp(),
+        Float::with_val(precision, 2).sqrt(),
+        Float::with_val(precision, 3).sqrt(),
+        Float::with_val(precision, Constant::Log2),
+        // The golden ratio, (1 + √5) / 2.
+        (Float::with_val(precision, 5).sqrt() + 1) / 2,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn parse(value: &str) -> Float {
+        Float::with_val(53, Float::parse(value).unwrap())
+    }
+
+    fn pi() -> Float {
+        Float::with_val(53, Constant::Pi)
+    }
+
+    #[test_case("2.0000001", 1e-6, Float::with_val(53, 2) ; "integer")]
+    #[test_case("0.3333333", 1e-6, Float::with_val(53, 1) / 3 ; "fraction")]
+    #[test_case("1.5707963", 1e-6, pi() / 2 ; "half pi")]
+    #[test_case("-2.3561944", 1e-6, -pi() * 3 / 4 ; "negative")]
+    #[test_case("1.4142135", 1e-6, Float::with_val(53, 2).sqrt() ; "root of two")]
+    fn test_nice_value(value: &str, tolerance: f64, expected: Float) {
+        let value = parse(value);
+
+        assert_eq!(nice_value(&value, tolerance).unwrap(), expected);
+    }
+
+    #[test_case("0.5", "1/2")]
+    #[test_case("-0.75", "-3/4")]
+    #[test_case("0.30000000000000004", "3/10" ; "rounding error")]
+    #[test_case("0.3333333", "1/3" ; "truncated")]
+    #[test_case("3", "3")]
+    #[test_case("1.5707963", "π/2")]
+    #[test_case("-2.35619449", "-3π/4" ; "negative multiple of pi")]
+    #[test_case("6.283185307179586", "2π" ; "two pi")]
+    #[test_case("1.3591409142295225", "e/2" ; "half e")]
+    fn test_estimate_fraction(value: &str, expected: &str) {
+        assert_eq!(estimate_fraction(&parse(value)).as_deref(), Some(expected));
+    }
+
+    #[test_case("0.8414709848078965" ; "sine of one")]
+    #[test_case("1.4142135623730951" ; "root of two")]
+    #[test_case("inf" ; "infinity")]
+    fn test_no_fraction(value: &str) {
+        assert_eq!(estimate_fraction(&parse(value)), None);
+    }
+
+    #[test_case("1.5707963", 1e-12 ; "too far away")]
+    #[test_case("inf", 1e-6 ; "infinity")]
+    fn test_no_nice_value(value: &str, tolerance: f64) {
+        assert_eq!(nice_value(&parse(value), tolerance), None);
+    }
+}
@@ -0,0 +1,665 @@
+use crate::ast::{Expr, Stmt};
+use crate::exact;
+use crate::lexer::TokenKind;
+use crate::parser::KalkType;
+use crate::symbol_table::SymbolTable;
+use crate::types;
+use rug::ops::Pow;
+use rug::{Integer, Rational};
+
+/// What the simplifications may assume about the values in an expression. Factors are only reordered
+/// and terms only combined if they are numbers, since eg. `A * B * A` isn't `A^2 * B` for matrices.
+pub(crate) struct Scope<'a> {
+    symbol_table: &'a SymbolTable,
+    /// Whether names that aren't defined are unknowns, that can be treated as numbers.
+    /// Otherwise they are left where they are, so that eg. `x - x` still fails to evaluate.
+    undefined_are_numbers: bool,
+}
+
+impl<'a> Scope<'a> {
+    pub(crate) fn symbolic(symbol_table: &'a SymbolTable) -> Self {
+        Scope {
+            symbol_table,
+            undefined_are_numbers: true,
+        }
+    }
+
+    pub(crate) fn evaluated(symbol_table: &'a SymbolTable) -> Self {
+        Scope {
+            symbol_table,
+            undefined_are_numbers: false,
+        }
+    }
+
+    fn is_number(&self, expr: &Expr) -> bool {
+        let kalk_type = if self.undefined_are_numbers {
+            types::check_with_unknowns(expr, self.symbol_table)
+        } else {
+            types::check(expr, self.symbol_table)
+        };
+
+        match kalk_type {
+            Ok(Some(KalkType::Scalar)) | Ok(Some(KalkType::Complex)) => true,
+            // Eg. a call to a function that isn't defined.
+            Ok(None) => self.undefined_are_numbers,
+            _ => false,
+        }
+    }
+}
+
+/// Apply basic algebraic simplifications to the expressions in a statement,
+/// eg. `x * 1 + 0` becomes `x`. Names that aren't defined are treated as numbers.
+pub fn simplify_stmt(stmt: Stmt, symbol_table: &SymbolTable) -> Stmt {
+    simplify_stmt_in(stmt, &Scope::symbolic(symbol_table))
+}
+
+/// Like `simplify_stmt`, but only for what is known to have the same value when evaluated,
+/// which is what `Context::auto_simplify` does.
+pub(crate) fn simplify_stmt_for_eval(stmt: Stmt, symbol_table: &SymbolTable) -> Stmt {
+    simplify_stmt_in(stmt, &Scope::evaluated(symbol_table))
+}
+
+fn simplify_stmt_in(stmt: Stmt, scope: &Scope) -> Stmt {
+    match stmt {
+        Stmt::VarDecl(identifier, expr) => {
+            Stmt::VarDecl(identifier, Box::new(simplify_expr(*expr, scope)))
+        }
+        Stmt::FnDecl(identifier, parameters, expr) => Stmt::FnDecl(
+            identifier,
+            parameters,
+            Box::new(simplify_expr(*expr, scope)),
+        ),
+        Stmt::UnitDecl(identifier, definition) => Stmt::UnitDecl(
+            identifier,
+            definition.map(|expr| Box::new(simplify_expr(*expr, scope))),
+        ),
+        Stmt::Expr(expr) => Stmt::Expr(Box::new(simplify_expr(*expr, scope))),
+    }
+}
+
+pub(crate) fn simplify_expr(expr: Expr, scope: &Scope) -> Expr {
+    match expr {
+        Expr::Binary(left, op, right) => simplify_binary_expr(
+            simplify_expr(*left, scope),
+            op,
+            simplify_expr(*right, scope),
+            scope,
+        ),
+        Expr::Unary(op, expr) => simplify_unary_expr(op, simplify_expr(*expr, scope)),
+        Expr::Unit(expr, unit) => Expr::Unit(Box::new(simplify_expr(*expr, scope)), unit),
+        Expr::Conversion(expr, unit) => {
+            Expr::Conversion(Box::new(simplify_expr(*expr, scope)), unit)
+        }
+        Expr::Group(expr) => match simplify_expr(*expr, scope) {
+            // The parentheses are redundant if there is nothing left to group.
+            expr @ Expr::Literal(_) | expr @ Expr::Var(_) => expr,
+            expr => Expr::Group(Box::new(expr)),
+        },
+        // `simplify(x)` only marks what to show the simplified form of.
+        Expr::FnCall(identifier, mut expressions)
+            if identifier == "simplify" && expressions.len() == 1 =>
+        {
+            simplify_expr(expressions.pop().unwrap(), scope)
+        }
+        Expr::FnCall(identifier, expressions) => Expr::FnCall(
+            identifier,
+            expressions
+                .into_iter()
+                .map(|expr| simplify_expr(expr, scope))
+                .collect(),
+        ),
+        Expr::Vector(elements) => Expr::Vector(
+            elements
+                .into_iter()
+                .map(|expr| simplify_expr(expr, scope))
+                .collect(),
+        ),
+        Expr::Equation(left, right) => Expr::Equation(
+            Box::new(simplify_expr(*left, scope)),
+            Box::new(simplify_expr(*right, scope)),
+        ),
+        Expr::Annotated(expr, annotation) => {
+            Expr::Annotated(Box::new(simplify_expr(*expr, scope)), annotation)
+        }
+        Expr::Conditional(condition, then, otherwise) => Expr::Conditional(
+            Box::new(simplify_expr(*condition, scope)),
+            Box::new(simplify_expr(*then, scope)),
+            Box::new(simplify_expr(*otherwise, scope)),
+        ),
+        Expr::Var(_) | Expr::Literal(_) => expr,
+    }
+}
+
+/// The largest number of digits a literal may get from folding constants, eg. `2^10` becomes `1024`,
+/// but `10^100` is left as it is.
+const MAX_FOLDED_DIGITS: usize = 20;
+
+/// A factor in a product and the power it is raised to, eg. `x^2` in `3x^2`.
+type Factor = (Expr, Rational);
+
+fn simplify_unary_expr(op: TokenKind, expr: Expr) -> Expr {
+    match (op, expr) {
+        (TokenKind::Minus, Expr::Unary(TokenKind::Minus, expr)) => *expr,
+        (op, expr) => {
+            let unary = Expr::Unary(op, Box::new(expr));
+            match constant_value(&unary).and_then(|value| decimal_expr(&value)) {
+                Some(folded) => folded,
+                None => unary,
+            }
+        }
+    }
+}
+
+fn simplify_binary_expr(left: Expr, op: TokenKind, right: Expr, scope: &Scope) -> Expr {
+    // Eg. `2 * 3 + 1` becomes `7`. Constants that aren't exact decimals, eg. `1/3`, are kept as fractions.
+    if let (Some(left_value), Some(right_value)) = (constant_value(&left), constant_value(&right)) {
+        let folded = exact::eval_rational_binary(left_value, &op, right_value)
+            .and_then(|value| decimal_expr(&value));
+        if let Some(folded) = folded {
+            return folded;
+        }
+    }
+
+    match op {
+        TokenKind::Plus | TokenKind::Minus => {
+            collect_like_terms(Expr::Binary(Box::new(left), op, Box::new(right)), scope)
+        }
+        TokenKind::Star | TokenKind::Slash => {
+            combine_factors(Expr::Binary(Box::new(left), op, Box::new(right)), scope)
+        }
+        TokenKind::Power => simplify_power(left, right, scope),
+        _ => Expr::Binary(Box::new(left), op, Box::new(right)),
+    }
+}
+
+fn simplify_power(base: Expr, exponent: Expr, scope: &Scope) -> Expr {
+    match constant_value(&exponent) {
+        Some(value) if value == 1 => return base,
+        // A matrix to the power of zero is the identity matrix.
+        Some(value) if value == 0 && scope.is_number(&base) => return literal("1"),
+        _ => (),
+    }
+
+    if is_literal(&base, 0) && is_positive_literal(&exponent) {
+        return literal("0");
+    }
+
+    // Eg. `(x^2)^3` becomes `x^6`. This is only true for integer exponents, since eg. `(x^2)^0.5` is `|x|`.
+    if let (Some((inner_base, inner_exponent)), Some(exponent)) =
+        (constant_power(&base), constant_value(&exponent))
+    {
+        if *exponent.denom() == 1 {
+            return power(inner_base, inner_exponent * exponent);
+        }
+    }
+
+    Expr::Binary(Box::new(base), TokenKind::Power, Box::new(exponent))
+}
+
+/// Combine terms that only differ by a constant factor, eg. `2x + 3 + x - 1` becomes `3 * x + 2`.
+/// The terms keep the order they first appear in, and the constant is where the first constant was.
+fn collect_like_terms(expr: Expr, scope: &Scope) -> Expr {
+    if !scope.is_number(&expr) {
+        return expr;
+    }
+
+    let mut terms = Vec::new();
+    collect_terms(&expr, false, scope, &mut terms);
+
+    let mut result: Option<Expr> = None;
+    for (coefficient, factors) in terms {
+        if coefficient == 0 {
+            continue;
+        }
+
+        let negative = coefficient < 0;
+        let term = build_product(coefficient.abs(), factors);
+        result = Some(match result {
+            None if negative => Expr::Unary(TokenKind::Minus, Box::new(term)),
+            None => term,
+            Some(sum) => {
+                let op = if negative {
+                    TokenKind::Minus
+                } else {
+                    TokenKind::Plus
+                };
+
+                Expr::Binary(Box::new(sum), op, Box::new(term))
+            }
+        });
+    }
+
+    result.unwrap_or_else(|| literal("0"))
+}
+
+/// Flatten a sum into terms, as a coefficient and the factors it is multiplied by.
+/// Terms with the same factors are added together.
+fn collect_terms(
+    expr: &Expr,
+    negated: bool,
+    scope: &Scope,
+    terms: &mut Vec<(Rational, Vec<Factor>)>,
+) {
+    match expr {
+        Expr::Binary(left, TokenKind::Plus, right) => {
+            collect_terms(left, negated, scope, terms);
+            collect_terms(right, negated, scope, terms);
+        }
+        Expr::Binary(left, TokenKind::Minus, right) => {
+            collect_terms(left, negated, scope, terms);
+            collect_terms(right, !negated, scope, terms);
+        }
+        Expr::Unary(TokenKind::Minus, expr) => collect_terms(expr, !negated, scope, terms),
+        Expr::Group(inner) if is_sum(inner) => collect_terms(inner, negated, scope, terms),
+        _ => {
+            let (coefficient, factors) = match split_factors(expr, scope) {
+                Some(split) => split,
+                None => (Rational::from(1), vec![(expr.clone(), Rational::from(1))]),
+            };
+            let coefficient = if negated { -coefficient } else { coefficient };
+
+            match terms.iter_mut().find(|(_, other)| *other == factors) {
+                Some((total, _)) => *total += coefficient,
+                None => terms.push((coefficient, factors)),
+            }
+        }
+    }
+}
+
+/// Combine the constants in a product and the powers of the same factor, eg. `2x * 3x` becomes `6 * x^2`
+/// and `x^3 / x` becomes `x^2`.
+fn combine_factors(expr: Expr, scope: &Scope) -> Expr {
+    if !scope.is_number(&expr) {
+        return expr;
+    }
+
+    match split_factors(&expr, scope) {
+        Some((coefficient, factors)) => build_product(coefficient, factors),
+        None => expr,
+    }
+}
+
+/// Flatten a product into a constant coefficient and factors raised to constant powers.
+/// `None` is returned if it divides by zero or any of the factors isn't a number, eg. the dot product of two vectors.
+fn split_factors(expr: &Expr, scope: &Scope) -> Option<(Rational, Vec<Factor>)> {
+    let mut coefficient = Rational::from(1);
+    let mut factors = Vec::new();
+    if collect_factors(expr, false, scope, &mut coefficient, &mut factors) {
+        factors.retain(|(_, exponent)| *exponent != 0);
+
+        Some((coefficient, factors))
+    } else {
+        None
+    }
+}
+
+fn collect_factors(
+    expr: &Expr,
+    inverted: bool,
+    scope: &Scope,
+    coefficient: &mut Rational,
+    factors: &mut Vec<Factor>,
+) -> bool {
+    match expr {
+        Expr::Binary(left, TokenKind::Star, right) => {
+            collect_factors(left, inverted, scope, coefficient, factors)
+                && collect_factors(right, inverted, scope, coefficient, factors)
+        }
+        Expr::Binary(left, TokenKind::Slash, right) => {
+            collect_factors(left, inverted, scope, coefficient, factors)
+                && collect_factors(right, !inverted, scope, coefficient, factors)
+        }
+        Expr::Unary(TokenKind::Minus, expr) => {
+            *coefficient *= -1;
+            collect_factors(expr, inverted, scope, coefficient, factors)
+        }
+        Expr::Group(inner) if is_product(inner) => {
+            collect_factors(inner, inverted, scope, coefficient, factors)
+        }
+        _ => {
+            if let Some(value) = constant_value(expr) {
+                if !inverted {
+                    *coefficient *= value;
+                } else if value == 0 {
+                    return false;
+                } else {
+                    *coefficient /= value;
+                }
+
+                return true;
+            }
+
+            let (base, exponent) =
+                constant_power(expr).unwrap_or_else(|| (expr.clone(), Rational::from(1)));
+            if !scope.is_number(&base) {
+                return false;
+            }
+
+            let exponent = if inverted { -exponent } else { exponent };
+            match factors.iter_mut().find(|(other, _)| *other == base) {
+                Some((_, total)) => *total += exponent,
+                None => factors.push((base, exponent)),
+            }
+
+            true
+        }
+    }
+}
+
+/// Write a product as the coefficient times the factors with positive powers, divided by the others,
+/// eg. `3 * x^2 / (2 * y)`.
+fn build_product(coefficient: Rational, factors: Vec<Factor>) -> Expr {
+    if coefficient == 0 {
+        return literal("0");
+    }
+
+    if factors.is_empty() {
+        return rational_expr(&coefficient);
+    }
+
+    let negative = coefficient < 0;
+    let (numerator, denominator) = coefficient.abs().into_numer_denom();
+    let mut dividends = Vec::new();
+    let mut divisors = Vec::new();
+    if numerator != 1 || factors.iter().all(|(_, exponent)| *exponent < 0) {
+        dividends.push(literal(&numerator.to_string()));
+    }
+
+    if denominator != 1 {
+        divisors.push(literal(&denominator.to_string()));
+    }
+
+    for (base, exponent) in factors {
+        if exponent < 0 {
+            divisors.push(power(base, -exponent));
+        } else {
+            dividends.push(power(base, exponent));
+        }
+    }
+
+    let dividend = multiply(dividends);
+    let product = if divisors.is_empty() {
+        dividend
+    } else {
+        let divisor = if divisors.len() > 1 {
+            Expr::Group(Box::new(multiply(divisors)))
+        } else {
+            multiply(divisors)
+        };
+
+        Expr::Binary(Box::new(dividend), TokenKind::Slash, Box::new(divisor))
+    };
+
+    if negative {
+        Expr::Unary(TokenKind::Minus, Box::new(product))
+    } else {
+        product
+    }
+}
+
+fn multiply(factors: Vec<Expr>) -> Expr {
+    factors
+        .into_iter()
+        .reduce(|product, factor| {
+            Expr::Binary(Box::new(product), TokenKind::Star, Box::new(factor))
+        })
+        .unwrap_or_else(|| literal("1"))
+}
+
+fn power(base: Expr, exponent: Rational) -> Expr {
+    if exponent == 1 {
+        return base;
+    }
+
+    if exponent == 0 {
+        return literal("1");
+    }
+
+    Expr::Binary(
+        Box::new(group(base)),
+        TokenKind::Power,
+        Box::new(group(rational_expr(&exponent))),
+    )
+}
+
+/// The base and exponent of a power with a constant exponent, eg. `x^2` or `(x^2)`.
+fn constant_power(expr: &Expr) -> Option<Factor> {
+    match expr {
+        Expr::Binary(base, TokenKind::Power, exponent) => {
+            Some((ungroup(base), constant_value(exponent)?))
+        }
+        Expr::Group(inner) => constant_power(inner),
+        _ => None,
+    }
+}
+
+/// The value of an expression that only consists of literals, eg. `2 * (3 + 1)`.
+/// Variables aren't replaced by their values, since they may be changed later.
+fn constant_value(expr: &Expr) -> Option<Rational> {
+    match expr {
+        Expr::Literal(literal) => exact::parse_decimal(literal),
+        Expr::Group(expr) => constant_value(expr),
+        Expr::Unary(TokenKind::Minus, expr) => Some(-constant_value(expr)?),
+        Expr::Binary(left, op, right) => {
+            exact::eval_rational_binary(constant_value(left)?, op, constant_value(right)?)
+        }
+        _ => None,
+    }
+}
+
+/// A rational number as an expression, eg. `0.5` or `1 / 3` if it isn't an exact decimal.
+fn rational_expr(value: &Rational) -> Expr {
+    if let Some(expr) = decimal_expr(value) {
+        return expr;
+    }
+
+    let fraction = Expr::Binary(
+        Box::new(literal(&value.numer().clone().abs().to_string())),
+        TokenKind::Slash,
+        Box::new(literal(&value.denom().to_string())),
+    );
+    if *value < 0 {
+        Expr::Unary(TokenKind::Minus, Box::new(fraction))
+    } else {
+        fraction
+    }
+}
+
+/// A rational number as a literal, eg. `0.25` or `-3`, if it is an exact decimal with at most `MAX_FOLDED_DIGITS` digits.
+fn decimal_expr(value: &Rational) -> Option<Expr> {
+    let mut denominator = value.denom().clone();
+    let mut decimals = 0;
+    for factor in [2u32, 5u32].iter() {
+        let mut count = 0;
+        while denominator.is_divisible_u(*factor) {
+            denominator /= *factor;
+            count += 1;
+        }
+
+        decimals = decimals.max(count);
+    }
+
+    if denominator != 1 {
+        return None;
+    }
+
+    let scaled =
+        Integer::from(value.numer().abs_ref()) * Integer::from(10).pow(decimals) / value.denom();
+    let mut digits = format!("{:0>width$}", scaled, width = decimals as usize + 1);
+    if digits.len() > MAX_FOLDED_DIGITS {
+        return None;
+    }
+
+    if decimals > 0 {
+        digits.insert(digits.len() - decimals as usize, '.');
+    }
+
+    Some(if *value < 0 {
+        Expr::Unary(TokenKind::Minus, Box::new(literal(&digits)))
+    } else {
+        literal(&digits)
+    })
+}
+
+fn is_sum(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Binary(_, TokenKind::Plus, _)
+            | Expr::Binary(_, TokenKind::Minus, _)
+            | Expr::Unary(TokenKind::Minus, _)
+    )
+}
+
+fn is_product(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Binary(_, TokenKind::Star, _) | Expr::Binary(_, TokenKind::Slash, _)
+    )
+}
+
+/// Put an expression in parentheses if it consists of more than a single value, eg. the base of a power.
+fn group(expr: Expr) -> Expr {
+    match expr {
+        Expr::Binary(_, _, _) | Expr::Unary(_, _) => Expr::Group(Box::new(expr)),
+        _ => expr,
+    }
+}
+
+fn ungroup(expr: &Expr) -> Expr {
+    match expr {
+        Expr::Group(inner) if matches!(**inner, Expr::Literal(_) | Expr::Var(_)) => {
+            (**inner).clone()
+        }
+        _ => expr.clone(),
+    }
+}
+
+fn is_literal(expr: &Expr, value: i32) -> bool {
+    match expr {
+        Expr::Literal(literal) => matches!(exact::parse_decimal(literal), Some(x) if x == value),
+        _ => false,
+    }
+}
+
+fn is_positive_literal(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(literal) => matches!(exact::parse_decimal(literal), Some(x) if x > 0),
+        _ => false,
+    }
+}
+
+fn literal(value: &str) -> Expr {
+    Expr::Literal(value.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{eval, parse_and_simplify, CalcError, Context};
+    use test_case::test_case;
+
+    fn parse_and_format(input: &str) -> String {
+        let mut context = Context::new();
+        let statements = parse_and_simplify(&mut context, input).unwrap();
+
+        statements[0].to_string()
+    }
+
+    #[test_case("x * 1 + 0", "x")]
+    #[test_case("0 + 1 * x", "x")]
+    #[test_case("x - 0.0", "x")]
+    #[test_case("x / 1", "x" ; "divided by one")]
+    #[test_case("x * 0", "0")]
+    #[test_case("x^1", "x" ; "to the power of one")]
+    #[test_case("x^0", "1" ; "to the power of zero")]
+    #[test_case("0^x", "0 ^ x" ; "zero to unknown power")]
+    #[test_case("0^2", "0" ; "zero to positive power")]
+    #[test_case("sin((x + 0)) * 1", "sin(x)")]
+    #[test_case("f(x) = (x * 1)^1", "f(x) = x")]
+    #[test_case("2 * (x + 1)", "2 * (x + 1)")]
+    fn test_simplify(input: &str, expected: &str) {
+        assert_eq!(parse_and_format(input), expected);
+    }
+
+    #[test_case("2 * 3 + 1", "7" ; "constants")]
+    #[test_case("1 / 4 + x", "0.25 + x")]
+    #[test_case("1 / 3 + 1 / 3", "2 / 3" ; "fraction")]
+    #[test_case("x + x", "2 * x")]
+    #[test_case("2x + 3 + x - 1", "3 * x + 2" ; "like terms")]
+    #[test_case("x - (x - y)", "y" ; "subtracted group")]
+    #[test_case("x - x", "0" ; "cancelled")]
+    #[test_case("-x + 2x", "x" ; "negative first")]
+    #[test_case("x * x", "x ^ 2")]
+    #[test_case("2x * 3x^2", "6 * x ^ 3" ; "powers")]
+    #[test_case("x^3 / x", "x ^ 2" ; "divided powers")]
+    #[test_case("y / (2 * x * y^2)", "1 / (2 * y * x)" ; "denominator")]
+    #[test_case("(x^2)^3", "x ^ 6" ; "power of power")]
+    #[test_case("(x^2)^0.5", "(x ^ 2) ^ 0.5" ; "non integer power of power")]
+    #[test_case("x / 0", "x / 0" ; "divided by zero")]
+    #[test_case("simplify(x + x) + 1", "2 * x + 1" ; "simplify call")]
+    fn test_simplify_terms(input: &str, expected: &str) {
+        assert_eq!(parse_and_format(input), expected);
+    }
+
+    #[test_case("x^2 + x*x - 3x/x")]
+    #[test_case("(2x + 1)^2 / (2x + 1) - 3")]
+    #[test_case("-(x - 2) * 4 + x^(-1) * x^3")]
+    fn test_simplify_same_value(input: &str) {
+        let mut context = Context::new();
+        context.eval_exact("x = 1.5", 53).unwrap();
+        let simplified = parse_and_simplify(&mut context, input).unwrap();
+        let expected = eval(&mut context, input, 53).unwrap().unwrap();
+
+        assert_eq!(simplified.len(), 1);
+        let simplified = simplified[0].to_string();
+        assert_eq!(
+            eval(&mut context, &simplified, 53).unwrap().unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_matrix_factors() {
+        let mut context = Context::new().auto_simplify(true);
+        eval(&mut context, "A = [[1, 2], [3, 4]]", 53).unwrap();
+        eval(&mut context, "B = [[0, 1], [1, 0]]", 53).unwrap();
+        let expected = Context::new()
+            .eval_value("A = [[1, 2], [3, 4]]; B = [[0, 1], [1, 0]]; A * B * A", 53)
+            .unwrap();
+
+        assert_eq!(
+            context.get_ast("A * B * A").unwrap()[0].to_string(),
+            "A * B * A"
+        );
+        assert_eq!(context.get_ast("A - A").unwrap()[0].to_string(), "A - A");
+        assert_eq!(context.get_ast("A^0").unwrap()[0].to_string(), "A ^ 0");
+        assert_eq!(
+            parse_and_simplify(&mut context, "A * y * A").unwrap()[0].to_string(),
+            "A * y * A"
+        );
+        assert_eq!(context.eval_value("A * B * A", 53).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_undefined_when_evaluated() {
+        let mut context = Context::new().auto_simplify(true);
+
+        assert_eq!(context.get_ast("x - x").unwrap()[0].to_string(), "x - x");
+        assert_eq!(context.get_ast("x^0").unwrap()[0].to_string(), "x ^ 0");
+        assert_eq!(
+            eval(&mut context, "x - x", 53),
+            Err(CalcError::UndefinedVar(String::from("x")))
+        );
+
+        eval(&mut context, "x = 3", 53).unwrap();
+        assert_eq!(context.get_ast("x - x").unwrap()[0].to_string(), "0");
+        assert_eq!(context.get_ast("x * x").unwrap()[0].to_string(), "x ^ 2");
+    }
+
+    #[test]
+    fn test_disabled() {
+        let context = Context::new();
+        let statements = context.get_ast("x * 1 + 0").unwrap();
+
+        assert_eq!(statements[0].to_string(), "x * 1 + 0");
+    }
+}
@@ -0,0 +1,183 @@
+use crate::parser::CalcError;
+use rug::Float;
+
+/// Starting points for Newton's method, tried in order.
+const INITIAL_GUESSES: [f64; 8] = [1.0, 0.0, -1.0, 0.5, 10.0, -10.0, 100.0, -100.0];
+
+const MAX_ITERATIONS: usize = 100;
+
+/// If Newton's method doesn't find a root, look for a sign change at the integers within this distance from zero.
+const SEARCH_RANGE: i32 = 1000;
+
+/// Find a root of `f`, ie. a value that it is zero for.
+///
+/// Newton's method is tried from a few starting points first. If it doesn't converge,
+/// the integers between -1000 and 1000 are searched for a sign change, which is then bisected.
+/// `None` is returned if no root was found.
+pub fn find_root<F>(precision: u32, mut f: F) -> Result<Option<Float>, CalcError>
+where
+    F: FnMut(&Float) -> Result<Float, CalcError>,
+{
+    for guess in INITIAL_GUESSES.iter() {
+        let guess = Float::with_val(precision, *guess);
+        if let Some(root) = newton(precision, &mut f, guess)? {
+            return Ok(Some(root));
+        }
+    }
+
+    // The previous point, and the value there if it is finite.
+    let mut previous: (Float, Option<Float>) = (Float::with_val(precision, -SEARCH_RANGE), None);
+    for x in -SEARCH_RANGE..=SEARCH_RANGE {
+        let x = Float::with_val(precision, x);
+        let y = f(&x)?;
+        if y.is_zero() {
+            return Ok(Some(x));
+        }
+
+        if let (a, Some(fa)) = &previous {
+            if y.is_finite() && fa.is_sign_negative() != y.is_sign_negative() {
+                if let Some(root) = bisect(precision, &mut f, a.clone(), x.clone())? {
+                    return Ok(Some(root));
+                }
+            }
+        }
+
+        previous = (x, Some(y).filter(|y| y.is_finite()));
+    }
+
+    Ok(None)
+}
+
+fn newton<F>(precision: u32, f: &mut F, mut x: Float) -> Result<Option<Float>, CalcError>
+where
+    F: FnMut(&Float) -> Result<Float, CalcError>,
+{
+    let epsilon = Float::with_val(precision, Float::i_exp(1, 8 - precision as i32));
+    for _ in 0..MAX_ITERATIONS {
+        let y = f(&x)?;
+        if y.is_zero() {
+            return Ok(Some(x));
+        }
+
+        if !y.is_finite() {
+            return Ok(None);
+        }
+
+        // Central difference, with a step that is large enough to not be lost to rounding.
+        let scale = Float::with_val(precision, x.abs_ref()).max(&Float::with_val(precision, 1));
+        let h = Float::with_val(precision, &scale * &half_precision(precision));
+        let above = f(&Float::with_val(precision, &x + &h))?;
+        let below = f(&Float::with_val(precision, &x - &h))?;
+        let slope = Float::with_val(precision, above - below) / Float::with_val(precision, &h * 2);
+        if slope.is_zero() || !slope.is_finite() {
+            return Ok(None);
+        }
+
+        let step = y / slope;
+        x -= &step;
+
+        if step.abs() <= Float::with_val(precision, &scale * &epsilon) {
+            return Ok(if is_root(precision, f, &x)? {
+                Some(x)
+            } else {
+                None
+            });
+        }
+    }
+
+    Ok(None)
+}
+
+/// Halve the interval until it can't get any smaller. `f(a)` and `f(b)` need to have opposite signs.
+fn bisect<F>(
+    precision: u32,
+    f: &mut F,
+    mut a: Float,
+    mut b: Float,
+) -> Result<Option<Float>, CalcError>
+where
+    F: FnMut(&Float) -> Result<Float, CalcError>,
+{
+    let a_is_negative = f(&a)?.is_sign_negative();
+    for _ in 0..precision * 2 {
+        let middle = Float::with_val(precision, &a + &b) / 2;
+        if middle == a || middle == b {
+            break;
+        }
+
+        let y = f(&middle)?;
+        if y.is_zero() {
+            return Ok(Some(middle));
+        }
+
+        if y.is_sign_negative() == a_is_negative {
+            a = middle;
+        } else {
+            b = middle;
+        }
+    }
+
+    // A sign change may also be a discontinuity, eg. in 1/x.
+    Ok(if is_root(precision, f, &a)? {
+        Some(a)
+    } else {
+        None
+    })
+}
+
+fn is_root<F>(precision: u32, f: &mut F, x: &Float) -> Result<bool, CalcError>
+where
+    F: FnMut(&Float) -> Result<Float, CalcError>,
+{
+    let scale = Float::with_val(precision, x.abs_ref()).max(&Float::with_val(precision, 1));
+    let tolerance = scale * half_precision(precision);
+
+    Ok(f(x)?.abs() <= tolerance)
+}
+
+/// A number with half as many zeros after the binary point as the precision, ie. 2^(-precision/2).
+fn half_precision(precision: u32) -> Float {
+    Float::with_val(precision, Float::i_exp(1, -(precision as i32) / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    fn find(f: fn(&Float) -> Float) -> Option<f64> {
+        find_root(53, |x| Ok(f(x)))
+            .unwrap()
+            .map(|root| root.to_f64())
+    }
+
+    #[test]
+    fn test_linear() {
+        assert_eq!(find(|x| Float::with_val(53, x * 2) - 8), Some(4f64));
+    }
+
+    #[test]
+    fn test_square_root() {
+        let root = find(|x| Float::with_val(53, x * x) - 2).unwrap();
+
+        assert_approx_eq!(
+            &Float::with_val(53, root),
+            &Float::with_val(53, 2).sqrt(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_bisection() {
+        // Newton's method diverges for cube roots.
+        let root = find(|x| Float::with_val(53, x - 37.3).cbrt()).unwrap();
+
+        assert_approx_eq!(&Float::with_val(53, root), &Float::with_val(53, 37.3), 4);
+    }
+
+    #[test]
+    fn test_no_root() {
+        assert_eq!(find(|x| Float::with_val(53, x * x) + 1), None);
+        assert_eq!(find(|x| Float::with_val(53, x.recip_ref())), None);
+    }
+}
@@ -1,40 +1,403 @@
-use crate::{ast::Stmt, prelude};
-use std::collections::HashMap;
+use crate::{ast::Stmt, hooks::NativeFn, prelude};
+use rug::Float;
+use std::collections::hash_map::{self, HashMap};
 
+#[derive(Clone)]
 pub struct SymbolTable {
-    hashmap: HashMap<String, Stmt>,
+    vars: HashMap<String, Stmt>,
+    fns: HashMap<String, Stmt>,
+    /// Functions registered by the host application, see `Context::register_native_fn`.
+    native_fns: HashMap<String, NativeFn>,
+    /// User-defined units. The built-in angle units are in `prelude::UNITS`.
+    units: HashMap<String, Stmt>,
+    /// Temporary variables, eg. the index of `sum`, which hide the other variables with the same name.
+    /// The innermost scope is last.
+    scopes: Vec<HashMap<String, Stmt>>,
+    /// The answer of the last evaluated expression, which `_` refers to.
+    last_answer: Option<Float>,
+    /// The only names that may be looked up, if restricted, see `set_allowed_names`.
+    allowed_names: Option<Vec<String>>,
+    /// Incremented on every mutation, so that cached results can be invalidated.
+    #[cfg(feature = "cache")]
+    generation: usize,
 }
 
 impl SymbolTable {
     pub fn new() -> Self {
         SymbolTable {
-            hashmap: HashMap::new(),
+            vars: HashMap::new(),
+            fns: HashMap::new(),
+            native_fns: HashMap::new(),
+            units: HashMap::new(),
+            scopes: Vec::new(),
+            last_answer: None,
+            allowed_names: None,
+            #[cfg(feature = "cache")]
+            generation: 0,
         }
     }
 
-    pub fn insert(&mut self, key: &str, value: Stmt) {
-        self.hashmap.insert(key.into(), value);
+    /// Insert a variable, function or unit declaration, replacing any previous one with the same name.
+    /// If a scoped variable has the same name, it is replaced instead, since the new one would be hidden by it.
+    pub fn insert(&mut self, value: Stmt) {
+        let (map, identifier) = match &value {
+            Stmt::VarDecl(identifier, _) => {
+                let scope = self
+                    .scopes
+                    .iter_mut()
+                    .rev()
+                    .find(|scope| scope.contains_key(identifier));
+
+                (scope.unwrap_or(&mut self.vars), identifier.clone())
+            }
+            Stmt::FnDecl(identifier, _, _) => {
+                self.native_fns.remove(identifier);
+
+                (&mut self.fns, identifier.clone())
+            }
+            Stmt::UnitDecl(identifier, _) => (&mut self.units, identifier.clone()),
+            Stmt::Expr(_) => return,
+        };
+
+        map.insert(identifier, value);
+
+        #[cfg(feature = "cache")]
+        {
+            self.generation += 1;
+        }
+    }
+
+    /// Insert a native function, replacing any previous function with the same name.
+    pub(crate) fn insert_native_fn(&mut self, identifier: &str, native_fn: NativeFn) {
+        self.fns.remove(identifier);
+        self.native_fns.insert(identifier.into(), native_fn);
+
+        #[cfg(feature = "cache")]
+        {
+            self.generation += 1;
+        }
     }
 
-    pub fn get(&self, key: &str) -> Option<&Stmt> {
-        self.hashmap.get(key)
+    /// Start a scope for temporary variables, see `insert_scoped`.
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
     }
 
-    pub fn set(&mut self, key: &str, value: Stmt) {
-        if let Some(stmt) = self.hashmap.get_mut(key) {
-            *stmt = value;
+    /// Remove the innermost scope, and the variables in it.
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop();
+
+        #[cfg(feature = "cache")]
+        {
+            self.generation += 1;
+        }
+    }
+
+    /// Insert a variable declaration into the innermost scope, or as a normal variable if there is no scope.
+    pub fn insert_scoped(&mut self, value: Stmt) {
+        if let (Some(scope), Stmt::VarDecl(identifier, _)) = (self.scopes.last_mut(), &value) {
+            scope.insert(identifier.clone(), value);
+
+            #[cfg(feature = "cache")]
+            {
+                self.generation += 1;
+            }
         } else {
-            self.insert(key, value);
+            self.insert(value);
         }
     }
 
+    pub fn remove_var(&mut self, identifier: &str) -> Option<Stmt> {
+        #[cfg(feature = "cache")]
+        {
+            self.generation += 1;
+        }
+
+        self.vars.remove(identifier)
+    }
+
+    /// Remove every user-defined variable, function and unit.
+    /// Built-in functions, constants and units are not stored in the symbol table, so they remain available,
+    /// and neither are the native functions removed, since they are registered by the host application.
+    pub fn clear_user_definitions(&mut self) {
+        self.vars.clear();
+        self.fns.clear();
+        self.units.clear();
+
+        #[cfg(feature = "cache")]
+        {
+            self.generation += 1;
+        }
+    }
+
+    /// This doesn't change the generation, so expressions referring to `_` must not be cached.
+    pub fn set_last_answer(&mut self, value: Float) {
+        self.last_answer = Some(value);
+    }
+
+    pub fn last_answer(&self) -> Option<&Float> {
+        self.last_answer.as_ref()
+    }
+
+    /// Whether the name refers to the last answer, ie. `_`, or `ans` unless a variable has been declared with that name.
+    pub fn is_last_answer(&self, identifier: &str) -> bool {
+        identifier == "_" || (identifier == "ans" && self.get_var(identifier).is_none())
+    }
+
+    /// Only allow the variables, functions and units with these names to be looked up,
+    /// and treat the others as if they weren't defined, see `Context::eval_in_sandbox`.
+    /// Built-in names aren't stored here, so the interpreter checks them with `is_allowed`.
+    pub(crate) fn set_allowed_names(&mut self, names: &[&str]) {
+        self.allowed_names = Some(names.iter().map(|name| name.to_string()).collect());
+    }
+
+    pub(crate) fn is_allowed(&self, identifier: &str) -> bool {
+        match &self.allowed_names {
+            Some(names) => names.iter().any(|name| name == identifier),
+            None => true,
+        }
+    }
+
+    pub fn get_var(&self, identifier: &str) -> Option<&Stmt> {
+        if !self.is_allowed(identifier) {
+            return None;
+        }
+
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(identifier))
+            .or_else(|| self.vars.get(identifier))
+    }
+
+    pub fn get_fn(&self, identifier: &str) -> Option<&Stmt> {
+        self.fns
+            .get(identifier)
+            .filter(|_| self.is_allowed(identifier))
+    }
+
+    pub(crate) fn get_native_fn(&self, identifier: &str) -> Option<&NativeFn> {
+        self.native_fns
+            .get(identifier)
+            .filter(|_| self.is_allowed(identifier))
+    }
+
+    /// The names of the native functions, in no particular order.
+    pub fn native_fns(&self) -> impl Iterator<Item = &String> {
+        self.native_fns.keys()
+    }
+
+    pub fn get_unit(&self, identifier: &str) -> Option<&Stmt> {
+        self.units
+            .get(identifier)
+            .filter(|_| self.is_allowed(identifier))
+    }
+
+    pub fn vars(&self) -> impl Iterator<Item = (&String, &Stmt)> {
+        self.vars.iter()
+    }
+
+    pub fn fns(&self) -> impl Iterator<Item = (&String, &Stmt)> {
+        self.fns.iter()
+    }
+
+    pub fn units(&self) -> impl Iterator<Item = (&String, &Stmt)> {
+        self.units.iter()
+    }
+
+    /// Iterate over the declarations of the user-defined variables and functions, variables first.
+    pub fn iter(&self) -> SymbolTableIter<'_> {
+        SymbolTableIter {
+            vars: Some(self.vars.iter()),
+            fns: Some(self.fns.iter()),
+        }
+    }
+
+    pub fn iter_vars(&self) -> SymbolTableIter<'_> {
+        SymbolTableIter {
+            vars: Some(self.vars.iter()),
+            fns: None,
+        }
+    }
+
+    pub fn iter_fns(&self) -> SymbolTableIter<'_> {
+        SymbolTableIter {
+            vars: None,
+            fns: Some(self.fns.iter()),
+        }
+    }
+
+    /// The number of user-defined variables and functions.
+    /// Built-in functions and constants are not counted, since they aren't stored in the symbol table.
+    pub fn len(&self) -> usize {
+        self.var_count() + self.fn_count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn var_count(&self) -> usize {
+        self.vars.len()
+    }
+
+    pub fn fn_count(&self) -> usize {
+        self.fns.len()
+    }
+
     pub fn contains_var(&self, identifier: &str) -> bool {
-        prelude::CONSTANTS.contains_key(identifier) || self.hashmap.contains_key(identifier)
+        prelude::CONSTANTS.contains_key(identifier)
+            || self.get_var(identifier).is_some()
+            || self.is_last_answer(identifier)
     }
 
     pub fn contains_fn(&self, identifier: &str) -> bool {
         prelude::UNARY_FUNCS.contains_key(identifier)
-            || prelude::UNARY_FUNCS.contains_key(identifier)
-            || self.hashmap.contains_key(&format!("{}()", identifier))
+            || prelude::BINARY_FUNCS.contains_key(identifier)
+            || self.fns.contains_key(identifier)
+            || self.native_fns.contains_key(identifier)
+    }
+
+    pub fn contains_unit(&self, identifier: &str) -> bool {
+        prelude::UNITS.contains_key(identifier) || self.units.contains_key(identifier)
+    }
+
+    #[cfg(feature = "cache")]
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+}
+
+/// Iterator over the names and declarations in a symbol table, see `SymbolTable::iter`.
+pub struct SymbolTableIter<'a> {
+    vars: Option<hash_map::Iter<'a, String, Stmt>>,
+    fns: Option<hash_map::Iter<'a, String, Stmt>>,
+}
+
+impl<'a> Iterator for SymbolTableIter<'a> {
+    type Item = (&'a str, &'a Stmt);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = match self.vars.as_mut().and_then(Iterator::next) {
+            Some(entry) => Some(entry),
+            None => self.fns.as_mut().and_then(Iterator::next),
+        };
+
+        next.map(|(identifier, stmt)| (identifier.as_str(), stmt))
+    }
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn test_separate_namespaces() {
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.insert(var_decl("f", literal("1")));
+        symbol_table.insert(fn_decl("f", vec![String::from("x")], var("x")));
+
+        assert!(matches!(
+            symbol_table.get_var("f"),
+            Some(Stmt::VarDecl(_, _))
+        ));
+        assert!(matches!(
+            symbol_table.get_fn("f"),
+            Some(Stmt::FnDecl(_, _, _))
+        ));
+        assert!(symbol_table.contains_fn("max"));
+        assert!(!symbol_table.contains_var("x"));
+    }
+
+    #[test]
+    fn test_len() {
+        let mut symbol_table = SymbolTable::new();
+        assert!(symbol_table.is_empty());
+
+        symbol_table.insert(var_decl("x", literal("1")));
+        symbol_table.insert(var_decl("x", literal("2")));
+        symbol_table.insert(fn_decl("x", vec![String::from("y")], var("y")));
+
+        assert!(!symbol_table.is_empty());
+        assert_eq!(symbol_table.var_count(), 1);
+        assert_eq!(symbol_table.fn_count(), 1);
+        assert_eq!(symbol_table.len(), 2);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut symbol_table = SymbolTable::new();
+        for name in &["x", "y", "z"] {
+            symbol_table.insert(var_decl(name, literal("1")));
+        }
+        for name in &["f", "g"] {
+            symbol_table.insert(fn_decl(name, vec![String::from("x")], var("x")));
+        }
+
+        assert_eq!(symbol_table.iter().count(), 5);
+        assert_eq!(symbol_table.iter_vars().count(), 3);
+        assert!(symbol_table.iter_fns().all(
+            |(name, stmt)| matches!(stmt, Stmt::FnDecl(identifier, _, _) if identifier == name)
+        ));
+    }
+
+    #[test]
+    fn test_scopes() {
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.insert(var_decl("x", literal("1")));
+        symbol_table.push_scope();
+        symbol_table.insert_scoped(var_decl("x", literal("2")));
+        symbol_table.insert_scoped(var_decl("y", literal("3")));
+
+        assert_eq!(
+            symbol_table.get_var("x"),
+            Some(&var_decl("x", literal("2")))
+        );
+        assert!(symbol_table.contains_var("y"));
+        assert_eq!(symbol_table.var_count(), 1);
+
+        symbol_table.pop_scope();
+
+        assert_eq!(
+            symbol_table.get_var("x"),
+            Some(&var_decl("x", literal("1")))
+        );
+        assert!(!symbol_table.contains_var("y"));
+    }
+
+    #[test]
+    fn test_clear_user_definitions() {
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.insert(var_decl("x", literal("1")));
+        symbol_table.insert(fn_decl("f", vec![String::from("x")], var("x")));
+        symbol_table.clear_user_definitions();
+
+        assert!(!symbol_table.contains_var("x"));
+        assert!(!symbol_table.contains_fn("f"));
+        assert!(symbol_table.contains_var("pi"));
+        assert!(symbol_table.contains_fn("sin"));
+    }
+
+    #[test]
+    fn test_units() {
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.insert(Stmt::UnitDecl(String::from("m"), None));
+
+        assert!(symbol_table.contains_unit("m"));
+        assert!(symbol_table.contains_unit("deg"));
+        assert!(!symbol_table.contains_var("m"));
+        assert!(symbol_table.is_empty());
+
+        symbol_table.clear_user_definitions();
+
+        assert!(!symbol_table.contains_unit("m"));
+        assert!(symbol_table.contains_unit("rad"));
     }
 }
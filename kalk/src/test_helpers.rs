@@ -3,6 +3,45 @@ use crate::ast::Expr;
 use crate::ast::Stmt;
 use crate::lexer::Token;
 use crate::lexer::TokenKind;
+use crate::parser::{self, Context};
+use rug::Float;
+
+/// Assert that two `rug::Float`s are equal, apart from a difference of at most `ulps`
+/// units in the last place of the least precise one.
+#[allow(unused_macros)]
+macro_rules! assert_approx_eq {
+    ($a:expr, $b:expr, $ulps:expr) => {{
+        let (a, b): (&rug::Float, &rug::Float) = ($a, $b);
+        assert!(
+            crate::test_helpers::approx_eq(a, b, $ulps),
+            "assertion failed: `{} ≈ {}` (within {} ulps)",
+            a,
+            b,
+            $ulps
+        );
+    }};
+}
+#[allow(unused_imports)]
+pub(crate) use assert_approx_eq;
+
+pub fn approx_eq(a: &Float, b: &Float, ulps: u32) -> bool {
+    if a == b {
+        return true;
+    }
+
+    if !a.is_finite() || !b.is_finite() {
+        return false;
+    }
+
+    let precision = a.prec().min(b.prec());
+    let largest =
+        Float::with_val(precision, a.abs_ref()).max(&Float::with_val(precision, b.abs_ref()));
+    let exponent = largest.get_exp().unwrap_or(0);
+    let ulp = Float::with_val(precision, 1) << (exponent - precision as i32);
+    let difference = Float::with_val(a.prec().max(b.prec()) * 2, a - b).abs();
+
+    difference <= ulp * ulps
+}
 
 pub fn token(kind: TokenKind, value: &str) -> Token {
     Token {
@@ -43,3 +82,33 @@ pub fn var_decl(identifier: &str, value: Box<Expr>) -> Stmt {
 pub fn fn_decl(identifier: &str, parameters: Vec<String>, value: Box<Expr>) -> Stmt {
     Stmt::FnDecl(identifier.into(), parameters, value)
 }
+
+/// Evaluate each input several times, both in fresh contexts and in a re-used one,
+/// and assert that the formatted result is exactly the same every time.
+pub fn eval_all_deterministic(inputs: &[&str]) {
+    const RUNS: usize = 3;
+
+    for input in inputs {
+        let expected = format_eval(&mut Context::new(), input);
+        let mut reused_context = Context::new();
+
+        for _ in 0..RUNS {
+            assert_eq!(
+                format_eval(&mut Context::new(), input),
+                expected,
+                "{}",
+                input
+            );
+            assert_eq!(
+                format_eval(&mut reused_context, input),
+                expected,
+                "{}",
+                input
+            );
+        }
+    }
+}
+
+fn format_eval(context: &mut Context, input: &str) -> String {
+    format!("{:?}", parser::eval(context, input, 53))
+}
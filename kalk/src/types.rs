@@ -0,0 +1,552 @@
+use crate::ast::{Expr, Stmt};
+use crate::lexer::TokenKind;
+use crate::parser::{CalcError, KalkType};
+use crate::symbol_table::SymbolTable;
+use crate::{complex, interpreter, interval, prelude};
+use rug::Float;
+use std::collections::HashMap;
+
+/// The type of a subexpression, or `Ok(None)` from the surrounding function if it isn't known.
+macro_rules! known {
+    ($kalk_type:expr) => {
+        match $kalk_type? {
+            Some(kalk_type) => kalk_type,
+            None => return Ok(None),
+        }
+    };
+}
+
+/// Find out which type an expression will evaluate to, without evaluating it.
+///
+/// `None` is returned if the type depends on values that are only known at runtime,
+/// eg. names that are not defined yet or whether `sqrt(x)` is complex, or if evaluating it would fail, see `check`.
+pub fn type_of(expr: &Expr, symbol_table: &SymbolTable) -> Option<KalkType> {
+    check(expr, symbol_table).ok().flatten()
+}
+
+/// Like `type_of`, but values of types that can't be combined result in the error that evaluating them would give,
+/// eg. adding a vector to a matrix in `[1, 2] + [[1, 2], [3, 4]]`.
+pub fn check(expr: &Expr, symbol_table: &SymbolTable) -> Result<Option<KalkType>, CalcError> {
+    infer(expr, symbol_table, &HashMap::new(), &[])
+}
+
+/// Like `check`, but names that aren't defined are assumed to be real numbers, like the unknowns in `x^2 + 2x`.
+pub(crate) fn check_with_unknowns(
+    expr: &Expr,
+    symbol_table: &SymbolTable,
+) -> Result<Option<KalkType>, CalcError> {
+    let mut unknowns = HashMap::new();
+    collect_unknowns(expr, symbol_table, &mut unknowns);
+
+    infer(expr, symbol_table, &unknowns, &[])
+}
+
+fn collect_unknowns(
+    expr: &Expr,
+    symbol_table: &SymbolTable,
+    unknowns: &mut HashMap<String, Option<KalkType>>,
+) {
+    match expr {
+        Expr::Binary(left, _, right) | Expr::Equation(left, right) => {
+            collect_unknowns(left, symbol_table, unknowns);
+            collect_unknowns(right, symbol_table, unknowns);
+        }
+        Expr::Unary(_, expr)
+        | Expr::Unit(expr, _)
+        | Expr::Conversion(expr, _)
+        | Expr::Group(expr)
+        | Expr::Annotated(expr, _) => collect_unknowns(expr, symbol_table, unknowns),
+        Expr::FnCall(_, expressions) | Expr::Vector(expressions) => {
+            for expr in expressions {
+                collect_unknowns(expr, symbol_table, unknowns);
+            }
+        }
+        Expr::Conditional(condition, then, otherwise) => {
+            collect_unknowns(condition, symbol_table, unknowns);
+            collect_unknowns(then, symbol_table, unknowns);
+            collect_unknowns(otherwise, symbol_table, unknowns);
+        }
+        Expr::Var(identifier) => {
+            let is_defined = prelude::CONSTANTS.contains_key(identifier.as_str())
+                || symbol_table.is_last_answer(identifier)
+                || symbol_table.get_var(identifier).is_some()
+                || identifier == complex::IMAGINARY_UNIT;
+            if !is_defined {
+                unknowns.insert(identifier.clone(), Some(KalkType::Scalar));
+            }
+        }
+        Expr::Literal(_) => (),
+    }
+}
+
+/// `parameters` contains the types of the arguments when inspecting the body of a function,
+/// and `functions` the functions whose bodies are being inspected.
+fn infer(
+    expr: &Expr,
+    symbol_table: &SymbolTable,
+    parameters: &HashMap<String, Option<KalkType>>,
+    functions: &[&str],
+) -> Result<Option<KalkType>, CalcError> {
+    Ok(match expr {
+        // The unknown isn't declared, so it can't be inferred.
+        Expr::Equation(_, _) => Some(KalkType::Scalar),
+        Expr::Binary(left, op, right) => {
+            let left_type = known!(infer(left, symbol_table, parameters, functions));
+            let right_type = known!(infer(right, symbol_table, parameters, functions));
+
+            match (left_type, right_type) {
+                (KalkType::Scalar, KalkType::Scalar) if *op == TokenKind::Power => {
+                    power_type(symbol_table, parameters, left, right)
+                }
+                (left_type, right_type) => binary_type(left_type, op, right_type)?,
+            }
+        }
+        Expr::Vector(elements) => {
+            let mut element_types = Vec::with_capacity(elements.len());
+            for expr in elements {
+                element_types.push(known!(infer(expr, symbol_table, parameters, functions)));
+            }
+
+            // A vector of vectors is a matrix, and vectors can only contain numbers.
+            match element_types.first() {
+                Some(KalkType::Vector) => {
+                    if element_types.iter().any(|x| *x != KalkType::Vector) {
+                        return Err(CalcError::Message(String::from(
+                            "The rows of a matrix must be vectors of the same length.",
+                        )));
+                    }
+
+                    Some(KalkType::Matrix)
+                }
+                _ if element_types.iter().all(|x| *x == KalkType::Scalar) => Some(KalkType::Vector),
+                _ => return Err(CalcError::UnexpectedVector),
+            }
+        }
+        Expr::Unary(op, expr) => {
+            match (op, known!(infer(expr, symbol_table, parameters, functions))) {
+                (TokenKind::Minus, kalk_type) => Some(kalk_type),
+                (TokenKind::Not, KalkType::Complex) => Some(KalkType::Scalar),
+                (TokenKind::Tilde, KalkType::Complex) => return Err(complex::unsupported("~")),
+                (TokenKind::Exclamation, KalkType::Complex) => {
+                    return Err(complex::unsupported("!"))
+                }
+                (_, KalkType::Complex) => return Err(CalcError::InvalidOperator),
+                (_, kalk_type) => Some(kalk_type),
+            }
+        }
+        Expr::Unit(expr, _)
+        | Expr::Conversion(expr, _)
+        | Expr::Group(expr)
+        | Expr::Annotated(expr, _) => infer(expr, symbol_table, parameters, functions)?,
+        // The branches need to have the same type, since it's only known at runtime which one is chosen.
+        Expr::Conditional(_, then, otherwise) => {
+            let then = infer(then, symbol_table, parameters, functions)?;
+            let otherwise = infer(otherwise, symbol_table, parameters, functions)?;
+
+            then.filter(|then| otherwise.as_ref() == Some(then))
+        }
+        Expr::Var(identifier) => {
+            if let Some(parameter_type) = parameters.get(identifier) {
+                return Ok(parameter_type.clone());
+            }
+
+            if prelude::CONSTANTS.contains_key(identifier.as_str())
+                || (symbol_table.is_last_answer(identifier) && symbol_table.last_answer().is_some())
+            {
+                return Ok(Some(KalkType::Scalar));
+            }
+
+            match symbol_table.get_var(identifier) {
+                Some(Stmt::VarDecl(_, expr)) => check(expr, symbol_table)?,
+                _ if identifier == complex::IMAGINARY_UNIT => Some(KalkType::Complex),
+                _ => None,
+            }
+        }
+        Expr::FnCall(identifier, expressions) => {
+            infer_fn_call(identifier, expressions, symbol_table, parameters, functions)?
+        }
+        Expr::Literal(_) => Some(KalkType::Scalar),
+    })
+}
+
+fn infer_fn_call(
+    identifier: &str,
+    expressions: &[Expr],
+    symbol_table: &SymbolTable,
+    parameters: &HashMap<String, Option<KalkType>>,
+    functions: &[&str],
+) -> Result<Option<KalkType>, CalcError> {
+    let mut argument_types: Vec<Option<KalkType>> = Vec::with_capacity(expressions.len());
+    for expr in expressions {
+        argument_types.push(infer(expr, symbol_table, parameters, functions)?);
+    }
+
+    if let Some(Stmt::FnDecl(_, arguments, fn_body)) = symbol_table.get_fn(identifier) {
+        // The type of a function that calls itself depends on which branches are taken.
+        if functions.contains(&identifier) {
+            return Ok(None);
+        }
+
+        let parameters = arguments.iter().cloned().zip(argument_types).collect();
+        let functions = [functions, &[identifier]].concat();
+
+        return infer(fn_body, symbol_table, &parameters, &functions);
+    }
+
+    // Only the built-in functions of one argument support complex numbers, except for the ones that take a vector or matrix.
+    let is_real_fn = match expressions.len() {
+        1 => matches!(
+            identifier,
+            "len"
+                | "avg"
+                | "sum"
+                | "Σ"
+                | "prod"
+                | "∏"
+                | "min"
+                | "max"
+                | "det"
+                | "inv"
+                | "transpose"
+        ),
+        2 => prelude::BINARY_FUNCS.contains_key(identifier),
+        _ => false,
+    };
+    if argument_types.contains(&Some(KalkType::Complex))
+        && (is_real_fn
+            || prelude::STAT_FUNCS.contains_key(identifier)
+            || prelude::PAIRED_STAT_FUNCS.contains_key(identifier))
+    {
+        return Err(complex::unsupported(identifier));
+    }
+
+    let is_prelude_func = prelude::UNARY_FUNCS.contains_key(identifier)
+        || prelude::BINARY_FUNCS.contains_key(identifier);
+    let has_unknown_argument = argument_types.contains(&None);
+    let has_vector_argument = argument_types.contains(&Some(KalkType::Vector));
+    let has_matrix_argument = argument_types.contains(&Some(KalkType::Matrix));
+    Ok(match identifier {
+        "len" | "avg" | "sum" | "Σ" | "prod" | "∏" | "min" | "max" | "det"
+            if expressions.len() == 1 =>
+        {
+            Some(KalkType::Scalar)
+        }
+        name if prelude::STAT_FUNCS.contains_key(name)
+            || prelude::PAIRED_STAT_FUNCS.contains_key(name) =>
+        {
+            Some(KalkType::Scalar)
+        }
+        name if prelude::VECTOR_VALUED_FUNCS.contains_key(name) && expressions.len() == 1 => {
+            Some(KalkType::Vector)
+        }
+        name if interpreter::is_matrix_constructor(name) => Some(KalkType::Matrix),
+        name if prelude::BINARY_VECTOR_VALUED_FUNCS.contains_key(name)
+            && expressions.len() == 2 =>
+        {
+            Some(KalkType::Vector)
+        }
+        "simplify" if expressions.len() == 1 => argument_types[0].clone(),
+        // The inverse or transpose of a number is a number.
+        "inv" | "transpose" if expressions.len() == 1 => match argument_types[0] {
+            Some(KalkType::Scalar) => Some(KalkType::Scalar),
+            Some(_) => Some(KalkType::Matrix),
+            None => None,
+        },
+        _ if is_prelude_func && has_unknown_argument => None,
+        _ if is_prelude_func && has_vector_argument && has_matrix_argument => {
+            return Err(CalcError::Message(String::from(
+                "A vector and a matrix can only be multiplied.",
+            )))
+        }
+        _ if is_prelude_func && has_matrix_argument => Some(KalkType::Matrix),
+        _ if is_prelude_func && has_vector_argument => Some(KalkType::Vector),
+        _ if is_prelude_func && argument_types.first() == Some(&Some(KalkType::Complex)) => {
+            complex_fn_type(identifier)?
+        }
+        _ if is_prelude_func
+            && expressions.len() == 1
+            && complex::is_complex_valued_fn(identifier) =>
+        {
+            // Eg. `sqrt(-1)` is complex, while `sqrt(2)` isn't.
+            match known_bounds(symbol_table, parameters, &expressions[0]) {
+                Some((lo, _)) if lo >= 0 => Some(KalkType::Scalar),
+                Some((_, hi)) if hi < 0 => Some(KalkType::Complex),
+                _ => None,
+            }
+        }
+        _ if is_prelude_func => Some(KalkType::Scalar),
+        "sum" | "Σ" | "prod" | "∏" | "equiv" | "diff" | "integrate" | "∫" => {
+            Some(KalkType::Scalar)
+        }
+        // Native functions only take and return numbers.
+        _ if symbol_table.get_native_fn(identifier).is_some() => Some(KalkType::Scalar),
+        _ => None,
+    })
+}
+
+/// The type of an operation on values that aren't both real numbers, like in the interpreter:
+/// operations are done element-wise on vectors and matrices, except for the ones from linear algebra,
+/// and vectors and matrices can't contain complex numbers.
+fn binary_type(
+    left: KalkType,
+    op: &TokenKind,
+    right: KalkType,
+) -> Result<Option<KalkType>, CalcError> {
+    use KalkType::*;
+
+    Ok(Some(match (left, op, right) {
+        (Matrix, TokenKind::Star, Vector) | (Vector, TokenKind::Star, Matrix) => Vector,
+        (Matrix, TokenKind::Power, Scalar) => Matrix,
+        (Complex, _, Vector)
+        | (Complex, _, Matrix)
+        | (Vector, _, Complex)
+        | (Matrix, _, Complex) => return Err(interpreter::complex_element_error()),
+        (Vector, _, Matrix) | (Matrix, _, Vector) => {
+            return Err(CalcError::Message(String::from(
+                "A vector and a matrix can only be multiplied.",
+            )))
+        }
+        (Matrix, _, _) | (_, _, Matrix) => Matrix,
+        (Vector, _, _) | (_, _, Vector) => Vector,
+        (Scalar, _, Scalar) => Scalar,
+        (Complex, op, Complex) => match op {
+            // The imaginary parts may cancel out, eg. in `i * i`.
+            TokenKind::Plus | TokenKind::Minus | TokenKind::Star | TokenKind::Slash => {
+                return Ok(None)
+            }
+            op => return complex_operator_type(op),
+        },
+        (_, op, _) => match op {
+            TokenKind::Plus | TokenKind::Minus | TokenKind::Star | TokenKind::Slash => Complex,
+            op => return complex_operator_type(op),
+        },
+    }))
+}
+
+/// The type of an operation where at least one of the operands is a complex number,
+/// other than the arithmetic that results in a complex number.
+fn complex_operator_type(op: &TokenKind) -> Result<Option<KalkType>, CalcError> {
+    Ok(match op {
+        // Eg. `i^2` is real.
+        TokenKind::Power => None,
+        TokenKind::EqualsEquals
+        | TokenKind::NotEquals
+        | TokenKind::And
+        | TokenKind::Or
+        | TokenKind::Distance => Some(KalkType::Scalar),
+        TokenKind::DoubleSlash => return Err(complex::unsupported("//")),
+        TokenKind::Percent => return Err(complex::unsupported("%")),
+        TokenKind::Ampersand
+        | TokenKind::Pipe
+        | TokenKind::Xor
+        | TokenKind::ShiftLeft
+        | TokenKind::ShiftRight => return Err(complex::unsupported("Bitwise arithmetic")),
+        TokenKind::GreaterThan
+        | TokenKind::LessThan
+        | TokenKind::GreaterOrEquals
+        | TokenKind::LessOrEquals => return Err(complex::unsupported("Comparison")),
+        _ => return Err(CalcError::InvalidOperator),
+    })
+}
+
+/// The type of a built-in function of a complex number, see `complex::call_func`.
+fn complex_fn_type(identifier: &str) -> Result<Option<KalkType>, CalcError> {
+    Ok(match identifier {
+        "abs" | "re" | "im" | "arg" => Some(KalkType::Scalar),
+        "sqrt" | "√" | "ln" | "conj" => Some(KalkType::Complex),
+        // Eg. `cos(i)` and `floor(0.5i)` are real.
+        "exp" | "sin" | "cos" | "tan" | "floor" | "ceil" | "round" => None,
+        _ => return Err(complex::unsupported(identifier)),
+    })
+}
+
+/// A power of real numbers is real if the exponent is an integer or the base isn't negative,
+/// and complex if the base is negative and the exponent isn't an integer, eg. `(-1)^0.5`.
+fn power_type(
+    symbol_table: &SymbolTable,
+    parameters: &HashMap<String, Option<KalkType>>,
+    base: &Expr,
+    exponent: &Expr,
+) -> Option<KalkType> {
+    let is_integer_exponent = match exponent {
+        Expr::Literal(exponent) => {
+            Some(Float::parse(exponent).map_or(false, |x| Float::with_val(53, x).is_integer()))
+        }
+        _ => None,
+    };
+    if is_integer_exponent == Some(true) {
+        return Some(KalkType::Scalar);
+    }
+
+    match known_bounds(symbol_table, parameters, base) {
+        Some((lo, _)) if lo >= 0 => Some(KalkType::Scalar),
+        Some((_, hi)) if hi < 0 && is_integer_exponent == Some(false) => Some(KalkType::Complex),
+        _ => None,
+    }
+}
+
+/// Bounds of the value of an expression that can be calculated before evaluating it, ie. of numbers, constants
+/// and variables defined as such. `None` is returned inside a function, where the values of the parameters aren't known.
+fn known_bounds(
+    symbol_table: &SymbolTable,
+    parameters: &HashMap<String, Option<KalkType>>,
+    expr: &Expr,
+) -> Option<(Float, Float)> {
+    if !parameters.is_empty() {
+        return None;
+    }
+
+    interval::eval(symbol_table, expr, 53).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::TokenKind::*;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn test_scalar() {
+        let symbol_table = SymbolTable::new();
+        let expr = binary(literal("1"), Plus, fn_call("sqrt", vec![*var("pi")]));
+
+        assert_eq!(type_of(&expr, &symbol_table), Some(KalkType::Scalar));
+    }
+
+    #[test]
+    fn test_undefined() {
+        let symbol_table = SymbolTable::new();
+
+        assert_eq!(type_of(&var("x"), &symbol_table), None);
+        assert_eq!(type_of(&fn_call("f", vec![]), &symbol_table), None);
+    }
+
+    #[test]
+    fn test_fn() {
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.insert(fn_decl(
+            "f",
+            vec![String::from("x")],
+            binary(var("x"), Plus, literal("2")),
+        ));
+
+        let call = fn_call("f", vec![*literal("1")]);
+        let call_with_undefined = fn_call("f", vec![*var("y")]);
+
+        assert_eq!(type_of(&call, &symbol_table), Some(KalkType::Scalar));
+        assert_eq!(type_of(&call_with_undefined, &symbol_table), None);
+    }
+
+    #[test]
+    fn test_vector() {
+        let symbol_table = SymbolTable::new();
+        let vector = Expr::Vector(vec![*literal("1"), *literal("2")]);
+        let doubled = binary(Box::new(vector.clone()), Star, literal("2"));
+        let length = fn_call("len", vec![vector.clone()]);
+        let mixed = Expr::Vector(vec![vector.clone(), *literal("3")]);
+
+        assert_eq!(type_of(&doubled, &symbol_table), Some(KalkType::Vector));
+        assert_eq!(type_of(&length, &symbol_table), Some(KalkType::Scalar));
+        assert_eq!(type_of(&mixed, &symbol_table), None);
+    }
+
+    #[test]
+    fn test_matrix() {
+        let symbol_table = SymbolTable::new();
+        let vector = Expr::Vector(vec![*literal("1"), *literal("2")]);
+        let matrix = Expr::Vector(vec![vector.clone(), vector.clone()]);
+        let product = binary(Box::new(matrix.clone()), Star, Box::new(vector));
+        let sum = binary(Box::new(matrix.clone()), Plus, literal("1"));
+        let determinant = fn_call("det", vec![matrix.clone()]);
+        let inverse = fn_call("inv", vec![matrix]);
+
+        assert_eq!(type_of(&product, &symbol_table), Some(KalkType::Vector));
+        assert_eq!(type_of(&sum, &symbol_table), Some(KalkType::Matrix));
+        assert_eq!(type_of(&determinant, &symbol_table), Some(KalkType::Scalar));
+        assert_eq!(type_of(&inverse, &symbol_table), Some(KalkType::Matrix));
+    }
+
+    #[test]
+    fn test_vector_and_matrix() {
+        let symbol_table = SymbolTable::new();
+        let vector = Expr::Vector(vec![*literal("1"), *literal("2")]);
+        let matrix = Expr::Vector(vec![vector.clone(), vector.clone()]);
+        let sum = binary(Box::new(matrix.clone()), Plus, Box::new(vector.clone()));
+        let quotient = binary(Box::new(vector.clone()), Slash, Box::new(matrix));
+        let complex_elements = binary(Box::new(vector), Star, var("i"));
+        let mismatch = Err(CalcError::Message(String::from(
+            "A vector and a matrix can only be multiplied.",
+        )));
+
+        assert_eq!(check(&sum, &symbol_table), mismatch);
+        assert_eq!(check(&quotient, &symbol_table), mismatch);
+        assert_eq!(type_of(&sum, &symbol_table), None);
+        assert!(check(&complex_elements, &symbol_table).is_err());
+    }
+
+    #[test]
+    fn test_complex() {
+        let symbol_table = SymbolTable::new();
+        let sqrt = |expr: Box<Expr>| fn_call("sqrt", vec![*expr]);
+        let negative_one = group(unary(Minus, literal("1")));
+
+        assert_eq!(
+            type_of(&sqrt(unary(Minus, literal("1"))), &symbol_table),
+            Some(KalkType::Complex)
+        );
+        assert_eq!(
+            type_of(&sqrt(literal("2")), &symbol_table),
+            Some(KalkType::Scalar)
+        );
+        assert_eq!(
+            type_of(&binary(literal("2"), Star, var("i")), &symbol_table),
+            Some(KalkType::Complex)
+        );
+        assert_eq!(
+            type_of(&fn_call("abs", vec![*var("i")]), &symbol_table),
+            Some(KalkType::Scalar)
+        );
+        assert_eq!(
+            type_of(
+                &binary(negative_one.clone(), Power, literal("0.5")),
+                &symbol_table
+            ),
+            Some(KalkType::Complex)
+        );
+        assert_eq!(
+            type_of(&binary(negative_one, Power, literal("2")), &symbol_table),
+            Some(KalkType::Scalar)
+        );
+        // The imaginary parts cancel out.
+        assert_eq!(
+            type_of(&binary(var("i"), Star, var("i")), &symbol_table),
+            None
+        );
+        assert!(check(&binary(var("i"), LessThan, literal("1")), &symbol_table).is_err());
+    }
+
+    #[test]
+    fn test_conditional() {
+        let symbol_table = SymbolTable::new();
+        let vector = Expr::Vector(vec![*literal("1"), *literal("2")]);
+        let scalars = Expr::Conditional(literal("1"), literal("2"), literal("3"));
+        let mixed = Expr::Conditional(literal("1"), literal("2"), Box::new(vector));
+
+        assert_eq!(type_of(&scalars, &symbol_table), Some(KalkType::Scalar));
+        assert_eq!(type_of(&mixed, &symbol_table), None);
+    }
+
+    #[test]
+    fn test_recursive_fn() {
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.insert(fn_decl(
+            "f",
+            vec![String::from("x")],
+            binary(literal("1"), Plus, fn_call("f", vec![*var("x")])),
+        ));
+
+        let call = fn_call("f", vec![*literal("1")]);
+
+        assert_eq!(type_of(&call, &symbol_table), None);
+    }
+}
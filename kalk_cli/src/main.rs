@@ -4,35 +4,29 @@ mod repl;
 use kalk::parser;
 use kalk::parser::Unit;
 use std::env;
-use std::fs::File;
-use std::io::Read;
 
 fn main() {
-    let mut parser_context = parser::Context::new().set_angle_unit(get_angle_unit());
+    let mut parser_context = parser::Context::new()
+        .set_angle_unit(get_angle_unit())
+        .set_output_base(get_output_base());
 
     // Command line argument input, execute it and exit.
     let mut args = env::args().skip(1);
     let mut expr_input: Option<String> = None;
-    loop {
-        // Get the next argument if possible, otherwise break the loop.
-        let arg = if let Some(arg) = args.next() {
-            arg
-        } else {
-            break;
-        };
-
+    let mut script: Option<String> = None;
+    while let Some(arg) = args.next() {
         if arg == "-i" {
             let file_name = &args.next().expect("Expected input file."); // The next argument will be the file name.
-            let mut file_content = String::new();
-            File::open(&file_name)
-                .expect("Couldn't find file.")
-                .read_to_string(&mut file_content)
-                .expect("Failed to read input file.");
 
-            // Parse the input file content, resulting in the symbol table being filled out.
+            // Evaluate the input file, resulting in the symbol table being filled out.
             // Output is not needed here.
-            parser::eval(&mut parser_context, &file_content, 53)
-                .expect("Failed to parse input file.");
+            if let Err(err) = parser::eval_file(&mut parser_context, file_name) {
+                panic!("Failed to load input file. {}", err);
+            }
+        } else if arg.ends_with(".kalk") {
+            // Eg. `kalk script.kalk`. The answers of the expressions in the file are printed.
+            // Only the extension is checked, since eg. `kalk e` should calculate e even if there is a file named `e`.
+            script = Some(arg);
         } else {
             // Main argument. This is expected to be a maths expression.
             // After the loop is finished, this will be parsed and outputted.
@@ -40,7 +34,9 @@ fn main() {
         }
     }
 
-    if let Some(input) = expr_input {
+    if let Some(path) = script {
+        output::eval_file(&mut parser_context, &path);
+    } else if let Some(input) = expr_input {
         // Direct output
         output::eval(&mut parser_context, &input);
     } else {
@@ -62,3 +58,16 @@ fn get_angle_unit() -> Unit {
         Unit::Radians
     }
 }
+
+fn get_output_base() -> u32 {
+    if let Ok(output_base_var) = env::var("OUTPUT_BASE") {
+        match output_base_var.parse() {
+            Ok(base) if (2..=36).contains(&base) => base,
+            _ => {
+                panic!("Unexpected output base: {}.", output_base_var);
+            }
+        }
+    } else {
+        parser::DEFAULT_OUTPUT_BASE
+    }
+}
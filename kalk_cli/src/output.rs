@@ -1,41 +1,76 @@
 use ansi_term::Colour::Red;
-use kalk::parser::{self, CalcError, CalcError::*};
+use kalk::output::FormatOptions;
+use kalk::parser::{self, CalcError};
+use kalk::simplify;
+use kalk::{Expr, Stmt, Value};
+use rug::Float;
 
 pub fn eval(parser: &mut parser::Context, input: &str) {
-    match parser::eval(parser, input, 53) {
-        Ok(Some(result)) => {
-            let (_, digits, exp_option) = result.to_sign_string_exp(10, None);
-            let exp = if let Some(exp) = exp_option { exp } else { 0 };
-
-            if result.is_infinite() {
-                print_err("Too big to process.");
-            } else {
-                let use_sci_notation = exp > 8 || exp < -6;
-                let comma_pos = if use_sci_notation { 1 } else { exp as usize };
-                let sign = if result >= 0 { "" } else { "-" };
-
-                let num = if exp <= 0 {
-                    // 0 < x < 1
-                    format!("0.{}{}", "0".repeat(exp.abs() as usize), digits)
-                } else if use_sci_notation || result.fract() != 0 {
-                    // Insert the comma if there are supposed to be decimals.
-                    let mut chars: Vec<char> = digits.trim_end_matches('0').chars().collect();
-                    chars.insert(comma_pos, '.');
-                    chars.into_iter().collect::<String>()
-                } else {
-                    // Regular number
-                    digits[..(exp as usize)].to_string()
-                };
-
-                if use_sci_notation {
-                    println!("{}{}*10^{}", sign, num, exp);
-                } else {
-                    println!("{}{}", sign, num);
+    let statements = match parser::parse(parser, input) {
+        Ok(statements) => statements,
+        Err(err) => {
+            print_calc_err(parser, input, err);
+            return;
+        }
+    };
+    if let Some(simplified) = simplified_form(parser, &statements) {
+        println!("{}", simplified);
+        return;
+    }
+
+    let options = FormatOptions::new();
+    match parser.evaluate_statements(statements, 53) {
+        Ok(Some(Value::Scalar(result))) if result.is_infinite() => print_err("Too big to process."),
+        Ok(Some(Value::Complex(result)))
+            if result.real().is_infinite() || result.imag().is_infinite() =>
+        {
+            print_err("Too big to process.")
+        }
+        Ok(Some(Value::Scalar(result))) => println!("{}", format_real(parser, &result, &options)),
+        Ok(Some(value)) => println!("{}", value.format(&options)),
+        Ok(None) => print!(""),
+        Err(err) => print_calc_err(parser, input, err),
+    }
+}
+
+/// Evaluate a script file, and print the answers of the lines that are expressions.
+pub fn eval_file(parser: &mut parser::Context, path: &str) {
+    let options = FormatOptions::new();
+    match parser::eval_file(parser, path) {
+        Ok(answers) => {
+            for (_, value) in answers {
+                match value {
+                    Value::Scalar(value) => println!("{}", format_real(parser, &value, &options)),
+                    value => println!("{}", value.format(&options)),
                 }
             }
         }
-        Ok(None) => print!(""),
-        Err(err) => print_calc_err(err),
+        Err(err) => print_err(&err.to_string()),
+    }
+}
+
+/// `simplify(expr)` shows the simplified expression rather than its value, eg. `2 * x` for `simplify(x + x)`.
+fn simplified_form(parser: &parser::Context, statements: &[Stmt]) -> Option<String> {
+    match statements.last()? {
+        Stmt::Expr(expr) => match &**expr {
+            Expr::FnCall(identifier, arguments)
+                if identifier == "simplify" && arguments.len() == 1 =>
+            {
+                let stmt = Stmt::Expr(expr.clone());
+
+                Some(simplify::simplify_stmt(stmt, parser.symbol_table()).to_string())
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Integers are formatted in the output base, if another one than base 10 has been chosen.
+fn format_real(parser: &parser::Context, value: &Float, options: &FormatOptions) -> String {
+    match parser.format_value(value) {
+        Some(formatted) if parser.output_base() != parser::DEFAULT_OUTPUT_BASE => formatted,
+        _ => options.format_number(value),
     }
 }
 
@@ -43,18 +78,11 @@ pub fn print_err(msg: &str) {
     println!("{}", Red.paint(msg));
 }
 
-fn print_calc_err(err: CalcError) {
-    print_err(&match err {
-        IncorrectAmountOfArguments(expected, func, got) => format!(
-            "Expected {} arguments for function {}, but got {}.",
-            expected, func, got
-        ),
-        InvalidNumberLiteral(x) => format!("Invalid number literal: '{}'.", x),
-        InvalidOperator => format!("Invalid operator."),
-        InvalidUnit => format!("Invalid unit."),
-        UnexpectedToken(kind) => format!("Unexpected token: '{:?}'.", kind),
-        UndefinedFn(name) => format!("Undefined function: '{}'.", name),
-        UndefinedVar(name) => format!("Undefined variable: '{}'.", name),
-        Unknown => format!("Unknown error."),
-    });
+/// Errors with a known location are printed with the input, and the location underlined.
+fn print_calc_err(parser: &parser::Context, input: &str, err: CalcError) {
+    if err.span().is_some() {
+        print_err(&parser.format_error(&err, input));
+    } else {
+        print_err(&err.to_string());
+    }
 }
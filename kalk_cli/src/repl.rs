@@ -16,7 +16,7 @@ use std::borrow::Cow;
 use std::borrow::Cow::Owned;
 use std::process;
 
-pub fn start(mut parser: &mut parser::Context) {
+pub fn start(parser: &mut parser::Context) {
     let mut editor = Editor::<RLHelper>::new();
     editor.set_helper(Some(RLHelper {
         highlighter: LineHighlighter {},
@@ -29,7 +29,7 @@ pub fn start(mut parser: &mut parser::Context) {
         match readline {
             Ok(input) => {
                 editor.add_history_entry(input.as_str());
-                eval_repl(&mut parser, &input);
+                eval_repl(parser, &input);
             }
             Err(ReadlineError::Interrupted) => break,
             _ => break,
@@ -38,6 +38,32 @@ pub fn start(mut parser: &mut parser::Context) {
 }
 
 fn eval_repl(parser: &mut parser::Context, input: &str) {
+    if let Some(name) = input.strip_prefix("help ") {
+        match parser.help(name.trim()) {
+            Some(doc) => println!("{}", doc),
+            None => output::print_err("No documentation found."),
+        }
+
+        return;
+    }
+
+    // Eg. `save session.kalk`, to keep the definitions for the next session, and `load session.kalk`.
+    if let Some(path) = input.strip_prefix("save ") {
+        if let Err(err) = parser.save_state(path.trim()) {
+            output::print_err(&err.to_string());
+        }
+
+        return;
+    }
+
+    if let Some(path) = input.strip_prefix("load ") {
+        if let Err(err) = parser.load_state(path.trim()) {
+            output::print_err(&err.to_string());
+        }
+
+        return;
+    }
+
     match input {
         "" => eprint!(""),
         "clear" => print!("\x1B[2J"),
@@ -144,7 +170,7 @@ impl Highlighter for RLHelper {
     }
 
     fn highlight_char(&self, line: &str, _: usize) -> bool {
-        line.len() > 0
+        !line.is_empty()
     }
 }
 